@@ -0,0 +1,47 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use ipcow::core::sockparse::{ip_iter, parse_ip_input};
+use ipcow::test_support::{allocated_bytes, CountingAllocator};
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+// Representative specs, from a single host up to a /16
+const SPECS: &[(&str, &str)] = &[
+    ("single", "192.168.1.10"),
+    ("cidr_24", "192.168.1.0/24"),
+    ("cidr_16", "10.20.0.0/16"),
+    ("wildcard", "10.20.X.X"),
+    ("list", "10.0.0.1, 10.0.0.5-10.0.0.20, 172.16.0.0/28"),
+];
+
+fn benchmark_parse_ip_input(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse_ip_input");
+    for (name, spec) in SPECS {
+        group.bench_function(*name, |b| {
+            b.iter(|| black_box(parse_ip_input(black_box(spec)).unwrap()))
+        });
+    }
+    group.finish();
+}
+
+fn benchmark_ip_iter(c: &mut Criterion) {
+    // ip_iter takes one range or CIDR at a time and has no wildcard form
+    let specs = SPECS
+        .iter()
+        .filter(|(name, _)| matches!(*name, "single" | "cidr_24" | "cidr_16"));
+
+    let mut group = c.benchmark_group("ip_iter");
+    for (name, spec) in specs {
+        // The lazy path must stay allocation-free however large the block
+        let (_, bytes) = allocated_bytes(|| ip_iter(spec).unwrap().count());
+        assert!(bytes < 1024, "ip_iter({}) allocated {} bytes", spec, bytes);
+
+        group.bench_function(*name, |b| {
+            b.iter(|| black_box(ip_iter(black_box(spec)).unwrap().count()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_parse_ip_input, benchmark_ip_iter);
+criterion_main!(benches);
@@ -4,6 +4,8 @@ use std::time::Duration;
 use std::io::{stdout, Write};
 use std::thread::sleep;
 use terminal_size::{Width, Height, terminal_size};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
 use nalgebra::{Matrix2, Matrix3, Vector2, Vector3, Rotation3, Const, ArrayStorage};
 
 const CUBE_VERTICES: [[f32; 3]; 8] = [
@@ -23,7 +25,60 @@ const CUBE_EDGES: [(usize, usize); 12] = [
     (0, 4), (1, 5), (2, 6), (3, 7),  // connecting edges
 ];
 
+/// Shape drawn by `AsciiCube`: points in model space (roughly within -1..=1 on
+/// each axis) and the pairs of vertex indices joined by lines
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wireframe {
+    pub vertices: Vec<[f32; 3]>,
+    pub edges: Vec<(usize, usize)>, // Edges naming a missing vertex are skipped
+}
+
+impl Wireframe {
+    pub fn cube() -> Self {
+        Self {
+            vertices: CUBE_VERTICES.to_vec(),
+            edges: CUBE_EDGES.to_vec(),
+        }
+    }
+
+    /// Regular tetrahedron on alternate corners of the cube
+    pub fn tetrahedron() -> Self {
+        Self {
+            vertices: vec![
+                [1.0, 1.0, 1.0],
+                [1.0, -1.0, -1.0],
+                [-1.0, 1.0, -1.0],
+                [-1.0, -1.0, 1.0],
+            ],
+            edges: vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)],
+        }
+    }
+
+    /// Regular octahedron with a vertex on each half-axis
+    pub fn octahedron() -> Self {
+        Self {
+            vertices: vec![
+                [1.0, 0.0, 0.0],
+                [-1.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0],
+                [0.0, -1.0, 0.0],
+                [0.0, 0.0, 1.0],
+                [0.0, 0.0, -1.0],
+            ],
+            // Every vertex joins all others except its opposite
+            edges: vec![
+                (0, 2), (0, 3), (0, 4), (0, 5),
+                (1, 2), (1, 3), (1, 4), (1, 5),
+                (2, 4), (2, 5), (3, 4), (3, 5),
+            ],
+        }
+    }
+}
+
 pub struct AsciiCube {
+    // Shape being animated
+    wireframe: Wireframe,
+
     // Existing fields
     angle_x: f32,
     angle_y: f32,
@@ -85,6 +140,10 @@ impl AsciiCube {
     const ROTATION_DAMPING: f32 = 0.95;
     const SCALE_BOUNDS: (f32, f32) = (0.3, 1.8);
 
+    // Keyboard speed control
+    const SPEED_STEP: f32 = 0.25;
+    const MAX_SPEED: f32 = 10.0;
+
     const SMOOTHING_FACTOR: f32 = 0.1;
     const SIZE_UPDATE_THRESHOLD: f32 = 0.05;
 
@@ -125,6 +184,19 @@ impl AsciiCube {
             target_height as f32, 
             Self::SMOOTHING_FACTOR
         ) as usize;
+
+        self.fit_buffers();
+    }
+
+    // Keeps both frame buffers the size of the canvas, which follows the terminal
+    fn fit_buffers(&mut self) {
+        let (width, height) = (self.canvas_width, self.canvas_height);
+        for buffer in [&mut self.buffer_a, &mut self.buffer_b] {
+            buffer.resize(height, Vec::new());
+            for row in buffer.iter_mut() {
+                row.resize(width, (' ', "\x1b[0m"));
+            }
+        }
     }
 
     pub fn new(width: usize, height: usize, speed: f32) -> Self {
@@ -139,6 +211,8 @@ impl AsciiCube {
         let eigen = system_matrix.symmetric_eigen();
         
         Self {
+            wireframe: Wireframe::cube(),
+
             // Existing initializations...
             angle_x: 0.0,
             angle_y: 0.0,
@@ -177,6 +251,11 @@ impl AsciiCube {
     }
 
     pub fn new_auto_size(speed: f32) -> Self {
+        Self::from_wireframe(Wireframe::cube(), speed)
+    }
+
+    /// Animates `wireframe` on a canvas sized to the terminal
+    pub fn from_wireframe(wireframe: Wireframe, speed: f32) -> Self {
         let (width, height) = Self::get_terminal_size();
         let empty_cell = (' ', "\x1b[0m");
         let buffer_a = vec![vec![empty_cell; width]; height];
@@ -184,7 +263,7 @@ impl AsciiCube {
         
         // Generate random eigenvalues for more interesting behavior
         use rand::Rng;
-        let mut rng = rand::thread_rng();
+        let mut rng = crate::utils::RngSource::global().derive("ascii-cube");
         
         let system_matrix = Matrix3::new(
             rng.gen_range(-2.0..2.0), rng.gen_range(-1.0..1.0), 0.0,
@@ -195,6 +274,7 @@ impl AsciiCube {
         let eigen = system_matrix.symmetric_eigen();
         
         Self {
+            wireframe,
             angle_x: 0.0,
             angle_y: 0.0,
             angle_z: 0.0,
@@ -299,10 +379,20 @@ impl AsciiCube {
         let depth = 5.0;
         let z = depth / (depth + point[2]);
         
-        let x = ((point[0] * z * adjusted_scale) + self.canvas_width as f32 / 2.0) as i32;
-        let y = ((point[1] * z * adjusted_scale) + self.canvas_height as f32 / 2.0) as i32;
+        let x = (point[0] * z * adjusted_scale) + self.canvas_width as f32 / 2.0;
+        let y = (point[1] * z * adjusted_scale) + self.canvas_height as f32 / 2.0;
         
-        (x, y)
+        // Points near the camera project far off the canvas; bound them so the
+        // line drawing between them stays short
+        let (w, h) = (self.canvas_width as f32, self.canvas_height as f32);
+        (x.clamp(-w, 2.0 * w) as i32, y.clamp(-h, 2.0 * h) as i32)
+    }
+
+    /// Advances the animation one step and returns the frame as text, one line per
+    /// canvas row with ANSI colors; nothing is written to the terminal
+    pub fn next_frame(&mut self) -> String {
+        self.update();
+        self.render()
     }
 
     pub fn render(&mut self) -> String {
@@ -337,8 +427,12 @@ impl AsciiCube {
         let color = colors[color_index.clamp(0, colors.len() - 1)];
 
         loop {
+            // The canvas may be smaller than the dimensions passed in
             if x >= 0 && x < canvas_width as i32 && y >= 0 && y < canvas_height as i32 {
-                canvas[y as usize][x as usize] = ('.', color);
+                let cell = canvas.get_mut(y as usize).and_then(|row| row.get_mut(x as usize));
+                if let Some(cell) = cell {
+                    *cell = ('.', color);
+                }
             }
             
             if x == x2 && y == y2 { break; }
@@ -425,21 +519,24 @@ impl AsciiCube {
         }
     }
 
+    /// Runs the animation until `q`, Esc or Ctrl+C; see `AnimationKey::from_event`
+    /// for the other controls. The terminal is restored on the way out
     pub fn start_animation(&mut self) {
-        // Set up ctrl+c handler for cleanup
-        ctrlc::set_handler(|| {
-            print!("\x1B[?25h"); // Show cursor
-            print!("\x1B[2J\x1B[1;1H"); // Clear screen
+        // Raw mode turns Ctrl+C into a key press; this covers a signal sent otherwise.
+        // It can only be installed once per process, which is all it needs
+        let _ = ctrlc::set_handler(|| {
+            restore_terminal();
             std::process::exit(0);
-        }).expect("Failed to set Ctrl+C handler");
+        });
 
-        // Hide cursor during animation
+        // Hide cursor during animation, read keys as they are pressed
+        let _ = terminal::enable_raw_mode();
         print!("\x1B[?25l");
         
         let frame_time = Duration::from_millis(33);
         let mut last_frame = std::time::Instant::now();
         
-        loop {
+        'animation: loop {
             self.smooth_terminal_update(); // Add dynamic terminal size handling
             let now = std::time::Instant::now();
             let elapsed = now - last_frame;
@@ -449,43 +546,152 @@ impl AsciiCube {
                 print!("\x1B[2J\x1B[1;1H");
                 
                 // Render directly to stdout without String allocation
+                // (raw mode needs explicit carriage returns)
                 let buffer = self.render_cube();
                 for row in buffer {
                     for (c, color) in row {
                         print!("{}{}", color, c);
                     }
-                    println!("\x1b[0m");
+                    print!("\x1b[0m\r\n");
+                }
+                if self.show_details {
+                    print!("{}\r\n", self.details());
                 }
+                print!("{}", CONTROLS_HINT);
                 
                 stdout().flush().unwrap();
                 last_frame = now;
             } else {
-                thread::sleep(frame_time - elapsed);
+                // Waiting for input doubles as the frame delay
+                match event::poll(frame_time - elapsed) {
+                    Ok(true) => {
+                        // Drain every pending key before the next frame
+                        while let Ok(event) = event::read() {
+                            match AnimationKey::from_event(&event) {
+                                Some(AnimationKey::Quit) => break 'animation,
+                                Some(key) => self.apply_key(key),
+                                None => {}
+                            }
+                            if !event::poll(Duration::ZERO).unwrap_or(false) {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(false) => {}
+                    // No terminal to read keys from; keep animating
+                    Err(_) => thread::sleep(frame_time - elapsed),
+                }
+            }
+        }
+        restore_terminal();
+    }
+
+    /// Applies one control to the animation state; `Quit` is left to the caller
+    pub fn apply_key(&mut self, key: AnimationKey) {
+        match key {
+            AnimationKey::RotateX(step) => self.angle_x = (self.angle_x + step) % (2.0 * PI),
+            AnimationKey::RotateY(step) => self.angle_y = (self.angle_y + step) % (2.0 * PI),
+            AnimationKey::RotateZ(step) => self.angle_z = (self.angle_z + step) % (2.0 * PI),
+            AnimationKey::Faster => {
+                self.rotation_speed = (self.rotation_speed + Self::SPEED_STEP).min(Self::MAX_SPEED)
             }
+            AnimationKey::Slower => {
+                self.rotation_speed = (self.rotation_speed - Self::SPEED_STEP).max(0.0)
+            }
+            AnimationKey::ToggleDetails => self.show_details = !self.show_details,
+            AnimationKey::Quit => {}
+        }
+    }
+
+    // Status line shown under the frame while `show_details` is on
+    fn details(&self) -> String {
+        format!(
+            "x={:.2} y={:.2} z={:.2}  speed={:.2}  scale={:.2}  energy={:.3}  eigen={:.2?}",
+            self.angle_x,
+            self.angle_y,
+            self.angle_z,
+            self.rotation_speed,
+            self.current_scale,
+            self.calculate_energy(),
+            self.eigenvalues,
+        )
+    }
+}
+
+/// Animation controls read from the keyboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AnimationKey {
+    RotateX(f32), // Radians added to the x angle
+    RotateY(f32),
+    RotateZ(f32),
+    Faster,
+    Slower,
+    ToggleDetails,
+    Quit,
+}
+
+/// Angle one arrow (or z/x) press turns the shape by
+const ROTATE_STEP: f32 = PI / 24.0;
+/// Printed under every frame
+const CONTROLS_HINT: &str = "arrows rotate  z/x roll  +/- speed  d details  q quit";
+
+impl AnimationKey {
+    /// Up/Down and Left/Right turn around the x and y axes, z/x around z;
+    /// +/- change the speed, d toggles details and q, Esc or Ctrl+C quit
+    pub fn from_event(event: &Event) -> Option<Self> {
+        let Event::Key(key) = event else {
+            return None;
+        };
+        // Some terminals also report key releases
+        if key.kind == KeyEventKind::Release {
+            return None;
         }
+        Some(match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Self::Quit,
+            KeyCode::Up => Self::RotateX(-ROTATE_STEP),
+            KeyCode::Down => Self::RotateX(ROTATE_STEP),
+            KeyCode::Left => Self::RotateY(-ROTATE_STEP),
+            KeyCode::Right => Self::RotateY(ROTATE_STEP),
+            KeyCode::Char('z') => Self::RotateZ(-ROTATE_STEP),
+            KeyCode::Char('x') => Self::RotateZ(ROTATE_STEP),
+            KeyCode::Char('+') | KeyCode::Char('=') => Self::Faster,
+            KeyCode::Char('-') | KeyCode::Char('_') => Self::Slower,
+            KeyCode::Char('d') => Self::ToggleDetails,
+            KeyCode::Char('q') | KeyCode::Esc => Self::Quit,
+            _ => return None,
+        })
     }
 }
 
+/// Leaves raw mode, shows the cursor and clears the screen after the animation
+fn restore_terminal() {
+    let _ = terminal::disable_raw_mode();
+    print!("\x1b[0m\x1B[?25h"); // Reset colors, show cursor
+    print!("\x1B[2J\x1B[1;1H"); // Clear screen
+    let _ = stdout().flush();
+}
+
 impl AsciiCube {
     fn render_cube(&mut self) -> &Vec<Vec<(char, &'static str)>> {
         // Calculate all transformations first
         let transform = self.calculate_stable_transformation();
-        let transformed_points: Vec<_> = CUBE_VERTICES.iter()
+        let transformed_points: Vec<_> = self.wireframe.vertices.iter()
             .map(|v| transform * Vector3::from_column_slice(v))
             .collect();
 
-        let edges: Vec<_> = CUBE_EDGES.iter()
-            .map(|(start_idx, end_idx)| {
-                let start = &transformed_points[*start_idx];
-                let end = &transformed_points[*end_idx];
+        let edges: Vec<_> = self.wireframe.edges.iter()
+            .filter_map(|(start_idx, end_idx)| {
+                let start = transformed_points.get(*start_idx)?;
+                let end = transformed_points.get(*end_idx)?;
                 
                 let (x1, y1) = self.project_point(&[start[0], start[1], start[2]]);
                 let (x2, y2) = self.project_point(&[end[0], end[1], end[2]]);
                 
-                ((x1, y1), (x2, y2), start[2])
+                Some(((x1, y1), (x2, y2), start[2]))
             })
             .collect();
 
+        self.fit_buffers();
         let buffer = if self.current_buffer {
             &mut self.buffer_a
         } else {
@@ -523,14 +729,17 @@ impl AsciiCube {
         let transform = self.calculate_stable_transformation();
         
         // Transform vertices using fixed array construction
-        let transformed_points: Vec<Vector3<f32>> = CUBE_VERTICES.iter()
+        let transformed_points: Vec<Vector3<f32>> = self.wireframe.vertices.iter()
             .map(|v| transform * Vector3::from_column_slice(v))
             .collect();
         
         // Rest of the rendering code...
-        for &(start_idx, end_idx) in CUBE_EDGES.iter() {
-            let start = &transformed_points[start_idx];
-            let end = &transformed_points[end_idx];
+        for &(start_idx, end_idx) in self.wireframe.edges.iter() {
+            let (Some(start), Some(end)) =
+                (transformed_points.get(start_idx), transformed_points.get(end_idx))
+            else {
+                continue;
+            };
             
             let (x1, y1) = self.project_point(&[start[0], start[1], start[2]]);
             let (x2, y2) = self.project_point(&[end[0], end[1], end[2]]);
@@ -546,4 +755,155 @@ pub fn display_rotating_cube() {
     let mut cube = AsciiCube::new_auto_size(1.0);
     println!("\nDisplaying ASCII Cube Animation (Press Ctrl+C to stop)...\n");
     cube.start_animation();
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_wireframes_are_closed_meshes() {
+        for (shape, vertices, edges) in [
+            (Wireframe::cube(), 8, 12),
+            (Wireframe::tetrahedron(), 4, 6),
+            (Wireframe::octahedron(), 6, 12),
+        ] {
+            assert_eq!((shape.vertices.len(), shape.edges.len()), (vertices, edges));
+            // Every vertex of a platonic solid has the same degree
+            let degree = 2 * edges / vertices;
+            for vertex in 0..vertices {
+                let touching = shape.edges.iter().filter(|(a, b)| *a == vertex || *b == vertex);
+                assert_eq!(touching.count(), degree, "{:?}", shape);
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_draws_any_wireframe() {
+        let drawn = |wireframe: Wireframe| {
+            let mut cube = AsciiCube::new(40, 20, 1.0);
+            cube.wireframe = wireframe;
+            let buffer = cube.render_buffer();
+            buffer.iter().flatten().filter(|(c, _)| *c == '.').count()
+        };
+
+        assert!(drawn(Wireframe::cube()) > 0);
+        assert!(drawn(Wireframe::tetrahedron()) > 0);
+        let mut broken = Wireframe::octahedron();
+        broken.edges.push((0, 99));
+        assert_eq!(drawn(broken), drawn(Wireframe::octahedron()));
+        let empty = Wireframe { vertices: Vec::new(), edges: vec![(0, 1)] };
+        assert_eq!(drawn(empty), 0);
+    }
+
+    #[test]
+    fn test_keys_map_to_animation_controls() {
+        use crossterm::event::KeyEvent;
+
+        let press = |code| AnimationKey::from_event(&Event::Key(KeyEvent::from(code)));
+        assert_eq!(press(KeyCode::Up), Some(AnimationKey::RotateX(-ROTATE_STEP)));
+        assert_eq!(press(KeyCode::Right), Some(AnimationKey::RotateY(ROTATE_STEP)));
+        assert_eq!(press(KeyCode::Char('+')), Some(AnimationKey::Faster));
+        assert_eq!(press(KeyCode::Char('d')), Some(AnimationKey::ToggleDetails));
+        assert_eq!(press(KeyCode::Char('q')), Some(AnimationKey::Quit));
+        assert_eq!(press(KeyCode::Char('?')), None);
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(AnimationKey::from_event(&Event::Key(ctrl_c)), Some(AnimationKey::Quit));
+
+        let mut cube = AsciiCube::new(40, 20, 1.0);
+        cube.apply_key(AnimationKey::ToggleDetails);
+        assert!(cube.show_details);
+        cube.apply_key(AnimationKey::RotateX(ROTATE_STEP));
+        assert_eq!(cube.angle_x, ROTATE_STEP);
+        for _ in 0..10 {
+            cube.apply_key(AnimationKey::Slower);
+        }
+        assert_eq!(cube.rotation_speed, 0.0);
+        for _ in 0..100 {
+            cube.apply_key(AnimationKey::Faster);
+        }
+        assert_eq!(cube.rotation_speed, AsciiCube::MAX_SPEED);
+    }
+
+    // Frame text without its color escapes
+    fn plain(frame: &str) -> String {
+        let mut text = String::new();
+        let mut chars = frame.chars();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                text.push(c);
+            }
+        }
+        text
+    }
+
+    #[test]
+    fn test_next_frame_is_headless_and_deterministic() {
+        // Without rotation the cube is seen face-on: the front face inside the back one
+        let mut still = AsciiCube::new(36, 28, 0.0);
+        let golden = [
+            "                                    ",
+            "     ..........................     ",
+            "     ..                      ..     ",
+            "     . .                    . .     ",
+            "     .  .                  .  .     ",
+            "     .   ..................   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   .                .   .     ",
+            "     .   ..................   .     ",
+            "     .  .                  .  .     ",
+            "     . .                    . .     ",
+            "     ..                      ..     ",
+            "     ..........................     ",
+            "                                    ",
+        ]
+        .join("\n");
+        assert_eq!(plain(&still.next_frame()), golden);
+        assert_eq!(plain(&still.next_frame()), golden);
+
+        let (mut a, mut b) = (AsciiCube::new(30, 15, 1.0), AsciiCube::new(30, 15, 1.0));
+        let first = a.next_frame();
+        assert_eq!(first, b.next_frame());
+        let later = (0..20).map(|_| a.next_frame()).last().unwrap();
+        assert_ne!(plain(&later), plain(&first));
+        assert_eq!(plain(&later).lines().count(), 15);
+    }
+
+    #[test]
+    fn test_render_follows_canvas_size_changes() {
+        let mut cube = AsciiCube::new(40, 20, 1.0);
+        // Growing, shrinking below the projected shape, and degenerate sizes
+        for (width, height) in [(80, 40), (7, 3), (1, 1), (0, 0), (120, 5), (40, 20)] {
+            cube.canvas_width = width;
+            cube.canvas_height = height;
+            cube.update();
+
+            let frame = cube.render_cube();
+            assert_eq!(frame.len(), height);
+            assert!(frame.iter().all(|row| row.len() == width));
+            let text = cube.render();
+            assert_eq!(text.split('\n').count(), height.max(1));
+        }
+        // Both buffers were used at every size and still match the last one
+        assert!(cube.buffer_a.iter().chain(&cube.buffer_b).all(|row| row.len() == 40));
+        // A vertex right at the camera projects to a bounded point
+        let (x, y) = cube.project_point(&[1.0, 1.0, -5.0]);
+        assert!(x.abs() <= 80 && y.abs() <= 40, "({}, {})", x, y);
+    }
+}
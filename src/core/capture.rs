@@ -0,0 +1,241 @@
+// Packet capture module writing connection payloads as classic pcap files
+// Each read/write becomes one record wrapped in a synthesized IP/TCP header so
+// wireshark/tshark can open the capture and follow the stream
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Classic pcap magic number (microsecond timestamps)
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+/// LINKTYPE_RAW: records start directly with an IPv4/IPv6 header
+const LINKTYPE_RAW: u32 = 101;
+/// Snapshot length advertised in the global header
+const SNAPLEN: u32 = 65535;
+/// Size of the pcap global header in bytes
+pub const GLOBAL_HEADER_LEN: usize = 24;
+/// Size of each pcap record header in bytes
+pub const RECORD_HEADER_LEN: usize = 16;
+
+/// Direction of a captured payload relative to the local listener
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Inbound,  // Peer -> listener
+    Outbound, // Listener -> peer
+}
+
+/// Writes one connection's payloads in pcap format, to a file by default
+/// Handlers capture into a `Vec<u8>` and save it off the async runtime once the
+/// connection ends, see `save`
+pub struct PcapWriter<W: Write = BufWriter<File>> {
+    writer: W,
+    local: SocketAddr,
+    peer: SocketAddr,
+    // Running TCP sequence numbers so stream reassembly works in wireshark
+    local_seq: u32,
+    peer_seq: u32,
+    records: usize,
+}
+
+impl PcapWriter {
+    /// Creates the capture file and writes the pcap global header
+    pub fn create(path: &Path, local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), local, peer)
+    }
+}
+
+impl PcapWriter<Vec<u8>> {
+    /// Starts a capture kept in memory until `save`
+    pub fn in_memory(local: SocketAddr, peer: SocketAddr) -> Self {
+        Self::new(Vec::new(), local, peer).expect("writing to a Vec never fails")
+    }
+
+    /// Writes the capture to `path` on the blocking thread pool
+    pub async fn save(self, path: PathBuf) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || std::fs::write(path, self.writer)).await?
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the pcap global header to `writer`, followed by each recorded payload
+    pub fn new(mut writer: W, local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?; // Version major
+        writer.write_all(&4u16.to_le_bytes())?; // Version minor
+        writer.write_all(&0i32.to_le_bytes())?; // GMT offset
+        writer.write_all(&0u32.to_le_bytes())?; // Timestamp accuracy
+        writer.write_all(&SNAPLEN.to_le_bytes())?;
+        writer.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+
+        Ok(Self {
+            writer,
+            local,
+            peer,
+            local_seq: 1,
+            peer_seq: 1,
+            records: 0,
+        })
+    }
+
+    /// Appends one payload as a timestamped record
+    pub fn record(&mut self, direction: Direction, payload: &[u8]) -> io::Result<()> {
+        let (src, dst, seq, ack) = match direction {
+            Direction::Inbound => (self.peer, self.local, self.peer_seq, self.local_seq),
+            Direction::Outbound => (self.local, self.peer, self.local_seq, self.peer_seq),
+        };
+
+        let packet = build_packet(src, dst, seq, ack, payload);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let captured = packet.len().min(SNAPLEN as usize);
+
+        self.writer
+            .write_all(&(now.as_secs() as u32).to_le_bytes())?;
+        self.writer.write_all(&now.subsec_micros().to_le_bytes())?;
+        self.writer.write_all(&(captured as u32).to_le_bytes())?;
+        self.writer
+            .write_all(&(packet.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&packet[..captured])?;
+
+        match direction {
+            Direction::Inbound => self.peer_seq = self.peer_seq.wrapping_add(payload.len() as u32),
+            Direction::Outbound => {
+                self.local_seq = self.local_seq.wrapping_add(payload.len() as u32)
+            }
+        }
+        self.records += 1;
+        Ok(())
+    }
+
+    /// Number of records written so far
+    pub fn records(&self) -> usize {
+        self.records
+    }
+
+    /// Flushes buffered records to disk
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+// Builds an IP + TCP (PSH/ACK) packet around the payload
+fn build_packet(src: SocketAddr, dst: SocketAddr, seq: u32, ack: u32, payload: &[u8]) -> Vec<u8> {
+    let mut tcp = Vec::with_capacity(20 + payload.len());
+    tcp.extend_from_slice(&src.port().to_be_bytes());
+    tcp.extend_from_slice(&dst.port().to_be_bytes());
+    tcp.extend_from_slice(&seq.to_be_bytes());
+    tcp.extend_from_slice(&ack.to_be_bytes());
+    tcp.push(5 << 4); // Data offset: 5 words, no options
+    tcp.push(0x18); // Flags: PSH | ACK
+    tcp.extend_from_slice(&u16::MAX.to_be_bytes()); // Window
+    tcp.extend_from_slice(&[0, 0, 0, 0]); // Checksum (unchecked by wireshark by default) + urgent pointer
+    tcp.extend_from_slice(payload);
+
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            let total_len = (20 + tcp.len()) as u16;
+            let mut ip = vec![0x45, 0];
+            ip.extend_from_slice(&total_len.to_be_bytes());
+            ip.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]); // ID, DF, TTL, TCP, checksum
+            ip.extend_from_slice(&s.octets());
+            ip.extend_from_slice(&d.octets());
+            let checksum = ipv4_checksum(&ip);
+            ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+            ip.extend_from_slice(&tcp);
+            ip
+        }
+        (s, d) => {
+            // Mixed families fall back to IPv6 with v4-mapped addresses
+            let mut ip = vec![0x60, 0, 0, 0];
+            ip.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+            ip.extend_from_slice(&[6, 64]); // Next header TCP, hop limit
+            ip.extend_from_slice(&to_v6_octets(s));
+            ip.extend_from_slice(&to_v6_octets(d));
+            ip.extend_from_slice(&tcp);
+            ip
+        }
+    }
+}
+
+fn to_v6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(v4) => v4.to_ipv6_mapped().octets(),
+        IpAddr::V6(v6) => v6.octets(),
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Counts the records in a pcap file, validating the global header first
+/// Returns an `InvalidData` error if the header or a record is malformed
+pub fn count_records(data: &[u8]) -> io::Result<usize> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    if data.len() < GLOBAL_HEADER_LEN {
+        return Err(invalid("truncated pcap global header"));
+    }
+    let word = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    if word(0) != PCAP_MAGIC || word(20) != LINKTYPE_RAW {
+        return Err(invalid("unexpected pcap magic or link type"));
+    }
+
+    let mut offset = GLOBAL_HEADER_LEN;
+    let mut records = 0;
+    while offset < data.len() {
+        if offset + RECORD_HEADER_LEN > data.len() {
+            return Err(invalid("truncated pcap record header"));
+        }
+        let captured = word(offset + 8) as usize;
+        offset += RECORD_HEADER_LEN + captured;
+        if offset > data.len() {
+            return Err(invalid("truncated pcap record"));
+        }
+        records += 1;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pcap_capture_short_exchange() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("exchange.pcap");
+        let local: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+        let peer: SocketAddr = "127.0.0.1:50000".parse().unwrap();
+
+        let mut writer = PcapWriter::create(&path, local, peer).unwrap();
+        writer
+            .record(Direction::Outbound, b"GET / HTTP/1.1\r\n\r\n")
+            .unwrap();
+        writer.record(Direction::Inbound, b"hello").unwrap();
+        writer
+            .record(Direction::Outbound, b"HTTP/1.1 200 OK\r\n\r\n")
+            .unwrap();
+        writer.flush().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        assert_eq!(&data[0..4], &PCAP_MAGIC.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([data[4], data[5]]), 2);
+        assert_eq!(u16::from_le_bytes([data[6], data[7]]), 4);
+        assert_eq!(count_records(&data).unwrap(), 3);
+        assert_eq!(writer.records(), 3);
+
+        // First record's IPv4 header must carry a valid checksum
+        let ip = &data[GLOBAL_HEADER_LEN + RECORD_HEADER_LEN..][..20];
+        assert_eq!(ipv4_checksum(ip), 0);
+    }
+}
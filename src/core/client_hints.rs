@@ -0,0 +1,161 @@
+// Passive client fingerprinting: TCP-level hints about who is connecting
+
+use std::fmt;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+/// Coarse operating system family guessed from TCP options
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OsFamily {
+    Linux,
+    Windows,
+    MacOs,
+    #[default]
+    Unknown,
+}
+
+impl fmt::Display for OsFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OsFamily::Linux => "Linux",
+            OsFamily::Windows => "Windows",
+            OsFamily::MacOs => "macOS",
+            OsFamily::Unknown => "unknown",
+        })
+    }
+}
+
+/// What the kernel reveals about a client's TCP stack after the handshake
+/// Every hint is `None` where the platform doesn't expose it (anything but Linux).
+/// The peer's TTL and initial window aren't readable from an accepted socket at all,
+/// so the guess relies on the negotiated options
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ClientHints {
+    pub mss: Option<u32>,         // Segment size the peer sends with
+    pub window_scale: Option<u8>, // Peer's window scale shift, when negotiated
+    pub timestamps: Option<bool>, // Whether the peer enabled TCP timestamps
+    pub sack: Option<bool>,       // Whether the peer enabled selective acks
+    pub rtt: Option<Duration>,    // Smoothed round-trip time measured so far
+    pub os_guess: OsFamily,
+}
+
+impl ClientHints {
+    /// Reads the hints of an accepted connection; never fails, missing data stays `None`
+    pub fn collect(stream: &TcpStream) -> Self {
+        let mut hints = Self::default();
+        #[cfg(target_os = "linux")]
+        if let Some(info) = linux::tcp_info(stream) {
+            hints.mss = Some(info.tcpi_snd_mss);
+            hints.timestamps = Some(info.tcpi_options & linux::TCPI_OPT_TIMESTAMPS != 0);
+            hints.sack = Some(info.tcpi_options & linux::TCPI_OPT_SACK != 0);
+            // The peer's shift is the low nibble of the `snd_wscale:4, rcv_wscale:4` bitfield
+            hints.window_scale = (info.tcpi_options & linux::TCPI_OPT_WSCALE != 0)
+                .then_some(info.tcpi_snd_rcv_wscale & 0x0f);
+            hints.rtt = Some(Duration::from_micros(info.tcpi_rtt.into()));
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = stream;
+        hints.os_guess = guess_os(&hints);
+        hints
+    }
+}
+
+/// Default stacks differ in their options: Windows leaves timestamps off, macOS and
+/// Linux turn them on with window scale 6 and 7 respectively
+pub fn guess_os(hints: &ClientHints) -> OsFamily {
+    match (hints.timestamps, hints.window_scale) {
+        (Some(false), _) => OsFamily::Windows,
+        (Some(true), Some(6)) => OsFamily::MacOs,
+        (Some(true), _) => OsFamily::Linux,
+        (None, _) => OsFamily::Unknown,
+    }
+}
+
+impl fmt::Display for ClientHints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn hint<T: fmt::Display>(value: Option<T>) -> String {
+            value.map_or_else(|| "unknown".to_string(), |v| v.to_string())
+        }
+        write!(
+            f,
+            "os={} mss={} wscale={} ts={} sack={} rtt={}",
+            self.os_guess,
+            hint(self.mss),
+            hint(self.window_scale),
+            hint(self.timestamps),
+            hint(self.sack),
+            hint(self.rtt.map(|rtt| format!("{}us", rtt.as_micros()))),
+        )
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::os::fd::AsRawFd;
+    use tokio::net::TcpStream;
+
+    // Bits of `tcp_info::tcpi_options` (linux/tcp.h)
+    pub const TCPI_OPT_TIMESTAMPS: u8 = 1;
+    pub const TCPI_OPT_SACK: u8 = 2;
+    pub const TCPI_OPT_WSCALE: u8 = 4;
+
+    pub fn tcp_info(stream: &TcpStream) -> Option<libc::tcp_info> {
+        // SAFETY: `tcp_info` is plain old data, so all-zero is a valid value, and the
+        // kernel writes at most `len` bytes into it
+        unsafe {
+            let mut info: libc::tcp_info = std::mem::zeroed();
+            let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+            let ret = libc::getsockopt(
+                stream.as_raw_fd(),
+                libc::IPPROTO_TCP,
+                libc::TCP_INFO,
+                (&mut info as *mut libc::tcp_info).cast(),
+                &mut len,
+            );
+            (ret == 0).then_some(info)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_localhost_hints_populated_or_unknown() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+
+        let hints = ClientHints::collect(&accepted);
+        let line = hints.to_string();
+        if cfg!(target_os = "linux") {
+            assert!(hints.mss.is_some_and(|mss| mss > 0));
+            assert!(hints.timestamps.is_some() && hints.sack.is_some());
+            assert_ne!(hints.os_guess, OsFamily::Unknown);
+            assert!(!line.contains("mss=unknown"));
+        } else {
+            assert_eq!(hints, ClientHints::default());
+            assert!(line.starts_with("os=unknown mss=unknown"));
+        }
+    }
+
+    #[test]
+    fn test_guess_os_from_options() {
+        let hints = |timestamps, window_scale| ClientHints {
+            timestamps,
+            window_scale,
+            ..Default::default()
+        };
+        assert_eq!(guess_os(&hints(Some(false), Some(8))), OsFamily::Windows);
+        assert_eq!(guess_os(&hints(Some(true), Some(6))), OsFamily::MacOs);
+        assert_eq!(guess_os(&hints(Some(true), Some(7))), OsFamily::Linux);
+        assert_eq!(guess_os(&hints(None, None)), OsFamily::Unknown);
+        assert_eq!(
+            hints(None, None).to_string(),
+            "os=unknown mss=unknown wscale=unknown ts=unknown sack=unknown rtt=unknown"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+// Connection logging module with optional sampling for high-traffic listeners
+
+use crate::core::client_hints::ClientHints;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Which accepted connections get a log line
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogSampling {
+    /// Log every connection
+    #[default]
+    All,
+    /// Log one connection out of every N
+    OneIn(u64),
+    /// Log at most N connections per second
+    PerSecond(u32),
+}
+
+/// Logs accepted connections according to a `LogSampling` policy
+/// Every connection is counted, whether or not it is logged
+#[derive(Debug, Default)]
+pub struct ConnectionLogger {
+    sampling: LogSampling,
+    seen: AtomicU64,
+    logged: AtomicU64,
+    // Start of the current one-second window and lines logged in it
+    window: Mutex<Option<(Instant, u32)>>,
+}
+
+impl ConnectionLogger {
+    pub fn new(sampling: LogSampling) -> Self {
+        Self {
+            sampling,
+            ..Default::default()
+        }
+    }
+
+    /// Counts a connection and prints it when the sampler selects it
+    /// `hints` (TCP only) are appended so the line carries the client's OS guess
+    pub fn log_accept(&self, peer: SocketAddr, local: SocketAddr, hints: Option<&ClientHints>) {
+        if !self.should_log(Instant::now()) {
+            return;
+        }
+        match hints {
+            Some(hints) => println!("Connection from {} on {} [{}]", peer, local, hints),
+            None => println!("Connection from {} on {}", peer, local),
+        }
+    }
+
+    /// Counts a connection seen at `now` and decides whether it is logged
+    pub fn should_log(&self, now: Instant) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed);
+        let selected = match self.sampling {
+            LogSampling::All => true,
+            LogSampling::OneIn(n) => seen.is_multiple_of(n.max(1)),
+            LogSampling::PerSecond(limit) => {
+                let mut window = self.window.lock().unwrap();
+                match window.as_mut() {
+                    Some((start, count)) if now.duration_since(*start) < Duration::from_secs(1) => {
+                        *count += 1;
+                        *count <= limit
+                    }
+                    _ => {
+                        *window = Some((now, 1));
+                        limit > 0
+                    }
+                }
+            }
+        };
+        if selected {
+            self.logged.fetch_add(1, Ordering::Relaxed);
+        }
+        selected
+    }
+
+    /// Connections counted so far, logged or not
+    pub fn seen(&self) -> u64 {
+        self.seen.load(Ordering::Relaxed)
+    }
+
+    /// Connections that produced a log line
+    pub fn logged(&self) -> u64 {
+        self.logged.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_one_in_n_sampling_counts_everything() {
+        let logger = ConnectionLogger::new(LogSampling::OneIn(10));
+        let now = Instant::now();
+        let logged = (0..1000).filter(|_| logger.should_log(now)).count();
+
+        assert_eq!(logged, 100);
+        assert_eq!(logger.logged(), 100);
+        assert_eq!(logger.seen(), 1000);
+    }
+
+    #[test]
+    fn test_per_second_sampling_resets_each_window() {
+        let logger = ConnectionLogger::new(LogSampling::PerSecond(5));
+        let start = Instant::now();
+        let first = (0..50).filter(|_| logger.should_log(start)).count();
+        let later = start + Duration::from_secs(1);
+        let second = (0..50).filter(|_| logger.should_log(later)).count();
+
+        assert_eq!((first, second), (5, 5));
+        assert_eq!(logger.seen(), 100);
+    }
+
+    #[test]
+    fn test_default_logs_everything() {
+        let logger = ConnectionLogger::default();
+        assert!((0..20).all(|_| logger.should_log(Instant::now())));
+    }
+}
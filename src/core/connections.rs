@@ -0,0 +1,346 @@
+// Live connection tracking: traffic meters and close handles for open connections
+
+use crate::core::types::ConnectionState;
+use serde::{Serialize, Serializer};
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::sync::CancellationToken;
+
+/// Width of the sliding window `Throughput::bps` averages over, in seconds
+pub const THROUGHPUT_WINDOW_SECS: u64 = 5;
+
+/// Received and sent byte totals plus a per-second history of the last
+/// `THROUGHPUT_WINDOW_SECS`, kept in atomics so every connection can feed it
+#[derive(Debug)]
+pub struct Throughput {
+    start: Instant,
+    rx: AtomicU64,
+    tx: AtomicU64,
+    slots: [WindowSlot; THROUGHPUT_WINDOW_SECS as usize], // Indexed by second modulo the window
+}
+
+#[derive(Debug, Default)]
+struct WindowSlot {
+    second: AtomicU64, // Seconds after `start` the bytes were moved in
+    bytes: AtomicU64,
+}
+
+impl Default for Throughput {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            rx: AtomicU64::new(0),
+            tx: AtomicU64::new(0),
+            slots: Default::default(),
+        }
+    }
+}
+
+impl Throughput {
+    /// Counts `bytes` received just now
+    pub fn record_rx(&self, bytes: usize) {
+        self.rx.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.record_at(self.start.elapsed(), bytes);
+    }
+
+    /// Counts `bytes` sent just now
+    pub fn record_tx(&self, bytes: usize) {
+        self.tx.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.record_at(self.start.elapsed(), bytes);
+    }
+
+    /// Bytes received so far
+    pub fn rx(&self) -> u64 {
+        self.rx.load(Ordering::Relaxed)
+    }
+
+    /// Bytes sent so far
+    pub fn tx(&self) -> u64 {
+        self.tx.load(Ordering::Relaxed)
+    }
+
+    /// Bytes moved in both directions so far
+    pub fn total(&self) -> u64 {
+        self.rx() + self.tx()
+    }
+
+    /// Bits per second in both directions over the last `THROUGHPUT_WINDOW_SECS`
+    /// (or since creation, when that is shorter)
+    pub fn bps(&self) -> f64 {
+        self.bps_at(self.start.elapsed())
+    }
+
+    fn record_at(&self, elapsed: Duration, bytes: usize) {
+        let second = elapsed.as_secs();
+        let slot = &self.slots[(second % THROUGHPUT_WINDOW_SECS) as usize];
+        // The first write of a new second recycles the slot; a write racing the
+        // reset may be lost, which only skews the estimate
+        if slot.second.swap(second, Ordering::Relaxed) != second {
+            slot.bytes.store(0, Ordering::Relaxed);
+        }
+        slot.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn bps_at(&self, elapsed: Duration) -> f64 {
+        let current = elapsed.as_secs();
+        let first = current.saturating_sub(THROUGHPUT_WINDOW_SECS - 1);
+        let bytes: u64 = self
+            .slots
+            .iter()
+            .filter(|slot| (first..=current).contains(&slot.second.load(Ordering::Relaxed)))
+            .map(|slot| slot.bytes.load(Ordering::Relaxed))
+            .sum();
+        let span = elapsed.as_secs_f64() - first as f64;
+        if span > 0.0 {
+            bytes as f64 * 8.0 / span
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Traffic of one connection and when it last carried any, updated while it is
+/// being served; every byte also counts towards the shared aggregate, if any
+#[derive(Debug)]
+pub struct TrafficMeter {
+    opened: Instant,
+    last_activity_ms: AtomicU64, // Milliseconds after `opened`
+    traffic: Throughput,
+    aggregate: Option<Arc<Throughput>>, // E.g. `CoreState::throughput`
+}
+
+impl Default for TrafficMeter {
+    fn default() -> Self {
+        Self {
+            opened: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+            traffic: Throughput::default(),
+            aggregate: None,
+        }
+    }
+}
+
+impl TrafficMeter {
+    /// Meter that also feeds `aggregate`
+    pub fn counted_in(aggregate: Arc<Throughput>) -> Self {
+        Self {
+            aggregate: Some(aggregate),
+            ..Self::default()
+        }
+    }
+
+    /// Counts `bytes` read from the peer just now
+    pub fn record_rx(&self, bytes: usize) {
+        if bytes > 0 {
+            self.traffic.record_rx(bytes);
+            if let Some(aggregate) = &self.aggregate {
+                aggregate.record_rx(bytes);
+            }
+            self.touch();
+        }
+    }
+
+    /// Counts `bytes` written to the peer just now
+    pub fn record_tx(&self, bytes: usize) {
+        if bytes > 0 {
+            self.traffic.record_tx(bytes);
+            if let Some(aggregate) = &self.aggregate {
+                aggregate.record_tx(bytes);
+            }
+            self.touch();
+        }
+    }
+
+    fn touch(&self) {
+        let now = self.opened.elapsed().as_millis() as u64;
+        self.last_activity_ms.fetch_max(now, Ordering::Relaxed);
+    }
+
+    /// This connection's traffic totals and recent rate
+    pub fn traffic(&self) -> &Throughput {
+        &self.traffic
+    }
+
+    /// Bytes moved in both directions
+    pub fn bytes(&self) -> u64 {
+        self.traffic.total()
+    }
+
+    /// Time since the connection was accepted
+    pub fn age(&self) -> Duration {
+        self.opened.elapsed()
+    }
+
+    /// Time since the last byte was read or written (or since accept, if none was)
+    pub fn idle(&self) -> Duration {
+        let last = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+        self.age().saturating_sub(last)
+    }
+}
+
+/// Stream wrapper counting every byte read or written in a `TrafficMeter`
+#[derive(Debug)]
+pub struct MeteredStream<S> {
+    inner: S,
+    meter: Arc<TrafficMeter>,
+}
+
+impl<S> MeteredStream<S> {
+    pub fn new(inner: S, meter: Arc<TrafficMeter>) -> Self {
+        Self { inner, meter }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for MeteredStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.meter.record_rx(buf.filled().len() - before);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for MeteredStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = ready!(Pin::new(&mut self.inner).poll_write(cx, buf))?;
+        self.meter.record_tx(written);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Held by the task serving a connection and registered in `CoreState`, so the
+/// connection can be inspected and closed from elsewhere
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionHandle {
+    pub meter: Arc<TrafficMeter>,
+    cancel: CancellationToken,
+    reason: Arc<OnceLock<String>>,
+}
+
+impl ConnectionHandle {
+    /// Handle whose traffic also counts towards `aggregate`
+    pub fn counted_in(aggregate: Arc<Throughput>) -> Self {
+        Self {
+            meter: Arc::new(TrafficMeter::counted_in(aggregate)),
+            ..Self::default()
+        }
+    }
+
+    /// Asks the serving task to drop the connection; the first reason given sticks
+    pub fn close(&self, reason: impl Into<String>) {
+        let _ = self.reason.set(reason.into());
+        self.cancel.cancel();
+    }
+
+    /// Resolves once `close` has been called
+    pub async fn closed(&self) {
+        self.cancel.cancelled().await
+    }
+
+    /// Why the connection was closed, if `close` was called
+    pub fn close_reason(&self) -> Option<&str> {
+        self.reason.get().map(String::as_str)
+    }
+}
+
+/// One open connection as listed by `IPCowCore::list_connections` and the
+/// dashboard's `/connections` endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConnectionInfo {
+    pub addr: SocketAddr,
+    pub state: ConnectionState,
+    #[serde(rename = "age_secs", serialize_with = "whole_secs")]
+    pub age: Duration, // Since accept
+    #[serde(rename = "idle_secs", serialize_with = "whole_secs")]
+    pub idle: Duration, // Since the last byte in either direction
+    pub rx_bytes: u64,       // Read from the peer so far
+    pub tx_bytes: u64,       // Written to the peer so far
+    pub throughput_bps: f64, // Over the last `THROUGHPUT_WINDOW_SECS`
+}
+
+fn whole_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_metered_stream_counts_both_directions() {
+        let (client, server) = tokio::io::duplex(64);
+        let aggregate = Arc::new(Throughput::default());
+        let meter = Arc::new(TrafficMeter::counted_in(aggregate.clone()));
+        let mut server = MeteredStream::new(server, meter.clone());
+        let mut client = client;
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(meter.idle() >= Duration::from_millis(30));
+
+        client.write_all(b"hello").await.unwrap();
+        let mut buf = [0_u8; 5];
+        server.read_exact(&mut buf).await.unwrap();
+        server.write_all(b"hi").await.unwrap();
+        client.read_exact(&mut buf[..2]).await.unwrap();
+
+        assert_eq!((meter.traffic().rx(), meter.traffic().tx()), (5, 2));
+        assert_eq!((aggregate.rx(), aggregate.tx()), (5, 2));
+        assert_eq!(meter.bytes(), 7);
+        assert!(aggregate.bps() > 0.0);
+        assert!(meter.idle() < Duration::from_millis(30));
+        assert!(meter.age() >= Duration::from_millis(30));
+    }
+
+    #[test]
+    fn test_throughput_averages_over_sliding_window() {
+        let secs = Duration::from_secs;
+        let throughput = Throughput::default();
+        assert_eq!(throughput.bps_at(Duration::ZERO), 0.0);
+
+        // 1000 bytes in each of the first two seconds
+        throughput.record_at(secs(0), 1000);
+        throughput.record_at(Duration::from_millis(1500), 1000);
+        assert_eq!(throughput.bps_at(secs(2)), 8000.0);
+
+        // Once the window has moved past them they no longer count
+        throughput.record_at(secs(6), 500);
+        assert_eq!(
+            throughput.bps_at(Duration::from_millis(6500)),
+            500.0 * 8.0 / 4.5
+        );
+        assert_eq!(throughput.bps_at(secs(20)), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_close_keeps_first_reason() {
+        let handle = ConnectionHandle::default();
+        assert_eq!(handle.close_reason(), None);
+        handle.close("closed by operator");
+        handle.close("idle");
+        handle.closed().await;
+        assert_eq!(handle.close_reason(), Some("closed by operator"));
+    }
+}
@@ -1,19 +1,95 @@
+use crate::utils::helpers::{csv_field, fingerprint_hash_with, HashAlgorithm};
+use base64::Engine;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{self, Write};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
+/// Default cap on the stored length of a single banner, in bytes
+pub const DEFAULT_MAX_BANNER_LEN: usize = 4096;
+/// Appended to banners cut at the length cap
+pub const TRUNCATION_MARKER: &str = "...[truncated]";
+/// Default log file, relative to the working directory
+pub const DISCOVERY_LOG_FILE: &str = "discovered_services.txt";
+
+/// Destination that receives every newly recorded service alongside the log file
+/// Sinks see banners after truncation and duplicate collapsing
+/// `record` is called on a blocking thread, so it may do synchronous I/O
+pub trait DiscoverySink: fmt::Debug + Send + Sync {
+    fn record(&self, addr: SocketAddr, banner: &str) -> io::Result<()>;
+}
+
+/// Layout of the entries appended to the discovery log file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LogFormat {
+    /// Timestamped human-readable block with a `-` separator
+    #[default]
+    Text,
+    /// One `{timestamp, ip, port, banner}` JSON object per line
+    /// Banners that aren't valid UTF-8 are base64-encoded and flagged with `"encoding": "base64"`
+    JsonLines,
+}
+
+// One line of the `LogFormat::JsonLines` log
+#[derive(Serialize)]
+struct JsonLogEntry<'a> {
+    timestamp: String,
+    ip: std::net::IpAddr,
+    port: u16,
+    banner: std::borrow::Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    service: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+/// A service banner seen at one address, with how often and when it was seen
+/// Each distinct banner at an address has its own record
+/// Exported, saved and shown on the dashboard
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveryRecord {
+    pub addr: SocketAddr,
+    pub banner: String,
+    /// Fingerprint of `banner`; together with `addr` it identifies the record
+    /// Files saved without it get it recomputed on `load`
+    #[serde(default)]
+    pub banner_hash: String,
+    #[serde(default)]
+    pub first_seen: DateTime<Local>, // When this banner was first recorded at `addr`
+    #[serde(default)]
+    pub last_seen: DateTime<Local>, // Most recent repeat of the same banner
+    #[serde(default)]
+    pub count: u64, // Times the banner was recorded, repeats included
+    /// Service name from the `FingerprintDb`, or the banner hash when no rule matched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub service: Option<String>,
+    /// Free-text triage note attached with `ServiceDiscovery::annotate`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
 /// ServiceDiscovery struct handles detection and logging of network services
 /// Maintains thread-safe state of discovered services and their details
 #[derive(Debug)]
 pub struct ServiceDiscovery {
     // Path to log file where service discoveries are persisted
     log_file: PathBuf,
-    // Thread-safe HashMap storing service details mapped to socket addresses
-    discoveries: Arc<Mutex<HashMap<SocketAddr, String>>>,
+    // Thread-safe HashMap storing one record per socket address and banner fingerprint
+    discoveries: Arc<Mutex<HashMap<(SocketAddr, String), DiscoveryRecord>>>,
+    // Algorithm used to fingerprint banners when collapsing duplicates
+    hash_algorithm: HashAlgorithm,
+    // Longest banner stored or logged before it is cut with `TRUNCATION_MARKER`
+    max_banner_len: usize,
+    // Layout of log file entries
+    log_format: LogFormat,
+    // Extra destinations for discoveries, e.g. a database
+    sinks: Vec<Arc<dyn DiscoverySink>>,
 }
 
 impl ServiceDiscovery {
@@ -21,19 +97,188 @@ impl ServiceDiscovery {
     /// Initializes empty discoveries map protected by mutex
     pub fn new() -> Self {
         Self {
-            log_file: PathBuf::from("discovered_services.txt"),
+            log_file: PathBuf::from(DISCOVERY_LOG_FILE),
             discoveries: Arc::new(Mutex::new(HashMap::new())),
+            hash_algorithm: HashAlgorithm::default(),
+            max_banner_len: DEFAULT_MAX_BANNER_LEN,
+            log_format: LogFormat::default(),
+            sinks: Vec::new(),
         }
     }
 
+    /// Writes discoveries to `path` instead of `discovered_services.txt`
+    pub fn with_log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = path.into();
+        self
+    }
+
+    /// Selects the log file layout (default `LogFormat::Text`)
+    pub fn with_log_format(mut self, format: LogFormat) -> Self {
+        self.log_format = format;
+        self
+    }
+
+    /// Caps stored banners at `max_len` bytes (default `DEFAULT_MAX_BANNER_LEN`)
+    pub fn with_max_banner_len(mut self, max_len: usize) -> Self {
+        self.max_banner_len = max_len;
+        self
+    }
+
+    /// Also forwards every new discovery to `sink`
+    pub fn with_sink(mut self, sink: Arc<dyn DiscoverySink>) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    /// Banner most recently seen at `addr`
+    pub async fn banner(&self, addr: SocketAddr) -> Option<String> {
+        self.discoveries
+            .lock()
+            .await
+            .values()
+            .filter(|record| record.addr == addr)
+            .max_by_key(|record| record.last_seen)
+            .map(|record| record.banner.clone())
+    }
+
+    /// Attaches `note` to the banner most recently seen at `addr`, replacing any earlier note
+    /// Returns false, storing nothing, when nothing was discovered at `addr`
+    pub async fn annotate(&self, addr: SocketAddr, note: impl Into<String>) -> bool {
+        let mut discoveries = self.discoveries.lock().await;
+        let latest = discoveries
+            .values_mut()
+            .filter(|record| record.addr == addr)
+            .max_by_key(|record| record.last_seen);
+        match latest {
+            Some(record) => {
+                record.note = Some(note.into());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every discovered banner, ordered by address and then by when it was first seen
+    pub async fn summary(&self) -> Vec<DiscoveryRecord> {
+        let mut records: Vec<DiscoveryRecord> =
+            self.discoveries.lock().await.values().cloned().collect();
+        records.sort_by_key(|r| (r.addr, r.first_seen));
+        records
+    }
+
+    /// Discoveries as a pretty-printed JSON array of `DiscoveryRecord`
+    pub async fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.summary().await).unwrap_or_default()
+    }
+
+    /// Discoveries as CSV with an `ip,port,banner,note` header
+    pub async fn to_csv(&self) -> String {
+        let mut out = String::from("ip,port,banner,note\n");
+        for record in self.summary().await {
+            out.push_str(&format!(
+                "{},{},{},{}\n",
+                record.addr.ip(),
+                record.addr.port(),
+                csv_field(&record.banner),
+                csv_field(record.note.as_deref().unwrap_or(""))
+            ));
+        }
+        out
+    }
+
+    /// Writes the discoveries and their notes to `path` as JSON
+    pub async fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_json().await)
+    }
+
+    /// Restores records written with `save`, replacing entries for the same address and banner
+    /// Loaded records are not logged or forwarded to sinks again
+    pub async fn load(&self, path: &Path) -> io::Result<()> {
+        let data = std::fs::read_to_string(path)?;
+        let records: Vec<DiscoveryRecord> = serde_json::from_str(&data)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut discoveries = self.discoveries.lock().await;
+        for mut record in records {
+            if record.banner_hash.is_empty() {
+                record.banner_hash =
+                    fingerprint_hash_with(record.banner.as_bytes(), self.hash_algorithm);
+            }
+            discoveries.insert((record.addr, record.banner_hash.clone()), record);
+        }
+        Ok(())
+    }
+
+    /// Selects the fingerprint algorithm used to detect duplicate banners
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = algorithm;
+        self
+    }
+
     /// Records discovered service information and logs it to file
     /// Args:
     ///   addr: Socket address where service was discovered
     ///   content: Service details/banner information
     pub async fn record_service(&self, addr: SocketAddr, content: &str) {
-        // Update in-memory map of discoveries
+        self.record_service_bytes(addr, content.as_bytes()).await;
+    }
+
+    /// Same as `record_service` for a banner as received on the wire
+    /// The stored banner is a lossy UTF-8 copy; the JSON-lines log keeps the exact
+    /// bytes by base64-encoding banners that aren't valid UTF-8
+    pub async fn record_service_bytes(&self, addr: SocketAddr, banner: &[u8]) {
+        self.record(addr, banner, None).await;
+    }
+
+    /// Same as `record_service_bytes`, storing the service the banner was identified as
+    pub async fn record_identified(&self, addr: SocketAddr, banner: &[u8], service: String) {
+        self.record(addr, banner, Some(service)).await;
+    }
+
+    async fn record(&self, addr: SocketAddr, banner: &[u8], service: Option<String>) {
+        let content = truncate_banner(&String::from_utf8_lossy(banner), self.max_banner_len);
+        let content = content.as_str();
+
+        // Update in-memory map of discoveries; repeats of an identical banner only
+        // bump the counters, a new banner at the address gets a record of its own
+        let timestamp = Local::now();
+        let fingerprint = fingerprint_hash_with(content.as_bytes(), self.hash_algorithm);
         let mut discoveries = self.discoveries.lock().await;
-        discoveries.insert(addr, content.to_string());
+        if let Some(existing) = discoveries.get_mut(&(addr, fingerprint.clone())) {
+            existing.last_seen = timestamp;
+            existing.count += 1;
+            return;
+        }
+        discoveries.insert(
+            (addr, fingerprint.clone()),
+            DiscoveryRecord {
+                addr,
+                banner: content.to_string(),
+                banner_hash: fingerprint,
+                first_seen: timestamp,
+                last_seen: timestamp,
+                count: 1,
+                service: service.clone(),
+                note: None,
+            },
+        );
+        drop(discoveries);
+
+        // Sinks such as the SQLite store do blocking I/O, so keep them off the runtime
+        if !self.sinks.is_empty() {
+            let sinks = self.sinks.clone();
+            let recorded = content.to_string();
+            let forwarded = tokio::task::spawn_blocking(move || {
+                for sink in &sinks {
+                    if let Err(e) = sink.record(addr, &recorded) {
+                        eprintln!("[Discovery] Sink failed to record {}: {}", addr, e);
+                    }
+                }
+            })
+            .await;
+            if let Err(e) = forwarded {
+                eprintln!("[Discovery] Sink task for {} failed: {}", addr, e);
+            }
+        }
 
         // Append discovery to log file with timestamp and formatting
         if let Ok(mut file) = OpenOptions::new()
@@ -41,17 +286,220 @@ impl ServiceDiscovery {
             .append(true)
             .open(&self.log_file)
         {
-            let timestamp = chrono::Local::now();
-            // Format log entry with timestamp, address and content
-            let formatted_entry = format!(
-                "[{}] {}:{}\n{}\n{}\n",
-                timestamp,
-                addr.ip(),      // Log IP address
-                addr.port(),    // Log port number
-                "-".repeat(50), // Visual separator
-                content.trim()  // Actual service content
-            );
-            let _ = writeln!(file, "{}", formatted_entry);
+            match self.log_format {
+                LogFormat::Text => {
+                    // Format log entry with timestamp, address and content
+                    let formatted_entry = format!(
+                        "[{}] {}:{}\n{}\n{}\n",
+                        timestamp,
+                        addr.ip(),      // Log IP address
+                        addr.port(),    // Log port number
+                        "-".repeat(50), // Visual separator
+                        content.trim()  // Actual service content
+                    );
+                    let _ = writeln!(file, "{}", formatted_entry);
+                }
+                LogFormat::JsonLines => {
+                    let (banner, encoding) = match std::str::from_utf8(banner) {
+                        Ok(_) => (content.into(), None),
+                        Err(_) => {
+                            let raw = &banner[..banner.len().min(self.max_banner_len)];
+                            let encoded = base64::engine::general_purpose::STANDARD.encode(raw);
+                            (encoded.into(), Some("base64"))
+                        }
+                    };
+                    let entry = JsonLogEntry {
+                        timestamp: timestamp.to_rfc3339(),
+                        ip: addr.ip(),
+                        port: addr.port(),
+                        banner,
+                        service: service.as_deref(),
+                        encoding,
+                    };
+                    if let Ok(line) = serde_json::to_string(&entry) {
+                        let _ = writeln!(file, "{}", line);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Cuts `banner` to at most `max_len` bytes (on a char boundary) and marks the cut
+pub fn truncate_banner(banner: &str, max_len: usize) -> String {
+    if banner.len() <= max_len {
+        return banner.to_string();
+    }
+    let mut end = max_len;
+    while !banner.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", &banner[..end], TRUNCATION_MARKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_oversized_banner_truncated() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = ServiceDiscovery::new()
+            .with_log_file(dir.path().join("services.txt"))
+            .with_max_banner_len(16);
+        let addr: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+
+        discovery.record_service(addr, &"A".repeat(100)).await;
+        let stored = discovery.banner(addr).await.unwrap();
+        assert_eq!(stored, format!("{}{}", "A".repeat(16), TRUNCATION_MARKER));
+
+        // Short banners are kept as-is
+        discovery.record_service(addr, "SSH-2.0").await;
+        assert_eq!(discovery.banner(addr).await.unwrap(), "SSH-2.0");
+    }
+
+    #[derive(Debug, Default)]
+    struct VecSink(std::sync::Mutex<Vec<(SocketAddr, String)>>);
+
+    impl DiscoverySink for VecSink {
+        fn record(&self, addr: SocketAddr, banner: &str) -> io::Result<()> {
+            self.0.lock().unwrap().push((addr, banner.to_string()));
+            Ok(())
         }
     }
+
+    #[tokio::test]
+    async fn test_sink_sees_new_discoveries_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = Arc::new(VecSink::default());
+        let discovery = ServiceDiscovery::new()
+            .with_log_file(dir.path().join("services.txt"))
+            .with_sink(sink.clone());
+        let addr: SocketAddr = "127.0.0.1:22".parse().unwrap();
+
+        discovery.record_service(addr, "SSH-2.0").await;
+        discovery.record_service(addr, "SSH-2.0").await;
+        assert_eq!(*sink.0.lock().unwrap(), vec![(addr, "SSH-2.0".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_annotations_exported_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery = ServiceDiscovery::new().with_log_file(dir.path().join("services.txt"));
+        let ssh: SocketAddr = "10.0.0.1:22".parse().unwrap();
+        let web: SocketAddr = "10.0.0.2:80".parse().unwrap();
+        discovery.record_service(ssh, "SSH-2.0-dropbear").await;
+        discovery.record_service(web, "HTTP/1.1 200 OK").await;
+
+        assert!(discovery.annotate(ssh, "old dropbear, check CVEs").await);
+        assert!(!discovery.annotate("10.0.0.3:21".parse().unwrap(), "nothing here").await);
+
+        let json: serde_json::Value = serde_json::from_str(&discovery.to_json().await).unwrap();
+        assert_eq!(json[0]["addr"], "10.0.0.1:22");
+        assert_eq!(json[0]["note"], "old dropbear, check CVEs");
+        assert!(json[1].get("note").is_none());
+        assert!(discovery
+            .to_csv()
+            .await
+            .contains("10.0.0.1,22,SSH-2.0-dropbear,\"old dropbear, check CVEs\"\n"));
+
+        let path = dir.path().join("discoveries.json");
+        discovery.save(&path).await.unwrap();
+        let restored = ServiceDiscovery::new().with_log_file(dir.path().join("other.txt"));
+        restored.load(&path).await.unwrap();
+        assert_eq!(restored.summary().await, discovery.summary().await);
+    }
+
+    #[tokio::test]
+    async fn test_load_accepts_records_without_counters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("discoveries.json");
+        std::fs::write(&path, r#"[{"addr":"10.0.0.1:22","banner":"SSH-2.0"}]"#).unwrap();
+
+        let discovery = ServiceDiscovery::new().with_log_file(dir.path().join("services.txt"));
+        discovery.load(&path).await.unwrap();
+        let addr: SocketAddr = "10.0.0.1:22".parse().unwrap();
+        let loaded = discovery.summary().await;
+        assert_eq!(loaded[0].count, 0);
+        assert_eq!(
+            loaded[0].banner_hash,
+            fingerprint_hash_with(b"SSH-2.0", HashAlgorithm::default())
+        );
+
+        // The recomputed hash matches, so seeing the banner again bumps the loaded record
+        discovery.record_service(addr, "SSH-2.0").await;
+        let summary = discovery.summary().await;
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_json_lines_log_encodes_binary_banners() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.jsonl");
+        let discovery = ServiceDiscovery::new()
+            .with_log_file(&path)
+            .with_log_format(LogFormat::JsonLines);
+        discovery
+            .record_service("10.0.0.1:22".parse().unwrap(), "SSH-2.0-OpenSSH_9.6")
+            .await;
+        discovery
+            .record_service_bytes("10.0.0.2:3389".parse().unwrap(), b"\x03\x00\xff\xfe")
+            .await;
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["ip"], "10.0.0.1");
+        assert_eq!(lines[0]["port"], 22);
+        assert_eq!(lines[0]["banner"], "SSH-2.0-OpenSSH_9.6");
+        assert!(lines[0].get("encoding").is_none());
+        assert!(chrono::DateTime::parse_from_rfc3339(lines[0]["timestamp"].as_str().unwrap()).is_ok());
+        assert_eq!(lines[1]["banner"], "AwD//g==");
+        assert_eq!(lines[1]["encoding"], "base64");
+    }
+
+    #[tokio::test]
+    async fn test_repeats_counted_and_changes_logged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.txt");
+        let discovery = ServiceDiscovery::new().with_log_file(&path);
+        let addr: SocketAddr = "10.0.0.1:22".parse().unwrap();
+
+        for _ in 0..3 {
+            discovery.record_service(addr, "SSH-2.0-OpenSSH_9.6").await;
+        }
+        let summary = discovery.summary().await;
+        assert_eq!(summary.len(), 1);
+        assert_eq!(summary[0].count, 3);
+        assert!(summary[0].first_seen <= summary[0].last_seen);
+
+        // A changed banner gets its own record and is logged again
+        discovery.record_service(addr, "SSH-2.0-OpenSSH_9.7").await;
+        let summary = discovery.summary().await;
+        assert_eq!(summary.len(), 2);
+        assert_eq!((summary[0].count, summary[1].count), (3, 1));
+        assert_eq!(summary[1].banner, "SSH-2.0-OpenSSH_9.7");
+        assert_ne!(summary[0].banner_hash, summary[1].banner_hash);
+        assert_eq!(discovery.banner(addr).await.unwrap(), "SSH-2.0-OpenSSH_9.7");
+
+        // Going back to the first banner counts towards its old record
+        discovery.record_service(addr, "SSH-2.0-OpenSSH_9.6").await;
+        assert_eq!(discovery.summary().await[0].count, 4);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(log.matches("10.0.0.1:22").count(), 2);
+    }
+
+    #[test]
+    fn test_truncate_banner_respects_char_boundaries() {
+        assert_eq!(
+            truncate_banner("héllo", 2),
+            format!("h{}", TRUNCATION_MARKER)
+        );
+        assert_eq!(truncate_banner("hello", 5), "hello");
+    }
 }
@@ -1,27 +1,388 @@
+use std::collections::hash_map::DefaultHasher;
+use crate::utils::helpers::{csv_field, write_atomic};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
 
+/// Highest port that only privileged processes may bind on Unix by default
+pub const MAX_PRIVILEGED_PORT: u16 = 1023;
+
+/// How serious a registered error is, from least to most severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum ErrorSeverity {
+    Debug,
+    Info,
+    /// Transient trouble the server recovers from, e.g. an accept timeout
+    Warning,
+    Error,
+    /// A listener or other component could not start at all, e.g. a failed bind
+    Critical,
+}
+
+impl ErrorSeverity {
+    /// Every severity, most severe first
+    pub const ALL: [ErrorSeverity; 5] = [
+        ErrorSeverity::Critical,
+        ErrorSeverity::Error,
+        ErrorSeverity::Warning,
+        ErrorSeverity::Info,
+        ErrorSeverity::Debug,
+    ];
+}
+
+impl fmt::Display for ErrorSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// What kind of failure a registered error records
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+pub enum ErrorCategory {
+    #[default]
+    General,
+    /// A privileged port (`<= MAX_PRIVILEGED_PORT`) could not be bound without elevated rights
+    PrivilegedPort,
+}
+
+/// Classifies a failed bind of `addr` and builds the message to report
+/// Permission errors on privileged ports get an actionable hint instead of the raw OS text
+pub fn classify_bind_error(addr: SocketAddr, err: &io::Error) -> (ErrorCategory, String) {
+    if err.kind() == io::ErrorKind::PermissionDenied && addr.port() <= MAX_PRIVILEGED_PORT {
+        let message = format!(
+            "binding port {} requires elevated privileges or CAP_NET_BIND_SERVICE",
+            addr.port()
+        );
+        return (ErrorCategory::PrivilegedPort, message);
+    }
+    (ErrorCategory::General, err.to_string())
+}
+
+/// File formats for `ErrorRegistry::export`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ExportFormat {
+    /// Array of `{id, message, count, severity, category}` objects
+    #[default]
+    Json,
+    /// `id,message,count,severity` rows with a header
+    Csv,
+}
+
+impl ExportFormat {
+    /// CSV for `.csv` files, JSON otherwise
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            other => Err(format!("unknown export format '{}' (expected json or csv)", other)),
+        }
+    }
+}
+
+/// One distinct error message and how often it was registered
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorEntry {
+    pub id: String,
+    pub message: String,
+    pub severity: ErrorSeverity, // Severity of the first registration
+    pub category: ErrorCategory, // Category of the first registration
+    pub count: u64,
+}
+
+/// Deduplicating store of runtime errors
+/// Every distinct message gets one ID, kept for all of its repeats
 #[derive(Debug, Default)]
 pub struct ErrorRegistry {
-    errors: HashMap<String, Vec<String>>,
+    // Distinct messages keyed by a hash of their text
+    errors: HashMap<u64, ErrorEntry>,
+    // Error ID to message hash, for lookups by ID
+    ids: HashMap<String, u64>,
 }
 
 impl ErrorRegistry {
     pub fn new() -> Self {
         Self {
             errors: HashMap::new(),
+            ids: HashMap::new(),
         }
     }
 
-    pub fn register_error(&mut self, error: &str) -> String {
-        let error_id = format!("ERR_{}", self.errors.len());
+    /// Registers `error`, returning its ID; repeats of a message return the same ID
+    pub fn register_error(&mut self, severity: ErrorSeverity, error: &str) -> String {
+        self.register_categorized(severity, ErrorCategory::General, error)
+    }
+
+    /// Registers `error` under `category` so it can be told apart from general errors
+    pub fn register_categorized(
+        &mut self,
+        severity: ErrorSeverity,
+        category: ErrorCategory,
+        error: &str,
+    ) -> String {
+        let key = message_hash(error);
+        let next_id = self.errors.len();
+        let entry = self.errors.entry(key).or_insert_with(|| ErrorEntry {
+            id: format!("ERR_{}", next_id),
+            message: error.to_string(),
+            severity,
+            category,
+            count: 0,
+        });
+        entry.count += 1;
+        self.ids.entry(entry.id.clone()).or_insert(key);
+        entry.id.clone()
+    }
+
+    /// Category an error ID was registered under
+    pub fn category(&self, error_id: &str) -> Option<ErrorCategory> {
+        self.get_error(error_id).map(|entry| entry.category)
+    }
+
+    /// Messages registered under `category`
+    pub fn errors_in(&self, category: ErrorCategory) -> Vec<&str> {
         self.errors
-            .entry(error_id.clone())
-            .or_insert_with(Vec::new)
-            .push(error.to_string());
-        error_id
+            .values()
+            .filter(|entry| entry.category == category)
+            .map(|entry| entry.message.as_str())
+            .collect()
+    }
+
+    /// Errors registered with `severity`, most frequent first
+    pub fn get_errors_by_severity(&self, severity: ErrorSeverity) -> Vec<&ErrorEntry> {
+        let mut entries: Vec<&ErrorEntry> = self
+            .errors
+            .values()
+            .filter(|entry| entry.severity == severity)
+            .collect();
+        entries.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.message.cmp(&b.message))
+        });
+        entries
     }
 
-    pub fn get_errors(&self, error_id: &str) -> Option<&Vec<String>> {
-        self.errors.get(error_id)
+    pub fn get_error(&self, error_id: &str) -> Option<&ErrorEntry> {
+        self.ids.get(error_id).and_then(|key| self.errors.get(key))
+    }
+
+    /// The `n` most often registered errors, most frequent first
+    pub fn most_frequent(&self, n: usize) -> Vec<&ErrorEntry> {
+        let mut entries: Vec<&ErrorEntry> = self.errors.values().collect();
+        entries.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.message.cmp(&b.message))
+        });
+        entries.truncate(n);
+        entries
+    }
+
+    /// Writes every error, most frequent first, to `path` in `format`
+    /// The file is replaced atomically, so an interrupted export leaves the old one intact
+    pub fn export(&self, path: &Path, format: ExportFormat) -> io::Result<()> {
+        let entries = self.most_frequent(self.errors.len());
+        let contents = match format {
+            ExportFormat::Json => serde_json::to_string_pretty(&entries)?,
+            ExportFormat::Csv => {
+                let mut out = String::from("id,message,count,severity\n");
+                for entry in entries {
+                    out.push_str(&format!(
+                        "{},{},{},{}\n",
+                        entry.id,
+                        csv_field(&entry.message),
+                        entry.count,
+                        entry.severity
+                    ));
+                }
+                out
+            }
+        };
+        write_atomic(path, contents.as_bytes())
+    }
+
+    /// Total number of errors registered, repeats included
+    pub fn error_count(&self) -> usize {
+        self.errors.values().map(|entry| entry.count as usize).sum()
+    }
+
+    /// Number of distinct error messages
+    pub fn distinct_count(&self) -> usize {
+        self.errors.len()
+    }
+}
+
+/// Errors grouped by severity, most severe group and most frequent error first
+impl fmt::Display for ErrorRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== Error Registry ===")?;
+        if self.errors.is_empty() {
+            writeln!(f, "No errors registered")?;
+        }
+        for severity in ErrorSeverity::ALL {
+            let entries = self.get_errors_by_severity(severity);
+            if entries.is_empty() {
+                continue;
+            }
+            writeln!(f, "{} ({}):", severity, entries.len())?;
+            for entry in entries {
+                writeln!(f, "  {} x{}: {}", entry.id, entry.count, entry.message)?;
+            }
+        }
+        write!(f, "======================")
+    }
+}
+
+fn message_hash(message: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    message.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_privileged_port_permission_error_is_classified() {
+        let denied = io::Error::from(io::ErrorKind::PermissionDenied);
+        let (category, message) = classify_bind_error("0.0.0.0:80".parse().unwrap(), &denied);
+        assert_eq!(category, ErrorCategory::PrivilegedPort);
+        assert_eq!(
+            message,
+            "binding port 80 requires elevated privileges or CAP_NET_BIND_SERVICE"
+        );
+
+        // Unprivileged ports and other failures keep the OS message
+        let (category, _) = classify_bind_error("0.0.0.0:8080".parse().unwrap(), &denied);
+        assert_eq!(category, ErrorCategory::General);
+        let in_use = io::Error::from(io::ErrorKind::AddrInUse);
+        let (category, message) = classify_bind_error("0.0.0.0:80".parse().unwrap(), &in_use);
+        assert_eq!(category, ErrorCategory::General);
+        assert_eq!(message, in_use.to_string());
+
+        let mut registry = ErrorRegistry::new();
+        let general = registry.register_error(ErrorSeverity::Error, "accept failed");
+        let privileged = registry.register_categorized(
+            ErrorSeverity::Critical,
+            ErrorCategory::PrivilegedPort,
+            "port 80",
+        );
+        assert_eq!(registry.category(&general), Some(ErrorCategory::General));
+        assert_eq!(
+            registry.category(&privileged),
+            Some(ErrorCategory::PrivilegedPort)
+        );
+        assert_eq!(
+            registry.errors_in(ErrorCategory::PrivilegedPort),
+            vec!["port 80"]
+        );
+    }
+
+    #[test]
+    fn test_repeated_errors_share_an_id() {
+        let mut registry = ErrorRegistry::new();
+        let ids: Vec<String> = (0..10_000)
+            .map(|_| registry.register_error(ErrorSeverity::Error, "Address already in use"))
+            .collect();
+        assert!(ids.iter().all(|id| *id == ids[0]));
+        let refused = registry.register_error(ErrorSeverity::Error, "Connection refused");
+        registry.register_error(ErrorSeverity::Error, "Connection refused");
+        let reset = registry.register_error(ErrorSeverity::Error, "Connection reset");
+        assert_ne!(refused, ids[0]);
+
+        assert_eq!(registry.distinct_count(), 3);
+        assert_eq!(registry.error_count(), 10_003);
+        assert_eq!(registry.get_error(&ids[0]).unwrap().count, 10_000);
+        assert_eq!(
+            registry.get_error(&reset).unwrap().message,
+            "Connection reset"
+        );
+
+        let top: Vec<(&str, u64)> = registry
+            .most_frequent(2)
+            .iter()
+            .map(|entry| (entry.message.as_str(), entry.count))
+            .collect();
+        assert_eq!(
+            top,
+            vec![
+                ("Address already in use", 10_000),
+                ("Connection refused", 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_errors_grouped_by_severity() {
+        let mut registry = ErrorRegistry::new();
+        registry.register_error(ErrorSeverity::Critical, "bind failed on 0.0.0.0:80");
+        registry.register_error(ErrorSeverity::Warning, "accept timed out");
+        registry.register_error(ErrorSeverity::Warning, "accept timed out");
+        registry.register_error(ErrorSeverity::Warning, "recv failed");
+
+        let warnings: Vec<(&str, u64)> = registry
+            .get_errors_by_severity(ErrorSeverity::Warning)
+            .iter()
+            .map(|entry| (entry.message.as_str(), entry.count))
+            .collect();
+        assert_eq!(warnings, vec![("accept timed out", 2), ("recv failed", 1)]);
+        assert!(registry
+            .get_errors_by_severity(ErrorSeverity::Debug)
+            .is_empty());
+
+        let summary = registry.to_string();
+        let critical = summary.find("Critical (1):").unwrap();
+        let warning = summary.find("Warning (2):").unwrap();
+        assert!(critical < warning);
+        assert!(summary.contains("x2: accept timed out"));
+        assert!(!summary.contains("Error ("));
+    }
+
+    #[test]
+    fn test_export_json_and_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = ErrorRegistry::new();
+        let bind = registry.register_error(ErrorSeverity::Critical, "bind failed, port 80");
+        registry.register_error(ErrorSeverity::Warning, "accept timed out");
+        registry.register_error(ErrorSeverity::Warning, "accept timed out");
+
+        let json_path = dir.path().join("errors.json");
+        registry.export(&json_path, ExportFormat::Json).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(json[0]["message"], "accept timed out");
+        assert_eq!(json[0]["count"], 2);
+        assert_eq!(json[1]["id"], bind.as_str());
+        assert_eq!(json[1]["severity"], "Critical");
+
+        let csv_path = dir.path().join("errors.csv");
+        assert_eq!(ExportFormat::from_path(&csv_path), ExportFormat::Csv);
+        std::fs::write(&csv_path, "stale").unwrap();
+        registry.export(&csv_path, ExportFormat::Csv).unwrap();
+        let csv = std::fs::read_to_string(&csv_path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "id,message,count,severity");
+        assert_eq!(lines[1], "ERR_1,accept timed out,2,Warning");
+        assert_eq!(lines[2], "ERR_0,\"bind failed, port 80\",1,Critical");
+
+        // Only the exported files remain; the temp file was renamed away
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
     }
 }
@@ -0,0 +1,236 @@
+// Service fingerprinting: maps received banners to service names using a rules file
+
+use crate::utils::helpers::fingerprint_hash;
+use regex::bytes::Regex;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, LazyLock};
+
+/// Rules used when no custom rules file is given
+/// Format, one rule per line: `<regex|prefix> <pattern> => <service>`
+/// Prefix patterns accept `\r`, `\n`, `\t`, `\\` and `\xNN` escapes; `#` starts a comment
+pub const DEFAULT_RULES: &str = r"# Remote shells
+regex ^SSH-2\.0-OpenSSH => OpenSSH
+regex ^SSH-[12]\. => SSH
+# Mail
+regex ^220 .*E?SMTP => SMTP
+regex ^\+OK => POP3
+regex ^\* OK => IMAP
+# File transfer
+regex ^220[ -].*FTP => FTP
+# Web
+regex ^HTTP/\d(\.\d)? \d{3} => HTTP
+regex ^(GET|POST|HEAD|PUT|DELETE|OPTIONS) \S+ HTTP/ => HTTP client
+prefix \x16\x03 => TLS
+# Datastores
+regex ^-ERR|^\+PONG => Redis
+prefix \x4a\x00\x00\x00\x0a => MySQL
+# Telephony
+regex ^(SIP/2\.0|OPTIONS sip:|INVITE sip:) => SIP
+";
+
+// `DEFAULT_RULES`, compiled on first use and shared from then on
+static DEFAULT_DB: LazyLock<Arc<FingerprintDb>> = LazyLock::new(|| {
+    Arc::new(FingerprintDb::parse(DEFAULT_RULES).expect("default fingerprint rules are valid"))
+});
+
+/// How a rule matches the start of a banner
+#[derive(Debug, Clone)]
+pub enum BannerPattern {
+    /// Regular expression over the raw bytes; anchor with `^` to match the start
+    Regex(Regex),
+    /// Exact leading bytes
+    Prefix(Vec<u8>),
+}
+
+impl BannerPattern {
+    pub fn matches(&self, banner: &[u8]) -> bool {
+        match self {
+            BannerPattern::Regex(regex) => regex.is_match(banner),
+            BannerPattern::Prefix(prefix) => banner.starts_with(prefix),
+        }
+    }
+}
+
+/// One `pattern => service` rule
+#[derive(Debug, Clone)]
+pub struct FingerprintRule {
+    pub pattern: BannerPattern,
+    pub service: String,
+}
+
+/// Ordered list of fingerprint rules; the first matching rule names the service
+#[derive(Debug, Clone)]
+pub struct FingerprintDb {
+    rules: Vec<FingerprintRule>,
+}
+
+impl Default for FingerprintDb {
+    /// The built-in `DEFAULT_RULES`
+    fn default() -> Self {
+        FingerprintDb::clone(&DEFAULT_DB)
+    }
+}
+
+impl FingerprintDb {
+    /// The built-in rules, compiled once per process; cloning the `Arc` is all
+    /// a caller pays
+    pub fn shared() -> Arc<Self> {
+        DEFAULT_DB.clone()
+    }
+
+    /// Database without any rules; every banner falls back to its hash
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Parses rules in the `DEFAULT_RULES` format
+    /// Fails with `InvalidData` naming the offending line
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut rules = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = parse_rule(line).map_err(|reason| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("fingerprint rule on line {}: {}", number + 1, reason),
+                )
+            })?;
+            rules.push(rule);
+        }
+        Ok(Self { rules })
+    }
+
+    /// Reads a rules file in the `DEFAULT_RULES` format
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Appends the built-in rules after the current ones, so custom rules are tried first
+    pub fn with_defaults(mut self) -> Self {
+        self.rules.extend(DEFAULT_DB.rules.iter().cloned());
+        self
+    }
+
+    /// Adds a rule tried after all existing ones
+    pub fn push(&mut self, pattern: BannerPattern, service: impl Into<String>) {
+        self.rules.push(FingerprintRule {
+            pattern,
+            service: service.into(),
+        });
+    }
+
+    pub fn rules(&self) -> &[FingerprintRule] {
+        &self.rules
+    }
+
+    /// Service named by the first rule matching `banner`
+    pub fn identify(&self, banner: &[u8]) -> Option<&str> {
+        self.rules
+            .iter()
+            .find(|rule| rule.pattern.matches(banner))
+            .map(|rule| rule.service.as_str())
+    }
+
+    /// Service name for `banner`, or its `Service-{hash}` fingerprint when no rule matches
+    pub fn classify(&self, banner: &[u8]) -> String {
+        match self.identify(banner) {
+            Some(service) => service.to_string(),
+            None => fingerprint_hash(banner),
+        }
+    }
+}
+
+fn parse_rule(line: &str) -> Result<FingerprintRule, String> {
+    let (kind, rest) = line
+        .split_once(char::is_whitespace)
+        .ok_or("expected `<regex|prefix> <pattern> => <service>`")?;
+    let (pattern, service) = rest.rsplit_once("=>").ok_or("missing `=> <service>`")?;
+    let (pattern, service) = (pattern.trim(), service.trim());
+    if pattern.is_empty() || service.is_empty() {
+        return Err("empty pattern or service name".to_string());
+    }
+    let pattern = match kind {
+        "regex" => BannerPattern::Regex(Regex::new(pattern).map_err(|e| e.to_string())?),
+        "prefix" => BannerPattern::Prefix(unescape(pattern)?),
+        other => return Err(format!("unknown rule kind `{}`", other)),
+    };
+    Ok(FingerprintRule {
+        pattern,
+        service: service.to_string(),
+    })
+}
+
+// Decodes the escapes allowed in prefix patterns
+fn unescape(pattern: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0_u8; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('r') => bytes.push(b'\r'),
+            Some('n') => bytes.push(b'\n'),
+            Some('t') => bytes.push(b'\t'),
+            Some('\\') => bytes.push(b'\\'),
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .map_err(|_| format!("invalid escape `\\x{}`", hex))?;
+                bytes.push(byte);
+            }
+            Some(other) => return Err(format!("invalid escape `\\{}`", other)),
+            None => return Err("trailing `\\`".to_string()),
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_rules_identify_common_services() {
+        let db = FingerprintDb::default();
+        assert_eq!(db.identify(b"SSH-2.0-OpenSSH_9.6\r\n"), Some("OpenSSH"));
+        assert_eq!(db.identify(b"SSH-2.0-dropbear\r\n"), Some("SSH"));
+        assert_eq!(
+            db.identify(b"220 mail.example.com ESMTP Postfix\r\n"),
+            Some("SMTP")
+        );
+        assert_eq!(db.identify(b"HTTP/1.1 200 OK\r\n"), Some("HTTP"));
+        assert_eq!(db.identify(b"\x16\x03\x01\x02\x00"), Some("TLS"));
+        assert_eq!(db.identify(b"hello"), None);
+        assert_eq!(db.classify(b"hello"), fingerprint_hash(b"hello"));
+    }
+
+    #[test]
+    fn test_custom_rules_take_precedence() {
+        let custom = FingerprintDb::parse(
+            "# lab devices\n\
+             prefix SSH-2.0-Cisco => Cisco IOS\n\
+             prefix \\x00\\x01 => Binary thing\n",
+        )
+        .unwrap()
+        .with_defaults();
+        assert_eq!(custom.identify(b"SSH-2.0-Cisco-1.25"), Some("Cisco IOS"));
+        assert_eq!(custom.identify(b"SSH-2.0-OpenSSH_8.0"), Some("OpenSSH"));
+        assert_eq!(custom.identify(b"\x00\x01\x02"), Some("Binary thing"));
+    }
+
+    #[test]
+    fn test_invalid_rules_report_line() {
+        let err = FingerprintDb::parse("regex ^ok => Ok\nregex ([ => Broken\n").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("line 2"), "{}", err);
+        assert!(FingerprintDb::parse("glob * => Any").is_err());
+        assert!(FingerprintDb::parse("prefix \\xZZ => Bad").is_err());
+    }
+}
@@ -1,11 +1,297 @@
 // Network connection handler module implementing connection processing and service detection
 
 use crate::core::discovery::ServiceDiscovery;
+use crate::core::fingerprint::FingerprintDb;
+use crate::core::types::NetworkConfig;
+use crate::utils::RngSource;
 use chrono::Local;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use rand::rngs::StdRng;
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::time::timeout;
+
+#[cfg(feature = "pcap")]
+use crate::core::capture::{Direction, PcapWriter};
+
+/// Width of one chargen line, excluding the trailing CRLF (RFC 864)
+const CHARGEN_LINE_WIDTH: usize = 72;
+/// Longest unterminated message buffered before it is processed anyway
+const MAX_LINE_LEN: usize = 8192;
+/// Time a streaming handler waits for a stalled reader before giving up on a write
+const STREAM_WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest payload a single UDP datagram can carry over IPv4
+pub const UDP_MAX_PAYLOAD: usize = 65507;
+/// Body of the default `ResponseProfile::Http` status page
+/// `{port}` is replaced with the peer's port and `{time}` with the local time
+pub const STATUS_PAGE: &str = "<html><body>\
+                               <h1>Port {port}</h1>\
+                               <p>Active since: {time}</p>\
+                               </body></html>";
+
+/// How a listening port treats accepted connections
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum PortBehavior {
+    /// Probe the client for a banner and answer with the status page
+    #[default]
+    Probe,
+    /// Read and drop everything, never reply (discard, RFC 863)
+    Discard,
+    /// Stream rotating printable characters until the peer closes (chargen, RFC 864)
+    Chargen,
+    /// Send the current date and time as one line, then close (daytime, RFC 867)
+    Daytime,
+    /// Serve files below the directory for HTTP GET requests
+    StaticDir(PathBuf),
+    /// Write every received byte back until the peer closes
+    Echo,
+    /// Write received bytes back until the peer closes or ends a message with CRLF
+    EchoLine,
+}
+
+/// What the probe handler sends once the client's banner has been read
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResponseProfile {
+    /// Send these bytes verbatim, e.g. a fake SSH or SMTP banner
+    Raw(Vec<u8>),
+    /// Send an HTTP 200 with this body; `{port}` and `{time}` are filled in
+    Http(String),
+    /// Write the banner back, then keep mirroring received bytes until the peer closes
+    Echo,
+    /// Never reply; read and discard until the peer closes
+    Silent,
+}
+
+impl Default for ResponseProfile {
+    fn default() -> Self {
+        ResponseProfile::Http(STATUS_PAGE.to_string())
+    }
+}
+
+/// Per-listener options controlling how accepted connections are handled
+/// Shared between all connection tasks of a `ListenerManager`
+#[derive(Debug, Clone)]
+pub struct HandlerConfig {
+    /// Behavior for ports without an explicit entry in `port_behaviors`
+    pub default_behavior: PortBehavior,
+    /// Per-port behavior overrides keyed by the local listening port
+    pub port_behaviors: HashMap<u16, PortBehavior>,
+    /// Behaviors for inclusive port ranges; the narrowest range containing the port wins
+    /// Exact entries in `port_behaviors` take precedence over any range
+    pub port_range_behaviors: Vec<(RangeInclusive<u16>, PortBehavior)>,
+    /// Reply sent by `PortBehavior::Probe` for ports without an entry in `port_responses`
+    pub response: ResponseProfile,
+    /// Per-port reply overrides keyed by the local listening port
+    pub port_responses: HashMap<u16, ResponseProfile>,
+    /// Per-client request limit for the HTTP status responder (unlimited when `None`)
+    pub throttle: Option<Arc<RequestThrottle>>,
+    /// Byte sequence ending each client message, e.g. `b"\r\n"` (no line framing when `None`)
+    /// When set, every terminated message is recorded and acknowledged
+    /// instead of the single probe/status exchange
+    pub line_terminator: Option<Vec<u8>>,
+    /// User-Agent header of the HTTP GET detection probe (omitted when `None`)
+    pub user_agent: Option<String>,
+    /// Exact bytes sent to detect the client's protocol, possibly binary
+    /// An empty probe sends nothing and just waits for the client to speak first;
+    /// `None` sends `http_probe(user_agent)`
+    pub detection_probe: Option<Vec<u8>>,
+    /// Receive buffer size for UDP datagrams (`UDP_MAX_PAYLOAD` when `None`)
+    pub udp_max_payload: Option<usize>,
+    /// Answer UDP datagrams as the port's behavior dictates, never with more bytes
+    /// than the datagram carried; off by default because a spoofed source address
+    /// would turn every reply into reflected traffic
+    pub udp_replies: bool,
+    /// Drops a random share of connections right after accept (`--fault-rate`, off when `None`)
+    pub fault_injector: Option<Arc<FaultInjector>>,
+    /// Rules naming the service behind each recorded banner (built-in rules by default)
+    pub fingerprints: Arc<FingerprintDb>,
+    /// Longest the probe (including line mode), echo, discard, daytime and static-dir
+    /// handlers wait on a single client read or write before closing the connection
+    /// (`NetworkConfig`'s default timeout when `None`)
+    pub io_timeout: Option<Duration>,
+    /// Directory receiving one pcap file per connection (disabled when `None`)
+    #[cfg(feature = "pcap")]
+    pub capture_dir: Option<PathBuf>,
+}
+
+impl Default for HandlerConfig {
+    /// Everything off or empty, with the shared built-in fingerprint rules
+    fn default() -> Self {
+        Self {
+            default_behavior: PortBehavior::default(),
+            port_behaviors: HashMap::new(),
+            port_range_behaviors: Vec::new(),
+            response: ResponseProfile::default(),
+            port_responses: HashMap::new(),
+            throttle: None,
+            line_terminator: None,
+            user_agent: None,
+            detection_probe: None,
+            udp_max_payload: None,
+            udp_replies: false,
+            fault_injector: None,
+            fingerprints: FingerprintDb::shared(),
+            io_timeout: None,
+            #[cfg(feature = "pcap")]
+            capture_dir: None,
+        }
+    }
+}
+
+impl HandlerConfig {
+    /// Resolves the behavior configured for a local listening port
+    /// Most specific match wins: exact port, then the narrowest range, then the default
+    pub fn behavior_for(&self, port: u16) -> &PortBehavior {
+        if let Some(behavior) = self.port_behaviors.get(&port) {
+            return behavior;
+        }
+        self.port_range_behaviors
+            .iter()
+            .filter(|(range, _)| range.contains(&port))
+            .min_by_key(|(range, _)| range.end() - range.start())
+            .map(|(_, behavior)| behavior)
+            .unwrap_or(&self.default_behavior)
+    }
+
+    /// Resolves the probe reply for a local listening port
+    pub fn response_for(&self, port: u16) -> &ResponseProfile {
+        self.port_responses.get(&port).unwrap_or(&self.response)
+    }
+
+    /// Effective bound on each probe read and write
+    pub fn io_timeout(&self) -> Duration {
+        self.io_timeout.unwrap_or_else(|| NetworkConfig::default().timeout)
+    }
+
+    /// Bytes the probe handler sends before reading the client's banner
+    pub fn detection_probe(&self) -> Vec<u8> {
+        match &self.detection_probe {
+            Some(bytes) => bytes.clone(),
+            None => http_probe(self.user_agent.as_deref()),
+        }
+    }
+}
+
+/// Minimal HTTP GET used to coax a banner out of a service, with a User-Agent
+/// header when `user_agent` is given
+pub fn http_probe(user_agent: Option<&str>) -> Vec<u8> {
+    let mut request = String::from("GET / HTTP/1.1\r\nHost: localhost\r\n");
+    if let Some(agent) = user_agent {
+        request.push_str(&format!("User-Agent: {}\r\n", agent));
+    }
+    request.push_str("\r\n");
+    request.into_bytes()
+}
+
+/// Decides which connections get dropped to simulate a flaky service
+/// Decisions come from `RngSource::derive("fault-injection")`, so a seeded
+/// source replays the same sequence of faults
+#[derive(Debug)]
+pub struct FaultInjector {
+    rate: f64,
+    rng: Mutex<StdRng>,
+}
+
+impl FaultInjector {
+    /// Faults each connection with probability `rate` (clamped to 0.0-1.0)
+    pub fn new(rate: f64, source: &RngSource) -> Self {
+        Self {
+            rate: rate.clamp(0.0, 1.0),
+            rng: Mutex::new(source.derive("fault-injection")),
+        }
+    }
+
+    /// Draws the next decision; true means drop the connection
+    pub fn should_fault(&self) -> bool {
+        self.rng.lock().unwrap().gen_bool(self.rate)
+    }
+}
+
+/// Bytes moved over one connection, as seen by the handler
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectionStats {
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub timed_out: bool, // The peer stalled past the I/O timeout and was disconnected
+}
+
+impl ConnectionStats {
+    /// Total bytes in both directions
+    pub fn bytes_transferred(&self) -> u64 {
+        self.bytes_read + self.bytes_written
+    }
+
+    fn timed_out(self) -> Self {
+        Self {
+            timed_out: true,
+            ..self
+        }
+    }
+}
+
+impl std::ops::AddAssign for ConnectionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.bytes_read += other.bytes_read;
+        self.bytes_written += other.bytes_written;
+        self.timed_out |= other.timed_out;
+    }
+}
+
+/// Sliding-window request counter keyed by client IP
+/// Emulates a rate-limited API: requests beyond `max_requests` within `window`
+/// are rejected until the oldest request in the window expires
+#[derive(Debug)]
+pub struct RequestThrottle {
+    max_requests: usize,
+    window: Duration,
+    requests: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl RequestThrottle {
+    /// Allows `max_requests` per client within any `window`-long span
+    pub fn new(max_requests: usize, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+            requests: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request from `ip` made at `now`
+    /// Returns `Err(retry_after)` without counting it when the limit is exceeded
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut requests = self.requests.lock().unwrap();
+        // Forget clients whose last request has left the window
+        requests.retain(|_, history| {
+            history
+                .back()
+                .is_some_and(|last| now.duration_since(*last) < self.window)
+        });
+        let history = requests.entry(ip).or_default();
+        while let Some(oldest) = history.front() {
+            if now.duration_since(*oldest) < self.window {
+                break;
+            }
+            history.pop_front();
+        }
+
+        if history.len() >= self.max_requests {
+            let retry_after = history
+                .front()
+                .map(|oldest| self.window.saturating_sub(now.duration_since(*oldest)))
+                .unwrap_or(self.window);
+            return Err(retry_after);
+        }
+        history.push_back(now);
+        Ok(())
+    }
+}
 
 /// Main connection handler function that processes new TCP connections
 /// Performs service detection and responds with connection status
@@ -14,41 +300,1561 @@ use tokio::net::TcpStream;
 ///   addr: Remote peer address
 ///   discovery: Shared service detection system
 pub async fn handle_connection(
-    mut socket: TcpStream,
+    socket: TcpStream,
     addr: SocketAddr,
     discovery: Arc<ServiceDiscovery>,
 ) {
+    handle_connection_with(socket, addr, discovery, &HandlerConfig::default()).await;
+}
+
+/// Same as `handle_connection` but driven by an explicit `HandlerConfig`
+/// The behavior is selected by the local port the connection was accepted on
+/// Returns the number of bytes read from and written to the peer
+pub async fn handle_connection_with(
+    socket: TcpStream,
+    addr: SocketAddr,
+    discovery: Arc<ServiceDiscovery>,
+    config: &HandlerConfig,
+) -> ConnectionStats {
+    let local = socket.local_addr().ok();
+    handle_stream(socket, local, addr, discovery, config).await
+}
+
+/// Same as `handle_connection_with` for any byte stream, e.g. a metered `TcpStream`
+/// `local` is the address the connection was accepted on; it selects the behavior
+pub async fn handle_stream<S>(
+    socket: S,
+    local: Option<SocketAddr>,
+    addr: SocketAddr,
+    discovery: Arc<ServiceDiscovery>,
+    config: &HandlerConfig,
+) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    if config
+        .fault_injector
+        .as_ref()
+        .is_some_and(|faults| faults.should_fault())
+    {
+        // Closing without a word looks like a crashed or overloaded service
+        return ConnectionStats::default();
+    }
+
+    let local_port = local.map(|a| a.port()).unwrap_or_default();
+    let limit = config.io_timeout();
+    match config.behavior_for(local_port) {
+        PortBehavior::Probe => handle_probe(socket, local, addr, discovery, config).await,
+        PortBehavior::Discard => handle_discard(socket, limit).await,
+        PortBehavior::Chargen => handle_chargen(socket).await,
+        PortBehavior::Daytime => handle_daytime(socket, limit).await,
+        PortBehavior::StaticDir(root) => handle_static_dir(socket, root, limit).await,
+        PortBehavior::Echo => handle_echo(socket, addr, None, limit).await,
+        PortBehavior::EchoLine => handle_echo(socket, addr, Some(b"\r\n"), limit).await,
+    }
+}
+
+// Probes the client for a banner, records it and answers per the port's `ResponseProfile`
+async fn handle_probe<S>(
+    mut socket: S,
+    local: Option<SocketAddr>,
+    addr: SocketAddr,
+    discovery: Arc<ServiceDiscovery>,
+    config: &HandlerConfig,
+) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stats = ConnectionStats::default();
+    // Every read and write below is bounded; a stalled peer gets disconnected
+    let limit = config.io_timeout();
+
+    if let Some(throttle) = &config.throttle {
+        if let Err(retry_after) = throttle.check(addr.ip(), Instant::now()) {
+            // Consume the request first so closing doesn't reset the connection
+            let mut request = [0_u8; 1024];
+            match timeout(limit, socket.read(&mut request)).await {
+                Ok(Ok(n)) => stats.bytes_read += n as u64,
+                Ok(Err(_)) => {}
+                Err(_) => return stats.timed_out(),
+            }
+
+            // Round up so clients never retry before the window frees a slot
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            let response = format!(
+                "HTTP/1.1 429 Too Many Requests\r\n\
+                 Retry-After: {}\r\n\
+                 Content-Length: 0\r\n\
+                 \r\n",
+                seconds
+            );
+            match timeout(limit, socket.write_all(response.as_bytes())).await {
+                Ok(Ok(())) => stats.bytes_written += response.len() as u64,
+                Ok(Err(_)) => {}
+                Err(_) => return stats.timed_out(),
+            }
+            let _ = socket.shutdown().await;
+            return stats;
+        }
+    }
+
+    if let Some(terminator) = config.line_terminator.as_deref().filter(|t| !t.is_empty()) {
+        let fingerprints = &config.fingerprints;
+        return handle_lines(
+            socket,
+            addr,
+            discovery,
+            fingerprints,
+            terminator,
+            limit,
+            stats,
+        )
+        .await;
+    }
+
+    #[cfg(feature = "pcap")]
+    let mut capture = open_capture(local, addr, config);
+
     // Buffer for reading service detection data
     let mut detection_buf = [0_u8; 1024];
-    let content;
+    let mut received = 0;
+    let mut content = String::new();
+
+    // Send the detection probe (if any) to coax out service information
+    let request = config.detection_probe();
+    let probe_sent = match timeout(limit, socket.write_all(&request)).await {
+        Ok(result) => result.is_ok(),
+        Err(_) => return stats.timed_out(),
+    };
+    if probe_sent {
+        stats.bytes_written += request.len() as u64;
+        #[cfg(feature = "pcap")]
+        if !request.is_empty() {
+            capture_payload(&mut capture, Direction::Outbound, &request);
+        }
 
-    // Send HTTP request to probe for service information
-    let request = "GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
-    if socket.write_all(request.as_bytes()).await.is_ok() {
         // Read response for service fingerprinting
-        if let Ok(n) = socket.read(&mut detection_buf).await {
+        let read = match timeout(limit, socket.read(&mut detection_buf)).await {
+            Ok(read) => read,
+            Err(_) => return stats.timed_out(),
+        };
+        if let Ok(n) = read {
             if n > 0 {
+                stats.bytes_read += n as u64;
+                received = n;
+                #[cfg(feature = "pcap")]
+                capture_payload(&mut capture, Direction::Inbound, &detection_buf[..n]);
+
                 // Convert response to string and record service details
                 content = String::from_utf8_lossy(&detection_buf[..n]).to_string();
-                discovery.record_service(addr, &content).await;
+                let banner = &detection_buf[..n];
+                let service = config.fingerprints.classify(banner);
+                discovery.record_identified(addr, banner, service).await;
             }
         }
     }
 
-    // Prepare and send HTTP response with connection details
-    // Includes port number and connection timestamp
-    let response = format!(
+    let local_port = local.map(|a| a.port()).unwrap_or_default();
+    let profile = config.response_for(local_port);
+    let response = match profile {
+        // Status page with connection details: port number and connection timestamp
+        ResponseProfile::Http(template) => {
+            let body = template
+                .replace("{port}", &addr.port().to_string())
+                .replace("{time}", &Local::now().format("%Y-%m-%d %H:%M:%S").to_string());
+            status_response(&body, &content)
+        }
+        ResponseProfile::Raw(bytes) => bytes.clone(),
+        ResponseProfile::Echo => detection_buf[..received].to_vec(),
+        ResponseProfile::Silent => Vec::new(),
+    };
+
+    // Send response back to client
+    let response_sent = match timeout(limit, socket.write_all(&response)).await {
+        Ok(result) => result.is_ok(),
+        Err(_) => return stats.timed_out(),
+    };
+    if !response.is_empty() && response_sent {
+        stats.bytes_written += response.len() as u64;
+        #[cfg(feature = "pcap")]
+        capture_payload(&mut capture, Direction::Outbound, &response);
+    }
+
+    #[cfg(feature = "pcap")]
+    if let Some((path, writer)) = capture {
+        if let Err(e) = writer.save(path).await {
+            eprintln!("Failed to write capture file for {}: {}", addr, e);
+        }
+    }
+
+    match profile {
+        ResponseProfile::Echo => stats += handle_echo(socket, addr, None, limit).await,
+        ResponseProfile::Silent => stats += handle_discard(socket, limit).await,
+        ResponseProfile::Http(_) | ResponseProfile::Raw(_) => {}
+    }
+    stats
+}
+
+/// Line-framed exchange: answers every `terminator`-ended message with `ACK <len>`
+/// Runs until the peer closes or stays silent past `limit`; the first message is
+/// recorded as the service banner
+async fn handle_lines<S>(
+    mut socket: S,
+    addr: SocketAddr,
+    discovery: Arc<ServiceDiscovery>,
+    fingerprints: &FingerprintDb,
+    terminator: &[u8],
+    limit: Duration,
+    mut stats: ConnectionStats,
+) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut pending = Vec::new();
+    let mut buf = [0_u8; 1024];
+    loop {
+        let n = match timeout(limit, socket.read(&mut buf)).await {
+            Ok(Ok(n)) if n > 0 => n,
+            Ok(_) => break,
+            Err(_) => return stats.timed_out(),
+        };
+        stats.bytes_read += n as u64;
+        pending.extend_from_slice(&buf[..n]);
+
+        loop {
+            let message = match find_subsequence(&pending, terminator) {
+                Some(end) => {
+                    let mut message: Vec<u8> = pending.drain(..end + terminator.len()).collect();
+                    message.truncate(end);
+                    message
+                }
+                // Oversized unterminated input is processed as-is to bound memory
+                None if pending.len() >= MAX_LINE_LEN => std::mem::take(&mut pending),
+                None => break,
+            };
+
+            let service = fingerprints.classify(&message);
+            discovery.record_identified(addr, &message, service).await;
+            let mut reply = format!("ACK {}", message.len()).into_bytes();
+            reply.extend_from_slice(terminator);
+            match timeout(limit, socket.write_all(&reply)).await {
+                Ok(Ok(())) => stats.bytes_written += reply.len() as u64,
+                Ok(Err(_)) => return stats,
+                Err(_) => return stats.timed_out(),
+            }
+        }
+    }
+    stats
+}
+
+/// One UDP datagram as received by `recv_datagram`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Datagram {
+    pub peer: SocketAddr,
+    pub data: Vec<u8>,
+    /// The datagram was larger than the receive limit and `data` holds only its prefix
+    pub truncated: bool,
+}
+
+/// Receives one datagram into a buffer of `max_payload` bytes
+/// The buffer carries one spare byte so oversized datagrams are flagged
+/// as truncated instead of silently cut
+pub async fn recv_datagram(socket: &UdpSocket, max_payload: usize) -> std::io::Result<Datagram> {
+    let mut buf = vec![0_u8; max_payload + 1];
+    let (n, peer) = socket.recv_from(&mut buf).await?;
+    let truncated = n > max_payload;
+    buf.truncate(n.min(max_payload));
+    Ok(Datagram {
+        peer,
+        data: buf,
+        truncated,
+    })
+}
+
+/// Receives datagrams until the socket errors, handling each with `handle_udp_datagram`
+pub async fn handle_datagrams(
+    socket: &UdpSocket,
+    discovery: Arc<ServiceDiscovery>,
+    config: &HandlerConfig,
+) -> ConnectionStats {
+    let max_payload = config.udp_max_payload.unwrap_or(UDP_MAX_PAYLOAD);
+    let mut stats = ConnectionStats::default();
+    while let Ok(datagram) = recv_datagram(socket, max_payload).await {
+        let handled = handle_udp_datagram(socket, datagram, discovery.clone(), config).await;
+        stats.bytes_read += handled.bytes_read;
+        stats.bytes_written += handled.bytes_written;
+    }
+    stats
+}
+
+/// UDP counterpart of `handle_connection_with`, answering one datagram
+/// The payload is recorded as the peer's service banner (truncated ones with a warning);
+/// with `udp_replies` set, the local port's behavior then decides the reply, cut to
+/// the payload's length:
+/// - Probe: the detection probe, if not empty
+/// - Echo / EchoLine: the payload itself
+/// - Daytime / Chargen: one time or pattern line (RFC 867 / RFC 864 over UDP)
+/// - Discard / StaticDir: no reply
+pub async fn handle_udp_datagram(
+    socket: &UdpSocket,
+    datagram: Datagram,
+    discovery: Arc<ServiceDiscovery>,
+    config: &HandlerConfig,
+) -> ConnectionStats {
+    let mut stats = ConnectionStats {
+        bytes_read: datagram.data.len() as u64,
+        ..Default::default()
+    };
+    if datagram.truncated {
+        eprintln!(
+            "UDP datagram from {} exceeded {} bytes and was truncated",
+            datagram.peer,
+            datagram.data.len()
+        );
+    }
+    let service = config.fingerprints.classify(&datagram.data);
+    discovery
+        .record_identified(datagram.peer, &datagram.data, service)
+        .await;
+    if !config.udp_replies {
+        return stats;
+    }
+
+    let local_port = socket.local_addr().map(|a| a.port()).unwrap_or_default();
+    let request_len = datagram.data.len();
+    let mut reply = match config.behavior_for(local_port) {
+        PortBehavior::Probe => config.detection_probe(),
+        PortBehavior::Echo | PortBehavior::EchoLine => datagram.data,
+        PortBehavior::Daytime => format!("{}\r\n", Local::now().to_rfc2822()).into_bytes(),
+        PortBehavior::Chargen => chargen_line(0),
+        PortBehavior::Discard | PortBehavior::StaticDir(_) => Vec::new(),
+    };
+    // Never send more than was received, so the listener can't amplify
+    reply.truncate(request_len);
+    if !reply.is_empty() {
+        if let Ok(n) = socket.send_to(&reply, datagram.peer).await {
+            stats.bytes_written += n as u64;
+        }
+    }
+    stats
+}
+
+/// Writes `buf` in a loop until it is fully sent or `limit` has elapsed
+/// Returns the number of bytes written; a count below `buf.len()` means the
+/// deadline passed while the peer was not reading
+pub async fn write_with_timeout<W>(
+    writer: &mut W,
+    buf: &[u8],
+    limit: Duration,
+) -> std::io::Result<usize>
+where
+    W: AsyncWrite + Unpin,
+{
+    let deadline = tokio::time::Instant::now() + limit;
+    let mut written = 0;
+    while written < buf.len() {
+        match tokio::time::timeout_at(deadline, writer.write(&buf[written..])).await {
+            Ok(Ok(0)) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(Ok(n)) => written += n,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => break,
+        }
+    }
+    if written == buf.len() {
+        writer.flush().await?;
+    }
+    Ok(written)
+}
+
+fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Wraps the status page in an HTTP response
+/// With the `compression` feature the body is gzipped when the request accepts it
+fn status_response(body: &str, request: &str) -> Vec<u8> {
+    #[cfg(feature = "compression")]
+    if accepts_gzip(request) {
+        if let Ok(compressed) = gzip(body.as_bytes()) {
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\n\
+                 Content-Type: text/html\r\n\
+                 Content-Encoding: gzip\r\n\
+                 Content-Length: {}\r\n\
+                 \r\n",
+                compressed.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(&compressed);
+            return response;
+        }
+    }
+    #[cfg(not(feature = "compression"))]
+    let _ = request;
+
+    format!(
         "HTTP/1.1 200 OK\r\n\
          Content-Type: text/html\r\n\
          \r\n\
-         <html><body>\
-         <h1>Port {}</h1>\
-         <p>Active since: {}</p>\
-         </body></html>",
+         {}",
+        body
+    )
+    .into_bytes()
+}
+
+/// Checks the request's Accept-Encoding header for gzip (ignoring `q=0` opt-outs)
+#[cfg(feature = "compression")]
+fn accepts_gzip(request: &str) -> bool {
+    request
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("accept-encoding"))
+        .flat_map(|(_, value)| value.split(','))
+        .any(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next().unwrap_or_default();
+            let disabled = params.any(|param| {
+                param
+                    .strip_prefix("q=")
+                    .and_then(|q| q.trim().parse::<f32>().ok())
+                    == Some(0.0)
+            });
+            (name.eq_ignore_ascii_case("gzip") || name == "*") && !disabled
+        })
+}
+
+#[cfg(feature = "compression")]
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Reads and drops all data until the peer closes or stays silent past `limit`,
+/// never replying
+async fn handle_discard<S>(mut socket: S, limit: Duration) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stats = ConnectionStats::default();
+    let mut buf = [0_u8; 4096];
+    loop {
+        match timeout(limit, socket.read(&mut buf)).await {
+            Ok(Ok(0)) | Ok(Err(_)) => return stats,
+            Ok(Ok(n)) => stats.bytes_read += n as u64,
+            Err(_) => return stats.timed_out(),
+        }
+    }
+}
+
+/// Writes back everything the peer sends until it closes or, with a `terminator`
+/// such as CRLF, until the bytes received so far end with it (echoed before closing)
+/// A read or write stalled past `limit` ends the exchange as timed out
+/// Returns the bytes read and echoed
+pub async fn handle_echo<S>(
+    mut socket: S,
+    addr: SocketAddr,
+    terminator: Option<&[u8]>,
+    limit: Duration,
+) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stats = ConnectionStats::default();
+    let mut buf = [0_u8; 4096];
+    let terminator = terminator.filter(|t| !t.is_empty());
+    // Last bytes received, so a terminator split across reads is still seen
+    let mut tail = Vec::new();
+    loop {
+        let n = match timeout(limit, socket.read(&mut buf)).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => n,
+            Ok(Err(e)) => {
+                eprintln!("Echo read from {} failed: {}", addr, e);
+                break;
+            }
+            Err(_) => {
+                stats = stats.timed_out();
+                break;
+            }
+        };
+        stats.bytes_read += n as u64;
+        match timeout(limit, socket.write_all(&buf[..n])).await {
+            Ok(Ok(())) => stats.bytes_written += n as u64,
+            Ok(Err(_)) => break,
+            Err(_) => {
+                stats = stats.timed_out();
+                break;
+            }
+        }
+
+        if let Some(terminator) = terminator {
+            tail.extend_from_slice(&buf[n.saturating_sub(terminator.len())..n]);
+            if tail.ends_with(terminator) {
+                break;
+            }
+            tail.drain(..tail.len().saturating_sub(terminator.len()));
+        }
+    }
+    let _ = timeout(limit, socket.shutdown()).await;
+    stats
+}
+
+/// Streams the chargen pattern until the peer stops reading or closes
+async fn handle_chargen<S: AsyncRead + AsyncWrite + Unpin>(mut socket: S) -> ConnectionStats {
+    let mut stats = ConnectionStats::default();
+    let mut line = 0;
+    loop {
+        let chunk = chargen_line(line);
+        match write_with_timeout(&mut socket, &chunk, STREAM_WRITE_TIMEOUT).await {
+            Ok(n) => {
+                stats.bytes_written += n as u64;
+                // A short write means the reader stalled past the timeout
+                if n < chunk.len() {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+        line += 1;
+    }
+    stats
+}
+
+/// Sends the current local time as an RFC 2822 line, then closes
+/// A client that doesn't take the line within `limit` is disconnected
+async fn handle_daytime<S>(mut socket: S, limit: Duration) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stats = ConnectionStats::default();
+    let line = format!("{}\r\n", Local::now().to_rfc2822());
+    match write_with_timeout(&mut socket, line.as_bytes(), limit).await {
+        Ok(n) if n == line.len() => stats.bytes_written += n as u64,
+        Ok(n) => {
+            stats.bytes_written += n as u64;
+            return stats.timed_out();
+        }
+        Err(_) => return stats,
+    }
+    let _ = timeout(limit, socket.shutdown()).await;
+    stats
+}
+
+/// Answers a single HTTP GET with a file from `root`, or 403/404/405
+/// A client that sends no request within `limit` is disconnected
+async fn handle_static_dir<S>(mut socket: S, root: &Path, limit: Duration) -> ConnectionStats
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut stats = ConnectionStats::default();
+    let mut request = [0_u8; 4096];
+    let n = match timeout(limit, socket.read(&mut request)).await {
+        Ok(Ok(n)) if n > 0 => n,
+        Ok(_) => return stats,
+        Err(_) => return stats.timed_out(),
+    };
+    stats.bytes_read += n as u64;
+    let request = String::from_utf8_lossy(&request[..n]);
+    let response = static_response(root, &request).await;
+    if let Ok(n) = write_with_timeout(&mut socket, &response, STREAM_WRITE_TIMEOUT).await {
+        stats.bytes_written += n as u64;
+    }
+    let _ = socket.shutdown().await;
+    stats
+}
+
+/// Builds the response for one request against a static directory
+async fn static_response(root: &Path, request: &str) -> Vec<u8> {
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let (method, target) = (request_line.next(), request_line.next());
+    let target = match (method, target) {
+        (Some("GET"), Some(target)) => target,
+        _ => {
+            return http_response(
+                "405 Method Not Allowed",
+                "text/plain",
+                b"Method Not Allowed",
+            )
+        }
+    };
+
+    let Some(mut path) = resolve_static_path(root, target) else {
+        return http_response("403 Forbidden", "text/plain", b"Forbidden");
+    };
+    if path.is_dir() {
+        path.push("index.html");
+    }
+
+    // Symlinks may still point outside the root, so compare canonical paths
+    let contained = match (
+        tokio::fs::canonicalize(root).await,
+        tokio::fs::canonicalize(&path).await,
+    ) {
+        (Ok(root), Ok(path)) => path.starts_with(root),
+        _ => return http_response("404 Not Found", "text/plain", b"Not Found"),
+    };
+    if !contained {
+        return http_response("403 Forbidden", "text/plain", b"Forbidden");
+    }
+
+    match tokio::fs::read(&path).await {
+        Ok(body) => http_response("200 OK", guess_content_type(&path), &body),
+        Err(_) => http_response("404 Not Found", "text/plain", b"Not Found"),
+    }
+}
+
+/// Maps a request target onto a path below `root`
+/// Returns `None` for targets that try to escape the root (`..`, absolute or encoded)
+fn resolve_static_path(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let decoded = percent_decode(path)?;
+
+    let mut resolved = root.to_path_buf();
+    for segment in decoded.split('/') {
+        match Path::new(segment).components().next() {
+            None | Some(Component::CurDir) => {}
+            Some(Component::Normal(name)) if !segment.contains('\\') && name == segment => {
+                resolved.push(segment)
+            }
+            _ => return None,
+        }
+    }
+    Some(resolved)
+}
+
+/// Decodes `%XX` escapes, rejecting malformed escapes and non-UTF-8 results
+fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Guesses a Content-Type from the file extension
+fn guess_content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html") | Some("htm") => "text/html",
+        Some("css") => "text/css",
+        Some("js") => "application/javascript",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain",
+        Some("xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+fn http_response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n",
+        status,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Builds line `n` of the chargen pattern: 72 printable ASCII characters
+/// starting one position later than the previous line, followed by CRLF
+pub fn chargen_line(n: usize) -> Vec<u8> {
+    const PRINTABLE: std::ops::RangeInclusive<u8> = b' '..=b'~';
+    let count = PRINTABLE.len();
+    let mut line: Vec<u8> = (0..CHARGEN_LINE_WIDTH)
+        .map(|i| b' ' + ((n + i) % count) as u8)
+        .collect();
+    line.extend_from_slice(b"\r\n");
+    line
+}
+
+// Starts an in-memory capture when a capture directory is configured, along with
+// the file it is saved to once the exchange is over
+#[cfg(feature = "pcap")]
+fn open_capture(
+    local: Option<SocketAddr>,
+    addr: SocketAddr,
+    config: &HandlerConfig,
+) -> Option<(PathBuf, PcapWriter<Vec<u8>>)> {
+    let dir = config.capture_dir.as_ref()?;
+    let local = local?;
+    let file_name = format!(
+        "{}_{}_{}.pcap",
+        addr.ip(),
         addr.port(),
-        Local::now().format("%Y-%m-%d %H:%M:%S")
-    );
+        Local::now().format("%Y%m%d%H%M%S%3f")
+    )
+    .replace(':', "-");
+    Some((dir.join(file_name), PcapWriter::in_memory(local, addr)))
+}
 
-    // Send response back to client
-    let _ = socket.write_all(response.as_bytes()).await;
+#[cfg(feature = "pcap")]
+fn capture_payload(
+    capture: &mut Option<(PathBuf, PcapWriter<Vec<u8>>)>,
+    direction: Direction,
+    payload: &[u8],
+) {
+    if let Some((_, writer)) = capture.as_mut() {
+        if let Err(e) = writer.record(direction, payload) {
+            eprintln!("Failed to write capture record: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::time::timeout;
+
+    // Serves every accepted connection with the given handler config
+    async fn spawn_handler(config: HandlerConfig) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        serve(listener, Arc::new(config))
+    }
+
+    // Runs the handler accept loop on an already bound listener
+    fn serve(listener: TcpListener, config: Arc<HandlerConfig>) -> SocketAddr {
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            // Lives as long as the accept loop, keeping banners out of the working directory
+            let dir = tempfile::tempdir().unwrap();
+            let discovery =
+                Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+            while let Ok((socket, peer)) = listener.accept().await {
+                let discovery = discovery.clone();
+                let config = config.clone();
+                tokio::spawn(async move {
+                    handle_connection_with(socket, peer, discovery, &config).await;
+                });
+            }
+        });
+        addr
+    }
+
+    fn behavior_config(behavior: PortBehavior) -> HandlerConfig {
+        HandlerConfig {
+            default_behavior: behavior,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_behavior_for_port_override() {
+        let mut config = behavior_config(PortBehavior::Discard);
+        config.port_behaviors.insert(19, PortBehavior::Chargen);
+        assert_eq!(config.behavior_for(19), &PortBehavior::Chargen);
+        assert_eq!(config.behavior_for(9), &PortBehavior::Discard);
+    }
+
+    #[test]
+    fn test_behavior_for_port_ranges() {
+        let mut config = behavior_config(PortBehavior::Discard);
+        config.port_range_behaviors = vec![
+            (1..=1023, PortBehavior::Probe),
+            (8000..=9000, PortBehavior::Echo),
+            (8080..=8080, PortBehavior::Daytime),
+        ];
+        config.port_behaviors.insert(22, PortBehavior::Chargen);
+
+        assert_eq!(config.behavior_for(80), &PortBehavior::Probe);
+        assert_eq!(config.behavior_for(8500), &PortBehavior::Echo);
+        assert_eq!(config.behavior_for(8080), &PortBehavior::Daytime);
+        assert_eq!(config.behavior_for(22), &PortBehavior::Chargen);
+        assert_eq!(config.behavior_for(30000), &PortBehavior::Discard);
+    }
+
+    #[tokio::test]
+    async fn test_range_rules_select_handler_per_listener() {
+        let mut listeners = Vec::new();
+        for _ in 0..3 {
+            listeners.push(TcpListener::bind("127.0.0.1:0").await.unwrap());
+        }
+        let mut ports: Vec<u16> = listeners
+            .iter()
+            .map(|l| l.local_addr().unwrap().port())
+            .collect();
+        ports.sort_unstable();
+        let (low, mid, high) = (ports[0], ports[1], ports[2]);
+
+        // One range covers all three ports, a narrower one only the middle port,
+        // and the highest port falls through to its own single-port range
+        let config = Arc::new(HandlerConfig {
+            default_behavior: PortBehavior::Probe,
+            port_range_behaviors: vec![
+                (low..=high, PortBehavior::Echo),
+                (mid..=mid, PortBehavior::Daytime),
+                (high..=high, PortBehavior::Discard),
+            ],
+            ..Default::default()
+        });
+        let addrs: HashMap<u16, SocketAddr> = listeners
+            .into_iter()
+            .map(|l| {
+                let addr = serve(l, config.clone());
+                (addr.port(), addr)
+            })
+            .collect();
+
+        let mut echo = TcpStream::connect(addrs[&low]).await.unwrap();
+        echo.write_all(b"ping").await.unwrap();
+        let mut buf = [0_u8; 4];
+        echo.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        let mut daytime = TcpStream::connect(addrs[&mid]).await.unwrap();
+        let mut line = String::new();
+        daytime.read_to_string(&mut line).await.unwrap();
+        assert!(chrono::DateTime::parse_from_rfc2822(line.trim_end()).is_ok());
+
+        let mut discard = TcpStream::connect(addrs[&high]).await.unwrap();
+        discard.write_all(b"hello").await.unwrap();
+        let read = timeout(Duration::from_millis(150), discard.read(&mut buf)).await;
+        assert!(read.is_err(), "discard rule must not reply");
+    }
+
+    #[tokio::test]
+    async fn test_fault_injector_drops_connections() {
+        let config = HandlerConfig {
+            fault_injector: Some(Arc::new(FaultInjector::new(1.0, &RngSource::new(Some(1))))),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+
+        // The probe handler would send its request first; a faulted one closes silently
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = Vec::new();
+        client.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_discard_consumes_without_replying() {
+        let addr = spawn_handler(behavior_config(PortBehavior::Discard)).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        client.write_all(&[b'x'; 8192]).await.unwrap();
+
+        let mut buf = [0_u8; 64];
+        let read = timeout(Duration::from_millis(150), client.read(&mut buf)).await;
+        assert!(read.is_err(), "discard service must not reply");
+    }
+
+    #[tokio::test]
+    async fn test_daytime_returns_parseable_timestamp() {
+        let addr = spawn_handler(behavior_config(PortBehavior::Daytime)).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        assert!(reply.ends_with("\r\n"));
+        assert!(chrono::DateTime::parse_from_rfc2822(reply.trim_end()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_chargen_streams_expected_pattern() {
+        let addr = spawn_handler(behavior_config(PortBehavior::Chargen)).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let line_len = CHARGEN_LINE_WIDTH + 2;
+        let mut received = vec![0_u8; line_len * 3];
+        client.read_exact(&mut received).await.unwrap();
+
+        assert_eq!(&received[..line_len], chargen_line(0).as_slice());
+        assert_eq!(
+            &received[line_len..line_len * 2],
+            chargen_line(1).as_slice()
+        );
+        assert_eq!(&received[..3], b" !\"");
+        assert_eq!(&received[line_len..line_len + 3], b"!\"#");
+    }
+
+    // Sends one raw HTTP request and returns the full response
+    async fn http_get(addr: SocketAddr, target: &str) -> String {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+        reply
+    }
+
+    // Creates `<tmp>/www/index.html` and a secret file next to (outside) the served root
+    fn static_site() -> (tempfile::TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("www");
+        std::fs::create_dir(&root).unwrap();
+        std::fs::write(root.join("index.html"), "<h1>hello</h1>").unwrap();
+        std::fs::write(dir.path().join("secret.txt"), "top secret").unwrap();
+        (dir, root)
+    }
+
+    #[tokio::test]
+    async fn test_static_dir_serves_file() {
+        let (_dir, root) = static_site();
+        let addr = spawn_handler(behavior_config(PortBehavior::StaticDir(root))).await;
+
+        let reply = http_get(addr, "/index.html").await;
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.contains("Content-Type: text/html\r\n"));
+        assert!(reply.contains("Content-Length: 14\r\n"));
+        assert!(reply.ends_with("\r\n\r\n<h1>hello</h1>"));
+
+        // Directories fall back to their index
+        assert!(http_get(addr, "/").await.ends_with("<h1>hello</h1>"));
+    }
+
+    #[tokio::test]
+    async fn test_static_dir_missing_file_is_404() {
+        let (_dir, root) = static_site();
+        let addr = spawn_handler(behavior_config(PortBehavior::StaticDir(root))).await;
+
+        let reply = http_get(addr, "/missing.css").await;
+        assert!(reply.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_static_dir_rejects_traversal() {
+        let (_dir, root) = static_site();
+        let addr = spawn_handler(behavior_config(PortBehavior::StaticDir(root))).await;
+
+        for target in [
+            "/../secret.txt",
+            "/%2e%2e/secret.txt",
+            "/a/../../secret.txt",
+        ] {
+            let reply = http_get(addr, target).await;
+            assert!(
+                reply.starts_with("HTTP/1.1 403 Forbidden\r\n"),
+                "{}",
+                target
+            );
+            assert!(!reply.contains("top secret"));
+        }
+    }
+
+    // Reads the probe, answers with `request`, and returns (headers, body, client port)
+    async fn exchange(addr: SocketAddr, request: &str) -> (String, Vec<u8>, u16) {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let port = client.local_addr().unwrap().port();
+        let mut probe = [0_u8; 1024];
+        let _ = client.read(&mut probe).await.unwrap();
+        client.write_all(request.as_bytes()).await.unwrap();
+
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        let split = reply.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let headers = String::from_utf8(reply[..split].to_vec()).unwrap();
+        (headers, reply[split..].to_vec(), port)
+    }
+
+    #[tokio::test]
+    async fn test_write_with_timeout_returns_partial_count() {
+        // The reader half is kept alive but never read, so the pipe fills up
+        let (mut writer, _reader) = tokio::io::duplex(1024);
+        let payload = vec![b'z'; 4096];
+
+        let start = Instant::now();
+        let written = write_with_timeout(&mut writer, &payload, Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(written, 1024);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        let (mut writer, _reader) = tokio::io::duplex(8192);
+        let written = write_with_timeout(&mut writer, &payload, Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(written, payload.len());
+    }
+
+    async fn udp_pair() -> (UdpSocket, UdpSocket) {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        sender
+            .connect(receiver.local_addr().unwrap())
+            .await
+            .unwrap();
+        (receiver, sender)
+    }
+
+    #[tokio::test]
+    async fn test_large_datagram_received_in_full() {
+        let (receiver, sender) = udp_pair().await;
+        let payload: Vec<u8> = (0..UDP_MAX_PAYLOAD).map(|i| (i % 251) as u8).collect();
+        sender.send(&payload).await.unwrap();
+
+        let datagram = timeout(
+            Duration::from_secs(1),
+            recv_datagram(&receiver, UDP_MAX_PAYLOAD),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert!(!datagram.truncated);
+        assert_eq!(datagram.data, payload);
+        assert_eq!(datagram.peer, sender.local_addr().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_datagram_flagged_as_truncated() {
+        let (receiver, sender) = udp_pair().await;
+        sender.send(&[b'u'; 2048]).await.unwrap();
+
+        let datagram = recv_datagram(&receiver, 1024).await.unwrap();
+        assert!(datagram.truncated);
+        assert_eq!(datagram.data.len(), 1024);
+    }
+
+    #[tokio::test]
+    async fn test_udp_datagram_echoed_and_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery =
+            Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+        let config = HandlerConfig {
+            default_behavior: PortBehavior::Echo,
+            udp_replies: true,
+            ..Default::default()
+        };
+        let (receiver, sender) = udp_pair().await;
+        sender.send(b"SIP/2.0 OPTIONS").await.unwrap();
+
+        let datagram = recv_datagram(&receiver, UDP_MAX_PAYLOAD).await.unwrap();
+        let stats = handle_udp_datagram(&receiver, datagram, discovery.clone(), &config).await;
+        assert_eq!(stats.bytes_read, 15);
+        assert_eq!(stats.bytes_written, 15);
+
+        let mut reply = [0_u8; 64];
+        let n = timeout(Duration::from_secs(1), sender.recv(&mut reply))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&reply[..n], b"SIP/2.0 OPTIONS");
+        let peer = sender.local_addr().unwrap();
+        assert_eq!(discovery.banner(peer).await.unwrap(), "SIP/2.0 OPTIONS");
+        assert_eq!(discovery.summary().await[0].service.as_deref(), Some("SIP"));
+    }
+
+    #[tokio::test]
+    async fn test_udp_replies_are_opt_in_and_capped() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery =
+            Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+        let (receiver, sender) = udp_pair().await;
+        let mut reply = [0_u8; 128];
+
+        // Recorded but unanswered by default
+        sender.send(b"ping").await.unwrap();
+        let datagram = recv_datagram(&receiver, UDP_MAX_PAYLOAD).await.unwrap();
+        let stats =
+            handle_udp_datagram(&receiver, datagram, discovery.clone(), &Default::default())
+                .await;
+        assert_eq!(stats.bytes_written, 0);
+        assert!(timeout(Duration::from_millis(200), sender.recv(&mut reply))
+            .await
+            .is_err());
+        assert!(discovery.banner(sender.local_addr().unwrap()).await.is_some());
+
+        // A full chargen line is cut to the size of the request
+        let config = HandlerConfig {
+            default_behavior: PortBehavior::Chargen,
+            udp_replies: true,
+            ..Default::default()
+        };
+        sender.send(b"ping").await.unwrap();
+        let datagram = recv_datagram(&receiver, UDP_MAX_PAYLOAD).await.unwrap();
+        handle_udp_datagram(&receiver, datagram, discovery, &config).await;
+        let n = timeout(Duration::from_secs(1), sender.recv(&mut reply))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(&reply[..n], &chargen_line(0)[..4]);
+    }
+
+    #[test]
+    fn test_default_config_shares_compiled_fingerprint_rules() {
+        let (a, b) = (HandlerConfig::default(), HandlerConfig::default());
+        assert!(Arc::ptr_eq(&a.fingerprints, &b.fingerprints));
+        assert!(!a.fingerprints.rules().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_probe_banner_identified_by_fingerprint_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery =
+            Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+        let rules = FingerprintDb::parse("prefix LAB-DEVICE => Lab device").unwrap();
+        let config = Arc::new(HandlerConfig {
+            detection_probe: Some(Vec::new()),
+            response: ResponseProfile::Silent,
+            fingerprints: Arc::new(rules.with_defaults()),
+            ..Default::default()
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = {
+            let discovery = discovery.clone();
+            tokio::spawn(async move {
+                for _ in 0..3 {
+                    let (socket, peer) = listener.accept().await.unwrap();
+                    handle_connection_with(socket, peer, discovery.clone(), &config).await;
+                }
+            })
+        };
+
+        for banner in [&b"LAB-DEVICE v2\r\n"[..], b"SSH-2.0-OpenSSH_9.6\r\n", b"\x01\x02"] {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(banner).await.unwrap();
+            client.shutdown().await.unwrap();
+            let _ = client.read_to_end(&mut Vec::new()).await;
+        }
+        server.await.unwrap();
+
+        let mut services: Vec<String> = discovery
+            .summary()
+            .await
+            .into_iter()
+            .filter_map(|record| record.service)
+            .collect();
+        services.sort();
+        assert_eq!(
+            services,
+            vec![
+                "Lab device".to_string(),
+                "OpenSSH".to_string(),
+                crate::utils::helpers::fingerprint_hash(b"\x01\x02"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_sends_configured_user_agent() {
+        let config = HandlerConfig {
+            user_agent: Some("Mozilla/5.0 (X11; Linux x86_64)".into()),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let mut probe = [0_u8; 1024];
+        let n = client.read(&mut probe).await.unwrap();
+        let probe = String::from_utf8_lossy(&probe[..n]);
+        assert!(probe.starts_with("GET / HTTP/1.1\r\n"));
+        assert!(probe.contains("User-Agent: Mozilla/5.0 (X11; Linux x86_64)\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_detection_probe_waits_for_client() {
+        let config = HandlerConfig {
+            detection_probe: Some(Vec::new()),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let mut buf = [0_u8; 1024];
+        let early = timeout(Duration::from_millis(100), client.read(&mut buf)).await;
+        assert!(
+            early.is_err(),
+            "handler must not send anything before reading"
+        );
+
+        client.write_all(b"SSH-2.0-OpenSSH_9.6\r\n").await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_silent_client_disconnected_after_io_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, peer) = listener.accept().await.unwrap();
+        let config = HandlerConfig {
+            io_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let discovery =
+            Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+        let stats = timeout(
+            Duration::from_secs(2),
+            handle_connection_with(socket, peer, discovery.clone(), &config),
+        )
+        .await
+        .expect("handler should give up on the silent client");
+        assert!(stats.timed_out);
+        assert!(discovery.summary().await.is_empty());
+
+        // The probe arrives, then the connection is closed without a status page
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, config.detection_probe());
+    }
+
+    #[tokio::test]
+    async fn test_custom_detection_probe_sent_verbatim() {
+        let probe = b"\x00\x01HELO\xff".to_vec();
+        let config = HandlerConfig {
+            detection_probe: Some(probe.clone()),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        let mut received = vec![0_u8; probe.len()];
+        client.read_exact(&mut received).await.unwrap();
+        assert_eq!(received, probe);
+    }
+
+    #[tokio::test]
+    async fn test_echo_line_stops_at_crlf() {
+        let addr = spawn_handler(behavior_config(PortBehavior::EchoLine)).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0_u8; 4];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+
+        // The terminating line is echoed, then the server closes
+        client.write_all(b" pong\r\n").await.unwrap();
+        let mut rest = Vec::new();
+        timeout(Duration::from_secs(1), client.read_to_end(&mut rest))
+            .await
+            .expect("server should close after CRLF")
+            .unwrap();
+        assert_eq!(rest, b" pong\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_echo_terminator_split_across_reads() {
+        let (mut client, server) = tokio::io::duplex(64);
+        let peer = "127.0.0.1:9".parse().unwrap();
+        let limit = Duration::from_secs(5);
+        let echo =
+            tokio::spawn(async move { handle_echo(server, peer, Some(b"\r\n"), limit).await });
+
+        let mut buf = [0_u8; 4];
+        client.write_all(b"hi\r").await.unwrap();
+        client.read_exact(&mut buf[..3]).await.unwrap();
+        assert_eq!(&buf[..3], b"hi\r");
+        client.write_all(b"\n").await.unwrap();
+        let mut rest = Vec::new();
+        timeout(Duration::from_secs(1), client.read_to_end(&mut rest))
+            .await
+            .expect("echo should stop once the terminator is complete")
+            .unwrap();
+        assert_eq!(rest, b"\n");
+        assert_eq!(echo.await.unwrap().bytes_written, 4);
+    }
+
+    #[tokio::test]
+    async fn test_idle_clients_time_out_in_echo_discard_and_static_dir() {
+        let limit = Duration::from_millis(50);
+        let peer = "127.0.0.1:9".parse().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        // Each client stays connected but never sends anything
+        let (_echo_client, server) = tokio::io::duplex(64);
+        let echo = timeout(Duration::from_secs(2), handle_echo(server, peer, None, limit));
+        assert!(echo.await.expect("echo should give up").timed_out);
+
+        let (_discard_client, server) = tokio::io::duplex(64);
+        let discard = timeout(Duration::from_secs(2), handle_discard(server, limit));
+        assert!(discard.await.expect("discard should give up").timed_out);
+
+        let (_static_client, server) = tokio::io::duplex(64);
+        let served = timeout(
+            Duration::from_secs(2),
+            handle_static_dir(server, dir.path(), limit),
+        );
+        assert!(served.await.expect("static dir should give up").timed_out);
+    }
+
+    #[tokio::test]
+    async fn test_clients_not_reading_time_out_in_daytime_and_line_mode() {
+        let limit = Duration::from_millis(50);
+        let peer = "127.0.0.1:9".parse().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+
+        // The pipe holds less than the daytime line and the client never reads
+        let (_daytime_client, server) = tokio::io::duplex(8);
+        let daytime = timeout(Duration::from_secs(2), handle_daytime(server, limit));
+        assert!(daytime.await.expect("daytime should give up").timed_out);
+
+        // The client keeps sending lines but never reads the acknowledgements
+        let (mut lines_client, server) = tokio::io::duplex(16);
+        let sender = tokio::spawn(async move {
+            let _ = lines_client.write_all(&b"line\r\n".repeat(64)).await;
+            lines_client
+        });
+        let discovery =
+            Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+        let (db, stats) = (FingerprintDb::default(), ConnectionStats::default());
+        let lines = handle_lines(server, peer, discovery, &db, b"\r\n", limit, stats);
+        let lines = timeout(Duration::from_secs(2), lines);
+        assert!(lines.await.expect("line mode should give up").timed_out);
+        sender.abort();
+    }
+
+    #[tokio::test]
+    async fn test_response_profiles_control_reply() {
+        let mut config = HandlerConfig {
+            detection_probe: Some(Vec::new()),
+            response: ResponseProfile::Raw(b"SSH-2.0-OpenSSH_9.6\r\n".to_vec()),
+            ..Default::default()
+        };
+        let listeners = [
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+            TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        ];
+        let ports: Vec<u16> = listeners
+            .iter()
+            .map(|l| l.local_addr().unwrap().port())
+            .collect();
+        config.port_responses.insert(ports[1], ResponseProfile::Echo);
+        config.port_responses.insert(ports[2], ResponseProfile::Silent);
+        let config = Arc::new(config);
+        let addrs: Vec<SocketAddr> = listeners
+            .into_iter()
+            .map(|l| serve(l, config.clone()))
+            .collect();
+
+        let mut raw = TcpStream::connect(addrs[0]).await.unwrap();
+        raw.write_all(b"hello").await.unwrap();
+        let mut response = Vec::new();
+        raw.read_to_end(&mut response).await.unwrap();
+        assert_eq!(response, b"SSH-2.0-OpenSSH_9.6\r\n");
+
+        // Echo mirrors the banner and everything sent after it
+        let mut echo = TcpStream::connect(addrs[1]).await.unwrap();
+        echo.write_all(b"first").await.unwrap();
+        let mut buf = [0_u8; 5];
+        echo.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"first");
+        echo.write_all(b"again").await.unwrap();
+        echo.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"again");
+
+        // Silent keeps reading but never answers
+        let mut silent = TcpStream::connect(addrs[2]).await.unwrap();
+        silent.write_all(b"anyone there?").await.unwrap();
+        silent.write_all(b"hello?").await.unwrap();
+        let early = timeout(Duration::from_millis(100), silent.read(&mut buf)).await;
+        assert!(early.is_err(), "silent listener must not reply");
+        silent.shutdown().await.unwrap();
+        assert_eq!(silent.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_status_response_uncompressed_without_accept_encoding() {
+        let addr = spawn_handler(HandlerConfig::default()).await;
+        let (headers, body, port) = exchange(addr, "GET / HTTP/1.1\r\n\r\n").await;
+
+        assert!(!headers.contains("Content-Encoding"));
+        let body = String::from_utf8(body).unwrap();
+        assert!(body.starts_with(&format!("<html><body><h1>Port {}</h1>", port)));
+        assert!(body.ends_with("</body></html>"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_status_response_gzipped_when_accepted() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let addr = spawn_handler(HandlerConfig::default()).await;
+        let request = "GET / HTTP/1.1\r\nAccept-Encoding: deflate, gzip\r\n\r\n";
+        let (headers, body, port) = exchange(addr, request).await;
+
+        assert!(headers.contains("Content-Encoding: gzip\r\n"));
+        assert!(headers.contains(&format!("Content-Length: {}\r\n", body.len())));
+        let mut decoded = String::new();
+        GzDecoder::new(body.as_slice())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert!(decoded.starts_with(&format!("<html><body><h1>Port {}</h1>", port)));
+        assert!(decoded.ends_with("</body></html>"));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn test_accepts_gzip_parsing() {
+        assert!(accepts_gzip(
+            "GET / HTTP/1.1\r\naccept-encoding: GZIP\r\n\r\n"
+        ));
+        assert!(accepts_gzip("GET / HTTP/1.1\r\nAccept-Encoding: *\r\n\r\n"));
+        assert!(!accepts_gzip(
+            "GET / HTTP/1.1\r\nAccept-Encoding: gzip;q=0\r\n\r\n"
+        ));
+        assert!(!accepts_gzip(
+            "GET / HTTP/1.1\r\nAccept-Encoding: br\r\n\r\n"
+        ));
+        assert!(!accepts_gzip("GET / HTTP/1.1\r\n\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_line_terminator_answers_each_line() {
+        let config = HandlerConfig {
+            line_terminator: Some(b"\n".to_vec()),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        // Both lines in one segment, plus a partial line that never completes
+        client.write_all(b"HELO a\nQUIT\npartial").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+
+        assert_eq!(reply, "ACK 6\nACK 4\n");
+    }
+
+    #[tokio::test]
+    async fn test_custom_terminator_sequence() {
+        let config = HandlerConfig {
+            line_terminator: Some(b"\r\n".to_vec()),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+
+        // Messages split across writes and containing a bare \n
+        client.write_all(b"one\ntwo\r").await.unwrap();
+        client.write_all(b"\nthree\r\n").await.unwrap();
+        client.shutdown().await.unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).await.unwrap();
+
+        assert_eq!(reply, "ACK 7\r\nACK 5\r\n");
+    }
+
+    #[tokio::test]
+    async fn test_throttle_rejects_requests_over_limit() {
+        let config = HandlerConfig {
+            throttle: Some(Arc::new(RequestThrottle::new(3, Duration::from_secs(10)))),
+            ..Default::default()
+        };
+        let addr = spawn_handler(config).await;
+
+        let mut statuses = Vec::new();
+        for _ in 0..6 {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            client.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+            let mut reply = String::new();
+            client.read_to_string(&mut reply).await.unwrap();
+            statuses.push(reply);
+        }
+
+        let ok = statuses
+            .iter()
+            .filter(|r| r.contains("HTTP/1.1 200 OK"))
+            .count();
+        let limited: Vec<_> = statuses
+            .iter()
+            .filter(|r| r.starts_with("HTTP/1.1 429"))
+            .collect();
+        assert_eq!(ok, 3);
+        assert_eq!(limited.len(), 3);
+        assert!(limited.iter().all(|r| r.contains("Retry-After: 10\r\n")));
+    }
+
+    #[test]
+    fn test_throttle_window_slides() {
+        let throttle = RequestThrottle::new(2, Duration::from_secs(1));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let start = Instant::now();
+
+        assert!(throttle.check(ip, start).is_ok());
+        assert!(throttle
+            .check(ip, start + Duration::from_millis(500))
+            .is_ok());
+        assert_eq!(
+            throttle.check(ip, start + Duration::from_millis(750)),
+            Err(Duration::from_millis(250))
+        );
+        // The first request has left the window
+        assert!(throttle.check(ip, start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn test_throttle_forgets_idle_clients() {
+        let throttle = RequestThrottle::new(2, Duration::from_secs(1));
+        let start = Instant::now();
+        for last in 1..=50_u8 {
+            let ip = IpAddr::from([192, 0, 2, last]);
+            assert!(throttle.check(ip, start).is_ok());
+        }
+        assert_eq!(throttle.requests.lock().unwrap().len(), 50);
+
+        let ip: IpAddr = "198.51.100.1".parse().unwrap();
+        assert!(throttle.check(ip, start + Duration::from_secs(2)).is_ok());
+        let requests = throttle.requests.lock().unwrap();
+        assert_eq!(requests.keys().collect::<Vec<_>>(), vec![&ip]);
+    }
+
+    #[cfg(feature = "pcap")]
+    #[tokio::test]
+    async fn test_handler_writes_pcap_capture() {
+        use crate::core::capture::count_records;
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = HandlerConfig {
+            capture_dir: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let server_addr = spawn_handler(config).await;
+
+        // Read the probe, answer it, then drain the response
+        let mut client = TcpStream::connect(server_addr).await.unwrap();
+        let mut buf = vec![0_u8; 1024];
+        let _ = client.read(&mut buf).await.unwrap();
+        client.write_all(b"hello").await.unwrap();
+        let _ = client.read_to_end(&mut buf).await;
+
+        let captures: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(captures.len(), 1);
+        let data = std::fs::read(captures[0].as_ref().unwrap().path()).unwrap();
+        assert_eq!(count_records(&data).unwrap(), 3);
+    }
 }
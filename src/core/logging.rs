@@ -0,0 +1,78 @@
+// Leveled logging shared by modules that need verbosity-controlled output
+
+use crate::core::LogLevel;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// Writes messages at or above a minimum `LogLevel` to stderr
+/// A capturing logger collects lines in memory instead, so tests can inspect them
+/// Clones share the same capture buffer
+#[derive(Debug, Clone)]
+pub struct Logger {
+    level: LogLevel,
+    captured: Option<Arc<Mutex<Vec<String>>>>,
+}
+
+impl Logger {
+    pub fn new(level: LogLevel) -> Self {
+        Self {
+            level,
+            captured: None,
+        }
+    }
+
+    /// Logger that records lines in memory rather than printing them
+    pub fn capturing(level: LogLevel) -> Self {
+        Self {
+            level,
+            captured: Some(Arc::default()),
+        }
+    }
+
+    /// Whether messages at `level` are emitted
+    pub fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    pub fn log(&self, level: LogLevel, args: fmt::Arguments<'_>) {
+        if !self.enabled(level) {
+            return;
+        }
+        let line = format!("[{:?}] {}", level, args);
+        match &self.captured {
+            Some(lines) => lines.lock().unwrap().push(line),
+            None => eprintln!("{}", line),
+        }
+    }
+
+    pub fn debug(&self, args: fmt::Arguments<'_>) {
+        self.log(LogLevel::Debug, args);
+    }
+
+    /// Lines recorded by a capturing logger (always empty otherwise)
+    pub fn captured(&self) -> Vec<String> {
+        self.captured
+            .as_ref()
+            .map(|lines| lines.lock().unwrap().clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self::new(LogLevel::Info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_level_filtering() {
+        let logger = Logger::capturing(LogLevel::Info);
+        logger.debug(format_args!("hidden"));
+        logger.log(LogLevel::Warning, format_args!("shown {}", 1));
+        assert_eq!(logger.captured(), vec!["[Warning] shown 1".to_string()]);
+    }
+}
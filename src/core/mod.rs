@@ -1,28 +1,45 @@
+#[cfg(feature = "pcap")]
+pub mod capture;
+pub mod client_hints;
+pub mod conn_log;
+pub mod connections;
 pub mod discovery;
 pub mod error;
+pub mod fingerprint;
 pub mod handlers;
+pub mod logging;
 pub mod network;
+pub mod signals;
 pub mod sockparse;
 pub mod state;
 pub mod types;
 pub mod ascii_cube;
 
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
 
 pub use ascii_cube::AsciiCube;
 pub use ascii_cube::display_rotating_cube;
 
 
+/// Where the dashboard listens unless configured otherwise
+pub const DEFAULT_WEB_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 3030);
+
 // Core configuration settings
 #[derive(Debug)]
 pub struct CoreConfig {
     pub max_workers: usize,
-    pub web_port: u16,
+    pub web_addr: SocketAddr, // Where the dashboard listens (`DEFAULT_WEB_ADDR` by default)
     pub log_level: LogLevel,
+    pub output_dir: Option<PathBuf>, // Where the shutdown summary JSON is written (disabled when None)
+    pub web_token: Option<String>,   // Bearer token the dashboard's data endpoints require (open when None)
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     Debug,
     Info,
@@ -40,6 +57,12 @@ pub struct IPCowCore {
     pub discovery_manager: Arc<Mutex<discovery::ServiceDiscovery>>,
     pub error_manager: Arc<Mutex<error::ErrorRegistry>>,
 
+    // Connection counters shared with listeners and the web server
+    pub server_state: state::ServerState,
+
+    // Cancelled by `shutdown` to stop the listeners
+    shutdown: CancellationToken,
+
     // Configuration
     pub config: CoreConfig,
 }
@@ -49,47 +72,153 @@ impl IPCowCore {
     pub fn new() -> Self {
         Self::with_config(CoreConfig {
             max_workers: 4,
-            web_port: 3030,
+            web_addr: DEFAULT_WEB_ADDR,
             log_level: LogLevel::Info,
+            output_dir: None,
+            web_token: None,
         })
     }
 
     // Constructor with custom configuration
     pub fn with_config(config: CoreConfig) -> Self {
+        let server_state = state::ServerState::new();
+        let error_manager = Arc::new(Mutex::new(error::ErrorRegistry::new()));
+        let shutdown = CancellationToken::new();
+        let state = Arc::new(Mutex::new(state::CoreState::new()));
         Self {
-            state: Arc::new(Mutex::new(state::CoreState::new())),
-            network_manager: Arc::new(Mutex::new(network::ListenerManager::new(
-                vec![],
-                config.max_workers,
-            ))),
+            state: state.clone(),
+            network_manager: Arc::new(Mutex::new(
+                network::ListenerManager::new(vec![], config.max_workers)
+                    .with_server_state(server_state.clone())
+                    .with_core_state(state)
+                    .with_error_registry(error_manager.clone())
+                    .with_shutdown(shutdown.clone()),
+            )),
             discovery_manager: Arc::new(Mutex::new(discovery::ServiceDiscovery::new())),
-            error_manager: Arc::new(Mutex::new(error::ErrorRegistry::new())),
+            error_manager,
+            server_state,
+            shutdown,
             config,
         }
     }
 
+    // Token that stops the listeners; pass it to any replacement `ListenerManager`
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    // Open connections with their age and bytes transferred, sorted by address
+    pub async fn list_connections(&self) -> Vec<connections::ConnectionInfo> {
+        self.state.lock().await.connections()
+    }
+
+    // Force-closes the connection from `addr`; false when no such connection is open
+    pub async fn kill_connection(&self, addr: SocketAddr) -> bool {
+        self.state.lock().await.kill_connection(addr)
+    }
+
+    // Connections without traffic for `timeout` get closed; `None` keeps them open
+    pub async fn set_idle_timeout(&self, timeout: Option<Duration>) {
+        self.state.lock().await.idle_timeout = timeout;
+    }
+
     // Core lifecycle methods
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
         println!("[Core] Starting IPCow core services...");
 
-        // Start network manager
+        self.state.lock().await.is_running = true;
+
+        // Start network manager; returns once `shutdown` stops the listeners
         let network = self.network_manager.lock().await;
-        network.run().await?;
+        let result = network.run().await.map_err(|e| e.to_string());
 
-        // Set running state
-        let mut state = self.state.lock().await;
-        state.is_running = true;
+        self.state.lock().await.is_running = false;
+        Ok(result?)
+    }
+
+    // Runs `start` until a shutdown signal from `signals` arrives or its deadline
+    // passes, then shuts down gracefully. SIGHUP reopens the log files without
+    // interrupting the listeners. A second shutdown signal while connections drain
+    // abandons them and returns `signals::ForcedShutdown`
+    pub async fn run_until_signal(
+        &self,
+        signals: signals::SignalSet,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut signals = signals.listen()?;
+        let server = self.start();
+        tokio::pin!(server);
+        loop {
+            tokio::select! {
+                result = &mut server => return result,
+                signal = signals.recv() => match signal {
+                    signals::ServerSignal::Shutdown(name) => {
+                        println!(
+                            "\n[Core] {} received, stopping listeners (send it again to force exit)...",
+                            name
+                        );
+                        break;
+                    }
+                    signals::ServerSignal::Deadline => {
+                        println!("\n[Core] Maximum runtime reached, stopping listeners...");
+                        break;
+                    }
+                    signals::ServerSignal::ReopenLogs => self.reopen_logs(),
+                },
+            }
+        }
 
-        Ok(())
+        // Shutdown waits for the listeners held by `server`, so both are driven together
+        let drained = async { tokio::join!(server, self.shutdown()) };
+        tokio::pin!(drained);
+        loop {
+            tokio::select! {
+                (served, summary) = &mut drained => {
+                    served?;
+                    summary?;
+                    return Ok(());
+                }
+                signal = signals.recv() => match signal {
+                    signals::ServerSignal::Shutdown(name) => {
+                        let forced = signals::ForcedShutdown(name);
+                        eprintln!("[Core] {}", forced);
+                        return Err(forced.into());
+                    }
+                    signals::ServerSignal::ReopenLogs => self.reopen_logs(),
+                    // Already shutting down
+                    signals::ServerSignal::Deadline => {}
+                },
+            }
+        }
+    }
+
+    // Log files are opened per entry, so once logrotate has moved them the next
+    // entry starts a fresh file; this only acknowledges the request
+    pub fn reopen_logs(&self) {
+        println!("[Core] SIGHUP received, log files will be reopened on next write");
     }
 
-    pub async fn shutdown(&self) -> Result<(), Box<dyn std::error::Error>> {
+    // Stops the listeners, waits for in-flight connections to drain and reports
+    // what they handled. When `start` is running, poll both together (e.g. `join!`)
+    pub async fn shutdown(&self) -> Result<state::ShutdownSummary, Box<dyn std::error::Error>> {
         println!("[Core] Shutting down IPCow core services...");
 
+        // `start` holds the manager while running, so getting it means it has drained
+        self.shutdown.cancel();
+        drop(self.network_manager.lock().await);
+
+        let error_count = self.error_manager.lock().await.error_count();
+        let traffic = self.state.lock().await.throughput.clone();
+        let summary = self.server_state.summary(error_count, &traffic);
+        println!("{}", summary);
+        if let Some(dir) = &self.config.output_dir {
+            let path = summary.write_json(dir)?;
+            println!("[Core] Summary written to {}", path.display());
+        }
+
         let mut state = self.state.lock().await;
         state.is_running = false;
 
-        Ok(())
+        Ok(summary)
     }
 }
 
@@ -100,3 +229,72 @@ pub use handlers::handle_connection;
 pub use network::ListenerManager;
 pub use sockparse::addr_input;
 pub use types::{AddrData, AddrType};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn test_shutdown_summary_counts_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery =
+            discovery::ServiceDiscovery::new().with_log_file(dir.path().join("services.txt"));
+        let core = Arc::new(IPCowCore::new());
+        {
+            let listener = AddrData {
+                info: AddrType::IPv4,
+                socket_type: AddrType::TCP,
+                address: IpAddr::from([127, 0, 0, 1]),
+                port: 0,
+            };
+            let mut manager = core.network_manager.lock().await;
+            *manager = ListenerManager::new(vec![listener], 1)
+                .with_server_state(core.server_state.clone())
+                .with_core_state(core.state.clone())
+                .with_error_registry(core.error_manager.clone())
+                .with_service_discovery(Arc::new(discovery))
+                .with_shutdown(core.shutdown_token());
+        }
+        let running = core.clone();
+        let server = tokio::spawn(async move { running.start().await.map_err(|e| e.to_string()) });
+
+        // Wait for the ephemeral listener to come up
+        let addr: SocketAddr = loop {
+            if let Some(addr) = core.server_state.bound_addrs().first() {
+                break *addr;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+
+        for _ in 0..3 {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let mut buf = vec![0_u8; 1024];
+            let _ = client.read(&mut buf).await.unwrap();
+            client.write_all(b"hello").await.unwrap();
+            let _ = client.read_to_end(&mut buf).await;
+        }
+        // Let the handler tasks finish recording their byte counts
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let summary = core.shutdown().await.unwrap();
+        // Shutdown stopped the listener, so `start` has returned cleanly
+        let started = tokio::time::timeout(Duration::from_secs(1), server)
+            .await
+            .expect("start should return after shutdown");
+        assert!(started.unwrap().is_ok());
+        assert!(!core.state.lock().await.is_running);
+
+        assert_eq!(summary.total_connections, 3);
+        assert_eq!(summary.top_sources, vec![(addr.ip(), 3)]);
+        assert_eq!(summary.ports_bound, vec![addr.port()]);
+        assert!(summary.bytes_transferred > 0);
+        assert_eq!(
+            summary.bytes_transferred,
+            core.state.lock().await.throughput.total()
+        );
+        assert_eq!(summary.error_count, 0);
+    }
+}
@@ -1,15 +1,78 @@
 // Network management module handling TCP listener initialization and connection handling
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::time::Duration;
+use tokio::net::{TcpListener, UdpSocket};
 use tokio::sync::{Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
 
 use crate::core::{
+    client_hints::ClientHints,
+    conn_log::{ConnectionLogger, LogSampling},
+    connections::MeteredStream,
     discovery::ServiceDiscovery,
-    error::ErrorRegistry,
-    handlers::handle_connection,
-    types::{socket_addr_create, AddrData},
+    error::{classify_bind_error, ErrorRegistry, ErrorSeverity},
+    handlers::{handle_stream, handle_udp_datagram, recv_datagram, HandlerConfig, UDP_MAX_PAYLOAD},
+    state::{CoreState, ServerState, ShutdownSummary},
+    types::{socket_addr_create, AddrData, AddrType, ConnectionState, NetworkConfig},
 };
 
+/// How long `run` waits for in-flight connections after shutdown before returning
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often `run` checks open connections against `CoreState::idle_timeout`
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Socket options applied to every listener before it binds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ListenerConfig {
+    /// SO_REUSEADDR, so a restarted server can rebind ports still in TIME_WAIT
+    pub reuse_addr: bool,
+    /// SO_REUSEPORT, letting several sockets share a port (Unix only, ignored elsewhere)
+    pub reuse_port: bool,
+    /// Pending-connection queue length passed to `listen`
+    pub backlog: u32,
+}
+
+impl Default for ListenerConfig {
+    fn default() -> Self {
+        Self {
+            reuse_addr: true,
+            reuse_port: false,
+            backlog: 1024,
+        }
+    }
+}
+
+impl ListenerConfig {
+    /// Binds a TCP listener on `addr` with these options
+    /// Must be called from within a tokio runtime
+    pub fn bind_tcp(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = self.socket(addr, Type::STREAM, Protocol::TCP)?;
+        socket.listen(self.backlog.min(i32::MAX as u32) as i32)?;
+        TcpListener::from_std(socket.into())
+    }
+
+    /// Binds a UDP socket on `addr` with these options
+    /// Must be called from within a tokio runtime
+    pub fn bind_udp(&self, addr: SocketAddr) -> io::Result<UdpSocket> {
+        let socket = self.socket(addr, Type::DGRAM, Protocol::UDP)?;
+        UdpSocket::from_std(socket.into())
+    }
+
+    // Non-blocking socket of the right family with the options set, bound to `addr`
+    fn socket(&self, addr: SocketAddr, ty: Type, protocol: Protocol) -> io::Result<Socket> {
+        let socket = Socket::new(Domain::for_address(addr), ty, Some(protocol))?;
+        socket.set_reuse_address(self.reuse_addr)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(self.reuse_port)?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&addr.into())?;
+        Ok(socket)
+    }
+}
+
 /// Main struct responsible for managing multiple TCP listeners
 /// Handles concurrent connections and service discovery across multiple ports
 pub struct ListenerManager {
@@ -17,10 +80,29 @@ pub struct ListenerManager {
     error_registry: Arc<Mutex<ErrorRegistry>>,
     // Vector of IP/Port combinations to listen on
     addr_data: Arc<Vec<AddrData>>,
-    // Maximum number of concurrent connections allowed
+    // Maximum number of client connections handled at once, across all listeners
     max_concurrent: usize,
     // Service detection and tracking system
     service_discovery: Arc<ServiceDiscovery>,
+    // Options shared by every connection handler spawned from this manager
+    handler_config: Arc<HandlerConfig>,
+    // Connection counters, shareable with the web server
+    server_state: ServerState,
+    // Per-connection log lines, optionally sampled
+    connection_logger: Arc<ConnectionLogger>,
+    // Per-listener accept limit; the listener closes once reached (unlimited when `None`)
+    max_accepts: Option<usize>,
+    // Cancelled to stop every accept loop and make `run` return
+    shutdown: CancellationToken,
+    // Pause after each accept before the socket reaches its handler
+    accept_delay: Duration,
+    // Socket options applied before each listener binds
+    listener_config: ListenerConfig,
+    // Per-connection states, e.g. the core's shared `state`
+    core_state: Arc<Mutex<CoreState>>,
+    // Bind retry policy: `retry_attempts` retries spaced by `backoff`, within `timeout`;
+    // `timeout` also bounds each client read/write unless the handler config sets one
+    network_config: NetworkConfig,
 }
 
 impl ListenerManager {
@@ -32,64 +114,444 @@ impl ListenerManager {
             addr_data: Arc::new(addr_data),
             max_concurrent,
             service_discovery: Arc::new(ServiceDiscovery::new()),
+            handler_config: Arc::new(HandlerConfig::default()),
+            server_state: ServerState::new(),
+            connection_logger: Arc::new(ConnectionLogger::default()),
+            max_accepts: None,
+            shutdown: CancellationToken::new(),
+            accept_delay: Duration::ZERO,
+            listener_config: ListenerConfig::default(),
+            network_config: NetworkConfig::default(),
+            core_state: Arc::new(Mutex::new(CoreState::new())),
         }
     }
 
+    /// Replaces the connection handler options used by every listener
+    pub fn with_handler_config(mut self, config: HandlerConfig) -> Self {
+        self.handler_config = Arc::new(config);
+        self
+    }
+
+    /// Uses `state` for connection counters, e.g. one also handed to the web server
+    pub fn with_server_state(mut self, state: ServerState) -> Self {
+        self.server_state = state;
+        self
+    }
+
+    /// Records services in `discovery`, e.g. one writing to a custom log file
+    pub fn with_service_discovery(mut self, discovery: Arc<ServiceDiscovery>) -> Self {
+        self.service_discovery = discovery;
+        self
+    }
+
+    /// Tracks each accepted connection in `state` (e.g. `IPCowCore::state`) while it
+    /// is open, then moves it to the recently closed ones
+    pub fn with_core_state(mut self, state: Arc<Mutex<CoreState>>) -> Self {
+        self.core_state = state;
+        self
+    }
+
+    /// Registers errors in a shared registry instead of a private one
+    pub fn with_error_registry(mut self, registry: Arc<Mutex<ErrorRegistry>>) -> Self {
+        self.error_registry = registry;
+        self
+    }
+
+    /// Connection report built from the shared counters and error registry
+    pub async fn summary(&self) -> ShutdownSummary {
+        let error_count = self.error_registry.lock().await.error_count();
+        let traffic = self.core_state.lock().await.throughput.clone();
+        self.server_state.summary(error_count, &traffic)
+    }
+
+    /// Limits how many accepted connections are logged; all are still counted
+    pub fn with_log_sampling(mut self, sampling: LogSampling) -> Self {
+        self.connection_logger = Arc::new(ConnectionLogger::new(sampling));
+        self
+    }
+
+    /// Handles at most `max` client connections at once across all listeners
+    /// A listener waiting for its next client holds one of the slots, so keep `max`
+    /// above the number of listeners
+    pub fn with_max_concurrent(mut self, max: usize) -> Self {
+        self.max_concurrent = max;
+        self
+    }
+
+    /// Stops each listener after it has accepted `max` connections
+    /// The listening socket is closed, so later connects are refused, while
+    /// already accepted connections keep being served
+    pub fn with_max_accepts(mut self, max: usize) -> Self {
+        self.max_accepts = Some(max);
+        self
+    }
+
+    /// Waits `delay` after each accept before handing the socket to its handler
+    /// The accept loop is held up too, so later clients queue in the SYN backlog,
+    /// simulating a saturated server for connect-timeout testing
+    pub fn with_accept_delay(mut self, delay: Duration) -> Self {
+        self.accept_delay = delay;
+        self
+    }
+
+    /// Replaces the socket options (SO_REUSEADDR, SO_REUSEPORT, backlog) used when binding
+    pub fn with_listener_config(mut self, config: ListenerConfig) -> Self {
+        self.listener_config = config;
+        self
+    }
+
+    /// Retries failed binds up to `retry_attempts` times with exponential backoff
+    /// starting at `retry_backoff`, giving up early once `timeout` would be exceeded
+    pub fn with_network_config(mut self, config: NetworkConfig) -> Self {
+        self.network_config = config;
+        self
+    }
+
+    /// Disconnects clients that stall a read or write for longer than `timeout`,
+    /// registering a warning for each; also caps the bind retry budget
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.network_config.timeout = timeout;
+        self
+    }
+
+    /// Stops the listeners when `token` is cancelled, e.g. a token shared with `IPCowCore`
+    pub fn with_shutdown(mut self, token: CancellationToken) -> Self {
+        self.shutdown = token;
+        self
+    }
+
+    /// Signals every accept loop to stop; `run` returns once connections drain
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Connection counters updated by the accept loops
+    pub fn server_state(&self) -> &ServerState {
+        &self.server_state
+    }
+
+    /// Addresses the listeners have bound so far, in bind order
+    /// Port-0 targets report the port the OS actually assigned
+    pub fn bound_addrs(&self) -> Vec<SocketAddr> {
+        self.server_state.bound_addrs()
+    }
+
     /// Main entry point for starting TCP listeners
     /// Spawns async tasks for each address/port combination
+    /// Runs until every listener stops; after `shutdown`, in-flight connections
+    /// get up to `DRAIN_TIMEOUT` to finish before this returns
     pub async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
         // Track spawned listener tasks
         let mut listener_tasks = Vec::new();
-        // Limit concurrent connections
-        let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
+        // One permit per client connection being handled, shared by every listener
+        let permits = self.max_concurrent.max(1);
+        let semaphore = Arc::new(Semaphore::new(permits));
+        // Handlers without their own I/O timeout use the manager's
+        let handler_config = match self.handler_config.io_timeout {
+            Some(_) => self.handler_config.clone(),
+            None => Arc::new(HandlerConfig {
+                io_timeout: Some(self.network_config.timeout),
+                ..HandlerConfig::clone(&self.handler_config)
+            }),
+        };
+
+        // Closes connections idle past the core state's timeout until shutdown
+        tokio::spawn(reap_idle_connections(
+            self.core_state.clone(),
+            self.shutdown.clone(),
+        ));
 
         // Iterate through each address/port combination
         for addr_data in self.addr_data.iter() {
-            // Acquire permission to create new listener
-            let permit = semaphore.clone().acquire_owned().await?;
+            if addr_data.socket_type == AddrType::UDP {
+                listener_tasks.push(self.spawn_udp_listener(addr_data));
+                continue;
+            }
+            let semaphore = semaphore.clone();
             let error_registry = self.error_registry.clone();
             let discovery = self.service_discovery.clone();
+            let core_state = self.core_state.clone();
+            let handler_config = handler_config.clone();
+            let server_state = self.server_state.clone();
+            let connection_logger = self.connection_logger.clone();
+            let max_accepts = self.max_accepts;
+            let shutdown = self.shutdown.clone();
+            let accept_delay = self.accept_delay;
+            let listener_config = self.listener_config;
+            let network_config = self.network_config.clone();
             let socket_addr = socket_addr_create(addr_data.address, addr_data.port);
 
             // Spawn individual listener task
             let task = tokio::spawn(async move {
-                match TcpListener::bind(&socket_addr).await {
+                let bound = bind_with_retry(&network_config, &shutdown, || {
+                    listener_config.bind_tcp(socket_addr)
+                })
+                .await;
+                match bound {
                     Ok(listener) => {
                         println!("Listening on: {}", socket_addr);
+                        let local_addr = listener.local_addr().unwrap_or(socket_addr);
+                        server_state.listener_bound(local_addr);
                         // Accept loop for handling incoming connections
-                        loop {
-                            let accept_result = listener.accept().await;
+                        let mut accepted = 0;
+                        while max_accepts.is_none_or(|max| accepted < max) {
+                            // Take a slot before accepting, so once every slot is in use
+                            // new clients wait in the backlog instead of holding a socket
+                            let permit = tokio::select! {
+                                _ = shutdown.cancelled() => break,
+                                permit = semaphore.clone().acquire_owned() => match permit {
+                                    Ok(permit) => permit,
+                                    Err(_) => break,
+                                },
+                            };
+                            let accept_result = tokio::select! {
+                                _ = shutdown.cancelled() => break,
+                                result = listener.accept() => result,
+                            };
                             match accept_result {
                                 Ok((socket, addr)) => {
+                                    accepted += 1;
+                                    if !accept_delay.is_zero() {
+                                        tokio::select! {
+                                            _ = shutdown.cancelled() => break,
+                                            _ = tokio::time::sleep(accept_delay) => {}
+                                        }
+                                    }
                                     // Spawn task for each accepted connection
                                     let discovery = discovery.clone();
+                                    let handler_config = handler_config.clone();
+                                    let error_registry = error_registry.clone();
+                                    let server_state = server_state.clone();
+                                    let connection_logger = connection_logger.clone();
+                                    let core_state = core_state.clone();
                                     tokio::spawn(async move {
-                                        handle_connection(socket, addr, discovery).await;
+                                        let _permit = permit;
+                                        let _connection = server_state
+                                            .connection_opened(addr.ip(), local_addr.port());
+                                        let hints = ClientHints::collect(&socket);
+                                        connection_logger.log_accept(
+                                            addr,
+                                            socket_addr,
+                                            Some(&hints),
+                                        );
+                                        let handle = {
+                                            let mut state = core_state.lock().await;
+                                            let handle = state.new_handle();
+                                            state.open_connection(addr, handle.clone());
+                                            handle
+                                        };
+                                        let local = socket.local_addr().ok();
+                                        let socket =
+                                            MeteredStream::new(socket, handle.meter.clone());
+                                        // Kills and idle reaping drop the handler mid-exchange
+                                        let stats = tokio::select! {
+                                            stats = handle_stream(
+                                                socket,
+                                                local,
+                                                addr,
+                                                discovery,
+                                                &handler_config,
+                                            ) => Some(stats),
+                                            _ = handle.closed() => None,
+                                        };
+                                        let outcome = if let Some(reason) = handle.close_reason() {
+                                            println!("Closed connection from {}: {}", addr, reason);
+                                            ConnectionState::Closed(reason.to_string())
+                                        } else if stats.is_some_and(|stats| stats.timed_out) {
+                                            // One registry entry for every timed-out client;
+                                            // only the log line names the address
+                                            let message = format!(
+                                                "client I/O timed out after {:?}",
+                                                handler_config.io_timeout()
+                                            );
+                                            let mut registry = error_registry.lock().await;
+                                            let error_id = registry
+                                                .register_error(ErrorSeverity::Warning, &message);
+                                            eprintln!(
+                                                "Client {}: {}: ID {}",
+                                                addr, message, error_id
+                                            );
+                                            ConnectionState::Error(message)
+                                        } else {
+                                            ConnectionState::Disconnected
+                                        };
+                                        core_state.lock().await.close_connection(addr, outcome);
                                     });
                                 }
                                 Err(e) => {
-                                    // Log accept errors with unique ID
+                                    // Log accept errors with unique ID; timeouts are transient
+                                    let severity = match e.kind() {
+                                        io::ErrorKind::TimedOut => ErrorSeverity::Warning,
+                                        _ => ErrorSeverity::Error,
+                                    };
                                     let mut registry = error_registry.lock().await;
-                                    let error_id = registry.register_error(&e.to_string());
+                                    let error_id =
+                                        registry.register_error(severity, &e.to_string());
                                     eprintln!("Accept error on {}: ID {}", socket_addr, error_id);
                                 }
                             }
                         }
+                        server_state.listener_stopped();
                     }
                     Err(e) => {
+                        server_state.listener_failed();
                         // Log bind errors with unique ID
+                        let (category, message) = classify_bind_error(socket_addr, &e);
                         let mut registry = error_registry.lock().await;
-                        let error_id = registry.register_error(&e.to_string());
-                        eprintln!("Bind error on {}: ID {}: {}", socket_addr, error_id, e);
+                        let error_id = registry.register_categorized(
+                            ErrorSeverity::Critical,
+                            category,
+                            &message,
+                        );
+                        eprintln!(
+                            "Bind error on {}: ID {}: {}",
+                            socket_addr, error_id, message
+                        );
                     }
                 }
-                drop(permit);
             });
 
             listener_tasks.push(task);
         }
 
         futures::future::join_all(listener_tasks).await;
+        if !self.shutdown.is_cancelled() {
+            // Listeners stopped on their own (e.g. `max_accepts`); connections keep running
+            return Ok(());
+        }
+
+        // Every connection task holds a permit, so getting all of them back means drained
+        let in_flight = permits - semaphore.available_permits();
+        if in_flight > 0 {
+            println!("Draining {} in-flight connections...", in_flight);
+        }
+        if tokio::time::timeout(DRAIN_TIMEOUT, semaphore.acquire_many(permits as u32))
+            .await
+            .is_err()
+        {
+            eprintln!(
+                "Gave up waiting for {} connections after {:?}",
+                permits - semaphore.available_permits(),
+                DRAIN_TIMEOUT
+            );
+        }
         Ok(())
     }
+
+    // Binds one UDP socket and answers datagrams until shutdown
+    // Each datagram counts as one connection from its sender
+    fn spawn_udp_listener(&self, addr_data: &AddrData) -> tokio::task::JoinHandle<()> {
+        let error_registry = self.error_registry.clone();
+        let discovery = self.service_discovery.clone();
+        let handler_config = self.handler_config.clone();
+        let server_state = self.server_state.clone();
+        let core_state = self.core_state.clone();
+        let connection_logger = self.connection_logger.clone();
+        let shutdown = self.shutdown.clone();
+        let listener_config = self.listener_config;
+        let network_config = self.network_config.clone();
+        let socket_addr = socket_addr_create(addr_data.address, addr_data.port);
+        let max_payload = handler_config.udp_max_payload.unwrap_or(UDP_MAX_PAYLOAD);
+
+        tokio::spawn(async move {
+            let bound = bind_with_retry(&network_config, &shutdown, || {
+                listener_config.bind_udp(socket_addr)
+            })
+            .await;
+            let socket = match bound {
+                Ok(socket) => socket,
+                Err(e) => {
+                    server_state.listener_failed();
+                    let (category, message) = classify_bind_error(socket_addr, &e);
+                    let mut registry = error_registry.lock().await;
+                    let error_id =
+                        registry.register_categorized(ErrorSeverity::Critical, category, &message);
+                    eprintln!(
+                        "Bind error on {}/udp: ID {}: {}",
+                        socket_addr, error_id, message
+                    );
+                    return;
+                }
+            };
+            println!("Listening on: {}/udp", socket_addr);
+            let local_addr = socket.local_addr().unwrap_or(socket_addr);
+            server_state.listener_bound(local_addr);
+            // Datagrams bypass the connection meters, so count them here
+            let traffic = core_state.lock().await.throughput.clone();
+
+            loop {
+                let received = tokio::select! {
+                    _ = shutdown.cancelled() => break,
+                    received = recv_datagram(&socket, max_payload) => received,
+                };
+                match received {
+                    Ok(datagram) => {
+                        let _connection =
+                            server_state.connection_opened(datagram.peer.ip(), local_addr.port());
+                        connection_logger.log_accept(datagram.peer, socket_addr, None);
+                        let stats = handle_udp_datagram(
+                            &socket,
+                            datagram,
+                            discovery.clone(),
+                            &handler_config,
+                        )
+                        .await;
+                        traffic.record_rx(stats.bytes_read as usize);
+                        traffic.record_tx(stats.bytes_written as usize);
+                    }
+                    Err(e) => {
+                        // ICMP errors from earlier replies surface here; keep serving
+                        let mut registry = error_registry.lock().await;
+                        let error_id =
+                            registry.register_error(ErrorSeverity::Warning, &e.to_string());
+                        eprintln!("Receive error on {}/udp: ID {}", socket_addr, error_id);
+                    }
+                }
+            }
+            server_state.listener_stopped();
+        })
+    }
+}
+
+// Every `IDLE_CHECK_INTERVAL`, closes the connections idle past `idle_timeout`;
+// their tasks record them as closed
+async fn reap_idle_connections(core_state: Arc<Mutex<CoreState>>, shutdown: CancellationToken) {
+    let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+        core_state.lock().await.reap_idle();
+    }
+}
+
+// Calls `bind` until it succeeds, retrying per `config` with exponential backoff
+// Returns the last error once retries run out, the next wait would pass
+// `config.timeout`, or shutdown is requested; permission errors are never retried
+async fn bind_with_retry<T>(
+    config: &NetworkConfig,
+    shutdown: &CancellationToken,
+    mut bind: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let start = tokio::time::Instant::now();
+    let mut attempt = 0;
+    loop {
+        let err = match bind() {
+            Ok(bound) => return Ok(bound),
+            Err(e) => e,
+        };
+        let wait = config.backoff(attempt);
+        if err.kind() == io::ErrorKind::PermissionDenied
+            || attempt >= config.retry_attempts
+            || start.elapsed() + wait > config.timeout
+        {
+            return Err(err);
+        }
+        tokio::select! {
+            _ = shutdown.cancelled() => return Err(err),
+            _ = tokio::time::sleep(wait) => {}
+        }
+        attempt += 1;
+    }
 }
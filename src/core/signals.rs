@@ -0,0 +1,133 @@
+// Process signal handling: graceful shutdown on SIGINT/SIGTERM, log reopen on SIGHUP
+
+use std::fmt;
+use std::future::pending;
+use std::io;
+use std::time::Instant;
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+
+#[cfg(unix)]
+type PlatformSignal = tokio::signal::unix::Signal;
+// SIGTERM and SIGHUP don't exist elsewhere, so these listeners are never created
+#[cfg(not(unix))]
+type PlatformSignal = std::convert::Infallible;
+
+/// What a received signal asks the server to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerSignal {
+    /// Stop the listeners and drain connections; carries the signal name for logging
+    Shutdown(&'static str),
+    /// Reopen log files, e.g. after logrotate moved them
+    ReopenLogs,
+    /// The `SignalSet::deadline` passed; stop like on `Shutdown`
+    Deadline,
+}
+
+/// Signals the server reacts to (all enabled by default)
+/// `terminate` and `hangup` only take effect on Unix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignalSet {
+    pub interrupt: bool,           // Ctrl+C / SIGINT shuts down
+    pub terminate: bool,           // SIGTERM (systemd, docker stop) shuts down
+    pub hangup: bool,              // SIGHUP reopens log files
+    pub deadline: Option<Instant>, // Shuts down once reached, e.g. at the maximum runtime
+}
+
+impl Default for SignalSet {
+    fn default() -> Self {
+        Self {
+            interrupt: true,
+            terminate: true,
+            hangup: true,
+            deadline: None,
+        }
+    }
+}
+
+impl SignalSet {
+    /// Installs handlers for the enabled signals
+    /// From here on those signals no longer kill the process, so keep the listener alive
+    /// for as long as they should be handled
+    pub fn listen(&self) -> io::Result<SignalListener> {
+        #[cfg(unix)]
+        let (terminate, hangup) = (
+            self.terminate
+                .then(|| signal(SignalKind::terminate()))
+                .transpose()?,
+            self.hangup
+                .then(|| signal(SignalKind::hangup()))
+                .transpose()?,
+        );
+        #[cfg(not(unix))]
+        let (terminate, hangup) = (None, None);
+
+        Ok(SignalListener {
+            interrupt: self.interrupt,
+            terminate,
+            hangup,
+            deadline: self.deadline,
+        })
+    }
+}
+
+/// A shutdown signal arrived again while connections were draining; the caller
+/// should exit at once (conventionally with status 130)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForcedShutdown(pub &'static str); // Name of the repeated signal
+
+impl fmt::Display for ForcedShutdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} received again, forcing exit", self.0)
+    }
+}
+
+impl std::error::Error for ForcedShutdown {}
+
+/// Installed signal handlers, created by `SignalSet::listen`
+#[derive(Debug)]
+pub struct SignalListener {
+    interrupt: bool,
+    terminate: Option<PlatformSignal>,
+    hangup: Option<PlatformSignal>,
+    deadline: Option<Instant>,
+}
+
+impl SignalListener {
+    /// Waits for the next enabled signal or the deadline; never returns when the set is empty
+    pub async fn recv(&mut self) -> ServerSignal {
+        tokio::select! {
+            _ = interrupt(self.interrupt) => ServerSignal::Shutdown("SIGINT"),
+            _ = wait(&mut self.terminate) => ServerSignal::Shutdown("SIGTERM"),
+            _ = wait(&mut self.hangup) => ServerSignal::ReopenLogs,
+            _ = until(self.deadline) => ServerSignal::Deadline,
+        }
+    }
+}
+
+async fn interrupt(enabled: bool) {
+    if enabled && tokio::signal::ctrl_c().await.is_ok() {
+        return;
+    }
+    pending().await
+}
+
+async fn until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => pending().await,
+    }
+}
+
+async fn wait(signal: &mut Option<PlatformSignal>) {
+    #[cfg(unix)]
+    if let Some(signal) = signal {
+        if signal.recv().await.is_some() {
+            return;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = signal;
+    pending().await
+}
@@ -17,9 +17,302 @@
  *********************************************************
  */
 
-use ipnetwork::Ipv4Network;
-use std::io;
-use std::net::Ipv4Addr;
+use crate::core::types::{AddrData, AddrType};
+use ipnetwork::{IpNetwork, Ipv4Network};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+
+/// Largest number of addresses a single spec may expand to without an override
+/// Large enough for an IPv4 /8; larger IPv6 prefixes like /64 are only iterated lazily
+/// or sampled
+pub const MAX_EXPANSION: u128 = 1 << 24;
+
+/// Addresses `parse_ip_input` takes, evenly spaced, from an IPv6 block larger than
+/// `MAX_EXPANSION` (e.g. a /64) instead of rejecting it
+pub const IPV6_SAMPLE_SIZE: u128 = 256;
+
+/// Nmap's default top-100 TCP ports, in ascending order
+const TOP_100_PORTS: [u16; 100] = [
+    7, 9, 13, 21, 22, 23, 25, 26, 37, 53, 79, 80, 81, 88, 106, 110, 111, 113, 119, 135, 139, 143,
+    144, 179, 199, 389, 427, 443, 444, 445, 465, 513, 514, 515, 543, 544, 548, 554, 587, 631, 646,
+    873, 990, 993, 995, 1025, 1026, 1027, 1028, 1029, 1110, 1433, 1720, 1723, 1755, 1900, 2000,
+    2001, 2049, 2121, 2717, 3000, 3128, 3306, 3389, 3986, 4899, 5000, 5009, 5051, 5060, 5101, 5190,
+    5357, 5432, 5631, 5666, 5800, 5900, 6000, 6001, 6646, 7070, 8000, 8008, 8009, 8080, 8081, 8443,
+    8888, 9100, 9999, 10000, 32768, 49152, 49153, 49154, 49155, 49156, 49157,
+];
+
+/// Listen IPs plus (port, protocol) pairs, as gathered by the `addr_spec_input*` readers
+pub type TargetSpecs = (Vec<IpAddr>, Vec<(u16, AddrType)>);
+
+/// Entries skipped by the lenient parsers, each as written alongside why it was rejected
+pub type Rejects = Vec<(String, ParseError)>;
+
+/// `Rejects` gathered from a file, each tagged with its 1-based line number
+pub type LineRejects = Vec<(usize, String, ParseError)>;
+
+/// Errors produced while parsing address specifications
+/// Every variant but `Empty` carries the offending substring (`token`) and, when it
+/// came from a comma-separated list, the zero-based position of its entry (`index`)
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    // Input held no specs at all
+    Empty,
+    // Malformed IP, range, CIDR or wildcard
+    InvalidAddress {
+        token: String,
+        index: Option<usize>,
+    },
+    // Range whose start comes after its end
+    ReversedRange {
+        token: String,
+        index: Option<usize>,
+    },
+    // Port that isn't a number in 0-65535
+    InvalidPort {
+        token: String,
+        index: Option<usize>,
+    },
+    // Port suffix other than /tcp or /udp
+    InvalidProtocol {
+        token: String,
+        index: Option<usize>,
+    },
+    // Spec covers more addresses than allowed
+    ExpansionTooLarge {
+        token: String,
+        index: Option<usize>,
+        count: u128,
+        max: u128,
+    },
+}
+
+impl ParseError {
+    /// Substring that failed to parse (`None` for `Empty`)
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            ParseError::Empty => None,
+            ParseError::InvalidAddress { token, .. }
+            | ParseError::ReversedRange { token, .. }
+            | ParseError::InvalidPort { token, .. }
+            | ParseError::InvalidProtocol { token, .. }
+            | ParseError::ExpansionTooLarge { token, .. } => Some(token),
+        }
+    }
+
+    /// Zero-based position of the offending entry in its list
+    /// (`None` for `Empty` and for errors from a single spec, e.g. `ip_iter`)
+    pub fn index(&self) -> Option<usize> {
+        match self {
+            ParseError::Empty => None,
+            ParseError::InvalidAddress { index, .. }
+            | ParseError::ReversedRange { index, .. }
+            | ParseError::InvalidPort { index, .. }
+            | ParseError::InvalidProtocol { index, .. }
+            | ParseError::ExpansionTooLarge { index, .. } => *index,
+        }
+    }
+
+    // Same error attributed to entry `at` of a list
+    fn at(mut self, at: usize) -> Self {
+        match &mut self {
+            ParseError::Empty => {}
+            ParseError::InvalidAddress { index, .. }
+            | ParseError::ReversedRange { index, .. }
+            | ParseError::InvalidPort { index, .. }
+            | ParseError::InvalidProtocol { index, .. }
+            | ParseError::ExpansionTooLarge { index, .. } => *index = Some(at),
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Empty => return write!(f, "No input given"),
+            ParseError::InvalidAddress { token, .. } => {
+                write!(f, "Invalid address spec: {}", token)?
+            }
+            ParseError::ReversedRange { token, .. } => {
+                write!(f, "Range start must not be after its end: {}", token)?
+            }
+            ParseError::InvalidPort { token, .. } => {
+                write!(f, "Invalid port (0-65535): {}", token)?
+            }
+            ParseError::InvalidProtocol { token, .. } => {
+                write!(f, "Invalid protocol suffix (tcp or udp): {}", token)?
+            }
+            ParseError::ExpansionTooLarge {
+                token, count, max, ..
+            } => write!(
+                f,
+                "Spec {} expands to {} addresses, more than the limit of {}",
+                token, count, max
+            )?,
+        }
+        // Entries are numbered from 1 for people reading the message
+        match self.index() {
+            Some(index) => write!(f, " (entry {})", index + 1),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Lazily yields the addresses of a contiguous IPv4 or IPv6 block, or an evenly
+/// spaced sample of one
+#[derive(Debug, Clone)]
+pub struct IpIter {
+    next: Option<u128>,
+    end: u128,
+    step: u128, // Distance between yielded addresses, 1 unless sampled
+    ipv6: bool,
+}
+
+impl IpIter {
+    fn new(start: u128, end: u128, ipv6: bool) -> Self {
+        Self {
+            next: Some(start),
+            end,
+            step: 1,
+            ipv6,
+        }
+    }
+
+    /// Number of addresses not yet yielded (saturates at `u128::MAX`)
+    pub fn remaining(&self) -> u128 {
+        self.next
+            .map_or(0, |next| ((self.end - next) / self.step).saturating_add(1))
+    }
+
+    /// At most `count` evenly spaced addresses of what remains, starting with the
+    /// next one; cheap even for blocks far too large to walk, such as an IPv6 /64
+    pub fn sample(mut self, count: u128) -> Self {
+        let remaining = self.remaining();
+        if count == 0 {
+            self.next = None;
+        } else if remaining > count {
+            // Rounded up so no more than `count` addresses fit before `end`
+            self.step = self.step.saturating_mul(remaining.div_ceil(count));
+        }
+        self
+    }
+
+    // Address `offset` steps past the next one, if still inside the block
+    fn advance(&mut self, offset: u128) -> Option<u128> {
+        let current = self
+            .next?
+            .checked_add(offset.checked_mul(self.step)?)
+            .filter(|&current| current <= self.end);
+        self.next = current.and_then(|current| {
+            current
+                .checked_add(self.step)
+                .filter(|&next| next <= self.end)
+        });
+        current
+    }
+}
+
+impl Iterator for IpIter {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<IpAddr> {
+        self.nth(0)
+    }
+
+    // Jumps straight to the address, so `step_by` and `skip` stay cheap on huge blocks
+    fn nth(&mut self, n: usize) -> Option<IpAddr> {
+        let current = self.advance(n as u128);
+        if current.is_none() {
+            self.next = None;
+        }
+        let current = current?;
+        Some(if self.ipv6 {
+            IpAddr::V6(Ipv6Addr::from(current))
+        } else {
+            IpAddr::V4(Ipv4Addr::from(current as u32))
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.remaining();
+        match usize::try_from(remaining) {
+            Ok(remaining) => (remaining, Some(remaining)),
+            Err(_) => (usize::MAX, None),
+        }
+    }
+}
+
+/// Iterates the addresses of a single IP, a range ("a-b") or a CIDR block
+/// Works for both IPv4 and IPv6 without materializing the block, but rejects
+/// specs larger than `MAX_EXPANSION` with `ParseError::ExpansionTooLarge`
+pub fn ip_iter(input: &str) -> Result<IpIter, ParseError> {
+    ip_iter_with_limit(input, Some(MAX_EXPANSION))
+}
+
+/// Same as `ip_iter` with an explicit cap; `None` disables the size check
+pub fn ip_iter_with_limit(input: &str, max: Option<u128>) -> Result<IpIter, ParseError> {
+    let input = input.trim();
+    let invalid = || ParseError::InvalidAddress {
+        token: input.to_string(),
+        index: None,
+    };
+
+    let (start, end, ipv6) = if let Some((first, last)) = input.split_once('-') {
+        let first: IpAddr = first.trim().parse().map_err(|_| invalid())?;
+        let last: IpAddr = last.trim().parse().map_err(|_| invalid())?;
+        if first.is_ipv6() != last.is_ipv6() {
+            return Err(invalid());
+        }
+        if ip_to_u128(first) > ip_to_u128(last) {
+            return Err(ParseError::ReversedRange {
+                token: input.to_string(),
+                index: None,
+            });
+        }
+        (ip_to_u128(first), ip_to_u128(last), first.is_ipv6())
+    } else if input.contains('/') {
+        let network: IpNetwork = input.parse().map_err(|_| invalid())?;
+        let bits = if network.is_ipv6() { 128 } else { 32 };
+        let host_bits = bits - u32::from(network.prefix());
+        let host_mask = if host_bits == 128 {
+            u128::MAX
+        } else {
+            (1_u128 << host_bits) - 1
+        };
+        let start = ip_to_u128(network.ip()) & !host_mask;
+        (start, start | host_mask, network.is_ipv6())
+    } else {
+        let ip: IpAddr = input.parse().map_err(|_| invalid())?;
+        (ip_to_u128(ip), ip_to_u128(ip), ip.is_ipv6())
+    };
+
+    let iter = IpIter::new(start, end, ipv6);
+    if let Some(max) = max {
+        let count = iter.remaining();
+        if count > max {
+            return Err(ParseError::ExpansionTooLarge {
+                token: input.to_string(),
+                index: None,
+                count,
+                max,
+            });
+        }
+    }
+    Ok(iter)
+}
+
+fn ip_to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(u32::from(v4)),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
 
 /// Reads input from user with a prompt
 pub fn read_input(prompt: &str) -> String {
@@ -32,45 +325,155 @@ pub fn read_input(prompt: &str) -> String {
 }
 
 /// Parses IP address input into supported formats
+/// Errors point at the first bad entry, e.g. `InvalidAddress { token: "10.0.0.300", index: Some(1) }`
 /// Supported formats:
-/// - IP range: "192.168.1.1-192.168.1.255"
-/// - CIDR block: "192.168.1.0/24"
-/// - Wildcards: "192.168.X.X" or "X.X.X.X"
-/// - Single IP: "192.168.1.1"
-pub fn parse_ip_input(input: &str) -> Vec<Ipv4Addr> {
+/// - IP range: "192.168.1.1-192.168.1.255" or "2001:db8::1-2001:db8::ff"
+/// - CIDR block: "192.168.1.0/24" or "2001:db8::/120"; IPv6 blocks over
+///   `MAX_EXPANSION` such as "::/64" yield `IPV6_SAMPLE_SIZE` evenly spaced addresses
+/// - Wildcards (IPv4 only): "192.168.X.X" or "X.X.X.X"
+/// - Single IP: "192.168.1.1" or "2001:db8::1"
+/// - Comma-separated mix of the above: "10.0.0.1-10.0.0.5, 2001:db8::/126"
+pub fn parse_ip_input(input: &str) -> Result<Vec<IpAddr>, ParseError> {
+    let results = parse_ip_entries(input, 0, None)?;
+    if results.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(results)
+}
+
+/// Lenient counterpart of `parse_ip_input_with_exclusions` for bulk, imperfect lists
+/// Malformed entries are skipped and returned as rejects instead of failing the input,
+/// so "10.0.0.1, 10.0.0.300, 10.0.0.3" yields two addresses and one reject
+/// Empty input is not an error here, it simply yields no addresses
+pub fn parse_ip_input_lenient(input: &str) -> (Vec<IpAddr>, Rejects) {
+    let mut rejects = Vec::new();
+    // Never fails: every error is recorded in `rejects` instead
+    let ips = ip_input_with_exclusions(input, Some(&mut rejects)).unwrap_or_default();
+    (ips, rejects)
+}
+
+// Expands each comma-separated entry, numbering entries from `first_index` in errors
+// With `rejects`, bad entries are recorded there and skipped rather than failing
+fn parse_ip_entries(
+    input: &str,
+    first_index: usize,
+    mut rejects: Option<&mut Rejects>,
+) -> Result<Vec<IpAddr>, ParseError> {
+    let mut results = Vec::new();
+    for (index, spec) in list_entries(input).enumerate() {
+        match parse_ip_entry(spec) {
+            Ok(ips) => results.extend(ips),
+            Err(e) => reject(spec, e.at(first_index + index), rejects.as_deref_mut())?,
+        }
+    }
+    Ok(results)
+}
+
+fn parse_ip_entry(spec: &str) -> Result<Vec<IpAddr>, ParseError> {
+    if spec.contains(':') {
+        // IPv6 specs are expanded lazily; blocks too large to list are sampled
+        let iter = ip_iter_with_limit(spec, None)?;
+        if iter.remaining() > MAX_EXPANSION {
+            Ok(iter.sample(IPV6_SAMPLE_SIZE).collect())
+        } else {
+            Ok(iter.collect())
+        }
+    } else {
+        Ok(parse_ipv4_spec(spec)?.into_iter().map(IpAddr::V4).collect())
+    }
+}
+
+// Records `error` for `entry` in lenient mode, or fails with it in strict mode
+fn reject(entry: &str, error: ParseError, rejects: Option<&mut Rejects>) -> Result<(), ParseError> {
+    match rejects {
+        Some(rejects) => {
+            rejects.push((entry.to_string(), error));
+            Ok(())
+        }
+        None => Err(error),
+    }
+}
+
+// Non-empty, trimmed entries of a comma-separated list
+fn list_entries(input: &str) -> impl Iterator<Item = &str> {
+    input.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+/// Parses IP input followed by exclusions, each introduced by '!'
+/// Example: "192.168.1.0/24 !192.168.1.1 !192.168.1.240/28"
+/// Exclusions accept every `parse_ip_input` format; excluded addresses are removed
+/// from the expanded set while the order of the remaining ones is kept
+/// Error indexes count entries across the whole input, exclusions included
+pub fn parse_ip_input_with_exclusions(input: &str) -> Result<Vec<IpAddr>, ParseError> {
+    ip_input_with_exclusions(input, None)
+}
+
+fn ip_input_with_exclusions(
+    input: &str,
+    mut rejects: Option<&mut Rejects>,
+) -> Result<Vec<IpAddr>, ParseError> {
+    let strict = rejects.is_none();
+    let mut chunks = input.split('!');
+    let included = chunks.next().unwrap_or_default();
+    let mut ips = parse_ip_entries(included, 0, rejects.as_deref_mut())?;
+    if strict && ips.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    let mut next_index = list_entries(included).count();
+    let mut excluded = HashSet::new();
+    for exclusion in chunks {
+        let parsed = parse_ip_entries(exclusion, next_index, rejects.as_deref_mut())?;
+        if strict && parsed.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        excluded.extend(parsed);
+        next_index += list_entries(exclusion).count();
+    }
+    if !excluded.is_empty() {
+        ips.retain(|ip| !excluded.contains(ip));
+    }
+    Ok(ips)
+}
+
+// Expands a single IPv4 range, CIDR block, wildcard or address
+fn parse_ipv4_spec(input: &str) -> Result<Vec<Ipv4Addr>, ParseError> {
     let mut results = Vec::new();
+    let invalid = || ParseError::InvalidAddress {
+        token: input.to_string(),
+        index: None,
+    };
 
     // Normalize input to uppercase for wildcard processing
     let normalized_input = input.to_uppercase();
-    // Initialize Chunk Stack
 
-    if normalized_input.contains('-') {
+    if let Some((start, end)) = normalized_input.split_once('-') {
         // Handle IP range: "192.168.1.1-192.168.1.255"
-        let parts: Vec<&str> = normalized_input.split('-').collect();
-        if parts.len() == 2 {
-            let start: Ipv4Addr = parts[0].parse().expect("Invalid start IP");
-            let end: Ipv4Addr = parts[1].parse().expect("Invalid end IP");
+        let start: Ipv4Addr = start.trim().parse().map_err(|_| invalid())?;
+        let end: Ipv4Addr = end.trim().parse().map_err(|_| invalid())?;
 
-            let start_u32 = u32::from(start);
-            let end_u32 = u32::from(end);
+        let start_u32 = u32::from(start);
+        let end_u32 = u32::from(end);
 
-            if start_u32 > end_u32 {
-                panic!("Start IP must be less than or equal to End IP");
-            }
+        if start_u32 > end_u32 {
+            return Err(ParseError::ReversedRange {
+                token: input.to_string(),
+                index: None,
+            });
+        }
 
-            for ip_int in start_u32..=end_u32 {
-                results.push(Ipv4Addr::from(ip_int));
-            }
+        for ip_int in start_u32..=end_u32 {
+            results.push(Ipv4Addr::from(ip_int));
         }
     } else if normalized_input.contains('/') {
         // Handle CIDR notation: "192.168.1.0/24"
-        let cidr: Ipv4Network = normalized_input.parse().expect("Invalid CIDR format");
+        let cidr: Ipv4Network = normalized_input.parse().map_err(|_| invalid())?;
         results.extend(cidr.iter());
     } else if normalized_input.contains('X') {
         // Handle wildcard notation: "X.X.X.X" or specific octet wildcards like "192.168.X.X"
         let octets: Vec<&str> = normalized_input.split('.').collect();
         if octets.len() != 4 {
-            panic!("Invalid wildcard IP format. Must be like X.X.X.X or similar.");
+            return Err(invalid());
         }
 
         let mut ranges = vec![];
@@ -79,7 +482,7 @@ pub fn parse_ip_input(input: &str) -> Vec<Ipv4Addr> {
             if *octet == "X" {
                 ranges.push(0..=255); // Add full range for wildcard octet
             } else {
-                let value: u8 = octet.parse().expect("Invalid octet value");
+                let value: u8 = octet.parse().map_err(|_| invalid())?;
                 ranges.push(value..=value); // Fixed value for non-wildcard octet
             }
         }
@@ -104,67 +507,310 @@ pub fn parse_ip_input(input: &str) -> Vec<Ipv4Addr> {
         }
     } else {
         // Single IP address
-        if let Ok(ip) = normalized_input.parse::<Ipv4Addr>() {
-            results.push(ip);
-        }
+        results.push(normalized_input.parse().map_err(|_| invalid())?);
     }
 
-    results
+    Ok(results)
 }
 
-/// Parses port input into a list of ports
-/// Supported formats:
-/// - Port range: "0-65535"
-/// - Comma-separated list: "80, 443, 8080"
-/// - Single port: "8080"
-pub fn parse_port_input(input: &str) -> Vec<u16> {
+/// Parses port input into a sorted, deduplicated list of ports, ignoring protocol suffixes
+/// Input is split on commas and each token is a single port or a "start-end" range,
+/// so entries can be mixed freely: "22,80,8000-8100,443"
+/// Any malformed token fails the whole input
+pub fn parse_port_input(input: &str) -> Result<Vec<u16>, ParseError> {
+    let mut ports: Vec<u16> = parse_port_spec(input)?
+        .into_iter()
+        .map(|(port, _)| port)
+        .collect();
+    ports.sort_unstable();
+    ports.dedup();
+    Ok(ports)
+}
+
+/// Expands a named port preset, case-insensitively
+/// - "web": 80, 443, 8080, 8443
+/// - "db": 3306, 5432, 1433, 27017
+/// - "top100": nmap's default top-100 TCP ports
+pub fn expand_port_alias(name: &str) -> Option<Vec<u16>> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "web" => Some(vec![80, 443, 8080, 8443]),
+        "db" => Some(vec![3306, 5432, 1433, 27017]),
+        "top100" => Some(TOP_100_PORTS.to_vec()),
+        _ => None,
+    }
+}
+
+/// Parses port input with optional per-entry protocol suffixes
+/// Entries are comma-separated single ports, ranges or `expand_port_alias` names,
+/// each optionally followed by "/tcp" or "/udp"; entries without a suffix default to TCP
+/// Example: "80/tcp, 53/udp, 1000-2000/tcp, web, 8080"
+/// Errors point at the first bad entry, e.g. `InvalidPort { token: "80a", index: Some(2) }` for "22, 443, 80a"
+pub fn parse_port_spec(input: &str) -> Result<Vec<(u16, AddrType)>, ParseError> {
+    let ports = parse_port_entries(input, None)?;
+    if ports.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    Ok(ports)
+}
+
+/// Lenient counterpart of `parse_port_spec`: malformed entries are skipped and
+/// returned as rejects, and empty input simply yields no ports
+pub fn parse_port_spec_lenient(input: &str) -> (Vec<(u16, AddrType)>, Rejects) {
+    let mut rejects = Vec::new();
+    // Never fails: every error is recorded in `rejects` instead
+    let ports = parse_port_entries(input, Some(&mut rejects)).unwrap_or_default();
+    (ports, rejects)
+}
+
+fn parse_port_entries(
+    input: &str,
+    mut rejects: Option<&mut Rejects>,
+) -> Result<Vec<(u16, AddrType)>, ParseError> {
     let mut ports = Vec::new();
-    if input.contains('-') {
-        // Handle range: "0-65535"
-        let parts: Vec<&str> = input.split('-').collect();
-        if parts.len() == 2 {
-            let start: u16 = parts[0].parse().expect("Invalid start port");
-            let end: u16 = parts[1].parse().expect("Invalid end port");
-            for port in start..=end {
-                ports.push(port);
-            }
+    for (index, entry) in list_entries(input).enumerate() {
+        if let Err(e) = parse_port_entry(entry, &mut ports) {
+            reject(entry, e.at(index), rejects.as_deref_mut())?;
         }
-    } else if input.contains(',') {
-        // Handle list of ports: "1, 2, 5"
-        for p in input.split(',') {
-            let port: u16 = p.trim().parse().expect("Invalid port number");
-            ports.push(port);
+    }
+    Ok(ports)
+}
+
+// Appends the ports of one `parse_port_spec` entry to `ports`
+fn parse_port_entry(entry: &str, ports: &mut Vec<(u16, AddrType)>) -> Result<(), ParseError> {
+    let (range, protocol) = match entry.split_once('/') {
+        Some((range, suffix)) => match suffix.trim().to_ascii_lowercase().as_str() {
+            "tcp" => (range.trim(), AddrType::TCP),
+            "udp" => (range.trim(), AddrType::UDP),
+            _ => {
+                return Err(ParseError::InvalidProtocol {
+                    token: suffix.trim().to_string(),
+                    index: None,
+                })
+            }
+        },
+        None => (entry, AddrType::TCP),
+    };
+
+    if let Some(aliased) = expand_port_alias(range) {
+        // Named preset: "web", "db", "top100"
+        ports.extend(aliased.into_iter().map(|port| (port, protocol.clone())));
+    } else if let Some((start, end)) = range.split_once('-') {
+        // Handle range: "0-65535"
+        let start = parse_port(start)?;
+        let end = parse_port(end)?;
+        if start > end {
+            return Err(ParseError::ReversedRange {
+                token: range.to_string(),
+                index: None,
+            });
         }
+        ports.extend((start..=end).map(|port| (port, protocol.clone())));
     } else {
         // Single port
-        ports.push(input.parse().expect("Invalid port"));
+        ports.push((parse_port(range)?, protocol));
+    }
+    Ok(())
+}
+
+fn parse_port(input: &str) -> Result<u16, ParseError> {
+    let input = input.trim();
+    input.parse().map_err(|_| ParseError::InvalidPort {
+        token: input.to_string(),
+        index: None,
+    })
+}
+
+/// Expands IPs x ports x protocols into listener targets
+/// Duplicates are dropped keyed on (ip, port, protocol), so the same port
+/// requested for both TCP and UDP yields two distinct targets
+pub fn expand_targets(ips: &[IpAddr], ports: &[u16], protocols: &[AddrType]) -> Vec<AddrData> {
+    let specs: Vec<(u16, AddrType)> = ports
+        .iter()
+        .flat_map(|&port| protocols.iter().map(move |p| (port, p.clone())))
+        .collect();
+    expand_target_specs(ips, &specs)
+}
+
+/// Expands IPs x (port, protocol) pairs from `parse_port_spec` into listener targets
+/// Duplicates are dropped keyed on (ip, port, protocol); IPv6 addresses become IPv6 targets
+pub fn expand_target_specs(ips: &[IpAddr], specs: &[(u16, AddrType)]) -> Vec<AddrData> {
+    let mut seen = HashSet::new();
+    let mut targets = Vec::new();
+    for ip in ips {
+        let info = match ip {
+            IpAddr::V4(_) => AddrType::IPv4,
+            IpAddr::V6(_) => AddrType::IPv6,
+        };
+        for (port, protocol) in specs {
+            if seen.insert((*ip, *port, protocol.clone())) {
+                targets.push(AddrData {
+                    info: info.clone(),
+                    socket_type: protocol.clone(),
+                    address: *ip,
+                    port: *port,
+                });
+            }
+        }
     }
+    targets
+}
+
+const IP_PROMPT: &str = "Enter the listen IP addresses.\nFormat: 255.255.255.0-255.255.255.255, 192.168.1.X, or 192.168.1.0/24 (exclude with \"!192.168.1.1\"):";
+const PORT_PROMPT: &str = "Enter the listen IP ports.\nFormat: 0-65535, \"1, 2, 5\", \"80/tcp, 53/udp\", or presets like \"web,22\":";
 
-    ports
+// Re-prompts until `parse` accepts a line of `input`, printing each error
+// Fails with `UnexpectedEof` if `input` ends before a valid value is entered
+fn prompt_until_valid<T>(
+    input: &mut impl BufRead,
+    prompt: &str,
+    parse: impl Fn(&str) -> Result<T, ParseError>,
+) -> io::Result<T> {
+    loop {
+        println!("{}", prompt);
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "input closed before a valid value was entered",
+            ));
+        }
+        match parse(line.trim()) {
+            Ok(value) => return Ok(value),
+            Err(e) => println!("{}. Please try again.", e),
+        }
+    }
 }
 
 /// Main function for input and parsing
-pub fn addr_input() -> (Vec<Ipv4Addr>, Vec<u16>) {
-    // Read IP address input
-    let ip_input = read_input(
-         "Enter the listen IP addresses.\nFormat: 255.255.255.0-255.255.255.255, 192.168.1.X, or 192.168.1.0/24:",
-     );
-    // Read port input
-    let port_input = read_input("Enter the listen IP ports.\nFormat: 0-65535, or \"1, 2, 5\":");
-    // Parse inputs
-    let ips = parse_ip_input(&ip_input);
-    let ports = parse_port_input(&port_input);
+/// Fails only if stdin closes before both values are entered
+pub fn addr_input() -> io::Result<(Vec<IpAddr>, Vec<u16>)> {
+    let (ips, specs) = addr_spec_input()?;
+    Ok((ips, specs.into_iter().map(|(port, _)| port).collect()))
+}
+
+/// Same as `addr_input` but keeps the protocol given for each port
+pub fn addr_spec_input() -> io::Result<TargetSpecs> {
+    let mut stdin = io::stdin().lock();
+    // Read and parse IP address input, asking again until it parses
+    let ips = prompt_until_valid(&mut stdin, IP_PROMPT, parse_ip_input_with_exclusions)?;
+    // Read and parse port input
+    let ports = prompt_until_valid(&mut stdin, PORT_PROMPT, parse_port_spec)?;
 
     // Output results
     println!("Parsed IP Addresses: {:?}", ips.len());
     println!("Parsed Ports: {:?}", ports.len());
 
-    (ips, ports)
+    Ok((ips, ports))
+}
+
+/// Like `addr_spec_input`, but takes the IPs and ports already parsed (e.g. from
+/// the command line) and only prompts for a missing one
+pub fn addr_spec_input_with(
+    ips: Option<Vec<IpAddr>>,
+    ports: Option<Vec<(u16, AddrType)>>,
+) -> io::Result<TargetSpecs> {
+    let mut stdin = io::stdin().lock();
+    let ips = match ips {
+        Some(ips) => ips,
+        None => prompt_until_valid(&mut stdin, IP_PROMPT, parse_ip_input_with_exclusions)?,
+    };
+    let ports = match ports {
+        Some(ports) => ports,
+        None => prompt_until_valid(&mut stdin, PORT_PROMPT, parse_port_spec)?,
+    };
+    Ok((ips, ports))
+}
+
+/// Non-interactive counterpart of `addr_input`, reading targets from a file
+pub fn addr_input_from_file(path: &Path) -> io::Result<(Vec<IpAddr>, Vec<u16>)> {
+    let (ips, specs) = addr_spec_input_from_file(path)?;
+    Ok((ips, specs.into_iter().map(|(port, _)| port).collect()))
+}
+
+/// Reads targets from a file holding one spec per line, merging all lines
+/// A line is either a port spec (as for `parse_port_spec`) or an IP spec
+/// (as for `parse_ip_input_with_exclusions`); blank lines and lines starting with '#' are skipped
+/// Example:
+/// # lab hosts
+/// 10.0.0.0/24
+/// 192.168.1.10, 192.168.1.20
+/// web, 53/udp
+pub fn addr_spec_input_from_file(path: &Path) -> io::Result<TargetSpecs> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut ips = Vec::new();
+    let mut ports = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Port specs never parse as addresses, so trying them first is unambiguous
+        if let Ok(specs) = parse_port_spec(line) {
+            ports.extend(specs);
+            continue;
+        }
+        match parse_ip_input_with_exclusions(line) {
+            Ok(parsed) => ips.extend(parsed),
+            Err(e) => return Err(invalid(format!("{}:{}: {}", path.display(), index + 1, e))),
+        }
+    }
+
+    if ips.is_empty() {
+        return Err(invalid(format!("{}: no IP addresses", path.display())));
+    }
+    if ports.is_empty() {
+        return Err(invalid(format!("{}: no ports", path.display())));
+    }
+    Ok((ips, ports))
+}
+
+/// Lenient counterpart of `addr_spec_input_from_file` for bulk, imperfect target files
+/// Bad entries are skipped and returned with their 1-based line number; a line
+/// holding '.' or ':' is read as IP specs, any other line as port specs
+/// Still fails if the file can't be read or yields no IPs or no ports at all
+pub fn addr_spec_input_from_file_lenient(path: &Path) -> io::Result<(TargetSpecs, LineRejects)> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    let mut ips = Vec::new();
+    let mut ports = Vec::new();
+    let mut rejects = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line_rejects = if line.contains(['.', ':']) {
+            let (parsed, line_rejects) = parse_ip_input_lenient(line);
+            ips.extend(parsed);
+            line_rejects
+        } else {
+            let (parsed, line_rejects) = parse_port_spec_lenient(line);
+            ports.extend(parsed);
+            line_rejects
+        };
+        rejects.extend(
+            line_rejects
+                .into_iter()
+                .map(|(entry, e)| (index + 1, entry, e)),
+        );
+    }
+
+    if ips.is_empty() {
+        return Err(invalid(format!("{}: no IP addresses", path.display())));
+    }
+    if ports.is_empty() {
+        return Err(invalid(format!("{}: no ports", path.display())));
+    }
+    Ok(((ips, ports), rejects))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::types::socket_addr_create;
     use std::io::Cursor;
     use std::net::Ipv4Addr;
 
@@ -186,46 +832,536 @@ mod tests {
 
     #[test]
     fn test_parse_ip_input() {
-        let result = parse_ip_input("127.0.0.1");
+        let result = parse_ip_input("127.0.0.1").unwrap();
         assert_eq!(result.len(), 1);
-        assert_eq!(result[0], Ipv4Addr::new(127, 0, 0, 1));
+        assert_eq!(result[0], IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)));
     }
 
     #[test]
     fn test_parse_ip_range() {
-        let result = parse_ip_input("127.0.0.1-127.0.0.3");
+        let result = parse_ip_input("127.0.0.1-127.0.0.3").unwrap();
         assert_eq!(result.len(), 3);
-        assert!(result.contains(&Ipv4Addr::new(127, 0, 0, 1)));
-        assert!(result.contains(&Ipv4Addr::new(127, 0, 0, 2)));
-        assert!(result.contains(&Ipv4Addr::new(127, 0, 0, 3)));
+        assert!(result.contains(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))));
+        assert!(result.contains(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2))));
+        assert!(result.contains(&IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3))));
     }
 
     #[test]
     fn test_parse_wildcard() {
-        let result = parse_ip_input("127.0.0.X");
+        let result = parse_ip_input("127.0.0.X").unwrap();
         assert!(!result.is_empty());
         for ip in result {
+            let IpAddr::V4(ip) = ip else {
+                panic!("wildcard produced a non-IPv4 address");
+            };
             assert_eq!(ip.octets()[0], 127);
             assert_eq!(ip.octets()[1], 0);
             assert_eq!(ip.octets()[2], 0);
         }
     }
 
+    #[test]
+    fn test_parse_ipv6_input() {
+        let v6 = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            parse_ip_input("2001:db8::1").unwrap(),
+            vec![v6("2001:db8::1")]
+        );
+        assert_eq!(
+            parse_ip_input("2001:db8::1-2001:db8::3").unwrap(),
+            vec![v6("2001:db8::1"), v6("2001:db8::2"), v6("2001:db8::3")]
+        );
+        assert_eq!(parse_ip_input("::/126").unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_parse_mixed_v4_range_and_v6_cidr() {
+        let result = parse_ip_input("10.0.0.1-10.0.0.3, 2001:db8::/126").unwrap();
+        assert_eq!(result.len(), 7);
+        assert_eq!(result.iter().filter(|ip| ip.is_ipv4()).count(), 3);
+        assert_eq!(result[3], "2001:db8::".parse::<IpAddr>().unwrap());
+
+        // Both halves become listener targets, tagged with their IP version
+        let targets = expand_targets(&result, &[80], &[AddrType::TCP]);
+        assert_eq!(targets.len(), 7);
+        assert_eq!(
+            targets.iter().filter(|t| t.info == AddrType::IPv6).count(),
+            4
+        );
+    }
+
+    #[test]
+    fn test_ip_iter_rejects_huge_ipv6_prefix() {
+        let err = ip_iter("2001:db8::/64").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::ExpansionTooLarge {
+                token: "2001:db8::/64".into(),
+                index: None,
+                count: 1 << 64,
+                max: MAX_EXPANSION
+            }
+        );
+        assert!(!err.to_string().contains("(entry"));
+
+        // An explicit override iterates lazily instead of materializing 2^64 addresses
+        let mut iter = ip_iter_with_limit("2001:db8::/64", None).unwrap();
+        assert_eq!(iter.next(), Some("2001:db8::".parse().unwrap()));
+        assert_eq!(iter.remaining(), (1 << 64) - 1);
+    }
+
+    #[test]
+    fn test_large_ipv6_prefix_is_sampled() {
+        let ips = parse_ip_input("::/64").unwrap();
+        assert_eq!(ips.len() as u128, IPV6_SAMPLE_SIZE);
+        assert_eq!(ips[0], "::".parse::<IpAddr>().unwrap());
+        assert_eq!(ips[1], IpAddr::V6(Ipv6Addr::from(1_u128 << 56)));
+        assert!(ips.iter().all(|ip| ip_to_u128(*ip) < 1 << 64));
+
+        // Mixed with IPv4 in one invocation, and with the whole address space
+        let ips = parse_ip_input("10.0.0.1, 2001:db8::/64, ::/0").unwrap();
+        assert_eq!(ips.len() as u128, 1 + 2 * IPV6_SAMPLE_SIZE);
+
+        // Sampling and skipping ahead never walk the block
+        let mut iter = ip_iter_with_limit("2001:db8::/64", None).unwrap().sample(4);
+        assert_eq!(iter.remaining(), 4);
+        assert_eq!(iter.nth(3), Some("2001:db8::c000:0:0:0".parse().unwrap()));
+        assert_eq!(iter.next(), None);
+        let fifth = ip_iter_with_limit("::/64", None)
+            .unwrap()
+            .step_by(1 << 40)
+            .nth(4);
+        assert_eq!(fifth, Some(IpAddr::V6(Ipv6Addr::from(4_u128 << 40))));
+    }
+
+    #[test]
+    fn test_ip_iter_small_ipv6_prefix() {
+        let addrs: Vec<IpAddr> = ip_iter("2001:db8::10/124").unwrap().collect();
+        assert_eq!(addrs.len(), 16);
+        assert_eq!(addrs[0], "2001:db8::10".parse::<IpAddr>().unwrap());
+        assert_eq!(addrs[15], "2001:db8::1f".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_ip_iter_ipv4_specs() {
+        assert_eq!(ip_iter("10.0.0.0/30").unwrap().count(), 4);
+        assert_eq!(ip_iter("10.0.0.250-10.0.1.5").unwrap().count(), 12);
+        let everything = ip_iter_with_limit("0.0.0.0/0", None).unwrap();
+        assert_eq!(everything.remaining(), 1 << 32);
+        assert!(ip_iter("10.0.0.0/7").is_err());
+        assert!(matches!(
+            ip_iter("10.0.0.5-10.0.0.1"),
+            Err(ParseError::ReversedRange { .. })
+        ));
+    }
+
+    #[test]
+    fn test_expand_targets_keeps_tcp_and_udp_apart() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let targets = expand_targets(&[ip, ip], &[53, 53], &[AddrType::TCP, AddrType::UDP]);
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0].socket_type, AddrType::TCP);
+        assert_eq!(targets[1].socket_type, AddrType::UDP);
+        assert!(targets.iter().all(|t| t.port == 53 && t.address == ip));
+    }
+
+    #[test]
+    fn test_expand_targets_includes_ipv6() {
+        let v4 = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let v6: IpAddr = "::1".parse().unwrap();
+        let targets = expand_targets(&[v4, v6], &[8080], &[AddrType::TCP]);
+
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[1].info, AddrType::IPv6);
+        assert_eq!(targets[1].address, v6);
+        assert!(socket_addr_create(targets[1].address, targets[1].port).is_ipv6());
+    }
+
     #[test]
     fn test_parse_port_input() {
-        let result = parse_port_input("9998-10000");
+        let result = parse_port_input("9998-10000").unwrap();
         assert_eq!(result.len(), 3);
         assert!(result.contains(&9998));
         assert!(result.contains(&9999));
         assert!(result.contains(&10000));
     }
 
+    #[test]
+    fn test_parse_port_input_lists_and_ranges() {
+        let mut expected = vec![80, 443];
+        expected.extend(8080..=8090);
+        assert_eq!(parse_port_input("80,443,8080-8090").unwrap(), expected);
+        assert_eq!(
+            parse_port_input("8090-8080,80").err(),
+            Some(ParseError::ReversedRange {
+                token: "8090-8080".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(
+            parse_port_input("80,http,443"),
+            Err(ParseError::InvalidPort {
+                token: "http".into(),
+                index: Some(1)
+            })
+        );
+        assert_eq!(
+            parse_port_input("80,8000-"),
+            Err(ParseError::InvalidPort {
+                token: "".into(),
+                index: Some(1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_port_input_overlapping_ranges() {
+        let result = parse_port_input("100-110, 105-115, 108, 443, 100").unwrap();
+        let mut expected: Vec<u16> = (100..=115).collect();
+        expected.push(443);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_port_aliases() {
+        assert_eq!(expand_port_alias("web"), Some(vec![80, 443, 8080, 8443]));
+        assert_eq!(expand_port_alias("DB"), Some(vec![3306, 5432, 1433, 27017]));
+        let top = expand_port_alias("top100").unwrap();
+        assert_eq!(top.len(), 100);
+        assert!(top.contains(&22) && top.contains(&3389));
+        assert_eq!(expand_port_alias("mail"), None);
+
+        assert_eq!(
+            parse_port_input("web,22").unwrap(),
+            vec![22, 80, 443, 8080, 8443]
+        );
+        assert_eq!(parse_port_spec("db/udp").unwrap()[0], (3306, AddrType::UDP));
+        assert_eq!(
+            parse_port_input("webb"),
+            Err(ParseError::InvalidPort {
+                token: "webb".into(),
+                index: Some(0)
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_port_spec_mixed_protocols() {
+        let result = parse_port_spec("80/tcp, 53/UDP, 1000-1002/tcp").unwrap();
+        assert_eq!(
+            result,
+            vec![
+                (80, AddrType::TCP),
+                (53, AddrType::UDP),
+                (1000, AddrType::TCP),
+                (1001, AddrType::TCP),
+                (1002, AddrType::TCP),
+            ]
+        );
+
+        let targets = expand_target_specs(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], &result[..2]);
+        assert_eq!(targets[1].socket_type, AddrType::UDP);
+    }
+
+    #[test]
+    fn test_parse_port_spec_defaults_to_tcp() {
+        assert_eq!(
+            parse_port_spec("8080").unwrap(),
+            vec![(8080, AddrType::TCP)]
+        );
+        assert_eq!(
+            parse_port_spec("22, 53/udp").unwrap(),
+            vec![(22, AddrType::TCP), (53, AddrType::UDP)]
+        );
+        assert_eq!(parse_port_input("22, 53/udp").unwrap(), vec![22, 53]);
+    }
+
+    #[test]
+    fn test_parse_errors_instead_of_panics() {
+        assert_eq!(
+            parse_ip_input("10.0.0.5-10.0.0.1"),
+            Err(ParseError::ReversedRange {
+                token: "10.0.0.5-10.0.0.1".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(
+            parse_ip_input("192.168.1.0/33"),
+            Err(ParseError::InvalidAddress {
+                token: "192.168.1.0/33".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(
+            parse_ip_input("10.0.0.300"),
+            Err(ParseError::InvalidAddress {
+                token: "10.0.0.300".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(parse_ip_input(""), Err(ParseError::Empty));
+        assert_eq!(parse_ip_input(" , "), Err(ParseError::Empty));
+
+        assert_eq!(
+            parse_port_input("70000"),
+            Err(ParseError::InvalidPort {
+                token: "70000".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(
+            parse_port_input("90-80"),
+            Err(ParseError::ReversedRange {
+                token: "90-80".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(
+            parse_port_input("80/sctp"),
+            Err(ParseError::InvalidProtocol {
+                token: "sctp".into(),
+                index: Some(0)
+            })
+        );
+        assert_eq!(parse_port_input(""), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_bad_entry() {
+        let err = parse_port_input("22, 443, 80a, 8080").unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::InvalidPort {
+                token: "80a".into(),
+                index: Some(2)
+            }
+        );
+        assert_eq!(err.to_string(), "Invalid port (0-65535): 80a (entry 3)");
+
+        // Inside a range only the bad bound is reported
+        assert_eq!(
+            parse_port_spec("21, 1000-20x0/udp"),
+            Err(ParseError::InvalidPort {
+                token: "20x0".into(),
+                index: Some(1)
+            })
+        );
+        assert_eq!(
+            parse_port_spec("80/tcp, 53/udp, 443/sctp"),
+            Err(ParseError::InvalidProtocol {
+                token: "sctp".into(),
+                index: Some(2)
+            })
+        );
+
+        let err = parse_ip_input("10.0.0.1, 2001:db8::1, 10.0.0.256, 10.0.0.4").unwrap_err();
+        assert_eq!(err.token(), Some("10.0.0.256"));
+        assert_eq!(err.index(), Some(2));
+        assert_eq!(
+            parse_ip_input("10.0.0.1, 2001:db8::5-2001:db8::1")
+                .unwrap_err()
+                .index(),
+            Some(1)
+        );
+
+        // Exclusion entries keep counting after the included ones
+        assert_eq!(
+            parse_ip_input_with_exclusions("10.0.0.0/24, 10.0.1.1 !10.0.0.1, 10.0.0.x9"),
+            Err(ParseError::InvalidAddress {
+                token: "10.0.0.x9".into(),
+                index: Some(3)
+            })
+        );
+        assert_eq!(ParseError::Empty.index(), None);
+    }
+
+    #[test]
+    fn test_lenient_parse_skips_bad_entries() {
+        let (ips, rejects) =
+            parse_ip_input_lenient("10.0.0.1, 10.0.0.300, 10.0.0.2-10.0.0.3, bogus, 2001:db8::1");
+        assert_eq!(
+            ips,
+            vec![
+                IpAddr::from([10, 0, 0, 1]),
+                IpAddr::from([10, 0, 0, 2]),
+                IpAddr::from([10, 0, 0, 3]),
+                "2001:db8::1".parse().unwrap(),
+            ]
+        );
+        assert_eq!(
+            rejects,
+            vec![
+                (
+                    "10.0.0.300".to_string(),
+                    ParseError::InvalidAddress {
+                        token: "10.0.0.300".into(),
+                        index: Some(1)
+                    }
+                ),
+                (
+                    "bogus".to_string(),
+                    ParseError::InvalidAddress {
+                        token: "bogus".into(),
+                        index: Some(3)
+                    }
+                ),
+            ]
+        );
+        // Strict parsing of the same input still fails on the first bad entry
+        assert_eq!(
+            parse_ip_input("10.0.0.1, 10.0.0.300, bogus")
+                .unwrap_err()
+                .index(),
+            Some(1)
+        );
+
+        let (ports, rejects) = parse_port_spec_lenient("22, 80a, 443/udp, 90-80, web");
+        let ports: Vec<u16> = ports.into_iter().map(|(port, _)| port).collect();
+        assert_eq!(ports, vec![22, 443, 80, 443, 8080, 8443]);
+        let rejected: Vec<&str> = rejects.iter().map(|(entry, _)| entry.as_str()).collect();
+        assert_eq!(rejected, vec!["80a", "90-80"]);
+
+        assert_eq!(parse_ip_input_lenient(""), (vec![], vec![]));
+    }
+
+    #[test]
+    fn test_lenient_file_reports_rejects_by_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("targets.txt");
+        fs::write(
+            &path,
+            "10.0.0.1, 10.0.0.999\n# ports\n22, 8o8o\n10.0.0.5 !10.0.0.256\n",
+        )
+        .unwrap();
+
+        let ((ips, ports), rejects) = addr_spec_input_from_file_lenient(&path).unwrap();
+        assert_eq!(
+            ips,
+            vec![IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 5])]
+        );
+        assert_eq!(ports, vec![(22, AddrType::TCP)]);
+        let lines: Vec<(usize, &str)> = rejects
+            .iter()
+            .map(|(line, entry, _)| (*line, entry.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![(1, "10.0.0.999"), (3, "8o8o"), (4, "10.0.0.256")]
+        );
+
+        // The strict reader rejects the same file outright
+        assert!(addr_spec_input_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_exclusions_remove_sub_cidr() {
+        let all = parse_ip_input("10.1.0.0/24").unwrap();
+        let kept = parse_ip_input_with_exclusions("10.1.0.0/24 !10.1.0.32/28").unwrap();
+        assert_eq!(all.len() - kept.len(), 16);
+
+        let excluded: Vec<IpAddr> = parse_ip_input("10.1.0.32/28").unwrap();
+        assert!(kept.iter().all(|ip| !excluded.contains(ip)));
+        // Remaining addresses keep their original order
+        let expected: Vec<IpAddr> = all
+            .into_iter()
+            .filter(|ip| !excluded.contains(ip))
+            .collect();
+        assert_eq!(kept, expected);
+    }
+
+    #[test]
+    fn test_exclusions_single_addresses() {
+        let kept =
+            parse_ip_input_with_exclusions("192.168.1.0/29 !192.168.1.1 !192.168.1.6").unwrap();
+        let hosts: Vec<u8> = kept
+            .iter()
+            .map(|ip| match ip {
+                IpAddr::V4(v4) => v4.octets()[3],
+                IpAddr::V6(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(hosts, vec![0, 2, 3, 4, 5, 7]);
+
+        assert_eq!(
+            parse_ip_input_with_exclusions("10.0.0.0/30 !bogus"),
+            Err(ParseError::InvalidAddress {
+                token: "bogus".into(),
+                index: Some(1)
+            })
+        );
+        assert_eq!(
+            parse_ip_input_with_exclusions("!10.0.0.1"),
+            Err(ParseError::Empty)
+        );
+    }
+
+    #[test]
+    fn test_addr_input_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("targets.txt");
+        fs::write(
+            &path,
+            "# lab hosts\n10.0.0.1-10.0.0.2\n\n  192.168.1.5\n22, 80\n53/udp\n",
+        )
+        .unwrap();
+
+        let (ips, ports) = addr_input_from_file(&path).unwrap();
+        assert_eq!(
+            ips,
+            vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                IpAddr::V4(Ipv4Addr::new(192, 168, 1, 5)),
+            ]
+        );
+        assert_eq!(ports, vec![22, 80, 53]);
+        let (_, specs) = addr_spec_input_from_file(&path).unwrap();
+        assert_eq!(specs[2], (53, AddrType::UDP));
+
+        fs::write(&path, "10.0.0.1\n22\nnot-a-host\n").unwrap();
+        let err = addr_input_from_file(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains(":3:"), "{}", err);
+
+        fs::write(&path, "# only addresses\n10.0.0.1\n").unwrap();
+        assert!(addr_input_from_file(&path).is_err());
+    }
+
+    #[test]
+    fn test_prompt_reports_closed_input() {
+        let mut input = Cursor::new(b"10.0.0.300\n10.0.0.1\n".to_vec());
+        let ips = prompt_until_valid(&mut input, IP_PROMPT, parse_ip_input).unwrap();
+        assert_eq!(ips, vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))]);
+
+        // Invalid lines are retried, but running out of input is an error
+        let mut input = Cursor::new(b"70000\n".to_vec());
+        let err = prompt_until_valid(&mut input, PORT_PROMPT, parse_port_spec).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_addr_spec_input_with_given_specs_skips_prompt() {
+        let given_ips = parse_ip_input_with_exclusions("10.0.0.0/30 !10.0.0.1").unwrap();
+        let given_ports = parse_port_spec("22, 53/udp").unwrap();
+        let (ips, ports) = addr_spec_input_with(Some(given_ips), Some(given_ports)).unwrap();
+        assert_eq!(
+            ips,
+            vec![
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)),
+            ]
+        );
+        assert_eq!(ports, vec![(22, AddrType::TCP), (53, AddrType::UDP)]);
+    }
+
     #[test]
     fn test_addr_input_format() {
         let input = "127.0.0.1\n80\n";
         let _mock = StdinMock::new(input);
 
-        let (ips, ports) = addr_input();
+        let (ips, ports) = addr_input().unwrap();
         assert!(!ips.is_empty(), "IP list should not be empty");
         assert!(!ports.is_empty(), "Port list should not be empty");
     }
@@ -1,9 +1,27 @@
+use crate::core::connections::{ConnectionHandle, ConnectionInfo, Throughput};
 use crate::core::types::{ConnectionState, NetworkConfig};
-use std::collections::HashMap;
-use std::net::SocketAddr;
+use chrono::Local;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Number of source addresses listed in a shutdown summary
+const TOP_SOURCES: usize = 5;
+/// Closed connections remembered in `CoreState::recently_closed`
+pub const RECENTLY_CLOSED: usize = 100;
 
 pub struct CoreState {
     pub active_connections: HashMap<SocketAddr, ConnectionState>,
+    pub recently_closed: VecDeque<(SocketAddr, ConnectionState)>, // Last closed ones, oldest first
+    pub handles: HashMap<SocketAddr, ConnectionHandle>, // Meters and close handles of open connections
+    pub idle_timeout: Option<Duration>, // Connections quiet this long are reaped (never when None)
+    pub throughput: Arc<Throughput>, // Traffic of every connection counted in it, see `throughput_bps`
     pub network_config: NetworkConfig,
     pub is_running: bool,
 }
@@ -12,11 +30,11 @@ impl CoreState {
     pub fn new() -> Self {
         Self {
             active_connections: HashMap::new(),
-            network_config: NetworkConfig {
-                max_connections: 1000,
-                timeout: std::time::Duration::from_secs(30),
-                retry_attempts: 3,
-            },
+            recently_closed: VecDeque::new(),
+            handles: HashMap::new(),
+            idle_timeout: None,
+            throughput: Arc::new(Throughput::default()),
+            network_config: NetworkConfig::default(),
             is_running: false,
         }
     }
@@ -25,10 +43,387 @@ impl CoreState {
         self.active_connections.insert(addr, state);
     }
 
+    /// Registers a newly accepted connection together with the handle its task serves it with
+    pub fn open_connection(&mut self, addr: SocketAddr, handle: ConnectionHandle) {
+        self.active_connections
+            .insert(addr, ConnectionState::Connected);
+        self.handles.insert(addr, handle);
+    }
+
+    /// Drops `addr` from the active connections, remembering how it ended
+    /// (`Disconnected`, `Closed` or `Error`); only the last `RECENTLY_CLOSED` are kept
+    pub fn close_connection(&mut self, addr: SocketAddr, state: ConnectionState) {
+        self.active_connections.remove(&addr);
+        self.handles.remove(&addr);
+        if self.recently_closed.len() >= RECENTLY_CLOSED {
+            self.recently_closed.pop_front();
+        }
+        self.recently_closed.push_back((addr, state));
+    }
+
     pub fn get_active_connections(&self) -> Vec<(SocketAddr, ConnectionState)> {
         self.active_connections
             .iter()
             .map(|(k, v)| (*k, v.clone()))
             .collect()
     }
+
+    /// Open connections with their age and traffic, sorted by address
+    /// Connections registered without a handle report zero age and traffic
+    pub fn connections(&self) -> Vec<ConnectionInfo> {
+        let mut connections: Vec<ConnectionInfo> = self
+            .active_connections
+            .iter()
+            .map(|(addr, state)| {
+                let meter = self.handles.get(addr).map(|handle| &handle.meter);
+                ConnectionInfo {
+                    addr: *addr,
+                    state: state.clone(),
+                    age: meter.map_or(Duration::ZERO, |m| m.age()),
+                    idle: meter.map_or(Duration::ZERO, |m| m.idle()),
+                    rx_bytes: meter.map_or(0, |m| m.traffic().rx()),
+                    tx_bytes: meter.map_or(0, |m| m.traffic().tx()),
+                    throughput_bps: meter.map_or(0.0, |m| m.traffic().bps()),
+                }
+            })
+            .collect();
+        connections.sort_by_key(|info| info.addr);
+        connections
+    }
+
+    /// Connection for handing to a newly accepted client's task; its traffic
+    /// counts towards `throughput`
+    pub fn new_handle(&self) -> ConnectionHandle {
+        ConnectionHandle::counted_in(self.throughput.clone())
+    }
+
+    /// Bits per second over every connection in the last `THROUGHPUT_WINDOW_SECS`
+    pub fn throughput_bps(&self) -> f64 {
+        self.throughput.bps()
+    }
+
+    /// Force-closes the connection from `addr`; false when it isn't open
+    /// Its task sees the close and records it as `Closed` when it ends
+    pub fn kill_connection(&self, addr: SocketAddr) -> bool {
+        match self.handles.get(&addr) {
+            Some(handle) => {
+                handle.close("closed by operator");
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Closes every connection idle for at least `idle_timeout`, returning their addresses
+    pub fn reap_idle(&self) -> Vec<SocketAddr> {
+        let Some(limit) = self.idle_timeout else {
+            return Vec::new();
+        };
+        let mut reaped = Vec::new();
+        for (addr, handle) in &self.handles {
+            if handle.close_reason().is_none() && handle.meter.idle() >= limit {
+                handle.close(format!("idle for {:?}", limit));
+                reaped.push(*addr);
+            }
+        }
+        reaped.sort();
+        reaped
+    }
+}
+
+/// Listener statistics
+/// Clones share the same counters, so the listener can update them while the
+/// web server reads them; the hot counters are lock-free atomics
+#[derive(Debug, Clone)]
+pub struct ServerState {
+    total_connections: Arc<AtomicU64>,
+    active_connections: Arc<AtomicU64>,
+    // Accepted connections per source address
+    sources: Arc<Mutex<HashMap<IpAddr, u64>>>,
+    // Accepted connections per local listener port
+    ports: Arc<Mutex<HashMap<u16, u64>>>,
+    // Addresses listeners actually bound to
+    bound: Arc<Mutex<Vec<SocketAddr>>>,
+    // Listeners currently accepting, and listeners that failed to bind
+    listeners_up: Arc<AtomicU64>,
+    listeners_failed: Arc<AtomicU64>,
+    start: Instant,
+}
+
+/// Listener availability as reported by `/health`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ListenerHealth {
+    pub healthy_listeners: u64,
+    pub failed_listeners: u64,
+}
+
+/// Traffic counters as reported by `/metrics`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerMetrics {
+    pub total_connections: u64,
+    pub active_connections: u64,
+    pub bytes_transferred: u64,
+    pub uptime_secs: u64,
+    pub connections_per_port: BTreeMap<u16, u64>, // Keyed by the local port that accepted
+}
+
+/// Point-in-time copy of the `ServerState` counters
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ServerSnapshot {
+    pub total_connections: u64,
+    pub active_connections: u64,
+    pub uptime_secs: u64,
+}
+
+impl ServerState {
+    pub fn new() -> Self {
+        Self {
+            total_connections: Arc::new(AtomicU64::new(0)),
+            active_connections: Arc::new(AtomicU64::new(0)),
+            sources: Arc::new(Mutex::new(HashMap::new())),
+            ports: Arc::new(Mutex::new(HashMap::new())),
+            bound: Arc::new(Mutex::new(Vec::new())),
+            listeners_up: Arc::new(AtomicU64::new(0)),
+            listeners_failed: Arc::new(AtomicU64::new(0)),
+            start: Instant::now(),
+        }
+    }
+
+    /// Counts a connection from `peer` accepted on local `port`; it stays active
+    /// until the guard drops
+    pub fn connection_opened(&self, peer: IpAddr, port: u16) -> ConnectionGuard {
+        *self.sources.lock().unwrap().entry(peer).or_default() += 1;
+        *self.ports.lock().unwrap().entry(port).or_default() += 1;
+        self.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard {
+            active_connections: self.active_connections.clone(),
+        }
+    }
+
+    /// Records an address a listener successfully bound to; it counts as healthy
+    /// until `listener_stopped`
+    pub fn listener_bound(&self, addr: SocketAddr) {
+        self.bound.lock().unwrap().push(addr);
+        self.listeners_up.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a bound listener leaving its accept loop
+    pub fn listener_stopped(&self) {
+        let _ = self
+            .listeners_up
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1));
+    }
+
+    /// Records a listener that could not bind
+    pub fn listener_failed(&self) {
+        self.listeners_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Current listener health counts
+    pub fn health(&self) -> ListenerHealth {
+        ListenerHealth {
+            healthy_listeners: self.listeners_up.load(Ordering::Relaxed),
+            failed_listeners: self.listeners_failed.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Addresses bound so far, in bind order
+    pub fn bound_addrs(&self) -> Vec<SocketAddr> {
+        self.bound.lock().unwrap().clone()
+    }
+
+    /// Assembles the end-of-run report from the shared counters; bytes come from
+    /// `traffic`, e.g. `CoreState::throughput`
+    pub fn summary(&self, error_count: usize, traffic: &Throughput) -> ShutdownSummary {
+        let mut ports_bound: Vec<u16> = self.bound_addrs().iter().map(|a| a.port()).collect();
+        ports_bound.sort_unstable();
+        ports_bound.dedup();
+
+        let mut top_sources: Vec<(IpAddr, u64)> = self
+            .sources
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(ip, count)| (*ip, *count))
+            .collect();
+        top_sources.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        top_sources.truncate(TOP_SOURCES);
+
+        ShutdownSummary {
+            ports_bound,
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            bytes_transferred: traffic.total(),
+            top_sources,
+            error_count,
+            uptime_secs: self.start.elapsed().as_secs(),
+        }
+    }
+
+    /// Totals and per-port connection counts since start; bytes come from `traffic`
+    pub fn metrics(&self, traffic: &Throughput) -> ServerMetrics {
+        ServerMetrics {
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            bytes_transferred: traffic.total(),
+            uptime_secs: self.start.elapsed().as_secs(),
+            connections_per_port: self.ports.lock().unwrap().clone().into_iter().collect(),
+        }
+    }
+
+    /// Reads the current counters and uptime
+    pub fn snapshot(&self) -> ServerSnapshot {
+        ServerSnapshot {
+            total_connections: self.total_connections.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            uptime_secs: self.start.elapsed().as_secs(),
+        }
+    }
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Connection report printed when the server stops
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ShutdownSummary {
+    pub ports_bound: Vec<u16>,
+    pub total_connections: u64,
+    pub bytes_transferred: u64,
+    pub top_sources: Vec<(IpAddr, u64)>, // Busiest sources, most connections first
+    pub error_count: usize,
+    pub uptime_secs: u64,
+}
+
+impl ShutdownSummary {
+    /// Writes the summary as `shutdown_summary_<timestamp>.json` inside `dir`
+    pub fn write_json(&self, dir: &Path) -> io::Result<PathBuf> {
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!(
+            "shutdown_summary_{}.json",
+            Local::now().format("%Y%m%d_%H%M%S")
+        ));
+        std::fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(path)
+    }
+}
+
+impl fmt::Display for ShutdownSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== Connection Summary ===")?;
+        writeln!(f, "Ports bound: {}", self.ports_bound.len())?;
+        writeln!(f, "Connections accepted: {}", self.total_connections)?;
+        writeln!(f, "Bytes transferred: {}", self.bytes_transferred)?;
+        writeln!(f, "Errors: {}", self.error_count)?;
+        writeln!(f, "Uptime: {}s", self.uptime_secs)?;
+        writeln!(f, "Top sources:")?;
+        for (ip, count) in &self.top_sources {
+            writeln!(f, "  {} ({} connections)", ip, count)?;
+        }
+        write!(f, "==========================")
+    }
+}
+
+/// Marks a connection active for as long as it is held
+pub struct ConnectionGuard {
+    active_connections: Arc<AtomicU64>,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_server_state_shared_between_tasks() {
+        let state = ServerState::new();
+        let listener_state = state.clone();
+        let peer = IpAddr::from([127, 0, 0, 1]);
+
+        let writer = tokio::spawn(async move {
+            for _ in 0..1000 {
+                let _guard = listener_state.connection_opened(peer, 8080);
+                tokio::task::yield_now().await;
+            }
+        });
+
+        // Reads never block the writer and never observe the total going backwards
+        let mut last_total = 0;
+        while !writer.is_finished() {
+            let snapshot = state.snapshot();
+            assert!(snapshot.total_connections >= last_total);
+            assert!(snapshot.active_connections <= 1);
+            last_total = snapshot.total_connections;
+            tokio::task::yield_now().await;
+        }
+        writer.await.unwrap();
+
+        let snapshot = state.snapshot();
+        assert_eq!(snapshot.total_connections, 1000);
+        assert_eq!(snapshot.active_connections, 0);
+    }
+
+    #[test]
+    fn test_closed_connections_leave_active_map_and_stay_bounded() {
+        let mut state = CoreState::new();
+        let addr = |port| SocketAddr::from(([10, 0, 0, 1], port));
+        for port in 0..(RECENTLY_CLOSED as u16 + 10) {
+            state.update_connection(addr(port), ConnectionState::Connected);
+            state.close_connection(addr(port), ConnectionState::Disconnected);
+        }
+        state.update_connection(addr(9000), ConnectionState::Connected);
+        state.close_connection(addr(9000), ConnectionState::Error("timed out".into()));
+
+        assert!(state.get_active_connections().is_empty());
+        assert_eq!(state.recently_closed.len(), RECENTLY_CLOSED);
+        assert_eq!(
+            state.recently_closed.back(),
+            Some(&(addr(9000), ConnectionState::Error("timed out".into())))
+        );
+        assert_eq!(state.recently_closed.front().unwrap().0, addr(11));
+    }
+
+    #[test]
+    fn test_kill_and_reap_close_registered_handles() {
+        let mut state = CoreState::new();
+        let (busy, quiet) = (
+            SocketAddr::from(([10, 0, 0, 1], 1)),
+            SocketAddr::from(([10, 0, 0, 2], 2)),
+        );
+        let busy_handle = state.new_handle();
+        let quiet_handle = state.new_handle();
+        state.open_connection(busy, busy_handle.clone());
+        state.open_connection(quiet, quiet_handle.clone());
+
+        assert_eq!(
+            state.reap_idle(),
+            Vec::<SocketAddr>::new(),
+            "no timeout set"
+        );
+        std::thread::sleep(Duration::from_millis(30));
+        busy_handle.meter.record_rx(10);
+        state.idle_timeout = Some(Duration::from_millis(20));
+        assert_eq!(state.reap_idle(), vec![quiet]);
+        assert!(quiet_handle.close_reason().unwrap().starts_with("idle"));
+        assert_eq!(busy_handle.close_reason(), None);
+
+        let listed = state.connections();
+        assert_eq!(listed.len(), 2);
+        assert_eq!((listed[0].addr, listed[0].rx_bytes), (busy, 10));
+        assert_eq!(state.throughput.rx(), 10);
+        assert!(state.throughput_bps() > 0.0);
+
+        assert!(state.kill_connection(busy));
+        assert_eq!(busy_handle.close_reason(), Some("closed by operator"));
+        state.close_connection(busy, ConnectionState::Disconnected);
+        assert!(!state.kill_connection(busy));
+    }
 }
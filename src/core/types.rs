@@ -1,9 +1,11 @@
+use crate::core::sockparse::{expand_targets, parse_ip_input, ParseError};
+use serde::{Deserialize, Serialize};
 use std::fmt;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, SocketAddr};
 
 /// Network address types supported by IPCow
 // Address type enum for specifying IP and socket protocol versions
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum AddrType {
     IPv4,
     IPv6,
@@ -16,36 +18,78 @@ pub enum AddrType {
 /// Used throughout the application for network endpoint representation
 #[derive(Debug, Clone)]
 pub struct AddrData {
-    pub info: AddrType,            // IP version (v4/v6)
-    pub socket_type: AddrType,     // Socket type (TCP/UDP)
-    pub address: (u8, u8, u8, u8), // IPv4 address octets
-    pub port: u16,                 // Port number
+    pub info: AddrType,        // IP version (v4/v6)
+    pub socket_type: AddrType, // Socket type (TCP/UDP)
+    pub address: IpAddr,       // IPv4 or IPv6 address
+    pub port: u16,             // Port number
+}
+
+impl AddrData {
+    /// Builds one target per address in `cidr` and port in `ports`, all using `protocol`
+    /// `cidr` accepts anything `parse_ip_input` does, e.g. "10.0.0.0/30" or "2001:db8::1"
+    pub fn from_spec(
+        cidr: &str,
+        ports: &[u16],
+        protocol: AddrType,
+    ) -> Result<Vec<AddrData>, ParseError> {
+        let ips = parse_ip_input(cidr)?;
+        Ok(expand_targets(&ips, ports, &[protocol]))
+    }
+
+    /// Returns a copy pointing at a neighboring port (`port + offset`)
+    /// Yields `None` instead of wrapping or panicking when the result leaves 0..=65535
+    pub fn with_port_offset(&self, offset: i32) -> Option<AddrData> {
+        let port = u16::try_from(i32::from(self.port).checked_add(offset)?).ok()?;
+        Some(AddrData {
+            port,
+            ..self.clone()
+        })
+    }
 }
 
 // Helper function to create SocketAddr from address components
-pub fn socket_addr_create(address: (u8, u8, u8, u8), port: u16) -> SocketAddr {
-    SocketAddr::from((
-        Ipv4Addr::new(address.0, address.1, address.2, address.3),
-        port,
-    ))
+// Yields `SocketAddr::V6` for IPv6 addresses so listeners bind the right family
+pub fn socket_addr_create(address: IpAddr, port: u16) -> SocketAddr {
+    SocketAddr::new(address, port)
 }
 
 /// Connection state for managed connections
 /// Tracks the current status of network connections
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ConnectionState {
-    Connected,     // Active connection
-    Disconnected,  // Terminated connection
-    Error(String), // Failed connection with error message
+    Connected,      // Active connection
+    Disconnected,   // Terminated connection
+    Closed(String), // Closed on purpose (operator kill or idle reaper) with the reason
+    Error(String),  // Failed connection with error message
 }
 
 /// Network configuration settings
 /// Contains tunable parameters for connection management
 #[derive(Debug, Clone)]
 pub struct NetworkConfig {
-    pub max_connections: usize,       // Maximum concurrent connections
-    pub timeout: std::time::Duration, // Connection/operation timeout
-    pub retry_attempts: u32,          // Number of retry attempts
+    pub max_connections: usize,             // Maximum concurrent connections
+    pub timeout: std::time::Duration,       // Connection/operation timeout
+    pub retry_attempts: u32,                // Number of retry attempts
+    pub retry_backoff: std::time::Duration, // Delay before the first retry, doubled after each
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 1000,
+            timeout: std::time::Duration::from_secs(30),
+            retry_attempts: 3,
+            retry_backoff: std::time::Duration::from_millis(250),
+        }
+    }
+}
+
+impl NetworkConfig {
+    /// Wait before retry number `attempt` (0-based): `retry_backoff * 2^attempt`
+    pub fn backoff(&self, attempt: u32) -> std::time::Duration {
+        self.retry_backoff.saturating_mul(2_u32.saturating_pow(attempt))
+    }
 }
 
 /// Custom error type for network operations
@@ -57,6 +101,8 @@ pub enum NetworkError {
     InvalidPort,              // Invalid port number
     Timeout,                  // Operation timeout
     IoError(std::io::Error),  // Underlying IO error
+    PermissionDenied(String), // Needs privileges the process lacks (e.g. raw sockets)
+    InvalidConfig(String),    // Settings that contradict each other
 }
 
 // Implementation of Display trait for NetworkError
@@ -69,6 +115,8 @@ impl fmt::Display for NetworkError {
             NetworkError::InvalidPort => write!(f, "Invalid port"),
             NetworkError::Timeout => write!(f, "Operation timed out"),
             NetworkError::IoError(e) => write!(f, "IO error: {}", e),
+            NetworkError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            NetworkError::InvalidConfig(msg) => write!(f, "Invalid configuration: {}", msg),
         }
     }
 }
@@ -84,3 +132,54 @@ impl From<std::io::Error> for NetworkError {
 
 /// Result type for network operations
 pub type NetworkResult<T> = Result<T, NetworkError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr_with_port(port: u16) -> AddrData {
+        AddrData {
+            info: AddrType::IPv4,
+            socket_type: AddrType::TCP,
+            address: IpAddr::from([127, 0, 0, 1]),
+            port,
+        }
+    }
+
+    #[test]
+    fn test_from_spec_expands_cidr_and_ports() {
+        let targets = AddrData::from_spec("10.0.0.0/30", &[80, 443], AddrType::UDP).unwrap();
+        assert_eq!(targets.len(), 8);
+        assert!(targets
+            .iter()
+            .all(|t| t.info == AddrType::IPv4 && t.socket_type == AddrType::UDP));
+
+        let pairs: Vec<(IpAddr, u16)> = targets.iter().map(|t| (t.address, t.port)).collect();
+        let expected: Vec<(IpAddr, u16)> = (0..4)
+            .flat_map(|host| [80, 443].map(|port| (IpAddr::from([10, 0, 0, host]), port)))
+            .collect();
+        assert_eq!(pairs, expected);
+
+        assert!(AddrData::from_spec("10.0.0.0/33", &[80], AddrType::TCP).is_err());
+    }
+
+    #[test]
+    fn test_port_offset_overflow_returns_none() {
+        assert!(addr_with_port(65535).with_port_offset(1).is_none());
+        assert!(addr_with_port(0).with_port_offset(-1).is_none());
+        assert!(addr_with_port(80).with_port_offset(i32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_port_offset_within_range() {
+        let base = addr_with_port(8080);
+        let next = base.with_port_offset(1).unwrap();
+        assert_eq!(next.port, 8081);
+        assert_eq!(next.address, base.address);
+        assert_eq!(base.with_port_offset(-80).unwrap().port, 8000);
+        assert_eq!(
+            addr_with_port(65534).with_port_offset(1).unwrap().port,
+            65535
+        );
+    }
+}
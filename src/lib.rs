@@ -12,6 +12,9 @@ pub mod core;
 pub mod modules;
 // Utility functions and helpers
 pub mod utils;
+// Ephemeral servers for tests and benches
+#[cfg(any(test, feature = "testing"))]
+pub mod test_support;
 
 // Re-export core components
 pub use crate::core::CoreConfig;
@@ -21,9 +24,10 @@ pub use crate::core::LogLevel;
 // Re-export commonly used types and functions for easier access
 pub use crate::core::{
     error::ErrorRegistry,        // Error tracking and management
-    handlers::handle_connection, // Connection handling
-    network::ListenerManager,    // Multi-threaded listener management
+    handlers::{handle_connection, HandlerConfig, PortBehavior, RequestThrottle}, // Connection handling
+    network::{ListenerConfig, ListenerManager}, // Multi-threaded listener management
     sockparse::addr_input,       // Address parsing utilities
+    state::ServerState,          // Shared connection counters
     types::{AddrData, AddrType}, // Network address type definitions
     ServiceDiscovery,            // Service discovery and logging
 };
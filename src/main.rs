@@ -28,17 +28,23 @@
  */
 
 use clap::{ArgAction, ArgGroup, Parser, Subcommand};
-use ipcow::core::IPCowCore;
+use ipcow::core::network::DRAIN_TIMEOUT;
+use ipcow::core::{IPCowCore, DEFAULT_WEB_ADDR};
 use ipcow::modules::*;
 use ipcow::{
-    core::{error::ErrorRegistry, sockparse::addr_input, ascii_cube::{display_rotating_cube}},
-    utils::helpers::get_thread_factor,
+    core::{discovery::{ServiceDiscovery, DISCOVERY_LOG_FILE}, fingerprint::FingerprintDb, error::{ErrorRegistry, ExportFormat}, handlers::{FaultInjector, HandlerConfig, PortBehavior}, signals::{ForcedShutdown, SignalSet}, sockparse::{addr_spec_input, addr_spec_input_from_file, addr_spec_input_from_file_lenient, addr_spec_input_with, expand_target_specs, parse_ip_input_with_exclusions, parse_port_spec, ParseError}, ascii_cube::{display_rotating_cube}},
+    utils::{helpers::{get_thread_factor_with, parse_duration, thread_factor_override}, RngSource},
     AddrData, AddrType, ListenerManager,
     modules::ping,  // Add ping module
 };
 use std::io::{self, Write};
-use std::sync::Arc;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
 
 /// A high-performance, async TCP server & tool for bug bounty/pentests.
 #[derive(Parser, Debug)]
@@ -81,20 +87,209 @@ struct Cli {
     #[arg(long, group = "mode", action = ArgAction::SetTrue)]
     test_network: bool,
 
+    /// Read listener IPs and ports from a file instead of prompting
+    /// (one IP or port spec per line, '#' comments); starts the Multi-Port TCP Server
+    #[arg(long, value_name = "FILE")]
+    targets: Option<PathBuf>,
+
+    /// Listener IPs for the Multi-Port TCP Server instead of prompting for them
+    /// (same syntax as the prompt, e.g. "127.0.0.1, 10.0.0.0/24 !10.0.0.1");
+    /// starts the server, prompting only for --ports if that is missing
+    // Fully qualified so clap takes the whole spec as one value
+    #[arg(long, value_name = "SPEC", conflicts_with = "targets", value_parser = ip_spec)]
+    ips: Option<::std::vec::Vec<IpAddr>>,
+
+    /// Listener ports for the Multi-Port TCP Server instead of prompting for them
+    /// (same syntax as the prompt, e.g. "8000-8010, 53/udp, web");
+    /// starts the server, prompting only for --ips if that is missing
+    #[arg(long, value_name = "SPEC", conflicts_with = "targets", value_parser = port_spec)]
+    ports: Option<::std::vec::Vec<(u16, AddrType)>>,
+
+    /// With --targets, skip malformed entries (reporting each) instead of rejecting the file
+    #[arg(long, requires = "targets", action = ArgAction::SetTrue)]
+    lenient: bool,
+
+    /// Echo every connection back until the client closes or sends CRLF (loopback testing);
+    /// starts the Multi-Port TCP Server
+    #[arg(long, action = ArgAction::SetTrue)]
+    echo: bool,
+
+    /// Answer UDP datagrams (echo, chargen, ...) instead of only recording them;
+    /// replies never exceed the request's size, but spoofed senders can still
+    /// aim them at a third party, so leave this off on exposed addresses
+    #[arg(long, action = ArgAction::SetTrue)]
+    udp_replies: bool,
+
+    /// Name recorded services with the rules in FILE (`<regex|prefix> <pattern> => <service>`
+    /// per line), tried before the built-in rules
+    #[arg(long, value_name = "FILE", value_parser = fingerprint_rules)]
+    fingerprints: Option<Arc<FingerprintDb>>,
+
+    /// Drop this share of connections (0.0-1.0) right after accepting them, to simulate
+    /// a flaky service; replayable with --seed
+    #[arg(long, value_name = "RATE", value_parser = fault_rate)]
+    fault_rate: Option<f64>,
+
+    /// Save each connection's probe exchange as a pcap file in DIR (created if missing)
+    #[cfg(feature = "pcap")]
+    #[arg(long, value_name = "DIR")]
+    capture_dir: Option<PathBuf>,
+
+    /// Stop after this much wall-clock time in any mode (e.g. 90s, 30m, 2h);
+    /// a running server shuts down gracefully and prints its summary, and a mode that
+    /// can't stop in time (e.g. waiting at a prompt) exits with status 124
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    max_runtime: Option<Duration>,
+
+    /// Close client connections that carry no traffic for this long (e.g. 30s, 5m);
+    /// the Connection Management Tools can change it while the server runs
+    #[arg(long, global = true, value_name = "DURATION", value_parser = parse_duration)]
+    idle_timeout: Option<Duration>,
+
+    /// Save the error registry to FILE (CSV for .csv, JSON otherwise) when the
+    /// server stops or the error registry module runs
+    #[arg(long, value_name = "FILE")]
+    error_out: Option<PathBuf>,
+
+    /// Write log files (discovered services, host status) into DIR instead of the
+    /// working directory, so several instances don't overwrite each other's logs
+    #[arg(long, global = true, value_name = "DIR")]
+    log_dir: Option<PathBuf>,
+
+    /// Serve the web dashboard on ADDR while the Multi-Port TCP Server runs
+    /// (127.0.0.1:3030 when given without a value); off unless asked for.
+    /// The Connection Management Tools reach the server's dashboard there too
+    #[arg(
+        long,
+        global = true,
+        value_name = "ADDR",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "127.0.0.1:3030"
+    )]
+    dashboard: Option<SocketAddr>,
+
+    /// Require `Authorization: Bearer TOKEN` on the web dashboard's data endpoints
+    /// (`/health` stays open); falls back to the IPCOW_WEB_TOKEN environment variable
+    #[arg(long, global = true, value_name = "TOKEN")]
+    web_token: Option<String>,
+
+    /// Measure the optimal worker count again instead of reusing the metrics
+    /// cached for this machine
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    rebench: bool,
+
+    /// Use exactly N worker threads, skipping the benchmark and leaving the
+    /// cached metrics untouched (reproducible runs, CI)
+    #[arg(long, global = true, value_name = "N", conflicts_with = "rebench")]
+    workers: Option<NonZeroUsize>,
+
+    /// Probe scanned hosts in random order instead of the order given (--test-network);
+    /// replayable with --seed
+    #[arg(long, global = true, action = ArgAction::SetTrue)]
+    shuffle_hosts: bool,
+
+    /// Seed every randomized feature (--shuffle-hosts, --fault-rate, animations)
+    /// so a run can be replayed; without it behavior is random on every run
+    #[arg(long, global = true, value_name = "N")]
+    seed: Option<u64>,
+
     /// Optional subcommands if you want more structured CLI
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+// Parses --ips up front so a typo is reported before the server starts
+fn ip_spec(spec: &str) -> Result<Vec<IpAddr>, ParseError> {
+    parse_ip_input_with_exclusions(spec.trim())
+}
+
+// Parses --ports up front so a typo is reported before the server starts
+fn port_spec(spec: &str) -> Result<Vec<(u16, AddrType)>, ParseError> {
+    parse_port_spec(spec.trim())
+}
+
+// Rejects a --fault-rate outside 0.0-1.0 instead of silently clamping it
+fn fault_rate(rate: &str) -> Result<f64, String> {
+    match rate.trim().parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        Ok(_) => Err("must be between 0.0 and 1.0".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Loads --fingerprints up front so a bad rule is reported before the server starts
+fn fingerprint_rules(path: &str) -> io::Result<Arc<FingerprintDb>> {
+    FingerprintDb::load(Path::new(path)).map(|db| Arc::new(db.with_defaults()))
+}
+
 /// Example subcommands (optional):
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Example subcommand to show usage
     ExampleSub,
+
+    /// Re-render a saved JSON scan report without re-scanning
+    Report {
+        /// Path to the saved report
+        path: PathBuf,
+
+        /// Output format: table, csv, grepable, json or xml
+        #[arg(long, default_value = "table")]
+        output_format: report::OutputFormat,
+
+        /// Print host/port counts instead of the full listing
+        #[arg(long, action = ArgAction::SetTrue)]
+        summary: bool,
+
+        /// Show ports added or removed relative to another saved report
+        #[arg(long, value_name = "OTHER")]
+        diff: Option<PathBuf>,
+    },
+}
+
+/// Loads a saved scan report and prints it, a summary, or a diff against `other`
+fn run_report(
+    path: &Path,
+    format: report::OutputFormat,
+    summary: bool,
+    other: Option<&Path>,
+) -> io::Result<()> {
+    let session = ScanSession::load(path)?;
+    if let Some(other) = other {
+        let diff = report::ReportDiff::between(&ScanSession::load(other)?, &session);
+        if diff.is_empty() {
+            println!("No differences");
+        } else {
+            print!("{}", diff);
+        }
+    } else if summary {
+        println!("{}", report::ReportSummary::from_session(&session));
+    } else {
+        print!("{}", report::render(&session, format));
+    }
+    Ok(())
 }
 
 fn main() {
     let cli = Cli::parse();
+    if let Some(seed) = cli.seed {
+        RngSource::set_global(seed);
+    }
+    if let Some(max_runtime) = cli.max_runtime {
+        arm_max_runtime(max_runtime);
+    }
+    REBENCH.store(cli.rebench, Ordering::SeqCst);
+    if let Some(limit) = cli.idle_timeout {
+        let _ = IDLE_TIMEOUT.set(limit);
+    }
+    if let Some(workers) = cli.workers {
+        thread_factor_override(workers.get());
+    }
+    let web_token = cli.web_token.clone().or_else(|| std::env::var("IPCOW_WEB_TOKEN").ok());
+    if let Some(token) = web_token.filter(|token| !token.is_empty()) {
+        let _ = WEB_TOKEN.set(token);
+    }
 
     if let Some(cmd) = cli.command {
         match cmd {
@@ -102,12 +297,35 @@ fn main() {
                 println!("You invoked the 'example-sub' subcommand!");
                 return;
             }
+            Commands::Report { path, output_format, summary, diff } => {
+                if let Err(e) = run_report(&path, output_format, summary, diff.as_deref()) {
+                    eprintln!("Failed to render report: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
         }
     }
 
     // Handle direct module invocations
-    if cli.multi_port_server {
-        let _ = start_multi_port_server();
+    let addr_given = cli.ips.is_some() || cli.ports.is_some();
+    if cli.multi_port_server || cli.targets.is_some() || addr_given || cli.echo {
+        let result = start_multi_port_server(ServerOptions {
+            targets: cli.targets.clone(),
+            lenient: cli.lenient,
+            ips: cli.ips.clone(),
+            ports: cli.ports.clone(),
+            echo: cli.echo,
+            ..ServerOptions::from_cli(&cli)
+        });
+        if let Some(path) = &cli.error_out {
+            export_error_registry(path);
+        }
+        if let Err(e) = result {
+            exit_if_forced(&*e);
+            eprintln!("[IPCow] Multi-Port TCP Server failed: {}", e);
+            std::process::exit(1);
+        }
         return;
     }
     if cli.service_discovery {
@@ -115,7 +333,7 @@ fn main() {
         return;
     }
     if cli.connection_mgmt {
-        let _ = manage_connections();
+        let _ = manage_connections(cli.dashboard.unwrap_or(DEFAULT_WEB_ADDR));
         return;
     }
     if cli.web_interface {
@@ -131,26 +349,34 @@ fn main() {
         return;
     }
     if cli.error_registry {
-        let _ = run_error_registry();
+        let _ = run_error_registry(cli.error_out.as_deref());
         return;
     }
     if cli.test_network {
-        let _ = run_network_tests();
+        let _ = run_network_tests(cli.log_dir.as_deref(), cli.shuffle_hosts);
         return;
     }
 
     // Interactive menu loop
     loop {
         print_main_menu();
-        match prompt_user("> ").trim() {
+        let Some(choice) = prompt_line("> ") else {
+            println!("\nInput closed. Exiting IPCow.");
+            break;
+        };
+        match choice.trim() {
             "1" => {
-                let _ = start_multi_port_server();
+                let result = start_multi_port_server(ServerOptions::from_cli(&cli));
+                if let Err(e) = result {
+                    exit_if_forced(&*e);
+                    eprintln!("[IPCow] Multi-Port TCP Server failed: {}", e);
+                }
             }
             "2" => {
                 let _ = run_service_discovery();
             }
             "3" => {
-                let _ = manage_connections();
+                let _ = manage_connections(cli.dashboard.unwrap_or(DEFAULT_WEB_ADDR));
             }
             "4" => {
                 let _ = start_web_interface();
@@ -165,7 +391,7 @@ fn main() {
                 let _ = show_error_registry();
             }
             "8" => {
-                let _ = run_network_tests();    // Add this case
+                let _ = run_network_tests(cli.log_dir.as_deref(), cli.shuffle_hosts);    // Add this case
             }
             "9" => {
                 let _ = display_rotating_cube();
@@ -176,6 +402,10 @@ fn main() {
             }
             _ => println!("Invalid choice. Please try again."),
         }
+        if runtime_token().is_cancelled() {
+            println!("Maximum runtime reached. Exiting IPCow.");
+            break;
+        }
     }
 }
 
@@ -198,65 +428,179 @@ fn print_main_menu() {
 }
 
 fn prompt_user(prompt: &str) -> String {
+    prompt_line(prompt).unwrap_or_default()
+}
+
+/// Like `prompt_user`, but `None` once stdin is closed
+fn prompt_line(prompt: &str) -> Option<String> {
     print!("{}", prompt);
     // Flush stdout so the prompt appears immediately
     io::stdout().flush().unwrap();
 
     let mut input = String::new();
-    io::stdin()
-        .read_line(&mut input)
-        .expect("Failed to read line");
-    input
+    match io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input),
+    }
 }
 
 // -------------------------------
 // Mock module implementations
 // -------------------------------
 
+/// How `start_multi_port_server` picks its listeners and serves them
+/// Targets come from `targets` when given, otherwise from `ips`/`ports`, prompting
+/// only for a missing one
+#[derive(Debug, Clone, Default)]
+struct ServerOptions {
+    targets: Option<PathBuf>,                 // Targets file instead of the prompt
+    lenient: bool,                            // Skip malformed `targets` entries
+    ips: Option<Vec<IpAddr>>,                 // Listener IPs given up front
+    ports: Option<Vec<(u16, AddrType)>>,      // Listener ports given up front
+    echo: bool,                               // CRLF-terminated echo loop on every connection
+    udp_replies: bool,                        // Answer UDP datagrams, not only record them
+    log_dir: Option<PathBuf>,                 // Log directory instead of the working directory
+    fingerprints: Option<Arc<FingerprintDb>>, // Custom rules ahead of the built-in ones
+    dashboard: Option<SocketAddr>,            // Serve the web dashboard here (off when None)
+    fault_rate: Option<f64>,                  // Share of connections dropped on accept
+    #[cfg(feature = "pcap")]
+    capture_dir: Option<PathBuf>,             // One pcap file per connection (off when None)
+}
+
+impl ServerOptions {
+    /// The options every server started from this command line shares; targets
+    /// and echo mode are left to the caller
+    fn from_cli(cli: &Cli) -> Self {
+        Self {
+            udp_replies: cli.udp_replies,
+            log_dir: cli.log_dir.clone(),
+            fingerprints: cli.fingerprints.clone(),
+            dashboard: cli.dashboard,
+            fault_rate: cli.fault_rate,
+            #[cfg(feature = "pcap")]
+            capture_dir: cli.capture_dir.clone(),
+            ..Default::default()
+        }
+    }
+}
+
 /// Initializes networking components and starts the listener manager
 #[tokio::main]
-async fn start_multi_port_server() -> Result<(), Box<dyn std::error::Error>> {
+async fn start_multi_port_server(
+    options: ServerOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let ServerOptions {
+        targets,
+        lenient,
+        ips,
+        ports,
+        echo,
+        udp_replies,
+        log_dir,
+        fingerprints,
+        dashboard,
+        fault_rate,
+        #[cfg(feature = "pcap")]
+        capture_dir,
+    } = options;
     println!("\n[IPCow] Starting Multi-Port TCP Server...");
 
-    let core = IPCowCore::new();
-    let max_workers = get_thread_factor();
-    let (ips_vec, ports_vec) = addr_input();
-
-    let ips: Arc<Vec<std::net::IpAddr>> =
-        Arc::new(ips_vec.into_iter().map(std::net::IpAddr::V4).collect());
-    let ports: Arc<Vec<u16>> = Arc::new(ports_vec);
+    let mut core = IPCowCore::new();
+    core.error_manager = shared_error_registry();
+    core.config.web_token = WEB_TOKEN.get().cloned();
+    core.set_idle_timeout(IDLE_TIMEOUT.get().copied()).await;
+    let max_workers = get_thread_factor_with(REBENCH.load(Ordering::SeqCst));
+    let (ips, ports) = match targets.as_deref() {
+        Some(path) if lenient => {
+            let (specs, rejects) = addr_spec_input_from_file_lenient(path)?;
+            for (line, entry, e) in &rejects {
+                eprintln!("[IPCow] {}:{}: skipping {:?}: {}", path.display(), line, entry, e);
+            }
+            specs
+        }
+        Some(path) => addr_spec_input_from_file(path)?,
+        None if ips.is_some() || ports.is_some() => addr_spec_input_with(ips, ports)?,
+        None => addr_spec_input()?,
+    };
 
     println!("\nServer Configuration:");
     println!("- Worker threads: {}", max_workers);
     println!("- IP addresses: {}", ips.len());
     println!("- Ports per IP: {}", ports.len());
 
-    let addr_data_list: Vec<AddrData> = ips
-        .iter()
-        .flat_map(|ip| {
-            ports.iter().map(move |port| AddrData {
-                info: AddrType::IPv4,
-                socket_type: AddrType::TCP,
-                address: match ip {
-                    std::net::IpAddr::V4(ipv4) => ipv4.octets().into(),
-                    _ => panic!("IPv6 not supported"),
-                },
-                port: *port,
-            })
-        })
-        .collect();
+    let addr_data_list: Vec<AddrData> = expand_target_specs(&ips, &ports);
 
     println!("- Total listeners: {}", addr_data_list.len());
+    if echo {
+        println!("- Mode: echo until CRLF");
+    }
+    if let Some(limit) = IDLE_TIMEOUT.get() {
+        println!("- Idle timeout: {:?}", limit);
+    }
+    if let Some(db) = &fingerprints {
+        println!("- Fingerprint rules: {}", db.rules().len());
+    }
+    if let Some(rate) = fault_rate {
+        println!("- Fault injection: dropping {:.0}% of connections", rate * 100.0);
+    }
+    #[cfg(feature = "pcap")]
+    if let Some(dir) = &capture_dir {
+        std::fs::create_dir_all(dir)?;
+        println!("- Packet captures: {}", dir.display());
+    }
+    let handler_config = HandlerConfig {
+        default_behavior: if echo {
+            PortBehavior::EchoLine
+        } else {
+            PortBehavior::default()
+        },
+        udp_replies,
+        fingerprints: fingerprints.unwrap_or_else(FingerprintDb::shared),
+        fault_injector: fault_rate
+            .map(|rate| Arc::new(FaultInjector::new(rate, &RngSource::global()))),
+        #[cfg(feature = "pcap")]
+        capture_dir,
+        ..Default::default()
+    };
 
-    {
-        let mut network_manager = core.network_manager.lock().await;
-        *network_manager = ListenerManager::new(addr_data_list, max_workers);
+    let mut discovery = ServiceDiscovery::new();
+    if let Some(dir) = &log_dir {
+        std::fs::create_dir_all(dir)?;
+        discovery = discovery.with_log_file(dir.join(DISCOVERY_LOG_FILE));
     }
+    let discovery = Arc::new(discovery);
+    // Every listener waiting for a client holds a slot, so leave `max_workers` for clients
+    let max_concurrent = addr_data_list.len() + max_workers;
+    *core.network_manager.lock().await = ListenerManager::new(addr_data_list, max_concurrent)
+        .with_handler_config(handler_config)
+        .with_server_state(core.server_state.clone())
+        .with_core_state(core.state.clone())
+        .with_error_registry(core.error_manager.clone())
+        .with_service_discovery(discovery.clone())
+        .with_shutdown(core.shutdown_token());
 
-    println!("\nPress Ctrl+C to stop the server...\n");
-    core.start().await?;
+    if let Some(addr) = dashboard {
+        core.config.web_addr = addr;
+        // The dashboard reads the same counters and discoveries as the listeners
+        let dashboard = web_server::WebServer::for_core(&core).with_discovery(discovery);
+        let shutdown = core.shutdown_token();
+        tokio::spawn(async move {
+            let bound = |addr| println!("- Web dashboard: http://{}/metrics", addr);
+            if let Err(e) = dashboard.run_until(shutdown, bound).await {
+                eprintln!("[IPCow] Web dashboard unavailable: {}", e);
+            }
+        });
+    }
 
-    Ok(())
+    println!("\nPress Ctrl+C to stop the server...\n");
+    let signals = SignalSet {
+        deadline: DEADLINE.get().copied(),
+        ..Default::default()
+    };
+    SERVER_RUNNING.store(true, Ordering::SeqCst);
+    let result = core.run_until_signal(signals).await;
+    SERVER_RUNNING.store(false, Ordering::SeqCst);
+    result
 }
 
 fn run_service_discovery() -> Result<(), Box<dyn std::error::Error>> {
@@ -267,45 +611,282 @@ fn run_service_discovery() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn manage_connections() -> Result<(), Box<dyn std::error::Error>> {
+/// Lists, closes and reaps connections of a server running in another process,
+/// through its dashboard on `--dashboard` (authenticated with `--web-token`)
+fn manage_connections(dashboard: SocketAddr) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] Opening Connection Management Tools...");
-    // TODO: Implement timeouts, graceful shutdown, etc.
-    println!("(Stub) Connection management. Press ENTER to return.");
-    wait_enter();
-    Ok(())
+    loop {
+        println!("\n1) List open connections");
+        println!("2) Close a connection");
+        println!("3) Set idle timeout");
+        println!("4) Back");
+        match prompt_user("> ").trim() {
+            "1" => match dashboard_request(dashboard, "GET", "/connections") {
+                Ok((200, body)) => print_connections(&body),
+                Ok((status, _)) => eprintln!("Dashboard answered {}", status),
+                Err(e) => eprintln!("Dashboard on {} unreachable: {}", dashboard, e),
+            },
+            "2" => {
+                let input = prompt_user("Client address (ip:port): ");
+                let Ok(addr) = input.trim().parse::<SocketAddr>() else {
+                    println!("Not an ip:port address: {}", input.trim());
+                    continue;
+                };
+                match dashboard_request(dashboard, "DELETE", &format!("/connections/{}", addr)) {
+                    Ok((204, _)) => println!("Closed connection from {}", addr),
+                    Ok((404, _)) => println!("No open connection from {}", addr),
+                    Ok((status, _)) => eprintln!("Dashboard answered {}", status),
+                    Err(e) => eprintln!("Dashboard on {} unreachable: {}", dashboard, e),
+                }
+            }
+            "3" => {
+                let input = prompt_user("Idle timeout (e.g. 30s, 5m; 0 disables): ");
+                let secs = match input.trim() {
+                    "0" | "off" => 0,
+                    spec => match parse_duration(spec) {
+                        // The dashboard takes whole seconds; round up so it never closes early
+                        Ok(limit) => limit.as_secs() + u64::from(limit.subsec_nanos() > 0),
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    },
+                };
+                let path = format!("/connections/idle-timeout/{}", secs);
+                match dashboard_request(dashboard, "PUT", &path) {
+                    Ok((204, _)) if secs == 0 => println!("Idle timeout disabled"),
+                    Ok((204, _)) => println!("Connections idle for {}s will be closed", secs),
+                    Ok((status, _)) => eprintln!("Dashboard answered {}", status),
+                    Err(e) => eprintln!("Dashboard on {} unreachable: {}", dashboard, e),
+                }
+            }
+            // Blank input also covers a closed stdin
+            "4" | "" => return Ok(()),
+            _ => println!("Invalid choice. Please try again."),
+        }
+    }
+}
+
+/// Sends one bodiless request to the dashboard on `addr`, returning the status code and body
+fn dashboard_request(addr: SocketAddr, method: &str, path: &str) -> io::Result<(u16, String)> {
+    use std::io::Read;
+
+    let mut stream = std::net::TcpStream::connect_timeout(&addr, Duration::from_secs(2))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: 0\r\n",
+        method, path, addr
+    );
+    if let Some(token) = WEB_TOKEN.get() {
+        request.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response"))?;
+    Ok((status, body.to_string()))
+}
+
+/// Prints the dashboard's `/connections` listing as a table
+fn print_connections(body: &str) {
+    let connections: Vec<serde_json::Value> = serde_json::from_str(body).unwrap_or_default();
+    if connections.is_empty() {
+        println!("No open connections");
+        return;
+    }
+    println!(
+        "{:<24} {:<12} {:>8} {:>8} {:>12} {:>12} {:>12}",
+        "ADDRESS", "STATE", "AGE", "IDLE", "RX BYTES", "TX BYTES", "BIT/S"
+    );
+    for connection in &connections {
+        let state = &connection["state"];
+        println!(
+            "{:<24} {:<12} {:>7}s {:>7}s {:>12} {:>12} {:>12.0}",
+            connection["addr"].as_str().unwrap_or("?"),
+            state.as_str().map_or_else(|| state.to_string(), str::to_string),
+            connection["age_secs"].as_u64().unwrap_or_default(),
+            connection["idle_secs"].as_u64().unwrap_or_default(),
+            connection["rx_bytes"].as_u64().unwrap_or_default(),
+            connection["tx_bytes"].as_u64().unwrap_or_default(),
+            connection["throughput_bps"].as_f64().unwrap_or_default(),
+        );
+    }
 }
 
 #[tokio::main]
 async fn start_web_interface() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] [WIP:3030]Launching Web Interface / Dashboard...");
-    web_server::run_web_server().await;
+    let shutdown = runtime_token().child_token();
+    tokio::spawn(cancel_on_ctrl_c(shutdown.clone(), "Stopping the dashboard"));
+    web_server::run_web_server(shutdown).await?;
     Ok(())
 }
 
-fn run_fuzzing_module() -> Result<(), Box<dyn std::error::Error>> {
+#[tokio::main]
+async fn run_fuzzing_module() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] Starting Fuzzing & Traffic Analysis...");
-    // TODO: Fuzzing logic, custom payload injection
-    println!("(Stub) Fuzzing completed. Press ENTER to return.");
+
+    let target = prompt_user("Fuzz target (ip:port, blank to skip): ");
+    if let Ok(addr) = target.trim().parse::<std::net::SocketAddr>() {
+        let corpus = prompt_user("Seed corpus directory: ");
+        let report = prompt_user("Anomaly report file [fuzz_report.txt]: ");
+        let report = match report.trim() {
+            "" => "fuzz_report.txt",
+            path => path,
+        };
+        let runtime = runtime_token();
+        let run = tokio::select! {
+            run = fuzzing::run_fuzzer(addr, Path::new(corpus.trim()), Path::new(report)) => run,
+            _ = runtime.cancelled() => {
+                println!("Fuzzing stopped: maximum runtime reached");
+                return Ok(());
+            }
+        };
+        match run {
+            Ok(anomalies) => {
+                for anomaly in &anomalies {
+                    println!("  ! {}", anomaly);
+                }
+                println!("{} anomalies written to {}", anomalies.len(), report);
+            }
+            Err(e) => eprintln!("Fuzzing failed: {}", e),
+        }
+    }
+    println!("Fuzzing completed. Press ENTER to return.");
     wait_enter();
     Ok(())
 }
 
-fn run_performance_metrics() -> Result<(), Box<dyn std::error::Error>> {
+#[tokio::main]
+async fn run_performance_metrics() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] Gathering Performance & Metrics...");
     // TODO: concurrency tests, resource usage stats
+
+    // Optional latency breakdown against a single HTTP endpoint
+    let target = prompt_user("Latency probe target (ip:port, blank to skip): ");
+    if let Ok(addr) = target.trim().parse::<std::net::SocketAddr>() {
+        let request = format!("GET / HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", addr.ip());
+        match ping::timing_probe(addr, request.as_bytes()).await {
+            Ok(timing) => {
+                println!("Connect: {:?}", timing.connect);
+                println!("Time to first byte: {:?}", timing.ttfb);
+                println!("Total: {:?}", timing.total);
+            }
+            Err(e) => eprintln!("Latency probe failed: {}", e),
+        }
+    }
     println!("(Stub) Performance metrics done. Press ENTER to return.");
     wait_enter();
     Ok(())
 }
 
-fn run_error_registry() -> Result<(), Box<dyn std::error::Error>> {
+/// When `--max-runtime` runs out
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+/// Set by `--rebench`: ignore cached benchmark metrics
+static REBENCH: AtomicBool = AtomicBool::new(false);
+/// Dashboard token from `--web-token` or IPCOW_WEB_TOKEN
+static WEB_TOKEN: OnceLock<String> = OnceLock::new();
+/// Set by `--idle-timeout`: servers close connections quiet for this long
+static IDLE_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+/// Set while a server is running; it stops itself at `DEADLINE`
+static SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Exit status when `--max-runtime` has to end a mode that didn't stop by itself
+const MAX_RUNTIME_EXIT_CODE: i32 = 124;
+/// Exit status when a second Ctrl+C (or SIGTERM) cuts a graceful shutdown short
+const FORCED_EXIT_CODE: i32 = 130;
+
+/// Cancelled once `--max-runtime` runs out; modules stop on it (or a child of it)
+fn runtime_token() -> CancellationToken {
+    static TOKEN: OnceLock<CancellationToken> = OnceLock::new();
+    TOKEN.get_or_init(CancellationToken::new).clone()
+}
+
+/// Cancels `runtime_token` once `max_runtime` has elapsed, whatever mode it is in
+/// The mode then gets a second to return, or a running server `DRAIN_TIMEOUT` plus a
+/// second, before the process exits with `MAX_RUNTIME_EXIT_CODE`
+fn arm_max_runtime(max_runtime: Duration) {
+    let deadline = *DEADLINE.get_or_init(|| Instant::now() + max_runtime);
+    std::thread::spawn(move || {
+        std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+        runtime_token().cancel();
+        let grace = if SERVER_RUNNING.load(Ordering::SeqCst) {
+            DRAIN_TIMEOUT + Duration::from_secs(1)
+        } else {
+            Duration::from_secs(1)
+        };
+        std::thread::sleep(grace);
+        eprintln!(
+            "\n[IPCow] Maximum runtime of {:?} reached and still running, exiting",
+            max_runtime
+        );
+        std::process::exit(MAX_RUNTIME_EXIT_CODE);
+    });
+}
+
+/// Cancels `cancel` on the first Ctrl+C, announcing `action`; a second Ctrl+C
+/// force-exits. Returns without waiting once `cancel` fires for another reason
+async fn cancel_on_ctrl_c(cancel: CancellationToken, action: &str) {
+    tokio::select! {
+        _ = cancel.cancelled() => return,
+        signal = tokio::signal::ctrl_c() => if signal.is_err() {
+            return;
+        },
+    }
+    eprintln!("\n{} (press Ctrl+C again to force exit)", action);
+    cancel.cancel();
+
+    if tokio::signal::ctrl_c().await.is_ok() {
+        eprintln!("Forced exit");
+        std::process::exit(FORCED_EXIT_CODE);
+    }
+}
+
+/// Exits with `FORCED_EXIT_CODE` when `error` is a server's `ForcedShutdown`
+fn exit_if_forced(error: &(dyn std::error::Error + 'static)) {
+    if error.is::<ForcedShutdown>() {
+        std::process::exit(FORCED_EXIT_CODE);
+    }
+}
+
+/// Errors registered by every server run in this process
+fn shared_error_registry() -> Arc<tokio::sync::Mutex<ErrorRegistry>> {
+    static REGISTRY: OnceLock<Arc<tokio::sync::Mutex<ErrorRegistry>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default).clone()
+}
+
+/// Prints the errors registered so far, grouped by severity, and saves them to
+/// `error_out`, or to a file named at the prompt
+fn run_error_registry(error_out: Option<&Path>) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] Opening Error Registry & Logging...");
-    // TODO: Show or manage deduplicated errors, correlation, etc.
-    println!("(Stub) Error registry. Press ENTER to return.");
-    wait_enter();
+    println!("{}", shared_error_registry().blocking_lock());
+    if let Some(path) = error_out {
+        export_error_registry(path);
+        return Ok(());
+    }
+    let path = prompt_user("Export to file (.csv or .json, ENTER to return): ");
+    if !path.trim().is_empty() {
+        export_error_registry(Path::new(path.trim()));
+    }
     Ok(())
 }
 
+fn export_error_registry(path: &Path) {
+    let registry = shared_error_registry();
+    let registry = registry.blocking_lock();
+    match registry.export(path, ExportFormat::from_path(path)) {
+        Ok(()) => println!("[IPCow] Error registry written to {}", path.display()),
+        Err(e) => eprintln!("[IPCow] Failed to write {}: {}", path.display(), e),
+    }
+}
+
 fn wait_enter() {
     let mut input = String::new();
     io::stdin()
@@ -322,34 +903,80 @@ fn show_performance_metrics() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn show_error_registry() -> Result<(), Box<dyn std::error::Error>> {
-    println!("\n[IPCow] Opening Error Registry & Logging...");
-    // TODO: Implement error logging system
-    println!("(Stub) Error registry displayed. Press ENTER to return.");
-    wait_enter();
-    Ok(())
+    run_error_registry(None)
 }
 
+/// Where scans append host state changes and port states: `HOST_LOG_FILE` inside
+/// `log_dir` (created if missing), otherwise in the working directory
+fn host_log_path(log_dir: Option<&Path>) -> io::Result<PathBuf> {
+    match log_dir {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            Ok(dir.join(HOST_LOG_FILE))
+        }
+        None => Ok(PathBuf::from(HOST_LOG_FILE)),
+    }
+}
+
+/// `log_dir` holds the host status log instead of the working directory, and
+/// `shuffle_hosts` randomizes the order scanned hosts are probed in
+/// Ctrl+C cancels the port checks, prints the ports checked so far and skips the rest
 #[tokio::main]
-async fn run_network_tests() -> Result<(), Box<dyn std::error::Error>> {
+async fn run_network_tests(
+    log_dir: Option<&Path>,
+    shuffle_hosts: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] Running Network Tests...");
     
     // Test local connectivity
     let local_ports = vec![80, 443, 8080];
     println!("Testing local ports: {:?}", local_ports);
     
-    for port in local_ports {
-        let addr = format!("127.0.0.1:{}", port);
-        match tokio::net::TcpStream::connect(&addr).await {
-            Ok(_) => println!("✅ Port {} is open", port),
-            Err(_) => println!("❌ Port {} is closed", port),
+    let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let cancel = runtime_token().child_token();
+    let action = "Cancelling network tests, keeping partial results";
+    tokio::spawn(cancel_on_ctrl_c(cancel.clone(), action));
+    let config = ScanConfig {
+        cancel: cancel.clone(),
+        host_log: host_log_path(log_dir)?,
+        shuffle_hosts,
+        ..Default::default()
+    };
+    let mut checked = Vec::new();
+    for &port in &local_ports {
+        if cancel.is_cancelled() {
+            break;
         }
+        let states = ping::scan_all_ports_with(&[localhost], port..=port, &config).await?;
+        let state = states.get(&localhost).and_then(|ports| ports.first());
+        checked.push((port, state.map(|&(_, state)| state)));
+    }
+    for (port, state) in checked.iter().copied() {
+        match state {
+            Some(PortState::Open) => println!("✅ Port {} is open", port),
+            Some(state) => println!("❌ Port {} is {}", port, state),
+            None => println!("❔ Port {} was not scanned", port),
+        }
+    }
+    if cancel.is_cancelled() {
+        println!(
+            "\nNetwork tests cancelled after {} of {} ports. Press ENTER to return.",
+            checked.len(),
+            local_ports.len()
+        );
+        wait_enter();
+        return Ok(());
     }
 
     // Test DNS resolution
     println!("\nTesting DNS resolution...");
     let domains = vec!["google.com", "github.com", "example.com"];
     for domain in domains {
-        match tokio::net::lookup_host(format!("{}:80", domain)).await {
+        let resolved = tokio::select! {
+            _ = cancel.cancelled() => break,
+            resolved = tokio::net::lookup_host(format!("{}:80", domain)) => resolved,
+        };
+        match resolved {
             Ok(addrs) => println!("✅ {} resolves to: {:?}", domain, addrs.collect::<Vec<_>>()),
             Err(e) => println!("❌ Failed to resolve {}: {}", domain, e),
         }
@@ -360,13 +987,21 @@ async fn run_network_tests() -> Result<(), Box<dyn std::error::Error>> {
     let targets = vec!["1.1.1.1:53", "8.8.8.8:53"];
     for target in targets {
         let start = std::time::Instant::now();
-        match tokio::net::TcpStream::connect(target).await {
+        let connected = tokio::select! {
+            _ = cancel.cancelled() => break,
+            connected = tokio::net::TcpStream::connect(target) => connected,
+        };
+        match connected {
             Ok(_) => println!("✅ {} latency: {:?}", target, start.elapsed()),
             Err(e) => println!("❌ Failed to connect to {}: {}", target, e),
         }
     }
 
-    println!("\nNetwork tests complete. Press ENTER to return.");
+    if cancel.is_cancelled() {
+        println!("\nNetwork tests cancelled. Press ENTER to return.");
+    } else {
+        println!("\nNetwork tests complete. Press ENTER to return.");
+    }
     wait_enter();
     Ok(())
 }
@@ -376,14 +1011,13 @@ async fn run_network_tests() -> Result<(), Box<dyn std::error::Error>> {
 async fn run_ping_discovery() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n[IPCow] Starting Host Discovery...");
     
-    let (ips_vec, ports) = addr_input();
-    let ips: Vec<IpAddr> = ips_vec.into_iter()
-        .map(std::net::IpAddr::V4)
-        .collect();
+    let (ips, ports) = addr_input();
 
     println!("Scanning {} hosts...", ips.len());
     
-    match ping::ping_range(&ips, ports[0], ports[ports.len()-1]).await {
+    let cancel = runtime_token().child_token();
+    tokio::spawn(cancel_on_ctrl_c(cancel.clone(), "Cancelling scan, keeping partial results"));
+    match ping::ping_range(&ips, ports[0], ports[ports.len()-1], cancel).await {
         Ok(alive_hosts) => {
             println!("\nDiscovered {} live hosts:", alive_hosts.len());
             for host in alive_hosts {
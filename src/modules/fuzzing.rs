@@ -1,8 +1,219 @@
+// Mutation fuzzing: sends mutated copies of a payload template and records unusual reactions
+
+use crate::utils::RngSource;
+use chrono::Local;
+use rand::rngs::StdRng;
+use rand::Rng;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Instant};
+
+/// Longest response kept per mutant
+const MAX_RESPONSE_LEN: usize = 4096;
+/// Bytes that often hit edge cases in length fields and parsers
+const BOUNDARY_BYTES: [u8; 6] = [0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff];
+
+/// How a template is altered to produce one mutant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MutationStrategy {
+    /// Flips a single random bit
+    BitFlip,
+    /// Inserts a random byte at a random position
+    InsertByte,
+    /// Removes the byte at a random position
+    DeleteByte,
+    /// Overwrites a random byte with a boundary value (0x00, 0x7f, 0xff, ...)
+    BoundaryValue,
+}
+
+impl MutationStrategy {
+    pub const ALL: [MutationStrategy; 4] = [
+        MutationStrategy::BitFlip,
+        MutationStrategy::InsertByte,
+        MutationStrategy::DeleteByte,
+        MutationStrategy::BoundaryValue,
+    ];
+
+    /// Mutated copy of `template`; an empty template only grows through `InsertByte`
+    pub fn mutate(&self, template: &[u8], rng: &mut impl Rng) -> Vec<u8> {
+        let mut mutant = template.to_vec();
+        match self {
+            MutationStrategy::InsertByte => {
+                let at = rng.gen_range(0..=mutant.len());
+                mutant.insert(at, rng.gen());
+            }
+            _ if mutant.is_empty() => {}
+            MutationStrategy::BitFlip => {
+                let at = rng.gen_range(0..mutant.len());
+                mutant[at] ^= 1 << rng.gen_range(0..8);
+            }
+            MutationStrategy::DeleteByte => {
+                mutant.remove(rng.gen_range(0..mutant.len()));
+            }
+            MutationStrategy::BoundaryValue => {
+                let at = rng.gen_range(0..mutant.len());
+                mutant[at] = BOUNDARY_BYTES[rng.gen_range(0..BOUNDARY_BYTES.len())];
+            }
+        }
+        mutant
+    }
+}
+
+/// How the target handled one payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FuzzReaction {
+    /// Bytes sent back before the connection closed or went quiet (may be empty)
+    Response(Vec<u8>),
+    /// The connection was reset or aborted
+    Reset,
+    /// Nothing came back within the response timeout
+    Timeout,
+    /// The target could not be reached at all, e.g. after it crashed
+    ConnectFailed(String),
+}
+
+/// A mutant whose reaction differed from the unmodified template's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FuzzResult {
+    pub strategy: MutationStrategy,
+    pub payload: Vec<u8>,
+    pub reaction: FuzzReaction,
+    pub elapsed: Duration, // Until the first response byte, or until the reaction was clear
+}
+
+impl FuzzReaction {
+    /// Status code of an HTTP response, e.g. 503 for `HTTP/1.1 503 Service Unavailable`
+    pub fn http_status(&self) -> Option<u16> {
+        let FuzzReaction::Response(bytes) = self else {
+            return None;
+        };
+        let rest = bytes.strip_prefix(b"HTTP/")?;
+        let space = rest.iter().position(|&b| b == b' ')?;
+        let code = rest.get(space + 1..space + 4)?;
+        std::str::from_utf8(code).ok()?.parse().ok()
+    }
+
+    /// First line of the response, which identifies the service for most protocols
+    pub fn banner(&self) -> Option<String> {
+        let FuzzReaction::Response(bytes) = self else {
+            return None;
+        };
+        let line = bytes.split(|&b| b == b'\n').next().unwrap_or_default();
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        Some(String::from_utf8_lossy(line).into_owned())
+    }
+}
+
+/// Why a mutant's reaction looks like a bug in the target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnomalyKind {
+    /// The target stopped accepting connections after the mutant
+    Crash(String),
+    /// The connection was reset instead of answered
+    Reset,
+    /// An HTTP 5xx status
+    ServerError(u16),
+    /// Answering took this much longer than the baseline
+    ResponseTimeSpike(Duration),
+    /// The first response line differs from the baseline's
+    BannerChanged { expected: String, got: String },
+}
+
+impl fmt::Display for AnomalyKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnomalyKind::Crash(e) => write!(f, "crash ({})", e),
+            AnomalyKind::Reset => write!(f, "connection reset"),
+            AnomalyKind::ServerError(code) => write!(f, "server error {}", code),
+            AnomalyKind::ResponseTimeSpike(extra) => {
+                write!(f, "response {}ms slower than baseline", extra.as_millis())
+            }
+            AnomalyKind::BannerChanged { expected, got } => {
+                write!(f, "banner changed from {:?} to {:?}", expected, got)
+            }
+        }
+    }
+}
+
+/// A mutant that made the target misbehave
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anomaly {
+    pub target: SocketAddr,
+    pub template: String,
+    pub kind: AnomalyKind,
+    pub result: FuzzResult, // Holds the triggering payload
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} template={} {} strategy={:?} elapsed={}ms payload=\"{}\"",
+            self.target,
+            self.template,
+            self.kind,
+            self.result.strategy,
+            self.result.elapsed.as_millis(),
+            self.result.payload.escape_ascii()
+        )
+    }
+}
+
+/// Flags a mutant's reaction against the baseline, the most severe finding first
+/// Timeouts aren't flagged on their own; a slow answer shows up as a spike
+pub fn detect_anomaly(
+    baseline: &FuzzReaction,
+    baseline_elapsed: Duration,
+    result: &FuzzResult,
+    spike_threshold: Duration,
+) -> Option<AnomalyKind> {
+    match &result.reaction {
+        FuzzReaction::ConnectFailed(e) => return Some(AnomalyKind::Crash(e.clone())),
+        FuzzReaction::Reset if *baseline != FuzzReaction::Reset => return Some(AnomalyKind::Reset),
+        _ => {}
+    }
+    if let Some(code @ 500..=599) = result.reaction.http_status() {
+        return Some(AnomalyKind::ServerError(code));
+    }
+    let extra = result.elapsed.saturating_sub(baseline_elapsed);
+    if extra > spike_threshold {
+        return Some(AnomalyKind::ResponseTimeSpike(extra));
+    }
+    match (baseline.banner(), result.reaction.banner()) {
+        (Some(expected), Some(got)) if expected != got => {
+            Some(AnomalyKind::BannerChanged { expected, got })
+        }
+        _ => None,
+    }
+}
+
+/// Appends one line per anomaly, with a timestamp and the escaped payload, to `path`
+pub fn write_anomaly_report(path: &Path, anomalies: &[Anomaly]) -> io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    let now = Local::now().format("%Y-%m-%d %H:%M:%S");
+    for anomaly in anomalies {
+        writeln!(file, "[{}] {}", now, anomaly)?;
+    }
+    Ok(())
+}
 
 pub struct Fuzzer {
     templates: HashMap<String, Vec<u8>>,
     active: bool,
+    strategies: Vec<MutationStrategy>, // Applied in turn, one per iteration
+    iterations: usize,                 // Mutants sent per `fuzz_target` call
+    timeout: Duration,                 // Bound on connecting and on each read
+    spike_threshold: Duration,         // Extra latency over the baseline that is an anomaly
+    rng: RngSource,
 }
 
 impl Fuzzer {
@@ -10,9 +221,45 @@ impl Fuzzer {
         Self {
             templates: HashMap::new(),
             active: false,
+            strategies: MutationStrategy::ALL.to_vec(),
+            iterations: 100,
+            timeout: Duration::from_secs(2),
+            spike_threshold: Duration::from_millis(500),
+            rng: RngSource::global(),
         }
     }
 
+    /// Mutation strategies cycled through (default all of them)
+    pub fn with_strategies(mut self, strategies: impl Into<Vec<MutationStrategy>>) -> Self {
+        self.strategies = strategies.into();
+        self
+    }
+
+    /// Number of mutants sent per target (default 100)
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+
+    /// Connect and per-read timeout (default two seconds)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How much slower than the baseline a response may be before it is an
+    /// anomaly (default 500ms)
+    pub fn with_spike_threshold(mut self, threshold: Duration) -> Self {
+        self.spike_threshold = threshold;
+        self
+    }
+
+    /// Seeds the mutations (process-wide `--seed` by default)
+    pub fn with_rng(mut self, rng: RngSource) -> Self {
+        self.rng = rng;
+        self
+    }
+
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
         self.active = true;
         println!("Fuzzing engine started");
@@ -27,9 +274,340 @@ impl Fuzzer {
     pub fn add_template(&mut self, name: &str, data: Vec<u8>) {
         self.templates.insert(name.to_string(), data);
     }
+
+    pub fn templates(&self) -> impl Iterator<Item = &str> {
+        self.templates.keys().map(String::as_str)
+    }
+
+    /// Adds every regular file in `dir` as a template named after the file
+    /// Returns how many seeds were loaded
+    pub fn load_corpus(&mut self, dir: &Path) -> io::Result<usize> {
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            self.add_template(&name, std::fs::read(entry.path())?);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Sends the unmodified `template` once as a baseline, then each mutant on its own
+    /// connection, keeping the mutants the target reacted to differently
+    /// Fails when the template is unknown or the baseline can't reach the target
+    pub async fn fuzz_target(
+        &self,
+        addr: SocketAddr,
+        template: &str,
+    ) -> io::Result<Vec<FuzzResult>> {
+        let ((baseline, _), results) = self.exchange(addr, template).await?;
+        Ok(results
+            .into_iter()
+            .filter(|result| result.reaction != baseline)
+            .collect())
+    }
+
+    /// Fuzzes `template` like `fuzz_target` and keeps the mutants that look like bugs:
+    /// crashes, resets, 5xx responses, latency spikes or a changed banner
+    pub async fn find_anomalies(
+        &self,
+        addr: SocketAddr,
+        template: &str,
+    ) -> io::Result<Vec<Anomaly>> {
+        let ((baseline, baseline_elapsed), results) = self.exchange(addr, template).await?;
+        Ok(results
+            .into_iter()
+            .filter_map(|result| {
+                let kind =
+                    detect_anomaly(&baseline, baseline_elapsed, &result, self.spike_threshold)?;
+                Some(Anomaly {
+                    target: addr,
+                    template: template.to_string(),
+                    kind,
+                    result,
+                })
+            })
+            .collect())
+    }
+
+    // Baseline reaction of the unmodified template, then the result of every mutant
+    async fn exchange(
+        &self,
+        addr: SocketAddr,
+        template: &str,
+    ) -> io::Result<((FuzzReaction, Duration), Vec<FuzzResult>)> {
+        let template = self.templates.get(template).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no fuzzing template named {:?}", template),
+            )
+        })?;
+        let baseline = send_payload(addr, template, self.timeout).await;
+        if let (FuzzReaction::ConnectFailed(e), _) = &baseline {
+            return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("baseline connection to {} failed: {}", addr, e),
+            ));
+        }
+
+        let mut rng: StdRng = self.rng.derive("fuzzing");
+        let mut results = Vec::with_capacity(self.iterations);
+        for strategy in self.strategies.iter().cycle().take(self.iterations) {
+            let payload = strategy.mutate(template, &mut rng);
+            let (reaction, elapsed) = send_payload(addr, &payload, self.timeout).await;
+            results.push(FuzzResult {
+                strategy: *strategy,
+                payload,
+                reaction,
+                elapsed,
+            });
+        }
+        Ok((baseline, results))
+    }
+}
+
+// Writes `payload` on a fresh connection and collects what comes back, along with
+// the time until the first response byte (or until the reaction was clear)
+async fn send_payload(
+    addr: SocketAddr,
+    payload: &[u8],
+    limit: Duration,
+) -> (FuzzReaction, Duration) {
+    let started = Instant::now();
+    let mut stream = match timeout(limit, TcpStream::connect(addr)).await {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            return (
+                FuzzReaction::ConnectFailed(e.to_string()),
+                started.elapsed(),
+            )
+        }
+        Err(_) => {
+            let reaction = FuzzReaction::ConnectFailed("connect timed out".to_string());
+            return (reaction, started.elapsed());
+        }
+    };
+    if let Err(e) = stream.write_all(payload).await {
+        return (reset_or_failed(e), started.elapsed());
+    }
+
+    let mut response = Vec::new();
+    let mut first_byte = None;
+    let mut buf = [0_u8; 1024];
+    while response.len() < MAX_RESPONSE_LEN {
+        match timeout(limit, stream.read(&mut buf)).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => {
+                first_byte.get_or_insert_with(|| started.elapsed());
+                response.extend_from_slice(&buf[..n]);
+            }
+            Ok(Err(e)) => return (reset_or_failed(e), started.elapsed()),
+            // A quiet server after a partial answer still answered
+            Err(_) if response.is_empty() => return (FuzzReaction::Timeout, started.elapsed()),
+            Err(_) => break,
+        }
+    }
+    response.truncate(MAX_RESPONSE_LEN);
+    let elapsed = first_byte.unwrap_or_else(|| started.elapsed());
+    (FuzzReaction::Response(response), elapsed)
 }
 
-pub async fn run_fuzzer() {
+fn reset_or_failed(e: io::Error) -> FuzzReaction {
+    match e.kind() {
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => FuzzReaction::Reset,
+        _ => FuzzReaction::ConnectFailed(e.to_string()),
+    }
+}
+
+/// Fuzzes `target` with every seed in `corpus`, appending the anomalies found to
+/// `report` as they are found
+pub async fn run_fuzzer(
+    target: SocketAddr,
+    corpus: &Path,
+    report: &Path,
+) -> io::Result<Vec<Anomaly>> {
     let mut fuzzer = Fuzzer::new();
+    let seeds = fuzzer.load_corpus(corpus)?;
     let _ = fuzzer.start().await;
+    println!("Loaded {} seeds from {}", seeds, corpus.display());
+
+    let mut names: Vec<String> = fuzzer.templates().map(str::to_string).collect();
+    names.sort();
+    let mut anomalies = Vec::new();
+    for name in names {
+        match fuzzer.find_anomalies(target, &name).await {
+            Ok(found) => {
+                println!("{}: {} anomalies", name, found.len());
+                write_anomaly_report(report, &found)?;
+                anomalies.extend(found);
+            }
+            Err(e) => eprintln!("Skipping {}: {}", name, e),
+        }
+    }
+    fuzzer.stop();
+    Ok(anomalies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_mutations_change_template() {
+        let mut rng = RngSource::new(Some(3)).derive("fuzzing");
+        let template = b"HELLO\r\n";
+        for _ in 0..50 {
+            let flipped = MutationStrategy::BitFlip.mutate(template, &mut rng);
+            let differing = flipped.iter().zip(template).filter(|(a, b)| a != b).count();
+            assert_eq!(differing, 1);
+            assert_eq!(
+                MutationStrategy::InsertByte
+                    .mutate(template, &mut rng)
+                    .len(),
+                8
+            );
+            assert_eq!(
+                MutationStrategy::DeleteByte
+                    .mutate(template, &mut rng)
+                    .len(),
+                6
+            );
+            let boundary = MutationStrategy::BoundaryValue.mutate(template, &mut rng);
+            assert!(boundary.iter().any(|b| BOUNDARY_BYTES.contains(b)));
+        }
+        assert!(MutationStrategy::DeleteByte
+            .mutate(b"", &mut rng)
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fuzz_target_records_differing_reactions() {
+        // Echoes "OK" for the exact greeting and resets on anything else
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0_u8; 64];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    if &buf[..n] == b"HELLO" {
+                        let _ = socket.write_all(b"OK").await;
+                    } else {
+                        // Zero linger turns the close into a reset
+                        let _ = socket.set_linger(Some(Duration::ZERO));
+                    }
+                });
+            }
+        });
+
+        let mut fuzzer = Fuzzer::new()
+            .with_strategies([MutationStrategy::BitFlip])
+            .with_iterations(10)
+            .with_timeout(Duration::from_millis(500))
+            .with_rng(RngSource::new(Some(1)));
+        fuzzer.add_template("greeting", b"HELLO".to_vec());
+
+        let results = fuzzer.fuzz_target(addr, "greeting").await.unwrap();
+        assert_eq!(results.len(), 10);
+        assert!(results
+            .iter()
+            .all(|r| r.strategy == MutationStrategy::BitFlip));
+        assert!(results.iter().all(|r| r.payload != b"HELLO"));
+        assert!(
+            results.iter().all(|r| r.reaction == FuzzReaction::Reset),
+            "{:?}",
+            results
+        );
+
+        let missing = fuzzer.fuzz_target(addr, "nope").await.unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_detect_anomaly_rules() {
+        let ok = FuzzReaction::Response(b"HTTP/1.1 200 OK\r\n\r\n".to_vec());
+        let fast = Duration::from_millis(5);
+        let threshold = Duration::from_millis(100);
+        let result = |reaction: FuzzReaction, elapsed| FuzzResult {
+            strategy: MutationStrategy::BitFlip,
+            payload: b"x".to_vec(),
+            reaction,
+            elapsed,
+        };
+        let detect = |r: &FuzzResult| detect_anomaly(&ok, fast, r, threshold);
+
+        assert_eq!(detect(&result(ok.clone(), fast)), None);
+        assert_eq!(
+            detect(&result(FuzzReaction::Reset, fast)),
+            Some(AnomalyKind::Reset)
+        );
+        assert!(matches!(
+            detect(&result(FuzzReaction::ConnectFailed("refused".into()), fast)),
+            Some(AnomalyKind::Crash(_))
+        ));
+        let error = FuzzReaction::Response(b"HTTP/1.0 502 Bad Gateway\r\n".to_vec());
+        assert_eq!(
+            detect(&result(error, fast)),
+            Some(AnomalyKind::ServerError(502))
+        );
+        assert_eq!(
+            detect(&result(ok.clone(), Duration::from_millis(500))),
+            Some(AnomalyKind::ResponseTimeSpike(Duration::from_millis(495)))
+        );
+        let other = FuzzReaction::Response(b"SSH-2.0-OpenSSH\r\n".to_vec());
+        assert_eq!(
+            detect(&result(other, fast)),
+            Some(AnomalyKind::BannerChanged {
+                expected: "HTTP/1.1 200 OK".into(),
+                got: "SSH-2.0-OpenSSH".into(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_fuzzer_reports_anomalies_from_corpus() {
+        // Fails with a 500 whenever the request line is damaged
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0_u8; 64];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let reply: &[u8] = if buf[..n].starts_with(b"GET / ") {
+                        b"HTTP/1.1 200 OK\r\n\r\n"
+                    } else {
+                        b"HTTP/1.1 500 Internal Server Error\r\n\r\n"
+                    };
+                    let _ = socket.write_all(reply).await;
+                });
+            }
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let corpus = dir.path().join("corpus");
+        std::fs::create_dir(&corpus).unwrap();
+        std::fs::write(corpus.join("get.txt"), "GET / HTTP/1.1\r\n\r\n").unwrap();
+        std::fs::create_dir(corpus.join("ignored")).unwrap();
+        let report = dir.path().join("report.txt");
+
+        let mut fuzzer = Fuzzer::new();
+        assert_eq!(fuzzer.load_corpus(&corpus).unwrap(), 1);
+        assert_eq!(fuzzer.templates().collect::<Vec<_>>(), ["get.txt"]);
+
+        let anomalies = run_fuzzer(addr, &corpus, &report).await.unwrap();
+        assert!(!anomalies.is_empty());
+        assert!(anomalies
+            .iter()
+            .all(|a| a.kind == AnomalyKind::ServerError(500) && a.template == "get.txt"));
+        let report = std::fs::read_to_string(&report).unwrap();
+        assert_eq!(report.lines().count(), anomalies.len());
+        assert!(report.contains("server error 500"), "{}", report);
+        assert!(report.contains("payload=\""), "{}", report);
+    }
 }
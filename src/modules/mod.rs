@@ -1,7 +1,17 @@
 pub mod fuzzing;
 pub mod ping;
+pub mod report;
+pub mod scan;
+pub mod session;
+pub mod sink;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod stress;
+pub mod traffic;
 pub mod web_server;
 
 // Re-export commonly used items
 pub use ping::*;
+pub use scan::*;
+pub use session::*;
 pub use web_server::*;
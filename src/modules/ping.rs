@@ -1,17 +1,34 @@
+use std::future::Future;
 use std::net::{IpAddr, SocketAddr};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tokio::net::TcpSocket;
+use tokio::net::{TcpSocket, TcpStream};
 use chrono::{DateTime, Local, NaiveDateTime};
 use serde::{Serialize, Deserialize};
+use std::fmt;
 use crate::core::types::{NetworkResult, NetworkError};
-use tokio::fs::OpenOptions;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use futures::stream::{self, StreamExt};
+use crate::core::types::AddrType;
+use crate::modules::scan::{
+    AdaptiveConcurrency, AdaptiveTimeout, ScanConfig, HOST_LOG_FILE,
+};
+use crate::modules::session::{PortResult, ScanResult};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio_util::sync::CancellationToken;
 
 const PING_TIMEOUT: Duration = Duration::from_millis(500);
 const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
-const LOG_FILE: &str = "host_status.log";
+/// Payload carried by ICMP echo requests
+const ICMP_PAYLOAD: &[u8] = b"IPCow ping";
+/// Sequence number of the next ICMP echo request
+static ICMP_SEQUENCE: AtomicU16 = AtomicU16::new(0);
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct HostStatus {
@@ -51,15 +68,23 @@ mod duration_serde {
 
 struct HostTracker {
     hosts: Arc<Mutex<HashMap<IpAddr, HostStatus>>>,
+    log_path: PathBuf, // State changes are appended here
 }
 
 impl HostTracker {
     fn new() -> Self {
         Self {
             hosts: Arc::new(Mutex::new(HashMap::new())),
+            log_path: PathBuf::from(HOST_LOG_FILE),
         }
     }
 
+    /// Appends state changes to `path` instead of `host_status.log`
+    fn with_log_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_path = path.into();
+        self
+    }
+
     async fn update_host_status(&self, ip: IpAddr, is_alive: bool) {
         let mut hosts = self.hosts.lock().await;
         let now = Local::now();
@@ -96,9 +121,9 @@ impl HostTracker {
         let mut file = OpenOptions::new()
             .create(true)
             .append(true)
-            .open(LOG_FILE)
+            .open(&self.log_path)
             .await
-            .map_err(|e| NetworkError::IoError(e))?;
+            .map_err(NetworkError::IoError)?;
 
         let entry = format!(
             "[{}] {} {} | Last alive: {} | Last down: {} | Total downtime: {:.2}s\n",
@@ -110,10 +135,11 @@ impl HostTracker {
             status.total_downtime.as_secs_f64()
         );
 
-        use tokio::io::AsyncWriteExt;
         file.write_all(entry.as_bytes())
             .await
-            .map_err(|e| NetworkError::IoError(e))?;
+            .map_err(NetworkError::IoError)?;
+        // tokio finishes writes in the background; flush so the entry is on disk on return
+        file.flush().await.map_err(NetworkError::IoError)?;
 
         Ok(())
     }
@@ -139,89 +165,687 @@ impl HostTracker {
     }
 }
 
-/// Performs TCP SYN scan on target address
-async fn syn_scan(addr: SocketAddr) -> NetworkResult<bool> {
-    let socket = TcpSocket::new_v4()?;
-    
+/// Outcome of a single port probe, used to drive adaptive scan concurrency
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ProbeOutcome {
+    Open,           // SYN-ACK received
+    Closed,         // RST received (connection refused)
+    TimedOut,       // No response within the timeout
+    Failed(String), // Any other connect error (unreachable, reset, ...)
+}
+
+impl ProbeOutcome {
+    /// Timeouts and non-refusal errors signal network/target overload
+    fn is_error(&self) -> bool {
+        matches!(self, ProbeOutcome::TimedOut | ProbeOutcome::Failed(_))
+    }
+
+    /// What the outcome says about the port, as nmap would report it
+    fn state(&self) -> PortState {
+        match self {
+            ProbeOutcome::Open => PortState::Open,
+            ProbeOutcome::Closed => PortState::Closed,
+            // No answer: something between us and the port drops probes
+            ProbeOutcome::TimedOut => PortState::Filtered,
+            ProbeOutcome::Failed(_) => PortState::Error,
+        }
+    }
+}
+
+/// State of a scanned port, following nmap's distinction
+/// A closed port answered with a reset, so the host is reachable but nothing listens;
+/// a filtered one never answered, which usually means a firewall is dropping probes.
+/// An errored probe says nothing about the port; the connect failed some other way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PortState {
+    Open,     // Connection accepted
+    Closed,   // Connection refused right away
+    Filtered, // Timed out without an answer
+    Error,    // Probe failed otherwise (unreachable, no local resources, ...)
+}
+
+impl fmt::Display for PortState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PortState::Open => "open",
+            PortState::Closed => "closed",
+            PortState::Filtered => "filtered",
+            PortState::Error => "error",
+        })
+    }
+}
+
+/// Probes a single address with a TCP connect bounded by `timeout`
+async fn probe_port(addr: SocketAddr, timeout: Duration) -> NetworkResult<ProbeOutcome> {
+    let socket = if addr.is_ipv6() {
+        TcpSocket::new_v6()?
+    } else {
+        TcpSocket::new_v4()?
+    };
+
     // Use non-blocking connect for SYN scanning
-    match tokio::time::timeout(CONNECT_TIMEOUT, socket.connect(addr)).await {
-        Ok(Ok(_)) => Ok(true),   // SYN-ACK received
-        Ok(Err(_)) => Ok(false), // RST received
-        Err(_) => Ok(false),     // Timeout - no response
+    Ok(match tokio::time::timeout(timeout, socket.connect(addr)).await {
+        Ok(Ok(_)) => ProbeOutcome::Open,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionRefused => ProbeOutcome::Closed,
+        Ok(Err(e)) => ProbeOutcome::Failed(e.to_string()),
+        Err(_) => ProbeOutcome::TimedOut,
+    })
+}
+
+/// Performs TCP SYN scan on target address
+/// A refused connect means `Closed`, a timeout `Filtered` and any other connect error `Error`
+pub async fn syn_scan(addr: SocketAddr) -> NetworkResult<PortState> {
+    Ok(probe_port(addr, CONNECT_TIMEOUT).await?.state())
+}
+
+/// Sends one ICMP echo request to `ip` and returns the round-trip time of the reply
+/// Raw sockets need root or CAP_NET_RAW; without them the unprivileged ping socket is
+/// used where the OS offers one (Linux `ping_group_range`, macOS), otherwise this fails
+/// with `NetworkError::PermissionDenied`
+pub async fn icmp_ping(ip: IpAddr, timeout: Duration) -> NetworkResult<Duration> {
+    tokio::task::spawn_blocking(move || icmp_echo(ip, timeout))
+        .await
+        .map_err(|e| NetworkError::ConnectionFailed(e.to_string()))?
+}
+
+fn icmp_echo(ip: IpAddr, timeout: Duration) -> NetworkResult<Duration> {
+    let (domain, protocol, request_type, reply_type) = match ip {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4, 8, 0),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6, 128, 129),
+    };
+    let (socket, raw) = match Socket::new(domain, Type::RAW, Some(protocol)) {
+        Ok(socket) => (socket, true),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+                Ok(socket) => (socket, false),
+                Err(_) => {
+                    return Err(NetworkError::PermissionDenied(format!(
+                        "ICMP ping needs raw sockets, run as root or grant CAP_NET_RAW ({})",
+                        e
+                    )))
+                }
+            }
+        }
+        Err(e) => return Err(e.into()),
+    };
+    // Plain datagram calls work on raw and ping sockets alike
+    let socket: std::net::UdpSocket = socket.into();
+
+    let id = std::process::id() as u16;
+    let seq = ICMP_SEQUENCE.fetch_add(1, Ordering::Relaxed);
+    let mut request = vec![request_type, 0, 0, 0];
+    request.extend_from_slice(&id.to_be_bytes());
+    request.extend_from_slice(&seq.to_be_bytes());
+    request.extend_from_slice(ICMP_PAYLOAD);
+    // The kernel fills in ICMPv6 checksums, which cover a pseudo-header
+    if ip.is_ipv4() {
+        let checksum = internet_checksum(&request);
+        request[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    let start = Instant::now();
+    socket.send_to(&request, SocketAddr::new(ip, 0))?;
+    let mut buf = [0_u8; 1500];
+    loop {
+        let remaining = timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            return Err(NetworkError::Timeout);
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let (n, from) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(NetworkError::Timeout)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // Raw IPv4 sockets deliver the IP header too
+        let header = if raw && ip.is_ipv4() {
+            usize::from(buf[0] & 0x0f) * 4
+        } else {
+            0
+        };
+        let Some(reply) = buf[..n].get(header..).filter(|reply| reply.len() >= 8) else {
+            continue;
+        };
+        // Ping sockets rewrite the identifier, so only raw replies can be matched on it
+        let matches = from.ip() == ip
+            && reply[0] == reply_type
+            && reply[6..8] == seq.to_be_bytes()
+            && (!raw || reply[4..6] == id.to_be_bytes());
+        if matches {
+            return Ok(start.elapsed());
+        }
+    }
+}
+
+// RFC 1071 ones' complement sum over 16-bit words
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Keeps the hosts that answer an ICMP echo within `PING_TIMEOUT`, in input order
+/// Without permission to send ICMP every host is kept, so the port scan still runs
+async fn icmp_sweep(ips: &[IpAddr], config: &ScanConfig) -> Vec<IpAddr> {
+    let replies: Vec<(IpAddr, NetworkResult<Duration>)> = stream::iter(ips.iter().copied())
+        .map(|ip| async move { (ip, icmp_ping(ip, PING_TIMEOUT).await) })
+        .buffered(config.max_concurrency.max(1))
+        .collect()
+        .await;
+
+    if let Some((_, Err(e))) = replies
+        .iter()
+        .find(|(_, reply)| matches!(reply, Err(NetworkError::PermissionDenied(_))))
+    {
+        eprintln!("Skipping ICMP sweep: {}", e);
+        return ips.to_vec();
+    }
+    replies
+        .into_iter()
+        .filter_map(|(ip, reply)| reply.ok().map(|_| ip))
+        .collect()
+}
+
+/// Latency of one request/response exchange, each measured from the start of the connect
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TimingBreakdown {
+    pub connect: Duration, // TCP handshake completed
+    pub ttfb: Duration,    // First response byte received
+    pub total: Duration,   // Last response byte received
+}
+
+/// Connects to `addr`, sends `request` and times the handshake, first byte and full response
+/// The response is read until the peer closes or stays silent for `PING_TIMEOUT`
+pub async fn timing_probe(addr: SocketAddr, request: &[u8]) -> NetworkResult<TimingBreakdown> {
+    use tokio::io::AsyncReadExt;
+    use tokio::time::timeout;
+
+    let start = Instant::now();
+    let mut stream = timeout(PING_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| NetworkError::Timeout)??;
+    let connect = start.elapsed();
+
+    stream.write_all(request).await?;
+    let mut buf = [0_u8; 4096];
+    let n = timeout(PING_TIMEOUT, stream.read(&mut buf))
+        .await
+        .map_err(|_| NetworkError::Timeout)??;
+    if n == 0 {
+        return Err(NetworkError::ConnectionFailed(format!("{} closed without responding", addr)));
+    }
+    let ttfb = start.elapsed();
+
+    // Drain the rest of the response, remembering when the last byte arrived
+    let mut total = ttfb;
+    while let Ok(Ok(n)) = timeout(PING_TIMEOUT, stream.read(&mut buf)).await {
+        if n == 0 {
+            break;
+        }
+        total = start.elapsed();
     }
+
+    Ok(TimingBreakdown { connect, ttfb, total })
 }
 
 /// Ping a range of ports on target IPs using SYN scanning
-pub async fn ping_range(ips: &[IpAddr], start_port: u16, end_port: u16) -> NetworkResult<Vec<IpAddr>> {
-    let tracker = HostTracker::new();
-    let mut alive_ips = Vec::new();
-    
+/// Cancelling `cancel` (e.g. on Ctrl+C) stops the scan and returns the hosts found so far
+pub async fn ping_range(
+    ips: &[IpAddr],
+    start_port: u16,
+    end_port: u16,
+    cancel: CancellationToken,
+) -> NetworkResult<Vec<IpAddr>> {
+    let config = ScanConfig {
+        cancel,
+        ..Default::default()
+    };
+    ping_range_with(ips, start_port, end_port, &config).await
+}
+
+/// Same as `ping_range` with explicit scan settings
+/// Every (host, port) pair is probed concurrently, bounded by `config.max_concurrency`,
+/// and the number of in-flight probes adapts to the observed error rate so an overloaded network or target gets backed off from
+/// Cancelling `config.cancel` ends the scan early with the partial results
+/// With `config.stream_only` the returned list is empty and per-host status isn't
+/// tracked; read the live hosts back from `config.result_sink` instead, which
+/// must be set or the scan fails with `InvalidConfig` before probing anything
+pub async fn ping_range_with(
+    ips: &[IpAddr],
+    start_port: u16,
+    end_port: u16,
+    config: &ScanConfig,
+) -> NetworkResult<Vec<IpAddr>> {
+    // `on_result` stops this scan through a child token, so a token the caller shares
+    // with other work (e.g. the Ctrl+C token) is never cancelled by it
+    let scan = ScanConfig {
+        cancel: config.cancel.child_token(),
+        ..config.clone()
+    };
+    let config = &scan;
+    config.validate()?;
+    let tracker = HostTracker::new().with_log_path(&config.host_log);
+    let controller = AdaptiveConcurrency::new(config);
+    let timeouts = AdaptiveTimeout::new(config);
+
     println!("Starting SYN scan of {} IPs across ports {}-{}", 
              ips.len(), start_port, end_port);
 
-    for ip in ips {
-        let mut is_alive = false;
-        for port in start_port..=end_port {
-            let addr = SocketAddr::new(*ip, port);
-            
-            match syn_scan(addr).await {
-                Ok(true) => {
-                    is_alive = true;
-                    tracker.update_host_status(*ip, true).await;
-                    log_alive_host(addr, true).await?;
-                    println!("Found open port {}:{}", ip, port);
-                    break;
-                }
-                Ok(false) => continue,
-                Err(e) => {
-                    eprintln!("Error scanning {}: {}", addr, e);
-                    continue;
-                }
-            }
-        }
-        
-        if !is_alive {
-            tracker.update_host_status(*ip, false).await;
+    let order = config.scan_order(ips);
+    let timeouts = &timeouts;
+    let scanned = scan_targets(
+        &order,
+        start_port..=end_port,
+        ScanDepth::FirstOpen,
+        config,
+        &controller,
+        |addr| timed_probe(addr, timeouts),
+    )
+    .await;
+    if let Some(sink) = &config.result_sink {
+        sink.finish().await;
+    }
+    let alive_ips: Vec<IpAddr> = scanned?.into_iter().map(|(ip, _)| ip).collect();
+
+    if config.stream_only {
+        println!("Scan complete. Results streamed to the result sink");
+        return Ok(alive_ips);
+    }
+
+    if config.cancel.is_cancelled() {
+        // Unscanned hosts have unknown state, so only record what was found
+        for ip in &alive_ips {
+            tracker.update_host_status(*ip, true).await;
+            println!("  ✓ {}", ip);
         }
-        
+        println!("Scan cancelled. Found {} alive hosts before cancellation", alive_ips.len());
+        return Ok(alive_ips);
+    }
+
+    for ip in ips {
+        let is_alive = alive_ips.contains(ip);
+        tracker.update_host_status(*ip, is_alive).await;
+
         // Print current status regardless of state
         tracker.print_status(*ip).await;
-        
-        if is_alive {
-            alive_ips.push(*ip);
-        }
     }
 
     println!("Scan complete. Found {} alive hosts", alive_ips.len());
     Ok(alive_ips)
 }
 
-/// Log discovered hosts with timestamp and scan type
-async fn log_alive_host(addr: SocketAddr, syn_scan: bool) -> NetworkResult<()> {
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+/// Reports the state of every port of every host with `syn_scan`, unlike `ping_range`
+/// which stops at the first open port of each host
+/// Every scanned host has an entry listing its ports in order
+/// Cancelling `cancel` (e.g. on Ctrl+C) returns the ports scanned so far
+pub async fn scan_all_ports(
+    ips: &[IpAddr],
+    ports: RangeInclusive<u16>,
+    cancel: CancellationToken,
+) -> NetworkResult<HashMap<IpAddr, Vec<(u16, PortState)>>> {
+    let config = ScanConfig {
+        cancel,
+        ..Default::default()
+    };
+    scan_all_ports_with(ips, ports, &config).await
+}
+
+/// Same as `scan_all_ports` with explicit scan settings
+/// Runs through the same pipeline as `ping_range_with`: probes are paced by
+/// `config.load_throttle` and `config.rate_limit`, the adaptive controller bounds how
+/// many are in flight, and every open port is emitted to `config.result_sink` and
+/// `config.on_result` as it is found. A probe that errors is reported for its port
+/// instead of ending the scan
+/// Hosts dropped by a liveness sweep have an empty list; with `config.stream_only`
+/// the map is empty and the open ports are only in the sink
+pub async fn scan_all_ports_with(
+    ips: &[IpAddr],
+    ports: RangeInclusive<u16>,
+    config: &ScanConfig,
+) -> NetworkResult<HashMap<IpAddr, Vec<(u16, PortState)>>> {
+    let scan = ScanConfig {
+        cancel: config.cancel.child_token(),
+        ..config.clone()
+    };
+    let config = &scan;
+    config.validate()?;
+    let controller = AdaptiveConcurrency::new(config);
+    let timeouts = AdaptiveTimeout::new(config);
 
-    let scan_type = if syn_scan { "SYN" } else { "CONNECT" };
+    let order = config.scan_order(ips);
+    let timeouts = &timeouts;
+    let scanned = scan_targets(
+        &order,
+        ports,
+        ScanDepth::EveryPort,
+        config,
+        &controller,
+        |addr| timed_probe(addr, timeouts),
+    )
+    .await;
+    if let Some(sink) = &config.result_sink {
+        sink.finish().await;
+    }
+    let scanned = scanned?;
+    if config.stream_only {
+        return Ok(HashMap::new());
+    }
+
+    let mut states: HashMap<IpAddr, Vec<(u16, PortState)>> =
+        ips.iter().map(|&ip| (ip, Vec::new())).collect();
+    states.extend(scanned);
+    Ok(states)
+}
 
-    let mut file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(LOG_FILE)
+/// Probes `addr` with the current adaptive timeout, feeding answered probes' RTT back
+/// A socket that can't even be created counts as a failed probe of that port
+async fn timed_probe(addr: SocketAddr, timeouts: &AdaptiveTimeout) -> ProbeOutcome {
+    let started = Instant::now();
+    let outcome = probe_port(addr, timeouts.timeout())
         .await
-        .map_err(|e| NetworkError::IoError(e))?;
+        .unwrap_or_else(|e| ProbeOutcome::Failed(e.to_string()));
+    if matches!(outcome, ProbeOutcome::Open | ProbeOutcome::Closed) {
+        timeouts.record_rtt(started.elapsed());
+    }
+    outcome
+}
+
+/// How far `scan_hosts` probes each host
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScanDepth {
+    /// Stop at the first open port, which alone is reported for the host
+    FirstOpen,
+    /// Probe every port and report each one's state
+    EveryPort,
+}
+
+/// A host and the (port, state) pairs reported for it, in port order
+type HostPorts = (IpAddr, Vec<(u16, PortState)>);
+
+/// Runs the scan over `ports`, optionally preceded by a liveness sweep
+/// With `config.discovery_first`, hosts that answer on none of the discovery
+/// ports are dropped before the full port range is probed
+/// With `config.icmp_first`, hosts that don't answer an ICMP echo are dropped first
+/// Only the final phase emits to `config.result_sink` and `config.on_result`, and
+/// only the final phase probes as deep as `depth`
+async fn scan_targets<P, F>(
+    ips: &[IpAddr],
+    ports: RangeInclusive<u16>,
+    depth: ScanDepth,
+    config: &ScanConfig,
+    controller: &AdaptiveConcurrency,
+    probe: P,
+) -> NetworkResult<Vec<HostPorts>>
+where
+    P: Fn(SocketAddr) -> F,
+    F: Future<Output = ProbeOutcome>,
+{
+    let pinged;
+    let ips = if config.icmp_first {
+        println!("ICMP sweep of {} hosts", ips.len());
+        pinged = icmp_sweep(ips, config).await;
+        println!("{} of {} hosts answered", pinged.len(), ips.len());
+        &pinged[..]
+    } else {
+        ips
+    };
+
+    if !config.discovery_first {
+        return scan_hosts(ips, ports, depth, config, controller, probe).await;
+    }
+
+    println!("Phase 1: liveness sweep on ports {:?}", config.discovery_ports);
+    let discovery_ports = config.discovery_ports.iter().copied();
+    let sweep = ScanConfig {
+        result_sink: None,
+        stream_only: false,
+        on_result: None,
+        ..config.clone()
+    };
+    let live = scan_hosts(
+        ips,
+        discovery_ports,
+        ScanDepth::FirstOpen,
+        &sweep,
+        controller,
+        &probe,
+    )
+    .await?;
+    println!("Phase 2: scanning {} of {} hosts that responded", live.len(), ips.len());
+    if config.cancel.is_cancelled() {
+        // The sweep's hits still say which hosts are alive, but not how their ports look
+        return Ok(if depth == ScanDepth::FirstOpen { live } else { Vec::new() });
+    }
+    let live: Vec<IpAddr> = live.into_iter().map(|(ip, _)| ip).collect();
+    scan_hosts(&live, ports, depth, config, controller, &probe).await
+}
+
+/// Probes the (host, port) product concurrently, at most `config.max_concurrency` at once
+/// With `ScanDepth::FirstOpen`, the first open port found for a host marks it alive;
+/// its remaining ports are skipped and later hits from probes already in flight are
+/// ignored. With `ScanDepth::EveryPort` every port is probed and reported
+/// Every probe holds a slot from the adaptive controller and reports its outcome back
+/// Once `config.cancel` fires, in-flight probes are abandoned and remaining hosts skipped
+/// Each reported open port is emitted to `config.result_sink`, then passed to
+/// `config.on_result`, which may stop the scan
+/// Reported ports are returned grouped by host in input order, and only when
+/// `config.stream_only` is off; dead hosts never occupy memory past their own probes
+async fn scan_hosts<I, P, F>(
+    ips: &[IpAddr],
+    ports: I,
+    depth: ScanDepth,
+    config: &ScanConfig,
+    controller: &AdaptiveConcurrency,
+    probe: P,
+) -> NetworkResult<Vec<HostPorts>>
+where
+    I: IntoIterator<Item = u16> + Clone,
+    P: Fn(SocketAddr) -> F,
+    F: Future<Output = ProbeOutcome>,
+{
+    let probe = &probe;
+    let port_log = &PortLog::new(&config.host_log);
+    let first_open_only = depth == ScanDepth::FirstOpen;
+    // Set once a host has an open port, so its other probes can be skipped
+    let found: Vec<AtomicBool> = ips.iter().map(|_| AtomicBool::new(false)).collect();
+    let found = &found;
+    // Closed, filtered and errored ports per host, summed over the hosts without open ports
+    let refused: Vec<[AtomicUsize; 3]> = ips.iter().map(|_| Default::default()).collect();
+    let refused = &refused;
+    let targets = ips.iter().copied().enumerate().flat_map(|(index, ip)| {
+        ports
+            .clone()
+            .into_iter()
+            .map(move |port| (index, SocketAddr::new(ip, port)))
+    });
 
-    use tokio::io::AsyncWriteExt;
-    file.write_all(format!(
-        "[{}] {} scan success: {}:{}\n", 
-        timestamp,
-        scan_type,
-        addr.ip(),
-        addr.port()
-    ).as_bytes()).await.map_err(|e| NetworkError::IoError(e))?;
+    let mut reported: Vec<(usize, u16, PortState)> = stream::iter(targets)
+        .map(|(index, addr)| async move {
+            if first_open_only && found[index].load(Ordering::Acquire) {
+                return None;
+            }
+            let outcome = tokio::select! {
+                // Checked first so nothing new is probed once the scan is stopped
+                biased;
+                _ = config.cancel.cancelled() => return None,
+                outcome = async {
+                    if let Some(throttle) = &config.load_throttle {
+                        throttle.wait_for_capacity().await;
+                    }
+                    if let Some(limiter) = &config.rate_limit {
+                        limiter.acquire().await;
+                    }
+                    let _permit = controller.acquire().await;
+                    // The host may have turned up alive while this probe was waiting
+                    if first_open_only && found[index].load(Ordering::Acquire) {
+                        return None;
+                    }
+                    let started = Instant::now();
+                    let outcome = probe(addr).await;
+                    config.logger.debug(format_args!(
+                        "probe {} -> {:?} in {:?}",
+                        addr,
+                        outcome,
+                        started.elapsed()
+                    ));
+                    Some(outcome)
+                } => outcome,
+            };
+            let outcome = outcome?;
+            controller.record(outcome.is_error());
+            if let ProbeOutcome::Failed(e) = &outcome {
+                eprintln!("Error scanning {}: {}", addr, e);
+            }
+            let state = outcome.state();
+            match state {
+                PortState::Open => {}
+                PortState::Closed => _ = refused[index][0].fetch_add(1, Ordering::Relaxed),
+                PortState::Filtered => _ = refused[index][1].fetch_add(1, Ordering::Relaxed),
+                PortState::Error => _ = refused[index][2].fetch_add(1, Ordering::Relaxed),
+            }
+            port_log.record(addr, state).await;
+
+            let first_open = state == PortState::Open && !found[index].swap(true, Ordering::AcqRel);
+            // Only the first open port of a host is reported unless every port is wanted
+            if first_open_only && !first_open {
+                return None;
+            }
+            if state == PortState::Open {
+                println!("Found open port {}:{}", addr.ip(), addr.port());
+                let result = ScanResult {
+                    ip: addr.ip(),
+                    ports: vec![PortResult {
+                        port: addr.port(),
+                        protocol: AddrType::TCP,
+                        banner: None,
+                    }],
+                };
+                if let Some(sink) = &config.result_sink {
+                    sink.emit(&result).await;
+                }
+                if let Some(callback) = &config.on_result {
+                    if callback.call(&result).is_break() {
+                        config.cancel.cancel();
+                    }
+                }
+            }
+            (!config.stream_only).then_some((index, addr.port(), state))
+        })
+        .buffer_unordered(config.max_concurrency.max(1))
+        .filter_map(|result| async move { result })
+        .collect()
+        .await;
+    port_log.finish().await;
+
+    reported.sort_unstable_by_key(|&(index, port, _)| (index, port));
+    if !config.cancel.is_cancelled() {
+        // One line for all of them; the host log has every port's state
+        let mut dead = 0;
+        let mut totals = [0; 3];
+        for (index, counts) in refused.iter().enumerate() {
+            if !found[index].load(Ordering::Acquire) {
+                dead += 1;
+                for (total, count) in totals.iter_mut().zip(counts) {
+                    *total += count.load(Ordering::Relaxed);
+                }
+            }
+        }
+        if dead > 0 {
+            println!(
+                "No open ports on {} of {} hosts: {} closed, {} filtered, {} errored",
+                dead,
+                ips.len(),
+                totals[0],
+                totals[1],
+                totals[2]
+            );
+        }
+    }
+    Ok(reported
+        .chunk_by(|a, b| a.0 == b.0)
+        .map(|host| {
+            let ports = host.iter().map(|&(_, port, state)| (port, state)).collect();
+            (ips[host[0].0], ports)
+        })
+        .collect())
+}
+
+/// Port lines of one scan in the host log, e.g. `[1700000000] SYN scan open: 10.0.0.1:22`
+/// or `[1700000000] SYN scan filtered: 10.0.0.1:23`
+/// The file is opened on the first line and buffered until `finish`; a failing log
+/// is reported once and then skipped, so it never costs the scan its results
+struct PortLog {
+    path: PathBuf,
+    writer: Mutex<PortLogWriter>,
+}
+
+enum PortLogWriter {
+    Unopened,
+    Open(BufWriter<File>),
+    Failed,
+}
+
+impl PortLog {
+    fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(PortLogWriter::Unopened),
+        }
+    }
+
+    async fn record(&self, addr: SocketAddr, state: PortState) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let line = format!("[{}] SYN scan {}: {}:{}\n", timestamp, state, addr.ip(), addr.port());
+
+        let mut writer = self.writer.lock().await;
+        if let PortLogWriter::Unopened = *writer {
+            let opened = OpenOptions::new().create(true).append(true).open(&self.path).await;
+            *writer = match opened {
+                Ok(file) => PortLogWriter::Open(BufWriter::new(file)),
+                Err(e) => self.failed(e),
+            };
+        }
+        if let PortLogWriter::Open(file) = &mut *writer {
+            if let Err(e) = file.write_all(line.as_bytes()).await {
+                *writer = self.failed(e);
+            }
+        }
+    }
+
+    /// Flushes the buffered lines so they are on disk once the scan returns
+    async fn finish(&self) {
+        let mut writer = self.writer.lock().await;
+        if let PortLogWriter::Open(file) = &mut *writer {
+            if let Err(e) = file.flush().await {
+                *writer = self.failed(e);
+            }
+        }
+    }
 
-    Ok(())
+    fn failed(&self, error: std::io::Error) -> PortLogWriter {
+        eprintln!(
+            "Warning: can't write host log {}: {}; continuing without it",
+            self.path.display(),
+            error
+        );
+        PortLogWriter::Failed
+    }
 }
 
 #[cfg(test)]
@@ -230,6 +854,19 @@ mod tests {
     use std::net::Ipv4Addr;
     use tokio::runtime::Runtime;
 
+    // Hosts a scan reported, without their ports
+    fn hosts(scanned: Vec<HostPorts>) -> Vec<IpAddr> {
+        scanned.into_iter().map(|(ip, _)| ip).collect()
+    }
+
+    // Scan settings that log host state below `dir` instead of the working directory
+    fn scratch_config(dir: &tempfile::TempDir) -> ScanConfig {
+        ScanConfig {
+            host_log: dir.path().join(HOST_LOG_FILE),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn test_syn_scan() {
         let rt = Runtime::new().unwrap();
@@ -241,13 +878,644 @@ mod tests {
         });
     }
 
+    #[tokio::test]
+    async fn test_syn_scan_distinguishes_closed_from_filtered() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert_eq!(syn_scan(addr).await.unwrap(), PortState::Open);
+        drop(listener);
+        assert_eq!(syn_scan(addr).await.unwrap(), PortState::Closed);
+
+        assert_eq!(ProbeOutcome::TimedOut.state(), PortState::Filtered);
+        assert_eq!(
+            ProbeOutcome::Failed("No route to host".into()).state(),
+            PortState::Error
+        );
+        assert_eq!(PortState::Filtered.to_string(), "filtered");
+        assert_eq!(PortState::Error.to_string(), "error");
+    }
+
+    #[tokio::test]
+    async fn test_scan_backs_off_when_errors_exceed_threshold() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ScanConfig {
+            max_concurrency: 16,
+            error_threshold: 0.5,
+            error_window: 8,
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let ips = vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+
+        // Every probe times out, simulating an overloaded target
+        let alive = scan_hosts(
+            &ips,
+            1..=64,
+            ScanDepth::FirstOpen,
+            &config,
+            &controller,
+            |_| async { ProbeOutcome::TimedOut },
+        )
+        .await
+        .unwrap();
+
+        assert!(alive.is_empty());
+        assert!(controller.limit() < config.max_concurrency);
+    }
+
+    #[tokio::test]
+    async fn test_load_throttle_pauses_and_resumes() {
+        let dir = tempfile::tempdir().unwrap();
+        use crate::modules::scan::{LoadThrottle, ThrottleEvent};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        // Any real machine uses some memory, so a 0% ceiling always pauses
+        let events: Arc<std::sync::Mutex<Vec<ThrottleEvent>>> = Arc::default();
+        let recorded = Arc::clone(&events);
+        let throttle = LoadThrottle::new(0.0)
+            .with_poll_interval(Duration::from_millis(10))
+            .on_change(move |event| recorded.lock().unwrap().push(event));
+        let config = ScanConfig {
+            load_throttle: Some(throttle.clone()),
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let ips = vec![IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+        let probes = AtomicUsize::new(0);
+
+        let scan = scan_hosts(
+            &ips,
+            1..=4,
+            ScanDepth::FirstOpen,
+            &config,
+            &controller,
+            |_| {
+                probes.fetch_add(1, Ordering::SeqCst);
+                async { ProbeOutcome::Closed }
+            },
+        );
+        let raise = async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(throttle.is_paused());
+            assert_eq!(probes.load(Ordering::SeqCst), 0);
+            throttle.set_ceiling(100.0);
+        };
+        let (alive, ()) = tokio::join!(scan, raise);
+
+        assert!(alive.unwrap().is_empty());
+        assert_eq!(probes.load(Ordering::SeqCst), 4);
+        assert!(!throttle.is_paused());
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], ThrottleEvent::Paused(_)));
+        assert!(matches!(events[1], ThrottleEvent::Resumed(_)));
+    }
+
+    #[tokio::test]
+    async fn test_debug_logging_records_each_probe() {
+        let dir = tempfile::tempdir().unwrap();
+        use crate::core::{logging::Logger, LogLevel};
+
+        let ips = vec![
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)),
+        ];
+        let run = |logger: Logger| {
+            let ips = ips.clone();
+            let config = ScanConfig {
+                logger: logger.clone(),
+                ..scratch_config(&dir)
+            };
+            async move {
+                let controller = AdaptiveConcurrency::new(&config);
+                let probe = |addr: SocketAddr| async move {
+                    if addr.port() == 2 {
+                        ProbeOutcome::TimedOut
+                    } else {
+                        ProbeOutcome::Closed
+                    }
+                };
+                scan_hosts(&ips, 1..=3, ScanDepth::FirstOpen, &config, &controller, probe)
+                    .await
+                    .unwrap();
+                logger.captured()
+            }
+        };
+
+        let lines = run(Logger::capturing(LogLevel::Debug)).await;
+        assert_eq!(lines.len(), 6);
+        assert!(lines.iter().all(|l| l.starts_with("[Debug] probe 192.0.2.")));
+        assert!(lines.iter().any(|l| l.contains("192.0.2.2:2 -> TimedOut in ")));
+
+        // Suppressed at normal verbosity
+        assert!(run(Logger::capturing(LogLevel::Info)).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_timing_probe_orders_phases() {
+        let (addr, server) = crate::test_support::spawn_echo_server().await;
+
+        let timing = timing_probe(addr, b"PING\r\n").await.unwrap();
+        assert!(timing.connect > Duration::ZERO);
+        assert!(timing.connect <= timing.ttfb);
+        assert!(timing.ttfb <= timing.total);
+        server.shutdown().await;
+    }
+
+    #[derive(Debug, Default)]
+    struct MemorySink {
+        results: std::sync::Mutex<Vec<ScanResult>>,
+        finished: std::sync::atomic::AtomicUsize,
+    }
+
+    #[async_trait::async_trait]
+    impl crate::modules::sink::ResultSink for MemorySink {
+        async fn emit(&self, result: &ScanResult) {
+            self.results.lock().unwrap().push(result.clone());
+        }
+
+        async fn finish(&self) {
+            self.finished
+                .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+
+    // Needs 127.0.0.2 and up, which only Linux routes to loopback out of the box
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_ping_range_streams_results_to_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        // Bound on all interfaces so every 127/8 address reaches it
+        let listener = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = Arc::new(MemorySink::default());
+        let config = ScanConfig {
+            result_sink: Some(sink.clone()),
+            ..scratch_config(&dir)
+        };
+        let ips = vec![
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+        ];
+
+        let mut alive = ping_range_with(&ips, port, port, &config).await.unwrap();
+        let mut emitted: Vec<IpAddr> = sink.results.lock().unwrap().iter().map(|r| r.ip).collect();
+        alive.sort();
+        emitted.sort();
+        assert_eq!(alive, vec![ips[0], ips[2]]);
+        assert_eq!(emitted, alive);
+        assert!(sink
+            .results
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|r| r.ports[0].port == port));
+        assert_eq!(sink.finished.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    // Needs 127.0.0.2 and up, which only Linux routes to loopback out of the box
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stream_only_scan_keeps_results_on_disk() {
+        use crate::modules::sink::{JsonLinesReader, JsonLinesSink};
+
+        let listener = tokio::net::TcpListener::bind("0.0.0.0:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.jsonl");
+        let config = ScanConfig {
+            result_sink: Some(Arc::new(JsonLinesSink::create(&path).await.unwrap())),
+            stream_only: true,
+            ..scratch_config(&dir)
+        };
+        let mut ips: Vec<IpAddr> = (1..=20)
+            .map(|i| IpAddr::V4(Ipv4Addr::new(127, 0, 0, i)))
+            .collect();
+        ips.push(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)));
+
+        let alive = ping_range_with(&ips, port, port, &config).await.unwrap();
+        assert!(alive.is_empty());
+
+        let mut streamed: Vec<IpAddr> = JsonLinesReader::open(&path)
+            .unwrap()
+            .map(|result| result.unwrap().ip)
+            .collect();
+        streamed.sort();
+        assert_eq!(streamed, ips[..20]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_only_without_sink_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ScanConfig {
+            stream_only: true,
+            ..scratch_config(&dir)
+        };
+        let ips = [IpAddr::V4(Ipv4Addr::LOCALHOST)];
+
+        let result = ping_range_with(&ips, 1, 1, &config).await;
+        assert!(matches!(result, Err(NetworkError::InvalidConfig(_))), "{:?}", result);
+        assert!(!config.host_log.exists());
+    }
+
+    // Needs 127.0.0.2 and up, which only Linux routes to loopback out of the box
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_host_log_written_to_configured_path() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hosts.log");
+        let config = ScanConfig {
+            host_log: path.clone(),
+            ..Default::default()
+        };
+        // Only 127.0.0.1 listens, so the same port on 127.0.0.2 is refused
+        let ips = [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+        ];
+
+        ping_range_with(&ips, port, port, &config).await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(log.contains(&format!("SYN scan open: 127.0.0.1:{}", port)), "{}", log);
+        assert!(log.contains(&format!("SYN scan closed: 127.0.0.2:{}", port)), "{}", log);
+        assert!(log.contains("127.0.0.2 DOWN"), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn test_discovery_first_skips_dead_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ScanConfig {
+            discovery_first: true,
+            discovery_ports: vec![80],
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let alive_host = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let ips: Vec<IpAddr> = (1..=4)
+            .map(|i| IpAddr::V4(Ipv4Addr::new(192, 0, 2, i)))
+            .collect();
+        let probed = std::sync::Mutex::new(Vec::new());
+
+        // Only the first host answers; its last scanned port is the open one
+        let probe = |addr: SocketAddr| {
+            probed.lock().unwrap().push(addr);
+            async move {
+                match (addr.ip() == alive_host, addr.port()) {
+                    (true, 80) | (true, 1010) => ProbeOutcome::Open,
+                    (true, _) => ProbeOutcome::Closed,
+                    (false, _) => ProbeOutcome::TimedOut,
+                }
+            }
+        };
+
+        let depth = ScanDepth::FirstOpen;
+        let alive = scan_targets(&ips, 1000..=1010, depth, &config, &controller, probe)
+            .await
+            .unwrap();
+        assert_eq!(hosts(alive), vec![alive_host]);
+
+        let probed = probed.into_inner().unwrap();
+        let phase_two: Vec<SocketAddr> = probed
+            .into_iter()
+            .filter(|addr| addr.port() != 80)
+            .collect();
+        let expected: Vec<SocketAddr> = (1000..=1010)
+            .map(|port| SocketAddr::new(alive_host, port))
+            .collect();
+        assert_eq!(phase_two, expected);
+    }
+
+    #[tokio::test]
+    async fn test_icmp_ping_loopback() {
+        // Works with raw or ping sockets; without either it must fail cleanly
+        match icmp_ping(IpAddr::V4(Ipv4Addr::LOCALHOST), Duration::from_secs(1)).await {
+            Ok(rtt) => assert!(rtt < Duration::from_secs(1)),
+            Err(NetworkError::PermissionDenied(msg)) => assert!(msg.contains("CAP_NET_RAW")),
+            Err(e) => panic!("unexpected ICMP error: {}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_icmp_first_drops_silent_hosts() {
+        let dir = tempfile::tempdir().unwrap();
+        let hosts = [
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1)),
+        ];
+        // Whatever answers a direct ping here is what the sweep must keep
+        let mut expected = Vec::new();
+        for &ip in &hosts {
+            match icmp_ping(ip, PING_TIMEOUT).await {
+                Ok(_) => expected.push(ip),
+                // No ICMP permission: the sweep is skipped and every host scanned
+                Err(NetworkError::PermissionDenied(_)) => expected = hosts.to_vec(),
+                Err(_) => {}
+            }
+        }
+        let config = ScanConfig {
+            icmp_first: true,
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let probed = std::sync::Mutex::new(Vec::new());
+        let probe = |addr: SocketAddr| {
+            probed.lock().unwrap().push(addr.ip());
+            async { ProbeOutcome::Closed }
+        };
+
+        scan_targets(&hosts, 80..=80, ScanDepth::FirstOpen, &config, &controller, probe)
+            .await
+            .unwrap();
+        assert_eq!(probed.into_inner().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_internet_checksum() {
+        // Echo request, id 1, seq 1, no payload
+        let packet = [8, 0, 0, 0, 0, 1, 0, 1];
+        assert_eq!(internet_checksum(&packet), 0xf7fd);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_scan_stops_at_first_open_port_per_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ScanConfig {
+            max_concurrency: 8,
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let slow = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let fast = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let probes = AtomicUsize::new(0);
+
+        // Every port is open; the first host just answers later than the second
+        let probe = |addr: SocketAddr| {
+            probes.fetch_add(1, Ordering::SeqCst);
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(current, Ordering::SeqCst);
+            let delay = if addr.ip() == slow { 40 } else { 5 };
+            let in_flight = &in_flight;
+            async move {
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                ProbeOutcome::Open
+            }
+        };
+
+        let depth = ScanDepth::FirstOpen;
+        let alive = scan_hosts(&[slow, fast], 1..=100, depth, &config, &controller, probe)
+            .await
+            .unwrap();
+        assert_eq!(hosts(alive), vec![slow, fast]);
+        assert!(peak.load(Ordering::SeqCst) <= 8);
+        // Ports after the first hit are skipped instead of probed
+        assert!(probes.load(Ordering::SeqCst) < 50, "{} probes", probes.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_scan_all_ports_reports_every_open_port() {
+        let dir = tempfile::tempdir().unwrap();
+        // Two adjacent ports keep the scanned range small
+        let (first, second) = loop {
+            let first = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let Some(next) = first.local_addr().unwrap().port().checked_add(2) else {
+                continue;
+            };
+            if let Ok(second) = tokio::net::TcpListener::bind(("127.0.0.1", next - 1)).await {
+                break (first, second);
+            }
+        };
+        let open = [
+            first.local_addr().unwrap().port(),
+            second.local_addr().unwrap().port(),
+        ];
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let found =
+            scan_all_ports_with(&[localhost], open[0]..=open[1] + 1, &scratch_config(&dir))
+                .await
+                .unwrap();
+
+        let ports = &found[&localhost];
+        assert_eq!(
+            ports[..2],
+            [(open[0], PortState::Open), (open[1], PortState::Open)]
+        );
+        // The port after them may belong to an unrelated listener
+        assert_eq!(ports[2].0, open[1] + 1);
+        assert_ne!(ports[2].1, PortState::Filtered);
+        assert_eq!(found.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scan_all_ports_streams_through_scan_pipeline() {
+        let dir = tempfile::tempdir().unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let sink = Arc::new(MemorySink::default());
+        let config = ScanConfig {
+            result_sink: Some(sink.clone()),
+            stream_only: true,
+            ..scratch_config(&dir)
+        };
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let found = scan_all_ports_with(&[localhost], port..=port, &config)
+            .await
+            .unwrap();
+
+        assert!(found.is_empty());
+        let results = sink.results.lock().unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!((results[0].ip, results[0].ports[0].port), (localhost, port));
+        assert_eq!(sink.finished.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_scan_all_ports_keeps_going_past_failed_probes() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = scratch_config(&dir);
+        let controller = AdaptiveConcurrency::new(&config);
+        let ips = [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+        let probe = |addr: SocketAddr| async move {
+            match addr.port() {
+                1 => ProbeOutcome::Failed("no buffer space".to_string()),
+                2 => ProbeOutcome::Open,
+                _ => ProbeOutcome::Closed,
+            }
+        };
+
+        let depth = ScanDepth::EveryPort;
+        let found = scan_hosts(&ips, 1..=3, depth, &config, &controller, probe)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            found,
+            vec![(
+                ips[0],
+                vec![
+                    (1, PortState::Error),
+                    (2, PortState::Open),
+                    (3, PortState::Closed)
+                ]
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unwritable_host_log_keeps_scan_results() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory can't be opened for appending
+        let config = ScanConfig {
+            host_log: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let ips = [IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))];
+        let probe = |addr: SocketAddr| async move {
+            match addr.port() {
+                2 => ProbeOutcome::Open,
+                _ => ProbeOutcome::Closed,
+            }
+        };
+
+        let depth = ScanDepth::EveryPort;
+        let found = scan_hosts(&ips, 1..=2, depth, &config, &controller, probe)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            found,
+            vec![(ips[0], vec![(1, PortState::Closed), (2, PortState::Open)])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_scan_keeps_partial_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = ScanConfig {
+            max_concurrency: 1,
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let ips: Vec<IpAddr> = (1..=4)
+            .map(|i| IpAddr::V4(Ipv4Addr::new(192, 0, 2, i)))
+            .collect();
+        let first = ips[0];
+        let stuck = ips[1];
+        let cancel = config.cancel.clone();
+
+        // The second host hangs until the scan is cancelled from "Ctrl+C"
+        let probe = |addr: SocketAddr| {
+            let cancel = cancel.clone();
+            async move {
+                if addr.ip() == stuck {
+                    cancel.cancel();
+                    std::future::pending::<()>().await;
+                }
+                if addr.ip() == first {
+                    ProbeOutcome::Open
+                } else {
+                    ProbeOutcome::Closed
+                }
+            }
+        };
+
+        let alive = tokio::time::timeout(
+            Duration::from_secs(5),
+            scan_hosts(&ips, 1..=4, ScanDepth::FirstOpen, &config, &controller, probe),
+        )
+        .await
+        .expect("cancelled scan should finish promptly")
+        .unwrap();
+
+        assert_eq!(hosts(alive), vec![first]);
+    }
+
+    #[tokio::test]
+    async fn test_result_callback_break_stops_scan() {
+        let dir = tempfile::tempdir().unwrap();
+        use crate::modules::scan::ResultCallback;
+        use std::ops::ControlFlow;
+
+        let seen: Arc<std::sync::Mutex<Vec<ScanResult>>> = Arc::default();
+        let recorded = Arc::clone(&seen);
+        let config = ScanConfig {
+            max_concurrency: 1,
+            on_result: Some(ResultCallback::new(move |result| {
+                recorded.lock().unwrap().push(result.clone());
+                ControlFlow::Break(())
+            })),
+            ..scratch_config(&dir)
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+        let ips: Vec<IpAddr> = (1..=4)
+            .map(|i| IpAddr::V4(Ipv4Addr::new(192, 0, 2, i)))
+            .collect();
+        let probed = std::sync::Mutex::new(Vec::new());
+
+        // Every host is open on port 2, but only the first should be probed
+        let probe = |addr: SocketAddr| {
+            probed.lock().unwrap().push(addr);
+            async move {
+                if addr.port() == 2 {
+                    ProbeOutcome::Open
+                } else {
+                    ProbeOutcome::Closed
+                }
+            }
+        };
+
+        let alive = scan_hosts(&ips, 1..=3, ScanDepth::FirstOpen, &config, &controller, probe)
+            .await
+            .unwrap();
+        assert_eq!(hosts(alive), vec![ips[0]]);
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 1);
+        assert_eq!(seen[0].ip, ips[0]);
+        assert_eq!(seen[0].ports[0].port, 2);
+        assert!(probed.lock().unwrap().iter().all(|addr| addr.ip() == ips[0]));
+    }
+
+    #[tokio::test]
+    async fn test_result_callback_break_leaves_caller_token_alone() {
+        use crate::modules::scan::ResultCallback;
+        use std::ops::ControlFlow;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let dir = tempfile::tempdir().unwrap();
+        let shared = CancellationToken::new();
+        let config = ScanConfig {
+            cancel: shared.clone(),
+            on_result: Some(ResultCallback::new(|_| ControlFlow::Break(()))),
+            ..scratch_config(&dir)
+        };
+
+        let alive = ping_range_with(&[IpAddr::V4(Ipv4Addr::LOCALHOST)], port, port, &config)
+            .await
+            .unwrap();
+        assert_eq!(alive, vec![IpAddr::V4(Ipv4Addr::LOCALHOST)]);
+        assert!(!shared.is_cancelled());
+    }
+
     #[test]
     fn test_ping_range() {
         let rt = Runtime::new().unwrap();
         let ips = vec![IpAddr::V4(Ipv4Addr::LOCALHOST)];
         
+        let dir = tempfile::tempdir().unwrap();
+
         rt.block_on(async {
-            let alive = ping_range(&ips, 79, 81).await.unwrap();
+            let config = scratch_config(&dir);
+            let alive = ping_range_with(&ips, 79, 81, &config).await.unwrap();
             assert!(!alive.is_empty());
         });
     }
@@ -0,0 +1,414 @@
+// Report module rendering saved scan sessions without re-scanning
+
+use crate::core::types::AddrType;
+use crate::modules::session::{PortResult, ScanSession};
+use crate::utils::helpers::csv_field;
+use chrono::Local;
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Presentation formats for a scan report
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Csv,
+    Grepable, // One line per host, nmap -oG style
+    Json,
+    NmapXml, // Nmap-compatible XML, see `nmap_xml`
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "table" => Ok(Self::Table),
+            "csv" => Ok(Self::Csv),
+            "grepable" | "grep" => Ok(Self::Grepable),
+            "json" => Ok(Self::Json),
+            "xml" | "nmap-xml" => Ok(Self::NmapXml),
+            other => Err(format!(
+                "unknown output format '{}' (expected table, csv, grepable, json or xml)",
+                other
+            )),
+        }
+    }
+}
+
+impl ScanSession {
+    /// Loads a session previously written with `save`
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        serde_json::from_str(&data).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the session as pretty-printed JSON
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Renders every recorded port of `session` in the chosen format
+pub fn render(session: &ScanSession, format: OutputFormat) -> String {
+    match format {
+        OutputFormat::Table => render_table(session),
+        OutputFormat::Csv => render_csv(session),
+        OutputFormat::Grepable => render_grepable(session),
+        OutputFormat::Json => serde_json::to_string_pretty(session).unwrap_or_default(),
+        OutputFormat::NmapXml => nmap_xml(session),
+    }
+}
+
+fn render_table(session: &ScanSession) -> String {
+    let mut out = format!("{:<39} {:>5} {:<5} {}\n", "HOST", "PORT", "PROTO", "BANNER");
+    for result in &session.results {
+        for port in &result.ports {
+            out.push_str(&format!(
+                "{:<39} {:>5} {:<5} {}\n",
+                result.ip,
+                port.port,
+                protocol_name(&port.protocol),
+                first_line(port.banner.as_deref())
+            ));
+        }
+    }
+    out
+}
+
+fn render_csv(session: &ScanSession) -> String {
+    let mut out = String::from("ip,port,protocol,banner\n");
+    for result in &session.results {
+        for port in &result.ports {
+            out.push_str(&format!(
+                "{},{},{},{}\n",
+                result.ip,
+                port.port,
+                protocol_name(&port.protocol),
+                csv_field(port.banner.as_deref().unwrap_or(""))
+            ));
+        }
+    }
+    out
+}
+
+fn render_grepable(session: &ScanSession) -> String {
+    let mut out = String::new();
+    for result in &session.results {
+        let ports: Vec<String> = result
+            .ports
+            .iter()
+            .map(|p| {
+                // Slashes and commas delimit fields, so strip them from banners
+                let banner: String = first_line(p.banner.as_deref())
+                    .chars()
+                    .filter(|c| *c != '/' && *c != ',')
+                    .collect();
+                format!(
+                    "{}/open/{}//{}/",
+                    p.port,
+                    protocol_name(&p.protocol),
+                    banner
+                )
+            })
+            .collect();
+        out.push_str(&format!(
+            "Host: {}\tPorts: {}\n",
+            result.ip,
+            ports.join(", ")
+        ));
+    }
+    out
+}
+
+/// Host and port counts for a session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportSummary {
+    pub hosts: usize,
+    pub tcp_ports: usize,
+    pub udp_ports: usize,
+    pub with_banner: usize,
+}
+
+impl ReportSummary {
+    pub fn from_session(session: &ScanSession) -> Self {
+        let mut summary = Self {
+            hosts: session.results.len(),
+            ..Default::default()
+        };
+        for port in session.results.iter().flat_map(|r| &r.ports) {
+            match port.protocol {
+                AddrType::UDP => summary.udp_ports += 1,
+                _ => summary.tcp_ports += 1,
+            }
+            if port.banner.is_some() {
+                summary.with_banner += 1;
+            }
+        }
+        summary
+    }
+}
+
+impl fmt::Display for ReportSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} hosts, {} open ports ({} tcp, {} udp), {} with banners",
+            self.hosts,
+            self.tcp_ports + self.udp_ports,
+            self.tcp_ports,
+            self.udp_ports,
+            self.with_banner
+        )
+    }
+}
+
+/// Ports that appeared or disappeared between two reports
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReportDiff {
+    pub added: Vec<(IpAddr, PortResult)>,
+    pub removed: Vec<(IpAddr, PortResult)>,
+}
+
+impl ReportDiff {
+    /// Compares `new` against `old`, matching ports by (ip, port, protocol)
+    pub fn between(old: &ScanSession, new: &ScanSession) -> Self {
+        Self {
+            added: missing_from(new, old),
+            removed: missing_from(old, new),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+impl fmt::Display for ReportDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (sign, entries) in [('+', &self.added), ('-', &self.removed)] {
+            for (ip, port) in entries {
+                writeln!(
+                    f,
+                    "{} {}:{}/{}",
+                    sign,
+                    ip,
+                    port.port,
+                    protocol_name(&port.protocol)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Ports in `from` with no (ip, port, protocol) match in `other`
+fn missing_from(from: &ScanSession, other: &ScanSession) -> Vec<(IpAddr, PortResult)> {
+    from.results
+        .iter()
+        .flat_map(|r| r.ports.iter().map(move |p| (r.ip, p)))
+        .filter(|(ip, port)| {
+            !other.results.iter().any(|r| {
+                r.ip == *ip
+                    && r.ports
+                        .iter()
+                        .any(|p| p.port == port.port && p.protocol == port.protocol)
+            })
+        })
+        .map(|(ip, port)| (ip, port.clone()))
+        .collect()
+}
+
+/// Renders `session` as a minimal Nmap-compatible XML document
+/// Every recorded port is reported open; a banner becomes the service's `extrainfo`
+pub fn nmap_xml(session: &ScanSession) -> String {
+    let now = Local::now().timestamp();
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE nmaprun>\n");
+    xml.push_str(&format!(
+        "<nmaprun scanner=\"ipcow\" start=\"{}\" version=\"{}\" xmloutputversion=\"1.05\">\n",
+        now,
+        env!("CARGO_PKG_VERSION")
+    ));
+    for result in &session.results {
+        let addrtype = if result.ip.is_ipv6() { "ipv6" } else { "ipv4" };
+        xml.push_str("<host>\n<status state=\"up\" reason=\"user-set\"/>\n");
+        xml.push_str(&format!(
+            "<address addr=\"{}\" addrtype=\"{}\"/>\n<ports>\n",
+            result.ip, addrtype
+        ));
+        for port in &result.ports {
+            let service = match first_line(port.banner.as_deref()) {
+                "" => "<service name=\"unknown\" method=\"table\" conf=\"3\"/>".to_string(),
+                banner => format!(
+                    "<service name=\"unknown\" extrainfo=\"{}\" method=\"probed\" conf=\"3\"/>",
+                    xml_escape(banner)
+                ),
+            };
+            xml.push_str(&format!(
+                "<port protocol=\"{}\" portid=\"{}\"><state state=\"open\" reason=\"response\"/>{}</port>\n",
+                protocol_name(&port.protocol),
+                port.port,
+                service
+            ));
+        }
+        xml.push_str("</ports>\n</host>\n");
+    }
+    let hosts = session.results.len();
+    xml.push_str(&format!(
+        "<runstats><finished time=\"{}\"/><hosts up=\"{}\" down=\"0\" total=\"{}\"/></runstats>\n</nmaprun>\n",
+        now, hosts, hosts
+    ));
+    xml
+}
+
+/// Writes `session` to `path` as Nmap XML
+pub fn export_nmap_xml(session: &ScanSession, path: &Path) -> io::Result<()> {
+    std::fs::write(path, nmap_xml(session))
+}
+
+// Escapes text for use in an XML attribute, dropping characters XML 1.0 forbids
+fn xml_escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            '\t' | '\n' | '\r' => out.push(' '),
+            c if c.is_control() => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn protocol_name(protocol: &AddrType) -> &'static str {
+    match protocol {
+        AddrType::UDP => "udp",
+        _ => "tcp",
+    }
+}
+
+fn first_line(banner: Option<&str>) -> &str {
+    banner.and_then(|b| b.lines().next()).unwrap_or("").trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+
+    fn sample_session() -> ScanSession {
+        let mut session = ScanSession::new();
+        let host = SocketAddr::from(([10, 0, 0, 1], 22));
+        session.record(host, AddrType::TCP, Some("SSH-2.0-OpenSSH_9.6\r\n".into()));
+        session.record(SocketAddr::new(host.ip(), 53), AddrType::UDP, None);
+        session.record(
+            SocketAddr::from(([10, 0, 0, 2], 80)),
+            AddrType::TCP,
+            Some("HTTP/1.1 200 OK, \"fine\"".into()),
+        );
+        session
+    }
+
+    #[test]
+    fn test_saved_report_renders_as_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.json");
+        sample_session().save(&path).unwrap();
+
+        let loaded = ScanSession::load(&path).unwrap();
+        assert_eq!(
+            render(&loaded, OutputFormat::Csv),
+            "ip,port,protocol,banner\n\
+             10.0.0.1,22,tcp,\"SSH-2.0-OpenSSH_9.6\r\n\"\n\
+             10.0.0.1,53,udp,\n\
+             10.0.0.2,80,tcp,\"HTTP/1.1 200 OK, \"\"fine\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_export_nmap_xml() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("scan.xml");
+        export_nmap_xml(&sample_session(), &path).unwrap();
+
+        let xml = std::fs::read_to_string(&path).unwrap();
+        // Nmap output carries a DOCTYPE, which roxmltree rejects by default
+        let options = roxmltree::ParsingOptions {
+            allow_dtd: true,
+            ..Default::default()
+        };
+        let doc = roxmltree::Document::parse_with_options(&xml, options).unwrap();
+        assert_eq!(doc.root_element().tag_name().name(), "nmaprun");
+
+        let hosts: Vec<_> = doc
+            .descendants()
+            .filter(|n| n.has_tag_name("host"))
+            .collect();
+        assert_eq!(hosts.len(), 2);
+        let address = hosts[0]
+            .children()
+            .find(|n| n.has_tag_name("address"))
+            .unwrap();
+        assert_eq!(address.attribute("addr"), Some("10.0.0.1"));
+
+        let ports: Vec<_> = hosts[0]
+            .descendants()
+            .filter(|n| n.has_tag_name("port"))
+            .collect();
+        let ids: Vec<_> = ports
+            .iter()
+            .map(|p| {
+                (
+                    p.attribute("portid").unwrap(),
+                    p.attribute("protocol").unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(ids, vec![("22", "tcp"), ("53", "udp")]);
+        let state = ports[0]
+            .children()
+            .find(|n| n.has_tag_name("state"))
+            .unwrap();
+        assert_eq!(state.attribute("state"), Some("open"));
+        let service = ports[0]
+            .children()
+            .find(|n| n.has_tag_name("service"))
+            .unwrap();
+        assert_eq!(service.attribute("extrainfo"), Some("SSH-2.0-OpenSSH_9.6"));
+
+        // Quotes in banners survive escaping
+        let service = hosts[1]
+            .descendants()
+            .find(|n| n.has_tag_name("service"))
+            .unwrap();
+        assert_eq!(
+            service.attribute("extrainfo"),
+            Some("HTTP/1.1 200 OK, \"fine\"")
+        );
+    }
+
+    #[test]
+    fn test_summary_and_diff() {
+        let old = sample_session();
+        let mut new = sample_session();
+        new.results[1].ports.clear();
+        new.record(SocketAddr::from(([10, 0, 0, 1], 443)), AddrType::TCP, None);
+
+        let summary = ReportSummary::from_session(&old);
+        assert_eq!(
+            (summary.hosts, summary.tcp_ports, summary.udp_ports),
+            (2, 2, 1)
+        );
+
+        let diff = ReportDiff::between(&old, &new);
+        assert_eq!(diff.to_string(), "+ 10.0.0.1:443/tcp\n- 10.0.0.2:80/tcp\n");
+        assert!(ReportDiff::between(&old, &old).is_empty());
+    }
+}
@@ -0,0 +1,521 @@
+// Scan configuration and flow-control primitives shared by the ping/scan module
+
+use rand::seq::SliceRandom;
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::ControlFlow;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
+use tokio::sync::Notify;
+use crate::core::logging::Logger;
+use crate::core::types::{NetworkError, NetworkResult};
+use crate::modules::session::ScanResult;
+use crate::modules::sink::ResultSink;
+use crate::utils::{RateLimiter, RngSource};
+use tokio_util::sync::CancellationToken;
+
+/// User-Agent sent by the benchmark client when `ScanConfig::user_agent` is unset
+pub const BENCHMARK_USER_AGENT: &str = "IPCow-Benchmark";
+
+/// Default `ScanConfig::host_log`, relative to the working directory
+pub const HOST_LOG_FILE: &str = "host_status.log";
+
+/// Tunable parameters for port/host scanning
+#[derive(Debug, Clone)]
+pub struct ScanConfig {
+    pub max_concurrency: usize,    // Upper bound on in-flight probes
+    pub min_concurrency: usize,    // Floor the adaptive controller never drops below
+    pub error_threshold: f64,      // Error ratio (0.0-1.0) in a window that triggers backoff
+    pub error_window: usize,       // Number of probes per error-rate evaluation
+    pub connect_timeout: Duration, // Per-probe connect timeout
+    pub adaptive_timeout: Option<AdaptiveTimeoutConfig>, // Derive the connect timeout from measured RTT
+    pub cancel: CancellationToken, // Stops the scan early, keeping results found so far
+    pub discovery_first: bool, // Sweep `discovery_ports` first, then scan only hosts that answered
+    pub discovery_ports: Vec<u16>, // Common ports used by the liveness sweep
+    pub icmp_first: bool, // Ping hosts first and port scan only those that replied
+    // Only the benchmark client sends requests: probes are bare connects, and the
+    // server's detection GET uses `HandlerConfig::user_agent`
+    pub user_agent: Option<String>, // Benchmark User-Agent (`BENCHMARK_USER_AGENT` when `None`)
+    pub rate_limit: Option<RateLimiter>, // Caps probes per second across the whole scan
+    pub logger: Logger, // Per-probe lines are logged at Debug level
+    pub load_throttle: Option<LoadThrottle>, // Holds back new probes while the machine is busy
+    pub shuffle_hosts: bool, // Probe hosts in random order, not input order (`--shuffle-hosts`)
+    pub rng: RngSource, // Seeds host shuffling (process-wide `--seed` by default)
+    pub result_sink: Option<Arc<dyn ResultSink>>, // Receives each live host as soon as it is found
+    pub stream_only: bool, // Hand results only to `result_sink` (required), keeping none in memory
+    pub on_result: Option<ResultCallback>, // Decides after each live host whether the scan goes on
+    pub host_log: PathBuf, // Host state changes and open ports are appended here
+}
+
+impl Default for ScanConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 64,
+            min_concurrency: 1,
+            error_threshold: 0.5,
+            error_window: 20,
+            connect_timeout: Duration::from_millis(200),
+            adaptive_timeout: None,
+            cancel: CancellationToken::new(),
+            discovery_first: false,
+            discovery_ports: vec![80, 443],
+            icmp_first: false,
+            user_agent: None,
+            rate_limit: None,
+            logger: Logger::default(),
+            load_throttle: None,
+            shuffle_hosts: false,
+            rng: RngSource::global(),
+            result_sink: None,
+            stream_only: false,
+            on_result: None,
+            host_log: PathBuf::from(HOST_LOG_FILE),
+        }
+    }
+}
+
+impl ScanConfig {
+    /// Rejects settings that would silently lose results
+    pub fn validate(&self) -> NetworkResult<()> {
+        if self.stream_only && self.result_sink.is_none() {
+            return Err(NetworkError::InvalidConfig(
+                "stream_only needs a result_sink to hand results to".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Order in which hosts are probed: `ips` as given, or shuffled by `rng`
+    /// when `shuffle_hosts` is set
+    pub fn scan_order(&self, ips: &[IpAddr]) -> Vec<IpAddr> {
+        let mut order = ips.to_vec();
+        if self.shuffle_hosts {
+            order.shuffle(&mut self.rng.derive("scan-order"));
+        }
+        order
+    }
+
+    /// Keep-alive GET issued by the benchmark client
+    /// Falls back to `BENCHMARK_USER_AGENT` when no User-Agent is configured
+    pub fn benchmark_request(&self) -> Vec<u8> {
+        format!(
+            "GET / HTTP/1.1\r\n\
+             Host: localhost\r\n\
+             User-Agent: {}\r\n\
+             Accept: */*\r\n\
+             Connection: keep-alive\r\n\r\n",
+            self.user_agent.as_deref().unwrap_or(BENCHMARK_USER_AGENT)
+        )
+        .into_bytes()
+    }
+}
+
+type ResultFn = dyn FnMut(&ScanResult) -> ControlFlow<()> + Send;
+
+/// Per-target callback run on each live host as it is found
+/// Returning `Break` skips the remaining probes and the scan returns the hosts found
+/// so far; only that scan stops, `ScanConfig::cancel` itself is left as it was.
+/// Clones share the same closure
+#[derive(Clone)]
+pub struct ResultCallback(Arc<Mutex<ResultFn>>);
+
+impl ResultCallback {
+    pub fn new(callback: impl FnMut(&ScanResult) -> ControlFlow<()> + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(callback)))
+    }
+
+    pub fn call(&self, result: &ScanResult) -> ControlFlow<()> {
+        (self.0.lock().unwrap())(result)
+    }
+}
+
+impl fmt::Debug for ResultCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ResultCallback")
+    }
+}
+
+/// One reading of machine load, in percent of capacity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadSample {
+    pub cpu_percent: f32,
+    pub memory_percent: f32,
+}
+
+impl LoadSample {
+    /// Reads global CPU usage and RAM usage from `system`
+    pub fn from_system(system: &mut System) -> Self {
+        system.refresh_cpu_usage();
+        system.refresh_memory_specifics(MemoryRefreshKind::nothing().with_ram());
+        let total = system.total_memory().max(1) as f32;
+        Self {
+            cpu_percent: system.global_cpu_usage(),
+            memory_percent: system.used_memory() as f32 / total * 100.0,
+        }
+    }
+
+    /// Whether either CPU or memory usage is above `ceiling` percent
+    pub fn exceeds(&self, ceiling: f32) -> bool {
+        self.cpu_percent > ceiling || self.memory_percent > ceiling
+    }
+}
+
+/// Pause/resume transition reported by `LoadThrottle`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThrottleEvent {
+    Paused(LoadSample),
+    Resumed(LoadSample),
+}
+
+type LoadSampler = Arc<dyn Fn() -> LoadSample + Send + Sync>;
+type ThrottleCallback = Arc<dyn Fn(ThrottleEvent) + Send + Sync>;
+
+/// Holds back new scan probes while CPU or memory usage is above a ceiling
+/// Load is sampled through `sysinfo` at most once per poll interval; probes already
+/// in flight are left alone. Clones share the ceiling and pause state
+#[derive(Clone)]
+pub struct LoadThrottle {
+    ceiling: Arc<Mutex<f32>>,
+    poll_interval: Duration,
+    sampler: LoadSampler,
+    on_change: Option<ThrottleCallback>,
+    state: Arc<Mutex<ThrottleState>>,
+}
+
+#[derive(Debug, Default)]
+struct ThrottleState {
+    paused: bool,
+    last_checked: Option<Instant>,
+}
+
+impl LoadThrottle {
+    /// Throttle pausing above `ceiling_percent` CPU or memory usage
+    pub fn new(ceiling_percent: f32) -> Self {
+        let system = Mutex::new(System::new_with_specifics(
+            RefreshKind::nothing()
+                .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+                .with_memory(MemoryRefreshKind::nothing().with_ram()),
+        ));
+        Self {
+            ceiling: Arc::new(Mutex::new(ceiling_percent)),
+            poll_interval: Duration::from_millis(500),
+            sampler: Arc::new(move || LoadSample::from_system(&mut system.lock().unwrap())),
+            on_change: None,
+            state: Arc::default(),
+        }
+    }
+
+    /// How often load is re-sampled, and how long a paused scan sleeps between checks
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Replaces the `sysinfo` reading with a custom load source
+    pub fn with_sampler(mut self, sampler: impl Fn() -> LoadSample + Send + Sync + 'static) -> Self {
+        self.sampler = Arc::new(sampler);
+        self
+    }
+
+    /// Called whenever probing pauses or resumes
+    pub fn on_change(mut self, callback: impl Fn(ThrottleEvent) + Send + Sync + 'static) -> Self {
+        self.on_change = Some(Arc::new(callback));
+        self
+    }
+
+    /// Adjusts the ceiling, taking effect at the next sample
+    pub fn set_ceiling(&self, ceiling_percent: f32) {
+        *self.ceiling.lock().unwrap() = ceiling_percent;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().paused
+    }
+
+    /// Waits until load is at or below the ceiling
+    pub async fn wait_for_capacity(&self) {
+        while self.check() {
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    // Samples load when due and returns whether probing should stay paused
+    fn check(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let fresh = state
+            .last_checked
+            .is_some_and(|at| at.elapsed() < self.poll_interval);
+        if !state.paused && fresh {
+            return false;
+        }
+
+        let sample = (self.sampler)();
+        state.last_checked = Some(Instant::now());
+        let over = sample.exceeds(*self.ceiling.lock().unwrap());
+        if over != state.paused {
+            state.paused = over;
+            if let Some(callback) = &self.on_change {
+                callback(if over {
+                    ThrottleEvent::Paused(sample)
+                } else {
+                    ThrottleEvent::Resumed(sample)
+                });
+            }
+        }
+        over
+    }
+}
+
+impl fmt::Debug for LoadThrottle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoadThrottle")
+            .field("ceiling", &*self.ceiling.lock().unwrap())
+            .field("poll_interval", &self.poll_interval)
+            .field("paused", &self.is_paused())
+            .finish()
+    }
+}
+
+/// AIMD concurrency controller for scan probes
+/// Halves the in-flight limit when the recent error rate exceeds the threshold
+/// and grows it by one for every healthy window, up to `max_concurrency`
+#[derive(Debug)]
+pub struct AdaptiveConcurrency {
+    state: Mutex<ControllerState>,
+    notify: Notify,
+    min: usize,
+    max: usize,
+    threshold: f64,
+    window_size: usize,
+}
+
+#[derive(Debug)]
+struct ControllerState {
+    limit: usize,
+    in_flight: usize,
+    // Recent probe outcomes, true = error (timeout/reset)
+    window: VecDeque<bool>,
+}
+
+impl AdaptiveConcurrency {
+    /// Creates a controller starting at the configured maximum concurrency
+    pub fn new(config: &ScanConfig) -> Self {
+        let max = config.max_concurrency.max(1);
+        Self {
+            state: Mutex::new(ControllerState {
+                limit: max,
+                in_flight: 0,
+                window: VecDeque::with_capacity(config.error_window),
+            }),
+            notify: Notify::new(),
+            min: config.min_concurrency.clamp(1, max),
+            max,
+            threshold: config.error_threshold,
+            window_size: config.error_window.max(1),
+        }
+    }
+
+    /// Waits until a probe slot is available under the current limit
+    pub async fn acquire(&self) -> ProbePermit<'_> {
+        loop {
+            // Register interest before checking so a concurrent release isn't missed
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return ProbePermit { controller: self };
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Records a probe outcome and adjusts the limit once a full window is observed
+    pub fn record(&self, is_error: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.window.push_back(is_error);
+        if state.window.len() < self.window_size {
+            return;
+        }
+
+        let errors = state.window.iter().filter(|e| **e).count();
+        let error_rate = errors as f64 / state.window.len() as f64;
+        state.window.clear();
+
+        if error_rate > self.threshold {
+            // Multiplicative decrease
+            state.limit = (state.limit / 2).max(self.min);
+        } else if state.limit < self.max {
+            // Additive increase
+            state.limit += 1;
+            drop(state);
+            self.notify.notify_waiters();
+        }
+    }
+
+    /// Current in-flight probe limit
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+}
+
+/// Bounds for deriving the connect timeout from observed round-trip times
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveTimeoutConfig {
+    pub samples: usize,      // Answered probes averaged before the timeout adapts
+    pub rtt_multiplier: f64, // Timeout = mean RTT * multiplier
+    pub min: Duration,       // Floor, so jitter on a fast LAN doesn't cause false negatives
+    pub max: Duration,       // Ceiling for very slow links
+}
+
+impl Default for AdaptiveTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            samples: 8,
+            rtt_multiplier: 4.0,
+            min: Duration::from_millis(20),
+            max: Duration::from_secs(3),
+        }
+    }
+}
+
+/// Connect timeout that tracks the RTT of recent answered probes
+/// Starts at `ScanConfig::connect_timeout`; once `samples` RTTs are measured, each
+/// new one moves the timeout to their mean times `rtt_multiplier`, within `min..=max`
+/// Without `ScanConfig::adaptive_timeout` the timeout stays fixed
+#[derive(Debug)]
+pub struct AdaptiveTimeout {
+    config: Option<AdaptiveTimeoutConfig>,
+    state: Mutex<TimeoutState>,
+}
+
+#[derive(Debug)]
+struct TimeoutState {
+    timeout: Duration,
+    // Most recent RTTs, oldest first
+    rtts: VecDeque<Duration>,
+}
+
+impl AdaptiveTimeout {
+    pub fn new(config: &ScanConfig) -> Self {
+        let samples = config.adaptive_timeout.map_or(0, |c| c.samples.max(1));
+        Self {
+            config: config.adaptive_timeout,
+            state: Mutex::new(TimeoutState {
+                timeout: config.connect_timeout,
+                rtts: VecDeque::with_capacity(samples),
+            }),
+        }
+    }
+
+    /// Timeout to apply to the next probe
+    pub fn timeout(&self) -> Duration {
+        self.state.lock().unwrap().timeout
+    }
+
+    /// Records the RTT of a probe that got an answer (open or refused)
+    pub fn record_rtt(&self, rtt: Duration) {
+        let Some(config) = self.config else {
+            return;
+        };
+        let samples = config.samples.max(1);
+        let mut state = self.state.lock().unwrap();
+        if state.rtts.len() == samples {
+            state.rtts.pop_front();
+        }
+        state.rtts.push_back(rtt);
+        if state.rtts.len() < samples {
+            return;
+        }
+
+        let mean = state.rtts.iter().sum::<Duration>() / samples as u32;
+        let scaled = mean.mul_f64(config.rtt_multiplier.max(0.0));
+        state.timeout = scaled.clamp(config.min, config.max.max(config.min));
+    }
+}
+
+/// Slot held by an in-flight probe, released on drop
+pub struct ProbePermit<'a> {
+    controller: &'a AdaptiveConcurrency,
+}
+
+impl Drop for ProbePermit<'_> {
+    fn drop(&mut self) {
+        self.controller.state.lock().unwrap().in_flight -= 1;
+        self.controller.notify.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_concurrency_recovers() {
+        let config = ScanConfig {
+            max_concurrency: 8,
+            error_window: 4,
+            ..Default::default()
+        };
+        let controller = AdaptiveConcurrency::new(&config);
+
+        // A fully failing window halves the limit
+        (0..4).for_each(|_| controller.record(true));
+        assert_eq!(controller.limit(), 4);
+
+        // Healthy windows ramp back up one step at a time
+        (0..8).for_each(|_| controller.record(false));
+        assert_eq!(controller.limit(), 6);
+    }
+
+    #[test]
+    fn test_adaptive_timeout_tightens_toward_rtt() {
+        let config = ScanConfig {
+            connect_timeout: Duration::from_secs(2),
+            adaptive_timeout: Some(AdaptiveTimeoutConfig {
+                samples: 4,
+                rtt_multiplier: 3.0,
+                min: Duration::from_millis(10),
+                max: Duration::from_secs(5),
+            }),
+            ..Default::default()
+        };
+        let timeout = AdaptiveTimeout::new(&config);
+
+        // Not enough samples yet, so the configured timeout still applies
+        (0..3).for_each(|_| timeout.record_rtt(Duration::from_millis(5)));
+        assert_eq!(timeout.timeout(), Duration::from_secs(2));
+
+        timeout.record_rtt(Duration::from_millis(5));
+        assert_eq!(timeout.timeout(), Duration::from_millis(15));
+
+        // Sub-millisecond LAN replies are held at the floor
+        (0..4).for_each(|_| timeout.record_rtt(Duration::from_micros(200)));
+        assert_eq!(timeout.timeout(), Duration::from_millis(10));
+
+        // Fixed mode ignores measurements
+        let fixed = AdaptiveTimeout::new(&ScanConfig::default());
+        (0..16).for_each(|_| fixed.record_rtt(Duration::from_millis(1)));
+        assert_eq!(fixed.timeout(), ScanConfig::default().connect_timeout);
+    }
+
+    #[test]
+    fn test_benchmark_request_follows_user_agent() {
+        let config = ScanConfig::default();
+        assert!(String::from_utf8(config.benchmark_request())
+            .unwrap()
+            .contains("User-Agent: IPCow-Benchmark\r\n"));
+
+        let config = ScanConfig {
+            user_agent: Some("curl/8.5.0".into()),
+            ..Default::default()
+        };
+        let request = String::from_utf8(config.benchmark_request()).unwrap();
+        assert!(request.contains("User-Agent: curl/8.5.0\r\n"));
+        assert!(request.ends_with("\r\n\r\n"));
+    }
+}
@@ -0,0 +1,163 @@
+// Scan session module collecting per-host results for reporting and analysis
+
+use crate::core::types::AddrType;
+use crate::utils::helpers::fingerprint_hash;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// A responsive port found on a host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortResult {
+    pub port: u16,
+    pub protocol: AddrType,     // TCP or UDP
+    pub banner: Option<String>, // First bytes the service sent, if any
+}
+
+/// Everything found on a single host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanResult {
+    pub ip: IpAddr,
+    pub ports: Vec<PortResult>,
+}
+
+impl ScanResult {
+    /// Folds another pass over the same host into this result
+    /// Ports are deduplicated by (port, protocol); a banner from `other` only
+    /// fills in an entry that has none
+    pub fn merge(&mut self, other: ScanResult) {
+        for port in other.ports {
+            match self
+                .ports
+                .iter_mut()
+                .find(|p| p.port == port.port && p.protocol == port.protocol)
+            {
+                Some(existing) => {
+                    if existing.banner.is_none() {
+                        existing.banner = port.banner;
+                    }
+                }
+                None => self.ports.push(port),
+            }
+        }
+    }
+}
+
+/// Results of one scan run, one entry per host in discovery order
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanSession {
+    pub results: Vec<ScanResult>,
+}
+
+impl ScanSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a responsive port, creating the host entry on first sight
+    pub fn record(&mut self, addr: SocketAddr, protocol: AddrType, banner: Option<String>) {
+        let port = PortResult {
+            port: addr.port(),
+            protocol,
+            banner,
+        };
+        match self.results.iter_mut().find(|r| r.ip == addr.ip()) {
+            Some(result) => result.ports.push(port),
+            None => self.results.push(ScanResult {
+                ip: addr.ip(),
+                ports: vec![port],
+            }),
+        }
+    }
+
+    /// Combines another session (e.g. a UDP pass after a TCP pass) into this one
+    /// Hosts are matched by IP; hosts only seen in `other` are appended
+    pub fn merge(&mut self, other: ScanSession) {
+        for result in other.results {
+            match self.results.iter_mut().find(|r| r.ip == result.ip) {
+                Some(existing) => existing.merge(result),
+                None => self.results.push(result),
+            }
+        }
+    }
+
+    /// Groups endpoints by banner fingerprint, smallest groups first
+    /// Unique or rare banners surface at the top, making odd hosts easy to spot
+    /// Ports without a banner are left out
+    pub fn group_by_banner(&self) -> Vec<(String, Vec<SocketAddr>)> {
+        let mut groups: HashMap<String, Vec<SocketAddr>> = HashMap::new();
+        for result in &self.results {
+            for port in &result.ports {
+                if let Some(banner) = &port.banner {
+                    groups
+                        .entry(fingerprint_hash(banner.as_bytes()))
+                        .or_default()
+                        .push(SocketAddr::new(result.ip, port.port));
+                }
+            }
+        }
+
+        let mut groups: Vec<_> = groups.into_iter().collect();
+        groups.sort_by(|a, b| a.1.len().cmp(&b.1.len()).then_with(|| a.0.cmp(&b.0)));
+        groups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_by_banner_isolates_odd_host() {
+        let mut session = ScanSession::new();
+        for i in 1..=4 {
+            let addr = SocketAddr::from(([10, 0, 0, i], 22));
+            session.record(addr, AddrType::TCP, Some("SSH-2.0-OpenSSH_9.6".into()));
+        }
+        let odd = SocketAddr::from(([10, 0, 0, 5], 22));
+        session.record(odd, AddrType::TCP, Some("SSH-2.0-dropbear_2022.83".into()));
+        session.record(SocketAddr::from(([10, 0, 0, 6], 80)), AddrType::TCP, None);
+
+        let groups = session.group_by_banner();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].1, vec![odd]);
+        assert_eq!(groups[1].1.len(), 4);
+        assert_eq!(groups[0].0, fingerprint_hash(b"SSH-2.0-dropbear_2022.83"));
+    }
+
+    #[test]
+    fn test_merge_tcp_and_udp_passes() {
+        let host = SocketAddr::from(([10, 0, 0, 1], 53));
+        let mut tcp = ScanSession::new();
+        tcp.record(host, AddrType::TCP, None);
+        tcp.record(SocketAddr::new(host.ip(), 22), AddrType::TCP, None);
+
+        let mut udp = ScanSession::new();
+        udp.record(host, AddrType::UDP, Some("dns".into()));
+        // Repeated TCP port from a second pass only contributes its banner
+        udp.record(
+            SocketAddr::new(host.ip(), 22),
+            AddrType::TCP,
+            Some("SSH-2.0".into()),
+        );
+        udp.record(SocketAddr::from(([10, 0, 0, 2], 161)), AddrType::UDP, None);
+
+        tcp.merge(udp);
+        assert_eq!(tcp.results.len(), 2);
+
+        let ports: Vec<_> = tcp.results[0]
+            .ports
+            .iter()
+            .map(|p| (p.port, p.protocol.clone(), p.banner.as_deref()))
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                (53, AddrType::TCP, None),
+                (22, AddrType::TCP, Some("SSH-2.0")),
+                (53, AddrType::UDP, Some("dns")),
+            ]
+        );
+        assert_eq!(tcp.results[1].ip, IpAddr::from([10, 0, 0, 2]));
+    }
+}
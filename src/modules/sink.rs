@@ -0,0 +1,136 @@
+// Push-based delivery of scan results to dashboards, files or queues as they are found
+
+use crate::modules::session::ScanResult;
+use async_trait::async_trait;
+use std::fmt;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::Mutex;
+
+/// Receives scan results while the scan runs
+/// `emit` is called once per result as soon as it is found, `finish` once when
+/// the scan ends (including after cancellation). Sinks report their own I/O errors
+#[async_trait]
+pub trait ResultSink: fmt::Debug + Send + Sync {
+    async fn emit(&self, result: &ScanResult);
+    async fn finish(&self);
+}
+
+/// Writes each result as one JSON object per line
+#[derive(Debug)]
+pub struct JsonLinesSink {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl JsonLinesSink {
+    /// Creates (or truncates) the output file at `path`
+    pub async fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::create(path).await?;
+        Ok(Self {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+}
+
+#[async_trait]
+impl ResultSink for JsonLinesSink {
+    async fn emit(&self, result: &ScanResult) {
+        let mut line = match serde_json::to_vec(result) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("[Sink] Failed to encode result for {}: {}", result.ip, e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = self.writer.lock().await.write_all(&line).await {
+            eprintln!("[Sink] Failed to write result for {}: {}", result.ip, e);
+        }
+    }
+
+    async fn finish(&self) {
+        if let Err(e) = self.writer.lock().await.flush().await {
+            eprintln!("[Sink] Failed to flush results: {}", e);
+        }
+    }
+}
+
+/// Reads a file written by `JsonLinesSink` back one result at a time
+/// Only the current line is held in memory, so arbitrarily large scans can be consumed
+#[derive(Debug)]
+pub struct JsonLinesReader {
+    lines: io::Lines<BufReader<std::fs::File>>,
+}
+
+impl JsonLinesReader {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(Self {
+            lines: BufReader::new(file).lines(),
+        })
+    }
+}
+
+impl Iterator for JsonLinesReader {
+    type Item = io::Result<ScanResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                serde_json::from_str(&line)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::types::AddrType;
+    use crate::modules::session::PortResult;
+
+    #[tokio::test]
+    async fn test_json_lines_sink_writes_one_line_per_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.jsonl");
+        let sink = JsonLinesSink::create(&path).await.unwrap();
+
+        let results: Vec<ScanResult> = (1..=2)
+            .map(|i| ScanResult {
+                ip: [10, 0, 0, i].into(),
+                ports: vec![PortResult {
+                    port: 22,
+                    protocol: AddrType::TCP,
+                    banner: None,
+                }],
+            })
+            .collect();
+        for result in &results {
+            sink.emit(result).await;
+        }
+        sink.finish().await;
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let parsed: Vec<ScanResult> = contents
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(parsed, results);
+
+        let read: Vec<ScanResult> = JsonLinesReader::open(&path)
+            .unwrap()
+            .collect::<io::Result<_>>()
+            .unwrap();
+        assert_eq!(read, results);
+    }
+}
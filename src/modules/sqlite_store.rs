@@ -0,0 +1,310 @@
+// SQLite persistence for scan sessions and live discoveries, keeping history across runs
+
+use crate::core::discovery::DiscoverySink;
+use crate::core::types::AddrType;
+use crate::modules::session::{PortResult, ScanResult, ScanSession};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::Mutex;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS hosts (
+    id INTEGER PRIMARY KEY,
+    ip TEXT NOT NULL UNIQUE
+);
+CREATE TABLE IF NOT EXISTS ports (
+    id INTEGER PRIMARY KEY,
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    host_id INTEGER NOT NULL REFERENCES hosts(id),
+    port INTEGER NOT NULL,
+    protocol TEXT NOT NULL,
+    UNIQUE (run_id, host_id, port, protocol)
+);
+CREATE TABLE IF NOT EXISTS services (
+    port_id INTEGER PRIMARY KEY REFERENCES ports(id),
+    banner TEXT NOT NULL
+);
+";
+
+/// Scan history stored in SQLite: every run is appended, hosts are shared across runs
+/// Also usable as a `DiscoverySink`, which records into one run opened on first use
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+    // Run that `DiscoverySink::record` writes into
+    sink_run: Mutex<Option<i64>>,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the database file at `path`
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// Database that lives only as long as the store
+    pub fn open_in_memory() -> rusqlite::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(conn: Connection) -> rusqlite::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            sink_run: Mutex::new(None),
+        })
+    }
+
+    /// Starts a new run and returns its id
+    pub fn begin_run(&self) -> rusqlite::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO runs (started_at) VALUES (?1)",
+            params![chrono::Local::now().to_rfc3339()],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Writes `session` as a new run and returns the run id
+    pub fn write_session(&self, session: &ScanSession) -> rusqlite::Result<i64> {
+        let run_id = self.begin_run()?;
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for result in &session.results {
+            for port in &result.ports {
+                insert_port(&tx, run_id, result.ip, port)?;
+            }
+        }
+        tx.commit()?;
+        Ok(run_id)
+    }
+
+    /// Number of runs stored so far
+    pub fn run_count(&self) -> rusqlite::Result<i64> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM runs", [], |row| row.get(0))
+    }
+
+    /// Whether no run has been stored yet
+    pub fn is_empty(&self) -> rusqlite::Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let run: Option<i64> = conn
+            .query_row("SELECT id FROM runs LIMIT 1", [], |row| row.get(0))
+            .optional()?;
+        Ok(run.is_none())
+    }
+
+    /// Each host's ports as seen in the most recent run that found anything on it
+    /// Hosts come back in first-seen order, ports sorted by number then protocol
+    pub fn latest_per_host(&self) -> rusqlite::Result<Vec<ScanResult>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT h.ip, p.port, p.protocol, s.banner
+             FROM ports p
+             JOIN hosts h ON h.id = p.host_id
+             LEFT JOIN services s ON s.port_id = p.id
+             WHERE p.run_id = (SELECT MAX(run_id) FROM ports WHERE host_id = p.host_id)
+             ORDER BY h.id, p.port, p.protocol",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u16>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+
+        let mut results: Vec<ScanResult> = Vec::new();
+        for row in rows {
+            let (ip, port, protocol, banner) = row?;
+            let ip: IpAddr = ip.parse().map_err(|e| conversion_error(0, e))?;
+            let protocol = parse_protocol(&protocol).map_err(|e| conversion_error(2, e))?;
+            let port = PortResult {
+                port,
+                protocol,
+                banner,
+            };
+            match results.last_mut() {
+                Some(last) if last.ip == ip => last.ports.push(port),
+                _ => results.push(ScanResult {
+                    ip,
+                    ports: vec![port],
+                }),
+            }
+        }
+        Ok(results)
+    }
+
+    // Run that sink writes go to, created on the first discovery
+    fn sink_run_id(&self) -> rusqlite::Result<i64> {
+        let mut sink_run = self.sink_run.lock().unwrap();
+        if let Some(id) = *sink_run {
+            return Ok(id);
+        }
+        let id = self.begin_run()?;
+        *sink_run = Some(id);
+        Ok(id)
+    }
+}
+
+impl DiscoverySink for SqliteStore {
+    fn record(&self, addr: SocketAddr, banner: &str) -> io::Result<()> {
+        let run_id = self.sink_run_id().map_err(io::Error::other)?;
+        let port = PortResult {
+            port: addr.port(),
+            protocol: AddrType::TCP,
+            banner: Some(banner.to_string()),
+        };
+        let conn = self.conn.lock().unwrap();
+        insert_port(&conn, run_id, addr.ip(), &port).map_err(io::Error::other)
+    }
+}
+
+// Inserts one port (and its banner) for `ip` into `run_id`
+// A repeated (run, host, port, protocol) keeps its row and replaces the banner
+fn insert_port(
+    conn: &Connection,
+    run_id: i64,
+    ip: IpAddr,
+    port: &PortResult,
+) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO hosts (ip) VALUES (?1)",
+        params![ip.to_string()],
+    )?;
+    let host_id: i64 = conn.query_row(
+        "SELECT id FROM hosts WHERE ip = ?1",
+        params![ip.to_string()],
+        |row| row.get(0),
+    )?;
+
+    let protocol = format!("{:?}", port.protocol);
+    conn.execute(
+        "INSERT OR IGNORE INTO ports (run_id, host_id, port, protocol) VALUES (?1, ?2, ?3, ?4)",
+        params![run_id, host_id, port.port, protocol],
+    )?;
+    let port_id: i64 = conn.query_row(
+        "SELECT id FROM ports WHERE run_id = ?1 AND host_id = ?2 AND port = ?3 AND protocol = ?4",
+        params![run_id, host_id, port.port, protocol],
+        |row| row.get(0),
+    )?;
+
+    if let Some(banner) = &port.banner {
+        conn.execute(
+            "INSERT OR REPLACE INTO services (port_id, banner) VALUES (?1, ?2)",
+            params![port_id, banner],
+        )?;
+    }
+    Ok(())
+}
+
+fn parse_protocol(protocol: &str) -> Result<AddrType, String> {
+    match protocol {
+        "TCP" => Ok(AddrType::TCP),
+        "UDP" => Ok(AddrType::UDP),
+        other => Err(format!("unknown protocol {}", other)),
+    }
+}
+
+fn conversion_error(column: usize, e: impl ToString) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(
+        column,
+        rusqlite::types::Type::Text,
+        e.to_string().into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::discovery::ServiceDiscovery;
+    use std::sync::Arc;
+
+    fn addr(last: u8, port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last], port))
+    }
+
+    #[test]
+    fn test_write_session_and_read_back() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        assert!(store.is_empty().unwrap());
+
+        let mut first = ScanSession::new();
+        first.record(
+            addr(1, 22),
+            AddrType::TCP,
+            Some("SSH-2.0-OpenSSH_9.6".into()),
+        );
+        first.record(addr(1, 80), AddrType::TCP, None);
+        first.record(addr(2, 53), AddrType::UDP, None);
+        store.write_session(&first).unwrap();
+
+        // Second run only revisits host .1, where port 80 has closed
+        let mut second = ScanSession::new();
+        second.record(
+            addr(1, 22),
+            AddrType::TCP,
+            Some("SSH-2.0-OpenSSH_9.7".into()),
+        );
+        store.write_session(&second).unwrap();
+
+        assert_eq!(store.run_count().unwrap(), 2);
+        let latest = store.latest_per_host().unwrap();
+        assert_eq!(
+            latest,
+            vec![
+                ScanResult {
+                    ip: addr(1, 0).ip(),
+                    ports: vec![PortResult {
+                        port: 22,
+                        protocol: AddrType::TCP,
+                        banner: Some("SSH-2.0-OpenSSH_9.7".into()),
+                    }],
+                },
+                ScanResult {
+                    ip: addr(2, 0).ip(),
+                    ports: vec![PortResult {
+                        port: 53,
+                        protocol: AddrType::UDP,
+                        banner: None,
+                    }],
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_as_discovery_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(SqliteStore::open_in_memory().unwrap());
+        let discovery = ServiceDiscovery::new()
+            .with_log_file(dir.path().join("services.txt"))
+            .with_sink(store.clone());
+
+        discovery.record_service(addr(3, 21), "220 FTP ready").await;
+        discovery
+            .record_service(addr(3, 25), "220 SMTP ready")
+            .await;
+
+        assert_eq!(store.run_count().unwrap(), 1);
+        let latest = store.latest_per_host().unwrap();
+        assert_eq!(latest.len(), 1);
+        let banners: Vec<_> = latest[0]
+            .ports
+            .iter()
+            .map(|p| (p.port, p.banner.clone().unwrap()))
+            .collect();
+        assert_eq!(
+            banners,
+            vec![(21, "220 FTP ready".into()), (25, "220 SMTP ready".into())]
+        );
+    }
+}
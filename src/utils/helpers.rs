@@ -1,42 +1,90 @@
+use crate::modules::scan::ScanConfig;
+use chrono::{DateTime, Local};
 use futures::stream::{self, StreamExt};
+use std::collections::BTreeMap;
 use std::fs::{File, OpenOptions};
-use std::io::{self, BufWriter};
+use std::io;
 use std::io::{BufRead, BufReader, Write};
 use std::net::SocketAddr;
 use std::num::NonZeroUsize;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::thread::{self, available_parallelism};
 use std::time::{Duration, Instant};
-use sysinfo::{CpuRefreshKind, RefreshKind, System};
+use sysinfo::{CpuRefreshKind, MemoryRefreshKind, RefreshKind, System};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
+use tokio_util::sync::CancellationToken;
 
+/// Measurements from one benchmark run at a fixed worker count
 #[derive(Debug)]
-struct BenchmarkResult {
-    cpu_usage: f32,
-    memory_usage: f64,
-    io_throughput: f64,
-    latency: Duration,
+pub struct BenchmarkResult {
+    pub cpu_usage: f32,
+    pub memory_usage: f64,
+    pub io_throughput: f64,
+    pub latency: Duration,
     cpu_tracker: Option<CpuTracker>,
-    total_tasks: u64,   // Add total tasks counter
-    total_threads: u64, // Add total threads counter
+    pub total_tasks: u64,   // Add total tasks counter
+    pub total_threads: u64, // Add total threads counter
+    pub failed_ops: u64,    // Client ops that errored or timed out
 }
 
+/// How long each benchmark worker drives client requests
+const BENCH_CLIENT_DURATION: Duration = Duration::from_secs(3);
+/// Upper bound for a single benchmark client connect/write/read
+const BENCH_IO_TIMEOUT: Duration = Duration::from_millis(500);
+/// Longest a benchmark client waits before retrying a refused or timed-out connect
+const BENCH_MAX_CONNECT_BACKOFF: Duration = Duration::from_millis(250);
+/// Latest benchmark per machine fingerprint, as a JSON object keyed by fingerprint
+const METRICS_CACHE_FILE: &str = "metrics.txt";
+/// Append-only log of every benchmark run, one JSON object per line
+const METRICS_HISTORY_FILE: &str = "metrics_history.jsonl";
+/// Budgets and stop conditions of the worker optimization benchmark
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    pub target_cpu: f32,           // CPU utilization (percent) the search aims for
+    pub max_duration: Duration,    // Total time budget of the search
+    pub warmup: Duration,          // Idle time before the first run
+    pub plateau_secs: u64,         // Stop after this long without a better configuration
+    pub cancel: CancellationToken, // Aborts the search, including a run in progress
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            target_cpu: 80.0,
+            max_duration: Duration::from_secs(15),
+            warmup: Duration::from_secs(5),
+            plateau_secs: 5,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+/// Worker count fixed by `thread_factor_override`, e.g. from `--workers`
+static THREAD_FACTOR_OVERRIDE: OnceLock<usize> = OnceLock::new();
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
-struct SystemMetrics {
-    max_cpu_usage: f32,
-    optimal_threads: usize,
-    total_workers: usize,
-    memory_usage_mb: f64,
+/// Outcome of a worker optimization benchmark, as cached in `metrics.txt`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemMetrics {
+    pub max_cpu_usage: f32,     // Highest CPU utilization seen in any run (percent)
+    pub optimal_threads: usize, // Recommended worker count
+    pub total_workers: usize,   // Worker configurations tested
+    pub memory_usage_mb: f64,
     #[serde(skip)]
-    benchmark_duration: Duration,
-    total_tasks: u64,   // Add total tasks counter
-    total_threads: u64, // Add total threads counter
+    pub benchmark_duration: Duration,
+    pub total_tasks: u64,   // Tasks completed across all runs
+    pub total_threads: u64, // Threads spawned across all runs
+    #[serde(default)]
+    pub recorded_at: Option<DateTime<Local>>, // When the benchmark finished (absent in old files)
+    #[serde(default)]
+    pub machine: Option<String>, // `machine_fingerprint` of the benchmarked machine (absent in old files)
 }
 
 #[derive(Debug)]
@@ -87,24 +135,76 @@ struct CpuMeasurement {
     per_core: Vec<f32>,
 }
 
+/// Identifies the hardware a benchmark ran on: logical CPUs plus total memory
+/// rounded up to a power of two GiB, so small reporting differences don't matter
+pub fn machine_fingerprint() -> String {
+    let cpus = available_parallelism().map_or(1, NonZeroUsize::get);
+    let system = System::new_with_specifics(
+        RefreshKind::nothing().with_memory(MemoryRefreshKind::nothing().with_ram()),
+    );
+    machine_key(cpus, system.total_memory())
+}
+
+fn machine_key(cpus: usize, total_memory_bytes: u64) -> String {
+    let gib = total_memory_bytes.div_ceil(1 << 30).max(1).next_power_of_two();
+    format!("{}cpu-{}gib", cpus, gib)
+}
+
+/// Makes every later `get_thread_factor` return `n` (at least 1) without benchmarking
+/// or touching the metrics files; returns false if an override was already installed
+pub fn thread_factor_override(n: usize) -> bool {
+    THREAD_FACTOR_OVERRIDE.set(n.max(1)).is_ok()
+}
+
 pub fn get_thread_factor() -> usize {
-    // Check for existing metrics on disk
-    if let Ok(metrics) = read_metrics_from_file() {
-        println!("Metrics loaded from file: {:?}", metrics);
-        return metrics.optimal_threads;
+    get_thread_factor_with(false)
+}
+
+/// Worker count benchmarked on this machine; `rebench` ignores the cached
+/// results and measures again. A `thread_factor_override` takes precedence
+pub fn get_thread_factor_with(rebench: bool) -> usize {
+    if let Some(&workers) = THREAD_FACTOR_OVERRIDE.get() {
+        return workers;
+    }
+    let machine = machine_fingerprint();
+    if !rebench {
+        // Check for existing metrics on disk, only trusting those from this machine
+        if let Ok(metrics) = read_metrics_from_file(&machine) {
+            println!("Metrics loaded from file: {:?}", metrics);
+            return metrics.optimal_threads;
+        }
+        // Fall back to the most recent run on this machine recorded in the history
+        let recorded = read_metrics_history()
+            .into_iter()
+            .rev()
+            .find(|metrics| metrics.machine.as_deref() == Some(machine.as_str()));
+        if let Some(metrics) = recorded {
+            println!("Metrics loaded from history: {:?}", metrics);
+            return metrics.optimal_threads;
+        }
     }
 
+    benchmark_thread_factor(&BenchConfig::default()).unwrap_or(1)
+}
+
+/// Runs the worker search and returns its full measurements without printing or
+/// writing any files; `optimal_threads` is the recommended worker count
+pub fn benchmark_workers(config: BenchConfig) -> SystemMetrics {
+    search_workers(&config, &|_| {}, &mut default_runner)
+}
+
+/// Searches for the optimal worker count and caches the result for this machine
+/// Returns `None`, caching nothing, when `bench.cancel` fires before the search ends
+pub fn benchmark_thread_factor(bench: &BenchConfig) -> Option<usize> {
     let system_threads = available_parallelism()
         .unwrap_or(NonZeroUsize::new(1).unwrap())
         .get();
-
-    let mut system = System::new_all();
-    system.refresh_all();
-
-    let base_workers = system_threads;
-    let max_workers = base_workers * 32; // Doubled from 16 to allow more headroom
-
-    let (optimal, metrics) = find_optimal_workers(&mut system, base_workers, max_workers);
+    let metrics = search_workers(bench, &|line| println!("{}", line), &mut default_runner);
+    let optimal = metrics.optimal_threads;
+    if bench.cancel.is_cancelled() {
+        println!("► Benchmark cancelled, keeping previous metrics");
+        return None;
+    }
 
     // Print detailed system metrics
     println!("\n=== System Performance Metrics ===");
@@ -120,8 +220,34 @@ pub fn get_thread_factor() -> usize {
 
     // Write metrics to file
     write_metrics_to_file(&metrics).expect("Failed to write metrics to file");
+    if let Err(e) = append_metrics_history(Path::new(METRICS_HISTORY_FILE), &metrics) {
+        eprintln!("Failed to append metrics history: {}", e);
+    }
 
-    optimal
+    Some(optimal)
+}
+
+// Benchmarks with the default client request
+fn default_runner(workers: usize, system: &mut System, cancel: &CancellationToken) -> BenchmarkResult {
+    run_benchmark(workers, system, &ScanConfig::default(), cancel)
+}
+
+// Searches from one worker per CPU up to 32 times that, on a freshly refreshed `System`
+// All output goes to `progress`, so a no-op `progress` keeps the search silent
+fn search_workers(
+    bench: &BenchConfig,
+    progress: &dyn Fn(&str),
+    run: &mut BenchRunner,
+) -> SystemMetrics {
+    let base_workers = available_parallelism()
+        .unwrap_or(NonZeroUsize::new(1).unwrap())
+        .get();
+
+    let mut system = System::new_all();
+    system.refresh_all();
+
+    let max_workers = base_workers * 32; // Doubled from 16 to allow more headroom
+    find_optimal_workers(&mut system, base_workers, max_workers, bench, progress, run).1
 }
 
 fn calculate_memory_factor(sys: &System) -> f64 {
@@ -162,7 +288,14 @@ fn calculate_max_safe_threads(sys: &System) -> usize {
     std::cmp::min(memory_limited_threads, cpu_limited_threads)
 }
 
-fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize, SystemMetrics) {
+fn find_optimal_workers(
+    system: &mut System,
+    base: usize,
+    max: usize,
+    bench: &BenchConfig,
+    progress: &dyn Fn(&str),
+    run: &mut BenchRunner,
+) -> (usize, SystemMetrics) {
     let mut best_workers = base;
     let mut best_score = 0.0;
     let mut optimal_cpu = 0.0;
@@ -171,26 +304,28 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
     let mut total_tested = 0;
     let mut last_cpu = 0.0;
     let mut plateau_counter = 0;
-    let target_cpu = 80.0; // Changed target CPU utilization to 80%
+    let target_cpu = bench.target_cpu;
+    let in_budget =
+        |start: Instant| start.elapsed() < bench.max_duration && !bench.cancel.is_cancelled();
     let mut total_tasks = 0;
     let mut total_threads = 0;
     let mut last_improvement = Instant::now();
 
-    println!("=== Worker Optimization in Progress ===\n");
-    println!("Target CPU Utilization: {:.1}%\n", target_cpu);
+    progress("=== Worker Optimization in Progress ===\n");
+    progress(&format!("Target CPU Utilization: {:.1}%\n", target_cpu));
 
     let mut next_workers = base;
 
     // Increase waiting time before the initial warm-up phase
-    thread::sleep(Duration::from_secs(5));
+    sleep_unless_cancelled(bench.warmup, &bench.cancel);
 
     // Initial warm-up
     system.refresh_all();
     thread::sleep(Duration::from_millis(50));
 
-    while next_workers <= max && start_time.elapsed() < Duration::from_secs(15) {
+    while next_workers <= max && in_budget(start_time) {
         let workers = next_workers;
-        let result = run_benchmark(workers, system);
+        let result = run(workers, system, &bench.cancel);
 
         total_tasks += result.total_tasks;
         total_threads += result.total_threads;
@@ -221,8 +356,8 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
             (workers as f32 * 0.9) as usize
         };
 
-        println!(
-            "{} | Workers: {} | CPU: {:.1}% | Target: {:.1}% | Progress: {:.1}% | Scale: {:.1}x",
+        progress(&format!(
+            "{} | Workers: {} | CPU: {:.1}% | Target: {:.1}% | Progress: {:.1}% | Scale: {:.1}x | Failed ops: {}",
             if cpu_percentage < 90.0 {
                 "Ramp"
             } else {
@@ -232,8 +367,9 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
             result.cpu_usage,
             target_cpu,
             cpu_percentage,
-            (next_workers as f32 / workers as f32)
-        );
+            (next_workers as f32 / workers as f32),
+            result.failed_ops
+        ));
 
         let score = calculate_efficiency_score(&result, workers);
         if score > best_score || (score >= best_score && result.cpu_usage > optimal_cpu) {
@@ -241,22 +377,26 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
             best_workers = workers;
             optimal_cpu = result.cpu_usage;
             last_improvement = Instant::now();
-            println!(
+            progress(&format!(
                 "► New best configuration found! Workers: {} | CPU: {:.1}%",
                 best_workers, optimal_cpu
-            );
+            ));
         }
 
         // Break conditions
-        if last_improvement.elapsed() > Duration::from_secs(5) && total_tested > 4 {
-            println!("► Optimization complete: no improvement for 5 seconds");
+        let plateau = Duration::from_secs(bench.plateau_secs);
+        if last_improvement.elapsed() > plateau && total_tested > 4 {
+            progress(&format!(
+                "► Optimization complete: no improvement for {} seconds",
+                bench.plateau_secs
+            ));
             break;
         }
 
         // Prevent getting stuck
         if next_workers == workers {
             next_workers = workers + (workers / 3);
-            println!("► Breaking plateau - increasing workers by 33%");
+            progress("► Breaking plateau - increasing workers by 33%");
         }
 
         next_workers = next_workers.min(max);
@@ -265,9 +405,9 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
     }
 
     // Rapid fine-tune phase
-    if next_workers <= max && start_time.elapsed() < Duration::from_secs(15) {
+    if next_workers <= max && in_budget(start_time) {
         let workers = next_workers;
-        let result = run_benchmark(workers, system);
+        let result = run(workers, system, &bench.cancel);
 
         total_tasks += result.total_tasks;
         total_threads += result.total_threads;
@@ -281,13 +421,13 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
             workers - 2
         };
 
-        println!(
+        progress(&format!(
             "Fine-Tune | Workers: {} | CPU: {:.1}% | Target: {:.1}% | Progress: {:.1}%",
             workers,
             result.cpu_usage,
             target_cpu,
             (result.cpu_usage / target_cpu) * 100.0
-        );
+        ));
 
         let score = calculate_efficiency_score(&result, workers);
         if score > best_score || (score >= best_score && result.cpu_usage > optimal_cpu) {
@@ -295,14 +435,14 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
             best_workers = workers;
             optimal_cpu = result.cpu_usage;
             last_improvement = Instant::now();
-            println!(
+            progress(&format!(
                 "► New best configuration found! Workers: {} | CPU: {:.1}%",
                 best_workers, optimal_cpu
-            );
+            ));
         }
 
         // Break after the first fine-tune iteration
-        println!("► First fine-tune iteration complete, stopping optimization.");
+        progress("► First fine-tune iteration complete, stopping optimization.");
     }
 
     let metrics = SystemMetrics {
@@ -313,18 +453,93 @@ fn find_optimal_workers(system: &mut System, base: usize, max: usize) -> (usize,
         benchmark_duration: start_time.elapsed(),
         total_tasks,
         total_threads,
+        recorded_at: Some(Local::now()),
+        machine: Some(machine_fingerprint()),
     };
 
-    // Write metrics to file
-    write_metrics_to_file(&metrics).expect("Failed to write metrics to file");
-
     (best_workers, metrics)
 }
 
-fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
+// Sleeps for `duration` in short slices; returns early once `cancel` fires
+fn sleep_unless_cancelled(duration: Duration, cancel: &CancellationToken) {
+    let deadline = Instant::now() + duration;
+    while !cancel.is_cancelled() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        thread::sleep(left.min(Duration::from_millis(50)));
+    }
+}
+
+/// Runs one benchmark with the given worker count; `run_benchmark` outside of tests
+type BenchRunner<'a> = dyn FnMut(usize, &mut System, &CancellationToken) -> BenchmarkResult + 'a;
+
+/// Benchmarks each worker count for side-by-side comparison
+/// Unlike `find_optimal_workers` no search heuristic is applied; zero counts are skipped
+/// Counts run one after another: each run already loads every CPU, so concurrent
+/// runs would measure each other
+pub fn benchmark_worker_sweep(counts: &[usize]) -> Vec<(usize, BenchmarkResult)> {
+    benchmark_worker_sweep_with(counts, &ScanConfig::default())
+}
+
+/// Same as `benchmark_worker_sweep` but with the client request taken from `config`
+pub fn benchmark_worker_sweep_with(
+    counts: &[usize],
+    config: &ScanConfig,
+) -> Vec<(usize, BenchmarkResult)> {
+    sweep_workers(counts, &mut |workers, system, cancel| {
+        run_benchmark(workers, system, config, cancel)
+    })
+}
+
+fn sweep_workers(counts: &[usize], run: &mut BenchRunner) -> Vec<(usize, BenchmarkResult)> {
+    let mut system = System::new_all();
+    system.refresh_all();
+
+    counts
+        .iter()
+        .filter(|&&workers| workers > 0)
+        .map(|&workers| {
+            println!("► Sweep: benchmarking {} workers", workers);
+            let cancel = CancellationToken::new();
+            (workers, run(workers, &mut system, &cancel))
+        })
+        .collect()
+}
+
+/// Renders sweep results as a fixed-width comparison table
+pub fn format_sweep_table(results: &[(usize, BenchmarkResult)]) -> String {
+    let mut table = format!(
+        "{:>8} | {:>7} | {:>10} | {:>9} | {:>8} | {:>6}\n",
+        "Workers", "CPU %", "Ops/sec", "Elapsed", "Tasks", "Failed"
+    );
+    table.push_str(&"-".repeat(table.len() - 1));
+    table.push('\n');
+    for (workers, result) in results {
+        table.push_str(&format!(
+            "{:>8} | {:>7.1} | {:>10.1} | {:>8.2}s | {:>8} | {:>6}\n",
+            workers,
+            result.cpu_usage,
+            result.io_throughput,
+            result.latency.as_secs_f64(),
+            result.total_tasks,
+            result.failed_ops
+        ));
+    }
+    table
+}
+
+/// Loads `workers` threads with client/server traffic; `cancel` cuts the run short
+fn run_benchmark(
+    workers: usize,
+    system: &mut System,
+    config: &ScanConfig,
+    cancel: &CancellationToken,
+) -> BenchmarkResult {
+    let request: Arc<[u8]> = config.benchmark_request().into();
     let start = Instant::now();
-    let ops_counter = Arc::new(AtomicU64::new(0));
-    let task_counter = Arc::new(AtomicU64::new(0));
+    let counters = Arc::new(BenchCounters::default());
     let thread_counter = Arc::new(AtomicU64::new(0));
     let cpu_samples = Arc::new(Mutex::new(Vec::<CpuSample>::new()));
     let mut cpu_tracker = CpuTracker::new();
@@ -339,6 +554,7 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
 
     // CPU sampling setup
     let samples = Arc::clone(&cpu_samples);
+    let sampler_cancel = cancel.clone();
     let sampler = thread::spawn(move || {
         let mut local_system = System::new_with_specifics(
             RefreshKind::default().with_cpu(CpuRefreshKind::everything()),
@@ -348,7 +564,7 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
         local_system.refresh_cpu_all();
         thread::sleep(Duration::from_millis(50));
 
-        while start.elapsed() < Duration::from_secs(1) {
+        while start.elapsed() < Duration::from_secs(1) && !sampler_cancel.is_cancelled() {
             // Reduced from 2s to 1s
             local_system.refresh_cpu_all();
             let usage = local_system.global_cpu_usage();
@@ -365,9 +581,9 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
     // Spawn worker threads
     let handles: Vec<_> = (0..workers)
         .map(|_| {
-            let ops = Arc::clone(&ops_counter);
-            let tasks = Arc::clone(&task_counter);
-            let threads = Arc::clone(&thread_counter);
+            let counters = Arc::clone(&counters);
+            let request = Arc::clone(&request);
+            let cancel = cancel.clone();
 
             thread::spawn(move || {
                 let runtime = tokio::runtime::Builder::new_current_thread()
@@ -380,13 +596,13 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
                     let addr = listener.local_addr().unwrap();
 
                     // Server task counter
-                    let server_tasks = Arc::clone(&tasks);
+                    let server_counters = Arc::clone(&counters);
 
                     let server = tokio::spawn(async move {
                         while let Ok((mut socket, _)) = listener.accept().await {
-                            server_tasks.fetch_add(1, Ordering::SeqCst);
+                            server_counters.tasks.fetch_add(1, Ordering::SeqCst);
 
-                            let handler_tasks = Arc::clone(&server_tasks);
+                            let handler_counters = Arc::clone(&server_counters);
                             tokio::spawn(async move {
                                 let mut buf = vec![0; 4096];
                                 loop {
@@ -404,7 +620,9 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
                                                     if socket.write_all(&response).await.is_err() {
                                                         break;
                                                     }
-                                                    handler_tasks.fetch_add(1, Ordering::SeqCst);
+                                                    handler_counters
+                                                        .tasks
+                                                        .fetch_add(1, Ordering::SeqCst);
                                                 }
                                             }
                                         }
@@ -415,35 +633,15 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
                         }
                     });
 
-                    // Client task counter
-                    let client_tasks = Arc::clone(&tasks);
-
-                    while start.elapsed().as_secs() < 3 {
-                        if let Ok(mut stream) = TcpStream::connect(addr).await {
-                            client_tasks.fetch_add(1, Ordering::SeqCst);
-                            // Send HTTP GET request with headers
-                            let request = format!(
-                                "GET / HTTP/1.1\r\n\
-                                 Host: localhost\r\n\
-                                 User-Agent: IPCow-Benchmark\r\n\
-                                 Accept: */*\r\n\
-                                 Connection: keep-alive\r\n\r\n"
-                            );
-
-                            if stream.write_all(request.as_bytes()).await.is_ok() {
-                                let mut response = vec![0; 4096];
-                                if let Ok(n) = stream.read(&mut response).await {
-                                    if n > 0
-                                        && String::from_utf8_lossy(&response[..n])
-                                            .starts_with("HTTP/1.1")
-                                    {
-                                        ops.fetch_add(1, Ordering::SeqCst);
-                                    }
-                                }
-                            }
-                            tokio::time::sleep(Duration::from_millis(5)).await; // Reduced sleep time
-                        }
-                    }
+                    run_benchmark_client(
+                        addr,
+                        &request,
+                        start + BENCH_CLIENT_DURATION,
+                        &cancel,
+                        BENCH_IO_TIMEOUT,
+                        &counters,
+                    )
+                    .await;
                     drop(server);
                 })
             })
@@ -484,11 +682,69 @@ fn run_benchmark(workers: usize, system: &mut System) -> BenchmarkResult {
     BenchmarkResult {
         cpu_usage: peak_cpu.max(avg_cpu),
         memory_usage: system.used_memory() as f64,
-        io_throughput: ops_counter.load(Ordering::Relaxed) as f64 / 3.0,
+        io_throughput: counters.ops.load(Ordering::Relaxed) as f64 / 3.0,
         latency: start.elapsed(),
         cpu_tracker: Some(cpu_tracker),
-        total_tasks: task_counter.load(Ordering::SeqCst),
+        total_tasks: counters.tasks.load(Ordering::SeqCst),
         total_threads: thread_counter.load(Ordering::SeqCst),
+        failed_ops: counters.failed.load(Ordering::SeqCst),
+    }
+}
+
+/// Totals shared by a benchmark's client and server tasks
+#[derive(Debug, Default)]
+struct BenchCounters {
+    ops: AtomicU64,    // Requests answered with an HTTP response
+    failed: AtomicU64, // Connects or exchanges that failed or timed out
+    tasks: AtomicU64,  // Connections made and requests served
+}
+
+/// Drives benchmark requests against `addr` until `deadline` or until `cancel` fires
+/// Every connect, write and read is bounded by `io_timeout`; a stalled server counts
+/// as a failed op instead of blocking the worker thread (and `handle.join()`) forever
+/// Failed connects back off exponentially (up to `BENCH_MAX_CONNECT_BACKOFF`) instead
+/// of spinning against a server that isn't accepting
+async fn run_benchmark_client(
+    addr: SocketAddr,
+    request: &[u8],
+    deadline: Instant,
+    cancel: &CancellationToken,
+    io_timeout: Duration,
+    counters: &BenchCounters,
+) {
+    let pacing = Duration::from_millis(5);
+    let mut backoff = pacing;
+    while Instant::now() < deadline && !cancel.is_cancelled() {
+        let mut stream = match timeout(io_timeout, TcpStream::connect(addr)).await {
+            Ok(Ok(stream)) => stream,
+            _ => {
+                counters.failed.fetch_add(1, Ordering::SeqCst);
+                sleep(backoff.min(deadline.saturating_duration_since(Instant::now()))).await;
+                backoff = (backoff * 2).min(BENCH_MAX_CONNECT_BACKOFF);
+                continue;
+            }
+        };
+        backoff = pacing;
+        counters.tasks.fetch_add(1, Ordering::SeqCst);
+
+        let mut response = vec![0; 4096];
+        let exchange = timeout(io_timeout, async {
+            stream.write_all(request).await?;
+            stream.read(&mut response).await
+        })
+        .await;
+
+        match exchange {
+            Ok(Ok(n))
+                if n > 0 && String::from_utf8_lossy(&response[..n]).starts_with("HTTP/1.1") =>
+            {
+                counters.ops.fetch_add(1, Ordering::SeqCst);
+            }
+            _ => {
+                counters.failed.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        sleep(pacing).await;
     }
 }
 
@@ -585,7 +841,17 @@ pub fn calculate_optimal_workers(max_workers: usize) -> usize {
         .unwrap_or(NonZeroUsize::new(1).unwrap())
         .get();
 
-    find_optimal_workers(&mut system, base_workers, max_workers).0
+    let progress = |line: &str| println!("{}", line);
+    let (optimal, metrics) = find_optimal_workers(
+        &mut system,
+        base_workers,
+        max_workers,
+        &BenchConfig::default(),
+        &progress,
+        &mut default_runner,
+    );
+    write_metrics_to_file(&metrics).expect("Failed to write metrics to file");
+    optimal
 }
 
 fn spawn_realistic_worker_thread(
@@ -655,15 +921,9 @@ fn spawn_realistic_worker_thread(
             let start = Instant::now();
             while start.elapsed().as_secs() < 3 {
                 if let Ok(mut stream) = TcpStream::connect(addr).await {
-                    let request = format!(
-                        "GET / HTTP/1.1\r\n\
-                         Host: localhost\r\n\
-                         User-Agent: IPCow-Benchmark\r\n\
-                         Accept: */*\r\n\
-                         Connection: keep-alive\r\n\r\n"
-                    );
-
-                    if stream.write_all(request.as_bytes()).await.is_ok() {
+                    let request = ScanConfig::default().benchmark_request();
+
+                    if stream.write_all(&request).await.is_ok() {
                         let mut response = vec![0; 4096];
                         if let Ok(n) = stream.read(&mut response).await {
                             if n > 0
@@ -716,15 +976,89 @@ fn process_mock_request(data: &[u8]) -> Vec<u8> {
     .into_bytes()
 }
 
-fn analyze_mock_service(data: &[u8]) -> String {
-    // Simulate service fingerprinting
-    let mut hash = 0u64;
-    for &byte in data {
-        hash = hash.wrapping_mul(31).wrapping_add(byte as u64);
-    }
+/// Hash algorithms available for service fingerprinting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    /// Original 31-multiplier rolling hash
+    Rolling,
+    /// 64-bit FNV-1a, better distribution for short banners
+    #[default]
+    Fnv1a,
+}
+
+/// Produces a stable "Service-{hash}" fingerprint for service data (banners, probe replies)
+/// Identical inputs always hash equal, so the result can be used to deduplicate records
+pub fn fingerprint_hash(data: &[u8]) -> String {
+    fingerprint_hash_with(data, HashAlgorithm::default())
+}
+
+/// Same as `fingerprint_hash` but with an explicitly selected algorithm
+pub fn fingerprint_hash_with(data: &[u8], algorithm: HashAlgorithm) -> String {
+    let hash = match algorithm {
+        HashAlgorithm::Rolling => data.iter().fold(0u64, |hash, &byte| {
+            hash.wrapping_mul(31).wrapping_add(byte as u64)
+        }),
+        HashAlgorithm::Fnv1a => data.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        }),
+    };
     format!("Service-{:x}", hash)
 }
 
+/// Quotes a CSV field when it contains separators, quotes or line breaks
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Parses a duration such as `500ms`, `30s`, `15m` or `2h`; a bare number is seconds
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration '{}'", input))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        other => {
+            return Err(format!(
+                "unknown duration unit '{}' (expected ms, s, m or h)",
+                other
+            ))
+        }
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|e| format!("invalid duration '{}': {}", input, e))
+}
+
+/// Replaces `path` with `contents` without ever leaving a partially written file:
+/// the data goes to a sibling temp file first, which is then renamed over `path`
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(".{}.tmp", std::process::id()));
+    let tmp = path.with_file_name(tmp_name);
+
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    match written.and_then(|_| std::fs::rename(&tmp, path)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&tmp);
+            Err(e)
+        }
+    }
+}
+
 fn create_cpu_tracker(measurements: &[CpuMeasurement]) -> CpuTracker {
     let mut cpu_tracker = CpuTracker::new();
     for measurement in measurements {
@@ -736,35 +1070,318 @@ fn create_cpu_tracker(measurements: &[CpuMeasurement]) -> CpuTracker {
 }
 
 fn write_metrics_to_file(metrics: &SystemMetrics) -> io::Result<()> {
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open("metrics.txt")?;
-    let mut writer = BufWriter::new(file);
-    let metrics_json = serde_json::to_string(metrics)?;
     let current_dir = std::env::current_dir().unwrap_or_default();
     println!(
         "Saving metrics to: {}",
-        current_dir.join("metrics.txt").display()
+        current_dir.join(METRICS_CACHE_FILE).display()
     );
-    writeln!(writer, "{}", metrics_json)?;
-    Ok(())
+    write_metrics_cache_entry(Path::new(METRICS_CACHE_FILE), metrics)
+}
+
+/// Stores `metrics` under its machine, keeping the entries of other machines
+/// A cache in the old single-entry format is replaced
+fn write_metrics_cache_entry(path: &Path, metrics: &SystemMetrics) -> io::Result<()> {
+    let mut cache = read_metrics_cache(path).unwrap_or_default();
+    let machine = metrics.machine.clone().unwrap_or_else(machine_fingerprint);
+    cache.insert(machine, metrics.clone());
+    write_atomic(path, serde_json::to_string_pretty(&cache)?.as_bytes())
 }
 
-fn read_metrics_from_file() -> io::Result<SystemMetrics> {
-    let file = File::open("metrics.txt")?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+fn read_metrics_from_file(machine: &str) -> io::Result<SystemMetrics> {
     let current_dir = std::env::current_dir().unwrap_or_default();
     println!(
         "Loading metrics from: {}",
-        current_dir.join("metrics.txt").display()
+        current_dir.join(METRICS_CACHE_FILE).display()
     );
-    if let Some(line) = lines.next() {
-        let metrics: SystemMetrics = serde_json::from_str(&line?)?;
-        Ok(metrics)
-    } else {
-        Err(io::Error::new(io::ErrorKind::NotFound, "No metrics found"))
+    read_metrics_cache(Path::new(METRICS_CACHE_FILE))?
+        .remove(machine)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No metrics for machine {}", machine),
+            )
+        })
+}
+
+/// Cached benchmark results by machine fingerprint
+fn read_metrics_cache(path: &Path) -> io::Result<BTreeMap<String, SystemMetrics>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// Appends one benchmark run as a JSON line, keeping all previous runs
+fn append_metrics_history(path: &Path, metrics: &SystemMetrics) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(metrics)?)
+}
+
+/// Reads every recorded benchmark run, oldest first
+/// A missing history file yields an empty list
+fn read_metrics_history() -> Vec<SystemMetrics> {
+    read_metrics_history_from(Path::new(METRICS_HISTORY_FILE)).unwrap_or_default()
+}
+
+fn read_metrics_history_from(path: &Path) -> io::Result<Vec<SystemMetrics>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut history = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        history.push(serde_json::from_str(&line)?);
+    }
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_metrics(optimal_threads: usize) -> SystemMetrics {
+        SystemMetrics {
+            max_cpu_usage: 50.0,
+            optimal_threads,
+            total_workers: optimal_threads * 2,
+            memory_usage_mb: 128.0,
+            benchmark_duration: Duration::ZERO,
+            total_tasks: 100,
+            total_threads: 8,
+            recorded_at: Some(Local::now()),
+            machine: Some(machine_key(8, 16 << 30)),
+        }
+    }
+
+    // Instant stand-in for `run_benchmark`, reporting `workers` threads
+    fn stub_result(workers: usize) -> BenchmarkResult {
+        BenchmarkResult {
+            cpu_usage: 50.0,
+            memory_usage: 0.0,
+            io_throughput: 100.0,
+            latency: Duration::from_millis(1),
+            cpu_tracker: Some(CpuTracker::new()),
+            total_tasks: 10,
+            total_threads: workers as u64,
+            failed_ops: 0,
+        }
+    }
+
+    #[test]
+    fn test_benchmark_worker_sweep_labels_results() {
+        let mut runs = Vec::new();
+        let results = sweep_workers(&[1, 0, 2], &mut |workers, _, _| {
+            runs.push(workers);
+            stub_result(workers)
+        });
+        assert_eq!(runs, vec![1, 2]);
+        let labels: Vec<usize> = results.iter().map(|(workers, _)| *workers).collect();
+        assert_eq!(labels, vec![1, 2]);
+        assert!(results.iter().all(|(workers, r)| r.total_threads == *workers as u64));
+
+        let table = format_sweep_table(&results);
+        assert_eq!(table.lines().count(), 4);
+    }
+
+    #[test]
+    fn test_metrics_history_appends_runs_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(METRICS_HISTORY_FILE);
+
+        append_metrics_history(&path, &sample_metrics(4)).unwrap();
+        append_metrics_history(&path, &sample_metrics(12)).unwrap();
+
+        let history = read_metrics_history_from(&path).unwrap();
+        let threads: Vec<usize> = history.iter().map(|m| m.optimal_threads).collect();
+        assert_eq!(threads, vec![4, 12]);
+        assert!(history.iter().all(|m| m.recorded_at.is_some()));
+        assert!(history[0].recorded_at <= history[1].recorded_at);
+    }
+
+    #[test]
+    fn test_metrics_cache_keyed_by_machine() {
+        assert_eq!(machine_key(8, 15_600_000_000), "8cpu-16gib");
+        assert_eq!(machine_key(8, 16 << 30), "8cpu-16gib");
+        assert_eq!(machine_key(2, 3 << 30), "2cpu-4gib");
+        assert_ne!(machine_key(4, 16 << 30), machine_key(8, 16 << 30));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(METRICS_CACHE_FILE);
+        // A single-entry file from an unknown machine is never reused
+        std::fs::write(&path, serde_json::to_string(&sample_metrics(3)).unwrap()).unwrap();
+        assert!(read_metrics_cache(&path).is_err());
+
+        let mut other = sample_metrics(2);
+        other.machine = Some(machine_key(2, 4 << 30));
+        write_metrics_cache_entry(&path, &sample_metrics(6)).unwrap();
+        write_metrics_cache_entry(&path, &other).unwrap();
+        write_metrics_cache_entry(&path, &sample_metrics(12)).unwrap();
+
+        let cache = read_metrics_cache(&path).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache["8cpu-16gib"].optimal_threads, 12);
+        assert_eq!(cache["2cpu-4gib"].optimal_threads, 2);
+    }
+
+    #[test]
+    fn test_fingerprint_hash_identical_banners() {
+        // Two banners read separately, as from two connections
+        let first = String::from("SSH-2.0-OpenSSH_9.6\r\n").into_bytes();
+        let second = format!("SSH-2.0-OpenSSH_{}\r\n", "9.6").into_bytes();
+        let other = b"SSH-2.0-OpenSSH_9.7\r\n";
+        for algorithm in [HashAlgorithm::Rolling, HashAlgorithm::Fnv1a] {
+            assert_eq!(
+                fingerprint_hash_with(&first, algorithm),
+                fingerprint_hash_with(&second, algorithm)
+            );
+            assert_ne!(
+                fingerprint_hash_with(&first, algorithm),
+                fingerprint_hash_with(other, algorithm)
+            );
+        }
+        assert_eq!(fingerprint_hash(&first), fingerprint_hash(&second));
+        assert_ne!(fingerprint_hash(&first), fingerprint_hash(other));
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_client_survives_silent_server() {
+        // Accept connections but never answer, holding sockets open
+        let (addr, server) = crate::test_support::spawn_silent_server().await;
+
+        let counters = BenchCounters::default();
+        let duration = Duration::from_millis(300);
+        let start = Instant::now();
+
+        run_benchmark_client(
+            addr,
+            &ScanConfig::default().benchmark_request(),
+            start + duration,
+            &CancellationToken::new(),
+            Duration::from_millis(50),
+            &counters,
+        )
+        .await;
+
+        // Budget plus at most one in-flight op timeout (and pacing sleep)
+        assert!(start.elapsed() < duration + Duration::from_millis(200));
+        assert_eq!(counters.ops.load(Ordering::SeqCst), 0);
+        assert!(counters.failed.load(Ordering::SeqCst) > 0);
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_benchmark_client_backs_off_when_connects_fail() {
+        // Nothing listens on a port we bound and released
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let counters = BenchCounters::default();
+        let duration = Duration::from_millis(500);
+        let start = Instant::now();
+
+        run_benchmark_client(
+            addr,
+            &ScanConfig::default().benchmark_request(),
+            start + duration,
+            &CancellationToken::new(),
+            Duration::from_millis(50),
+            &counters,
+        )
+        .await;
+
+        // 5, 10, 20 ... ms between attempts: a handful of failures, not thousands
+        let failed = counters.failed.load(Ordering::SeqCst);
+        assert!((1..=10).contains(&failed), "{} failed connects", failed);
+        assert!(start.elapsed() < duration + Duration::from_millis(100));
+        assert_eq!(counters.tasks.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_cancelled_benchmark_stops_early_without_result() {
+        let bench = BenchConfig {
+            warmup: Duration::ZERO,
+            ..Default::default()
+        };
+        let cancel = bench.cancel.clone();
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            cancel.cancel();
+        });
+
+        // Like a real run, the stub only returns early once cancelled
+        let mut run = |workers, _: &mut System, cancel: &CancellationToken| {
+            sleep_unless_cancelled(Duration::from_secs(3), cancel);
+            stub_result(workers)
+        };
+        let start = Instant::now();
+        let mut system = System::new();
+        let (_, metrics) = find_optimal_workers(&mut system, 1, 4, &bench, &|_| {}, &mut run);
+        canceller.join().unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(2), "took {:?}", start.elapsed());
+        assert!(metrics.total_workers <= 1);
+
+        // Cancelled before the search starts: nothing is measured or cached
+        let cancelled = BenchConfig::default();
+        cancelled.cancel.cancel();
+        let start = Instant::now();
+        assert_eq!(benchmark_thread_factor(&cancelled), None);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_search_reports_only_through_progress() {
+        let cached = Path::new(METRICS_CACHE_FILE).exists();
+        let bench = BenchConfig {
+            warmup: Duration::ZERO,
+            max_duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+        // The budget runs out during the first run, so exactly one configuration is tested
+        let mut run = |workers, _: &mut System, _: &CancellationToken| {
+            thread::sleep(Duration::from_millis(150));
+            stub_result(workers)
+        };
+        let lines = Mutex::new(Vec::new());
+        let metrics = search_workers(
+            &bench,
+            &|line| lines.lock().unwrap().push(line.to_string()),
+            &mut run,
+        );
+
+        assert_eq!(metrics.total_workers, 1);
+        assert!(metrics.optimal_threads >= 1);
+        assert_eq!(metrics.total_threads, metrics.optimal_threads as u64);
+        assert_eq!(metrics.machine, Some(machine_fingerprint()));
+        assert_eq!(Path::new(METRICS_CACHE_FILE).exists(), cached);
+        // Every line the search reports went to `progress`, which `benchmark_workers`
+        // leaves empty
+        let lines = lines.into_inner().unwrap();
+        assert!(lines.iter().any(|line| line.contains("Worker Optimization")));
+        assert!(lines.iter().any(|line| line.contains("New best configuration")));
+    }
+
+    #[test]
+    fn test_fingerprint_hash_different_banners() {
+        let a = b"220 mail.example.com ESMTP Postfix";
+        let b = b"220 mail.example.com ESMTP Exim";
+        for algorithm in [HashAlgorithm::Rolling, HashAlgorithm::Fnv1a] {
+            assert_ne!(
+                fingerprint_hash_with(a, algorithm),
+                fingerprint_hash_with(b, algorithm)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_duration_units() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("soon").is_err());
     }
 }
@@ -1 +1,6 @@
 pub mod helpers;
+pub mod rate_limit;
+pub mod rng;
+
+pub use rate_limit::RateLimiter;
+pub use rng::RngSource;
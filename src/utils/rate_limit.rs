@@ -0,0 +1,125 @@
+// Token bucket rate limiter shared by scan, connection and bandwidth limits
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+/// Token bucket refilled at `rate` tokens per second, holding at most `burst`
+/// Clones share the same bucket, so one limiter can pace many tasks
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    rate: f64,
+    burst: f64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a full bucket allowing `burst` immediate acquisitions,
+    /// then `rate_per_sec` on average
+    pub fn new(rate_per_sec: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            bucket: Arc::new(Mutex::new(Bucket {
+                tokens: burst,
+                last_refill: Instant::now(),
+            })),
+            rate: rate_per_sec.max(f64::MIN_POSITIVE),
+            burst,
+        }
+    }
+
+    /// Takes one token if available without waiting
+    pub fn try_acquire(&self) -> bool {
+        self.take(1.0).is_ok()
+    }
+
+    /// Waits until one token is available and takes it
+    pub async fn acquire(&self) {
+        self.acquire_n(1).await;
+    }
+
+    /// Waits until `n` tokens are available and takes them
+    /// Requests above the burst size are capped at the burst so they can't wait forever
+    pub async fn acquire_n(&self, n: u32) {
+        let n = f64::from(n).min(self.burst);
+        while let Err(wait) = self.take(n) {
+            sleep(wait).await;
+        }
+    }
+
+    /// Tokens currently available, after refilling for elapsed time
+    pub fn available(&self) -> f64 {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket);
+        bucket.tokens
+    }
+
+    // Takes `n` tokens, or returns how long until enough have accumulated
+    fn take(&self, n: f64) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket);
+        if bucket.tokens >= n {
+            bucket.tokens -= n;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((n - bucket.tokens) / self.rate))
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst_is_available_immediately() {
+        let limiter = RateLimiter::new(1.0, 5);
+        for _ in 0..5 {
+            assert!(limiter.try_acquire());
+        }
+        assert!(!limiter.try_acquire());
+    }
+
+    #[tokio::test]
+    async fn test_steady_state_rate() {
+        let limiter = RateLimiter::new(100.0, 1);
+        limiter.acquire().await;
+
+        // 10 more tokens at 100/s need roughly 100ms
+        let start = std::time::Instant::now();
+        for _ in 0..10 {
+            limiter.acquire().await;
+        }
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(85), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_blocks_when_depleted() {
+        let limiter = RateLimiter::new(10.0, 2);
+        let shared = limiter.clone();
+        shared.acquire_n(2).await;
+        assert!(!limiter.try_acquire());
+
+        // The clone drained the shared bucket; the next token takes ~100ms
+        let blocked = tokio::time::timeout(Duration::from_millis(30), limiter.acquire()).await;
+        assert!(blocked.is_err());
+        tokio::time::timeout(Duration::from_millis(200), limiter.acquire())
+            .await
+            .expect("token should refill within one interval");
+    }
+}
@@ -0,0 +1,21 @@
+use ipcow::core::sockparse::{ip_iter, parse_ip_input};
+use ipcow::test_support::{allocated_bytes, CountingAllocator};
+use std::net::IpAddr;
+
+#[global_allocator]
+static ALLOC: CountingAllocator = CountingAllocator;
+
+// A materialized /16 takes 65536 * size_of::<IpAddr>() bytes
+const FULL_SLASH_16: usize = 65536 * std::mem::size_of::<IpAddr>();
+
+#[test]
+fn test_ip_iter_over_slash_16_does_not_allocate() {
+    let (count, bytes) = allocated_bytes(|| ip_iter("10.20.0.0/16").unwrap().count());
+    assert_eq!(count, 65536);
+    assert!(bytes < 1024, "ip_iter allocated {} bytes", bytes);
+
+    // The eager parser is the baseline the lazy path avoids
+    let (ips, eager_bytes) = allocated_bytes(|| parse_ip_input("10.20.0.0/16").unwrap());
+    assert_eq!(ips.len(), 65536);
+    assert!(eager_bytes >= FULL_SLASH_16);
+}
@@ -0,0 +1,425 @@
+use ipcow::core::fingerprint::FingerprintDb;
+use ipcow::utils::helpers::machine_fingerprint;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+// Working directory with metrics cached for this machine, so the server skips the
+// worker benchmark
+fn server_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("metrics.txt"),
+        format!(
+            r#"{{"{}":{{"max_cpu_usage":50.0,"optimal_threads":2,"total_workers":2,"memory_usage_mb":1.0,"total_tasks":1,"total_threads":1}}}}"#,
+            machine_fingerprint()
+        ),
+    )
+    .unwrap();
+    dir
+}
+
+#[test]
+fn test_max_runtime_stops_server_with_summary() {
+    let dir = server_dir();
+    let targets = dir.path().join("targets.txt");
+    std::fs::write(&targets, "127.0.0.1\n0\n").unwrap();
+
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .arg("--targets")
+        .arg(&targets)
+        .args(["--max-runtime", "1s"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let elapsed = started.elapsed();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(elapsed >= Duration::from_secs(1), "stopped early after {:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(5), "took {:?} to stop", elapsed);
+    assert!(stdout.contains("Maximum runtime reached"), "{}", stdout);
+    assert!(stdout.contains("=== Connection Summary ==="), "{}", stdout);
+}
+
+#[test]
+fn test_max_runtime_ends_blocked_mode_with_failure() {
+    let dir = server_dir();
+
+    // The fuzzer waits at its target prompt on a stdin that never closes
+    let started = Instant::now();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--fuzzing", "--max-runtime", "1s"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let stdin = child.stdin.take();
+    let output = child.wait_with_output().unwrap();
+    drop(stdin);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(124), "{}", stderr);
+    assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+    assert!(stderr.contains("Maximum runtime of 1s reached"), "{}", stderr);
+}
+
+#[test]
+fn test_log_dir_is_created_for_server_logs() {
+    let dir = server_dir();
+    let log_dir = dir.path().join("logs/instance-1");
+    let targets = dir.path().join("targets.txt");
+    std::fs::write(&targets, "127.0.0.1\n0\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .arg("--targets")
+        .arg(&targets)
+        .arg("--log-dir")
+        .arg(&log_dir)
+        .args(["--max-runtime", "1s"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(log_dir.is_dir());
+    assert!(!dir.path().join("discovered_services.txt").exists());
+}
+
+#[test]
+fn test_workers_flag_skips_benchmark_and_metrics() {
+    // No cached metrics: without the override this would benchmark for many seconds
+    let dir = tempfile::tempdir().unwrap();
+    let targets = dir.path().join("targets.txt");
+    std::fs::write(&targets, "127.0.0.1\n0\n").unwrap();
+
+    let started = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .arg("--targets")
+        .arg(&targets)
+        .args(["--workers", "3", "--max-runtime", "1s"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(started.elapsed() < Duration::from_secs(5), "took {:?}", started.elapsed());
+    assert!(stdout.contains("- Worker threads: 3"), "{}", stdout);
+    assert!(!dir.path().join("metrics.txt").exists());
+    assert!(!dir.path().join("metrics_history.jsonl").exists());
+}
+
+// Signals go to a child server, never to the test harness itself
+#[cfg(unix)]
+#[test]
+fn test_sigterm_shuts_down_gracefully() {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    let dir = server_dir();
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let targets = dir.path().join("targets.txt");
+    std::fs::write(&targets, format!("127.0.0.1\n{}\n", port)).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .arg("--targets")
+        .arg(&targets)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("server never listened");
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let status = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let stopped = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if stopped.elapsed() > Duration::from_secs(5) {
+            child.kill().unwrap();
+            panic!("SIGTERM should stop the server");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let mut stdout = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut stdout).unwrap();
+    assert!(status.success(), "exited with {}: {}", status, stdout);
+    assert!(stdout.contains("SIGTERM received, stopping listeners"), "{}", stdout);
+    assert!(stdout.contains("=== Connection Summary ==="), "{}", stdout);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_second_interrupt_forces_exit_while_draining() {
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    let dir = server_dir();
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let targets = dir.path().join("targets.txt");
+    std::fs::write(&targets, format!("127.0.0.1\n{}\n", port)).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .arg("--targets")
+        .arg(&targets)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // An idle client keeps the first interrupt waiting on the drain
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let _client = loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(client) => break client,
+            Err(_) if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(50)),
+            Err(e) => panic!("server never listened: {}", e),
+        }
+    };
+    std::thread::sleep(Duration::from_millis(200));
+
+    let interrupt = || {
+        let status = Command::new("kill")
+            .args(["-INT", &child.id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+    };
+    interrupt();
+    std::thread::sleep(Duration::from_millis(500));
+    interrupt();
+
+    let forced = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if forced.elapsed() > Duration::from_secs(3) {
+            child.kill().unwrap();
+            panic!("server kept draining after the second interrupt");
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+
+    let (mut stdout, mut stderr) = (String::new(), String::new());
+    child.stdout.take().unwrap().read_to_string(&mut stdout).unwrap();
+    child.stderr.take().unwrap().read_to_string(&mut stderr).unwrap();
+    assert_eq!(status.code(), Some(130), "{}{}", stdout, stderr);
+    assert!(stdout.contains("send it again to force exit"), "{}", stdout);
+    assert!(stderr.contains("SIGINT received again, forcing exit"), "{}", stderr);
+}
+
+#[test]
+fn test_ips_and_ports_flags_start_server_without_prompting() {
+    let dir = server_dir();
+
+    // stdin is closed, so any prompt would abort the run
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--multi-port-server", "--ips", "127.0.0.1", "--ports", "0"])
+        .args(["--max-runtime", "1s"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(!stdout.contains("Enter the listen"), "{}", stdout);
+    assert!(stdout.contains("- Total listeners: 1"), "{}", stdout);
+    assert!(stdout.contains("=== Connection Summary ==="), "{}", stdout);
+    // The dashboard only starts when asked for
+    assert!(!stdout.contains("Web dashboard"), "{}", stdout);
+}
+
+#[test]
+fn test_dashboard_flag_serves_dashboard_on_given_addr() {
+    let dir = server_dir();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--ips", "127.0.0.1", "--ports", "0", "--max-runtime", "1s"])
+        .arg("--dashboard=127.0.0.1:0")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(stdout.contains("- Web dashboard: http://127.0.0.1:"), "{}", stdout);
+    assert!(!stdout.contains("http://127.0.0.1:3030/"), "{}", stdout);
+}
+
+#[cfg(feature = "pcap")]
+#[test]
+fn test_capture_dir_flag_enables_packet_capture() {
+    let dir = server_dir();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--ips", "127.0.0.1", "--ports", "0", "--max-runtime", "1s"])
+        .args(["--capture-dir", "captures/run1"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(stdout.contains("- Packet captures: captures/run1"), "{}", stdout);
+    assert!(dir.path().join("captures/run1").is_dir());
+}
+
+#[test]
+fn test_ips_flag_alone_prompts_for_ports() {
+    use std::io::Write;
+
+    let dir = server_dir();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--ips", "127.0.0.1", "--max-runtime", "1s"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"0\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(stdout.contains("Enter the listen IP ports"), "{}", stdout);
+    assert!(!stdout.contains("Enter the listen IP addresses"), "{}", stdout);
+    assert!(stdout.contains("- Total listeners: 1"), "{}", stdout);
+}
+
+#[test]
+fn test_malformed_ports_flag_is_rejected_before_startup() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .args(["--ips", "127.0.0.1", "--ports", "80a"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("--ports"), "{}", stderr);
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Starting Multi-Port"));
+}
+
+#[test]
+fn test_fault_rate_flag_enables_fault_injection() {
+    let dir = server_dir();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--ips", "127.0.0.1", "--ports", "0", "--max-runtime", "1s"])
+        .args(["--fault-rate", "0.25", "--seed", "7"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(stdout.contains("- Fault injection: dropping 25% of connections"), "{}", stdout);
+}
+
+#[test]
+fn test_fault_rate_outside_unit_range_is_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .args(["--ips", "127.0.0.1", "--ports", "0", "--fault-rate", "1.5"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("must be between 0.0 and 1.0"), "{}", stderr);
+}
+
+#[test]
+fn test_closed_stdin_fails_instead_of_panicking() {
+    let dir = server_dir();
+
+    // The port prompt hits end of input
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--ips", "127.0.0.1"])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr);
+    assert!(stderr.contains("input closed"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+
+    // So does the interactive menu, which then exits
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    assert!(stdout.contains("Input closed"), "{}", stdout);
+}
+
+#[test]
+fn test_malformed_fingerprints_file_is_rejected_before_startup() {
+    let dir = tempfile::tempdir().unwrap();
+    let rules = dir.path().join("rules.txt");
+    std::fs::write(&rules, "# lab gear\nprefix LAB => Lab device\nregex ^( => Broken\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .args(["--ips", "127.0.0.1", "--ports", "0", "--fingerprints"])
+        .arg(&rules)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "{}", stderr);
+    assert!(stderr.contains("--fingerprints"), "{}", stderr);
+    assert!(stderr.contains("line 3"), "{}", stderr);
+}
+
+#[test]
+fn test_fingerprints_flag_loads_rules() {
+    let dir = server_dir();
+    let rules = dir.path().join("rules.txt");
+    std::fs::write(&rules, "prefix LAB => Lab device\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ipcow"))
+        .current_dir(dir.path())
+        .args(["--ips", "127.0.0.1", "--ports", "0", "--max-runtime", "1s", "--fingerprints"])
+        .arg(&rules)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "exited with {}: {}", output.status, stdout);
+    // The custom rule comes first, followed by the built-in ones
+    let expected = format!("- Fingerprint rules: {}", FingerprintDb::default().rules().len() + 1);
+    assert!(stdout.contains(&expected), "{}", stdout);
+}
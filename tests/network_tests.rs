@@ -1,13 +1,60 @@
+use ipcow::core::discovery::ServiceDiscovery;
+use ipcow::{AddrData, AddrType, ListenerManager};
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncReadExt;
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 
 const TEST_PORT_1: u16 = 9999;
 const TEST_PORT_2: u16 = 9998;
 const TEST_DURATION: u64 = 10;
 
+// IPv4 loopback listener on a port the OS picks
+fn loopback(socket_type: AddrType) -> AddrData {
+    AddrData {
+        info: AddrType::IPv4,
+        socket_type,
+        address: IpAddr::from([127, 0, 0, 1]),
+        port: 0,
+    }
+}
+
+// Runs a manager over `addr_data`, adjusted by `configure`, once every listener has bound
+// Discovered banners go to a temporary log that lives as long as the run task
+// Returns the first bound address and the task yielding whether `run` succeeded
+async fn spawn_manager(
+    addr_data: Vec<AddrData>,
+    configure: impl FnOnce(ListenerManager) -> ListenerManager,
+) -> (Arc<ListenerManager>, SocketAddr, JoinHandle<bool>) {
+    let listeners = addr_data.len();
+    let dir = tempfile::tempdir().unwrap();
+    let discovery = ServiceDiscovery::new().with_log_file(dir.path().join("services.txt"));
+    let manager = ListenerManager::new(addr_data, 4).with_service_discovery(Arc::new(discovery));
+    let manager = Arc::new(configure(manager));
+    let run = tokio::spawn({
+        let manager = Arc::clone(&manager);
+        async move {
+            let _dir = dir;
+            manager.run().await.is_ok()
+        }
+    });
+    let addr = tokio::time::timeout(Duration::from_secs(2), async {
+        loop {
+            let addrs = manager.bound_addrs();
+            if addrs.len() == listeners {
+                break addrs[0];
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("every listener should bind");
+    (manager, addr, run)
+}
+
 #[tokio::test]
 async fn test_network_throughput() {
     let addr1 = format!("127.0.0.1:{}", TEST_PORT_1);
@@ -68,3 +115,575 @@ async fn handle_connection(socket: &mut tokio::net::TcpStream, total_bytes: Arc<
         }
     }
 }
+
+#[tokio::test]
+async fn test_max_accepts_stops_listener() {
+    use tokio::net::TcpStream;
+
+    let (manager, addr, run) =
+        spawn_manager(vec![loopback(AddrType::TCP)], |m| m.with_max_accepts(2)).await;
+    let state = manager.server_state();
+
+    let _first = TcpStream::connect(addr).await.unwrap();
+    let _second = TcpStream::connect(addr).await.unwrap();
+
+    // The accept loop exits after the second connection, closing the listener
+    let finished = tokio::time::timeout(Duration::from_secs(2), run)
+        .await
+        .expect("listener should stop after two accepts");
+    assert!(finished.unwrap());
+
+    let third = tokio::time::timeout(Duration::from_millis(500), TcpStream::connect(addr)).await;
+    assert!(matches!(third, Err(_) | Ok(Err(_))));
+    assert_eq!(state.snapshot().total_connections, 2);
+}
+
+#[tokio::test]
+async fn test_max_concurrent_caps_active_connections() {
+    use ipcow::core::handlers::{HandlerConfig, PortBehavior};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    let (manager, addr, run) = spawn_manager(vec![loopback(AddrType::TCP)], |m| {
+        m.with_max_concurrent(2).with_handler_config(HandlerConfig {
+            default_behavior: PortBehavior::Echo,
+            ..Default::default()
+        })
+    })
+    .await;
+    let state = manager.server_state();
+
+    // Echo connections stay open until the client closes, each holding a permit
+    async fn echo_once(client: &mut TcpStream) -> bool {
+        client.write_all(b"ping").await.unwrap();
+        let mut buf = [0_u8; 4];
+        tokio::time::timeout(Duration::from_millis(200), client.read_exact(&mut buf))
+            .await
+            .is_ok()
+    }
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    assert!(echo_once(&mut first).await);
+    assert!(echo_once(&mut second).await);
+
+    // The third handshake completes in the backlog but isn't served yet
+    let mut third = TcpStream::connect(addr).await.unwrap();
+    assert!(!echo_once(&mut third).await);
+    assert_eq!(state.snapshot().total_connections, 2);
+
+    // Closing one connection frees a permit and the waiting client gets served
+    drop(first);
+    let mut buf = [0_u8; 4];
+    tokio::time::timeout(Duration::from_secs(2), third.read_exact(&mut buf))
+        .await
+        .expect("queued client should be served once a slot frees")
+        .unwrap();
+    assert_eq!(&buf, b"ping");
+    assert_eq!(state.snapshot().total_connections, 3);
+
+    run.abort();
+}
+
+#[tokio::test]
+async fn test_accepts_wait_for_a_permit() {
+    use ipcow::core::handlers::{HandlerConfig, PortBehavior};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    // The listener closes after its second accept, so its task finishing shows
+    // whether the queued client was accepted
+    let (_, addr, run) = spawn_manager(vec![loopback(AddrType::TCP)], |m| {
+        m.with_max_concurrent(1)
+            .with_max_accepts(2)
+            .with_handler_config(HandlerConfig {
+                default_behavior: PortBehavior::Echo,
+                ..Default::default()
+            })
+    })
+    .await;
+
+    let mut first = TcpStream::connect(addr).await.unwrap();
+    first.write_all(b"ping").await.unwrap();
+    let mut buf = [0_u8; 4];
+    first.read_exact(&mut buf).await.unwrap();
+
+    let mut second = TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!run.is_finished(), "second client accepted without a permit");
+
+    drop(first);
+    second.write_all(b"pong").await.unwrap();
+    tokio::time::timeout(Duration::from_secs(2), second.read_exact(&mut buf))
+        .await
+        .expect("queued client should be accepted once the slot frees")
+        .unwrap();
+    assert_eq!(&buf, b"pong");
+    let finished = tokio::time::timeout(Duration::from_secs(2), run).await;
+    assert!(finished.expect("listener should stop after two accepts").unwrap());
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_in_flight_connections() {
+    use ipcow::core::handlers::{HandlerConfig, PortBehavior};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    let (manager, addr, mut run) = spawn_manager(vec![loopback(AddrType::TCP)], |m| {
+        m.with_handler_config(HandlerConfig {
+            default_behavior: PortBehavior::Echo,
+            ..Default::default()
+        })
+    })
+    .await;
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    client.write_all(b"ping").await.unwrap();
+    let mut buf = [0_u8; 4];
+    client.read_exact(&mut buf).await.unwrap();
+
+    // The listener closes right away, but `run` waits for the open echo session
+    manager.shutdown();
+    assert!(tokio::time::timeout(Duration::from_millis(200), &mut run)
+        .await
+        .is_err());
+    assert!(
+        tokio::time::timeout(Duration::from_millis(500), TcpStream::connect(addr))
+            .await
+            .is_ok_and(|connect| connect.is_err())
+    );
+
+    drop(client);
+    let finished = tokio::time::timeout(Duration::from_secs(2), run)
+        .await
+        .expect("run should return once the connection drains");
+    assert!(finished.unwrap());
+}
+
+#[tokio::test]
+async fn test_mixed_tcp_and_udp_listeners() {
+    use ipcow::core::handlers::{HandlerConfig, PortBehavior};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpStream, UdpSocket};
+
+    let listeners = vec![loopback(AddrType::TCP), loopback(AddrType::UDP)];
+    let (manager, _, run) = spawn_manager(listeners, |m| {
+        m.with_handler_config(HandlerConfig {
+            default_behavior: PortBehavior::Echo,
+            udp_replies: true,
+            ..Default::default()
+        })
+    })
+    .await;
+    let state = manager.server_state();
+    let addrs = manager.bound_addrs();
+
+    // Both sockets get ephemeral ports, so try each address with each protocol
+    let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut udp_echoed = false;
+    let mut tcp_echoed = false;
+    for addr in addrs {
+        udp.send_to(b"udp-ping", addr).await.unwrap();
+        let mut buf = [0_u8; 16];
+        if let Ok(Ok((n, from))) =
+            tokio::time::timeout(Duration::from_millis(200), udp.recv_from(&mut buf)).await
+        {
+            udp_echoed |= from == addr && &buf[..n] == b"udp-ping";
+        }
+
+        if let Ok(mut stream) = TcpStream::connect(addr).await {
+            stream.write_all(b"tcp-ping").await.unwrap();
+            let mut buf = [0_u8; 8];
+            if let Ok(Ok(_)) =
+                tokio::time::timeout(Duration::from_millis(200), stream.read_exact(&mut buf)).await
+            {
+                tcp_echoed |= &buf == b"tcp-ping";
+            }
+        }
+    }
+    assert!(udp_echoed, "UDP listener should echo datagrams");
+    assert!(tcp_echoed, "TCP listener should echo streams");
+    assert_eq!(state.health().healthy_listeners, 2);
+
+    manager.shutdown();
+    let finished = tokio::time::timeout(Duration::from_secs(2), run)
+        .await
+        .expect("both listeners should stop on shutdown");
+    assert!(finished.unwrap());
+}
+
+#[tokio::test]
+async fn test_accept_delay_holds_back_first_response() {
+    use tokio::net::TcpStream;
+
+    let delay = Duration::from_millis(300);
+    let (manager, addr, run) = spawn_manager(vec![loopback(AddrType::TCP)], |m| {
+        m.with_accept_delay(delay)
+    })
+    .await;
+
+    // The default probe handler writes as soon as it gets the socket
+    let started = std::time::Instant::now();
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0_u8; 1];
+    client.read_exact(&mut buf).await.unwrap();
+    assert!(
+        started.elapsed() >= delay,
+        "first byte after {:?}",
+        started.elapsed()
+    );
+
+    manager.shutdown();
+    drop(client);
+    let _ = tokio::time::timeout(Duration::from_secs(2), run).await;
+}
+
+#[tokio::test]
+async fn test_ipv6_listener_binds_loopback() {
+    use tokio::net::TcpStream;
+
+    let addr_data = vec![AddrData {
+        info: AddrType::IPv6,
+        address: IpAddr::from(std::net::Ipv6Addr::LOCALHOST),
+        ..loopback(AddrType::TCP)
+    }];
+    let (manager, addr, run) = spawn_manager(addr_data, |m| m).await;
+    assert!(addr.is_ipv6(), "bound {}", addr);
+
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let mut buf = [0_u8; 1];
+    client.read_exact(&mut buf).await.unwrap();
+
+    manager.shutdown();
+    drop(client);
+    let _ = tokio::time::timeout(Duration::from_secs(2), run).await;
+}
+
+// SO_REUSEPORT is ignored outside Unix, so the second bind would fail there
+#[cfg(unix)]
+#[tokio::test]
+async fn test_listener_config_reuse_port_shares_port() {
+    use ipcow::ListenerConfig;
+
+    let shared = ListenerConfig {
+        reuse_port: true,
+        ..Default::default()
+    };
+    let first = shared.bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = first.local_addr().unwrap();
+    let second = shared
+        .bind_tcp(addr)
+        .expect("SO_REUSEPORT should allow a second bind");
+    assert_eq!(second.local_addr().unwrap(), addr);
+
+    // Without SO_REUSEPORT the port is still exclusive
+    let err = ListenerConfig::default().bind_tcp(addr).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+}
+
+#[tokio::test]
+async fn test_rebind_after_connection_in_time_wait() {
+    use ipcow::ListenerConfig;
+    use tokio::net::TcpStream;
+
+    let config = ListenerConfig::default();
+    let listener = config.bind_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = TcpStream::connect(addr).await.unwrap();
+    let (server_side, _) = listener.accept().await.unwrap();
+    // The server closes first, leaving its end of the connection in TIME_WAIT
+    drop(server_side);
+    drop(listener);
+    drop(client);
+
+    assert!(config.bind_tcp(addr).is_ok());
+}
+
+#[tokio::test]
+async fn test_bind_retries_until_port_frees_up() {
+    use ipcow::core::types::NetworkConfig;
+
+    // Hold the port so the first binds fail
+    let blocker = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr_data = vec![AddrData {
+        port: blocker.local_addr().unwrap().port(),
+        ..loopback(AddrType::TCP)
+    }];
+    let manager = Arc::new(
+        ListenerManager::new(addr_data, 4).with_network_config(NetworkConfig {
+            retry_attempts: 6,
+            retry_backoff: Duration::from_millis(10),
+            ..Default::default()
+        }),
+    );
+    let state = manager.server_state().clone();
+    let run = tokio::spawn({
+        let manager = Arc::clone(&manager);
+        async move { manager.run().await.is_ok() }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(state.bound_addrs().is_empty());
+    drop(blocker);
+
+    let bound = tokio::time::timeout(Duration::from_secs(2), async {
+        while state.bound_addrs().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(bound.is_ok(), "listener should bind once the port is free");
+    assert_eq!(state.health().failed_listeners, 0);
+
+    manager.shutdown();
+    let _ = tokio::time::timeout(Duration::from_secs(2), run).await;
+}
+
+#[tokio::test]
+async fn test_bind_gives_up_after_retries() {
+    use ipcow::core::types::NetworkConfig;
+    use ipcow::ErrorRegistry;
+
+    let blocker = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr_data = vec![AddrData {
+        port: blocker.local_addr().unwrap().port(),
+        ..loopback(AddrType::TCP)
+    }];
+    let registry = Arc::new(Mutex::new(ErrorRegistry::new()));
+    let manager = ListenerManager::new(addr_data, 4)
+        .with_error_registry(Arc::clone(&registry))
+        .with_network_config(NetworkConfig {
+            retry_attempts: 2,
+            retry_backoff: Duration::from_millis(20),
+            ..Default::default()
+        });
+
+    // Two retries wait 20ms then 40ms before the final failure is registered
+    let started = std::time::Instant::now();
+    tokio::time::timeout(Duration::from_secs(2), manager.run())
+        .await
+        .expect("run should return once the bind gives up")
+        .unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(60));
+    assert_eq!(manager.server_state().health().failed_listeners, 1);
+    assert_eq!(registry.lock().await.error_count(), 1);
+}
+
+#[tokio::test]
+async fn test_privileged_port_bind_reports_permission_error() {
+    use ipcow::core::error::ErrorCategory;
+    use ipcow::core::types::NetworkConfig;
+    use ipcow::ErrorRegistry;
+
+    // Only meaningful without privileges: root, CAP_NET_BIND_SERVICE or a lowered
+    // ip_unprivileged_port_start let the bind succeed
+    let port = 81;
+    match std::net::TcpListener::bind(("127.0.0.1", port)) {
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {}
+        _ => return,
+    }
+
+    let addr_data = vec![AddrData {
+        port,
+        ..loopback(AddrType::TCP)
+    }];
+    let registry = Arc::new(Mutex::new(ErrorRegistry::new()));
+    let manager = ListenerManager::new(addr_data, 4)
+        .with_error_registry(Arc::clone(&registry))
+        .with_network_config(NetworkConfig {
+            retry_attempts: 3,
+            retry_backoff: Duration::from_secs(1),
+            ..Default::default()
+        });
+
+    // Permission errors fail immediately instead of waiting out the retries
+    tokio::time::timeout(Duration::from_millis(500), manager.run())
+        .await
+        .expect("permission errors should not be retried")
+        .unwrap();
+    let registry = registry.lock().await;
+    assert_eq!(
+        registry.errors_in(ErrorCategory::PrivilegedPort),
+        vec!["binding port 81 requires elevated privileges or CAP_NET_BIND_SERVICE"]
+    );
+}
+
+#[tokio::test]
+async fn test_bound_addrs_report_assigned_ports() {
+    assert!(ListenerManager::new(vec![loopback(AddrType::TCP)], 4)
+        .bound_addrs()
+        .is_empty());
+    let (manager, _, run) = spawn_manager(vec![loopback(AddrType::TCP); 2], |m| m).await;
+    let addrs = manager.bound_addrs();
+
+    assert!(addrs.iter().all(|addr| addr.port() != 0));
+    assert_ne!(addrs[0].port(), addrs[1].port());
+    for addr in &addrs {
+        assert!(tokio::net::TcpStream::connect(addr).await.is_ok());
+    }
+
+    manager.shutdown();
+    let _ = tokio::time::timeout(Duration::from_secs(2), run).await;
+}
+
+#[tokio::test]
+async fn test_stalled_client_times_out_with_registered_error() {
+    use ipcow::ErrorRegistry;
+    use tokio::net::TcpStream;
+
+    let registry = Arc::new(Mutex::new(ErrorRegistry::new()));
+    let (manager, addr, run) = spawn_manager(vec![loopback(AddrType::TCP)], |m| {
+        m.with_error_registry(Arc::clone(&registry))
+            .with_timeout(Duration::from_millis(200))
+    })
+    .await;
+
+    // Connect and never send: the handler must close instead of waiting forever
+    for _ in 0..2 {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut received = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), client.read_to_end(&mut received))
+            .await
+            .expect("stalled client should be disconnected")
+            .unwrap();
+        assert!(!received.starts_with(b"HTTP/1.1 200"));
+    }
+
+    let registered = tokio::time::timeout(Duration::from_secs(2), async {
+        while registry.lock().await.error_count() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(registered.is_ok(), "timeouts should be registered");
+    // Clients come from different ports, yet share one registry entry
+    assert_eq!(registry.lock().await.distinct_count(), 1);
+
+    manager.shutdown();
+    let _ = tokio::time::timeout(Duration::from_secs(2), run).await;
+}
+
+#[tokio::test]
+async fn test_core_state_tracks_open_connections() {
+    use ipcow::core::state::CoreState;
+    use ipcow::core::types::ConnectionState;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+
+    let core_state = Arc::new(Mutex::new(CoreState::new()));
+    let (manager, addr, run) = spawn_manager(vec![loopback(AddrType::TCP)], |m| {
+        m.with_core_state(Arc::clone(&core_state))
+    })
+    .await;
+
+    // The probe handler keeps the connection open until the client answers
+    let mut client = TcpStream::connect(addr).await.unwrap();
+    let mut probe = [0_u8; 16];
+    client.read_exact(&mut probe).await.unwrap();
+    let local = client.local_addr().unwrap();
+    assert_eq!(
+        core_state.lock().await.get_active_connections(),
+        vec![(local, ConnectionState::Connected)]
+    );
+
+    client.write_all(b"hello\r\n").await.unwrap();
+    let mut response = Vec::new();
+    client.read_to_end(&mut response).await.unwrap();
+    let closed = tokio::time::timeout(Duration::from_secs(2), async {
+        while !core_state.lock().await.get_active_connections().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await;
+    assert!(closed.is_ok(), "closed connection should leave the active map");
+    assert_eq!(
+        core_state.lock().await.recently_closed.back(),
+        Some(&(local, ConnectionState::Disconnected))
+    );
+
+    manager.shutdown();
+    let _ = tokio::time::timeout(Duration::from_secs(2), run).await;
+}
+
+#[tokio::test]
+async fn test_core_kills_and_reaps_connections() {
+    use ipcow::core::types::ConnectionState;
+    use ipcow::core::IPCowCore;
+    use tokio::net::TcpStream;
+
+    let core = Arc::new(IPCowCore::new());
+    let dir = tempfile::tempdir().unwrap();
+    let discovery = ServiceDiscovery::new().with_log_file(dir.path().join("services.txt"));
+    {
+        let mut manager = core.network_manager.lock().await;
+        *manager = ListenerManager::new(vec![loopback(AddrType::TCP)], 4)
+            .with_service_discovery(Arc::new(discovery))
+            .with_server_state(core.server_state.clone())
+            .with_core_state(core.state.clone())
+            .with_shutdown(core.shutdown_token());
+    }
+    let running = Arc::clone(&core);
+    let server = tokio::spawn(async move { running.start().await.is_ok() });
+    let addr = loop {
+        if let Some(addr) = core.server_state.bound_addrs().first().copied() {
+            break addr;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    let last_closed = || async { core.state.lock().await.recently_closed.back().cloned() };
+    let is_open = |addr| {
+        let core = &core;
+        async move { core.list_connections().await.iter().any(|c| c.addr == addr) }
+    };
+
+    // Force-close a connection waiting in the probe handler
+    let mut killed = TcpStream::connect(addr).await.unwrap();
+    let mut probe = [0_u8; 16];
+    killed.read_exact(&mut probe).await.unwrap();
+    let local = killed.local_addr().unwrap();
+    let listed = core.list_connections().await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(
+        (listed[0].addr, listed[0].state.clone()),
+        (local, ConnectionState::Connected)
+    );
+    assert!(listed[0].tx_bytes >= probe.len() as u64);
+    assert_eq!(core.state.lock().await.throughput.tx(), listed[0].tx_bytes);
+
+    assert!(core.kill_connection(local).await);
+    let mut rest = Vec::new();
+    tokio::time::timeout(Duration::from_secs(2), killed.read_to_end(&mut rest))
+        .await
+        .expect("killed connection should be closed")
+        .unwrap();
+    while is_open(local).await {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(
+        last_closed().await,
+        Some((local, ConnectionState::Closed("closed by operator".into())))
+    );
+    assert!(!core.kill_connection(local).await);
+
+    // A quiet client is reaped once the idle timeout passes
+    let limit = Duration::from_millis(200);
+    core.set_idle_timeout(Some(limit)).await;
+    let mut quiet = TcpStream::connect(addr).await.unwrap();
+    quiet.read_exact(&mut probe).await.unwrap();
+    let local = quiet.local_addr().unwrap();
+    tokio::time::timeout(Duration::from_secs(2), quiet.read_to_end(&mut rest))
+        .await
+        .expect("idle connection should be reaped")
+        .unwrap();
+    while is_open(local).await {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    match last_closed().await {
+        Some((addr, ConnectionState::Closed(reason))) => {
+            assert_eq!(addr, local);
+            assert!(reason.starts_with("idle"), "{}", reason);
+        }
+        other => panic!("unexpected close record {:?}", other),
+    }
+
+    let _ = core.shutdown().await;
+    let _ = tokio::time::timeout(Duration::from_secs(2), server).await;
+}
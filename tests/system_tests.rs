@@ -1,4 +1,5 @@
 use ipcow::{AddrData, AddrType, ListenerManager};
+use std::net::IpAddr;
 use std::thread;
 use std::time::Duration;
 use sysinfo::{RefreshKind, System};
@@ -73,7 +74,7 @@ fn test_system_resources_with_server() {
     let addr_data = vec![AddrData {
         info: AddrType::IPv4,
         socket_type: AddrType::TCP,
-        address: (127, 0, 0, 1),
+        address: IpAddr::from([127, 0, 0, 1]),
         port: 8080,
     }];
 